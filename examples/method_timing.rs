@@ -0,0 +1,119 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+
+//! Times the solve of small chains (2 to 5 parts) with each minimization
+//! method. Each part is fixed relative to the previous one by a FixBase and a
+//! FixRotation constraint, and its initial placement is off the solution.
+//!
+//!     cargo run --release --example method_timing
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use solver::{Method, SolverOptions, System};
+
+
+/// Number of times each system is solved
+const REPETITIONS: u32 = 200;
+
+
+/// Input maps of a chain of n parts after a locked base
+struct Chain {
+    objects: Vec<String>,
+    constraints: Vec<String>,
+}
+
+
+impl Chain {
+    fn new(n: usize) -> Chain {
+        let objects = (0..=n).map(|i| format!("Part{}", i)).collect();
+        let constraints = (0..=n)
+            .flat_map(|i| vec![format!("Lock{}", i), format!("FixBase{}", i), format!("FixRotation{}", i)])
+            .collect();
+        Chain {
+            objects,
+            constraints,
+        }
+    }
+
+    fn system(&self) -> System {
+        let mut objects = HashMap::new();
+        for (i, name) in self.objects.iter().enumerate() {
+            let t = i as f64;
+            let placement: HashMap<&str, f64> = [
+                ("x", t), ("y", 0.5 * t), ("z", -t), ("phi", 0.3 * t), ("theta", 0.1 * t), ("psi", -0.3 * t),
+            ].iter().cloned().collect();
+            objects.insert(name.as_str(), placement);
+        }
+
+        let mut constraint_names = HashMap::new();
+        let mut constraint_parameters = HashMap::new();
+        let lock: HashMap<&str, f64> = [
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ].iter().cloned().collect();
+        let roles: HashMap<&str, &str> = [("Object", self.objects[0].as_str())].iter().cloned().collect();
+        constraint_names.insert(self.constraints[0].as_str(), roles);
+        constraint_parameters.insert(self.constraints[0].as_str(), lock);
+
+        for i in 1..self.objects.len() {
+            let roles: HashMap<&str, &str> = [
+                ("Object", self.objects[i].as_str()), ("Reference", self.objects[i - 1].as_str()),
+            ].iter().cloned().collect();
+            let offset: HashMap<&str, f64> = [("x", 1.0), ("y", 2.0), ("z", 0.5)].iter().cloned().collect();
+            let rotation: HashMap<&str, f64> = [("phi", 0.2), ("theta", 0.0), ("psi", 0.0)].iter().cloned().collect();
+            constraint_names.insert(self.constraints[3 * i + 1].as_str(), roles.clone());
+            constraint_parameters.insert(self.constraints[3 * i + 1].as_str(), offset);
+            constraint_names.insert(self.constraints[3 * i + 2].as_str(), roles);
+            constraint_parameters.insert(self.constraints[3 * i + 2].as_str(), rotation);
+        }
+
+        let mut system = System::new();
+        solver::build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)
+            .expect("the synthetic system is valid");
+        system
+    }
+}
+
+
+fn main() {
+    let methods = [Method::Auto, Method::Newton, Method::TrustNCG, Method::LevenbergMarquardt];
+    for parts in 2..=5 {
+        let chain = Chain::new(parts);
+        println!("{} parts", parts);
+        for method in methods.iter().cloned() {
+            let options = SolverOptions {
+                method,
+                ..SolverOptions::new()
+            };
+            let mut time = Duration::default();
+            let mut iterations = 0;
+            let mut success = true;
+            for _ in 0..REPETITIONS {
+                let mut system = chain.system();
+                let start = Instant::now();
+                let stats = system.solve(&options);
+                time += start.elapsed();
+                iterations = stats.iterations;
+                success &= stats.success;
+            }
+            println!(
+                "{:>20}: {:?} per solve, {} iterations, success: {}",
+                format!("{:?}", method), time / REPETITIONS, iterations, success,
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! Times the evaluation of the constraint functions, the gradient and the
+//! hessian of a synthetic system: a chain of 101 objects joined by 100
+//! PointCoincident constraints.
+//!
+//! The constraints are evaluated in parallel only when the crate is built with
+//! the parallel feature, so the example is run twice to compare both ways:
+//!
+//!     cargo run --release --example parallel_eval
+//!     cargo run --release --example parallel_eval --features parallel
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ndarray::{Array1, Array2};
+use optimization::problem::{Hessian, Objective};
+
+use solver::System;
+
+
+/// Number of constraints of the chain
+const CONSTRAINTS: usize = 100;
+
+/// Number of times each evaluation is repeated
+const REPETITIONS: u32 = 50;
+
+
+fn main() {
+    let object_names: Vec<String> = (0..=CONSTRAINTS).map(|i| format!("Part{}", i)).collect();
+    let names: Vec<String> = (0..CONSTRAINTS).map(|i| format!("PointCoincident{:03}", i)).collect();
+
+    let mut objects = HashMap::new();
+    for (i, name) in object_names.iter().enumerate() {
+        let t = i as f64;
+        let placement: HashMap<&str, f64> = [
+            ("x", 1.4 * t), ("y", 0.1 * t), ("z", 0.0), ("phi", 0.01 * t), ("theta", 0.02), ("psi", -0.01 * t),
+        ].iter().cloned().collect();
+        objects.insert(name.as_str(), placement);
+    }
+    // The end of each part (1.5 along its X-axis) is the origin of the next one
+    let mut constraint_names = HashMap::new();
+    let mut constraint_parameters = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        let roles: HashMap<&str, &str> = [
+            ("Object1", object_names[i].as_str()), ("Object2", object_names[i + 1].as_str()),
+        ].iter().cloned().collect();
+        constraint_names.insert(name.as_str(), roles);
+        let params: HashMap<&str, f64> = [("x1", 1.5)].iter().cloned().collect();
+        constraint_parameters.insert(name.as_str(), params);
+    }
+
+    let mut system = System::new();
+    solver::build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)
+        .expect("the synthetic system is valid");
+    system.add_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    let n = x.len();
+
+    let mut grad = Array1::zeros(n);
+    let mut hess = Array2::zeros((n, n));
+    let mut times = [Duration::default(); 3];
+    for _ in 0..REPETITIONS {
+        let start = Instant::now();
+        system.eval();
+        times[0] += start.elapsed();
+
+        let start = Instant::now();
+        system.constraint_gradient(&mut grad);
+        times[1] += start.elapsed();
+
+        let start = Instant::now();
+        system.hess(&mut hess);
+        times[2] += start.elapsed();
+    }
+
+    println!(
+        "{} constraints, {} variables, evaluated in parallel: {}",
+        system.constraints.len(), n, system.parallel_constraints(),
+    );
+    for (label, time) in ["constraints", "gradient", "hessian"].iter().zip(&times) {
+        println!("{:>11}: {:?} per evaluation", label, *time / REPETITIONS);
+    }
+}
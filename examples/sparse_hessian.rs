@@ -0,0 +1,120 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! Compares the dense and the sparse hessian of a synthetic system with 50
+//! objects: a locked base and a chain of 49 parts, each one fixed relative to
+//! the previous one by a FixBase and a FixRotation constraint. It times the
+//! assembly of both hessians and the solve with the methods that use them.
+//!
+//!     cargo run --release --example sparse_hessian
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ndarray::{Array1, Array2};
+use optimization::problem::{Gradient, Hessian, Objective};
+
+use solver::{Method, SolverOptions, System};
+
+
+/// Number of objects of the system
+const OBJECTS: usize = 50;
+
+/// Number of times each hessian is assembled
+const REPETITIONS: u32 = 50;
+
+
+fn main() {
+    let object_names: Vec<String> = (0..OBJECTS).map(|i| format!("Part{}", i)).collect();
+    let base_names: Vec<String> = (0..OBJECTS).map(|i| format!("FixBase{}", i)).collect();
+    let rotation_names: Vec<String> = (0..OBJECTS).map(|i| format!("FixRotation{}", i)).collect();
+
+    let mut objects = HashMap::new();
+    for (i, name) in object_names.iter().enumerate() {
+        let t = i as f64;
+        let placement: HashMap<&str, f64> = [
+            ("x", 0.8 * t), ("y", 1.5 * t), ("z", 0.2), ("phi", 0.01 * t), ("theta", 0.05), ("psi", -0.02 * t),
+        ].iter().cloned().collect();
+        objects.insert(name.as_str(), placement);
+    }
+
+    let mut constraint_names = HashMap::new();
+    let mut constraint_parameters = HashMap::new();
+    let lock: HashMap<&str, f64> = [
+        ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+    ].iter().cloned().collect();
+    let roles: HashMap<&str, &str> = [("Object", object_names[0].as_str())].iter().cloned().collect();
+    constraint_names.insert("Lock", roles);
+    constraint_parameters.insert("Lock", lock);
+    for i in 1..OBJECTS {
+        let roles: HashMap<&str, &str> = [
+            ("Object", object_names[i].as_str()), ("Reference", object_names[i - 1].as_str()),
+        ].iter().cloned().collect();
+        let offset: HashMap<&str, f64> = [("x", 1.0), ("y", 1.5), ("z", 0.0)].iter().cloned().collect();
+        let rotation: HashMap<&str, f64> = [("phi", 0.0), ("theta", 0.0), ("psi", 0.02)].iter().cloned().collect();
+        constraint_names.insert(base_names[i].as_str(), roles.clone());
+        constraint_parameters.insert(base_names[i].as_str(), offset);
+        constraint_names.insert(rotation_names[i].as_str(), roles);
+        constraint_parameters.insert(rotation_names[i].as_str(), rotation);
+    }
+
+    let build = || {
+        let mut system = System::new();
+        solver::build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)
+            .expect("the synthetic system is valid");
+        system
+    };
+
+    let mut system = build();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    let n = x.len();
+    let mut grad = Array1::zeros(n);
+    system.grad(&mut grad);
+
+    let mut hess = Array2::zeros((n, n));
+    let mut times = [Duration::default(); 2];
+    let mut stored_entries = 0;
+    for _ in 0..REPETITIONS {
+        let start = Instant::now();
+        hess.fill(0.0);
+        system.hess(&mut hess);
+        times[0] += start.elapsed();
+
+        let start = Instant::now();
+        stored_entries = system.sparse_hessian().stored_entries();
+        times[1] += start.elapsed();
+    }
+    println!("{} objects, {} variables", OBJECTS, n);
+    println!("{:>7}: {:?} per assembly, {} entries", "dense", times[0] / REPETITIONS, n * n);
+    println!("{:>7}: {:?} per assembly, {} entries", "sparse", times[1] / REPETITIONS, stored_entries);
+
+    for method in [Method::Newton, Method::TrustNCG, Method::NewtonCg].iter().cloned() {
+        let options = SolverOptions {
+            method,
+            ..SolverOptions::new()
+        };
+        let mut system = build();
+        let start = Instant::now();
+        let stats = system.solve(&options);
+        println!(
+            "{:>10}: {:?} per solve, {} iterations, {} bytes estimated, success: {}",
+            format!("{:?}", method), start.elapsed(), stats.iterations, stats.memory_estimate, stats.success,
+        );
+    }
+}
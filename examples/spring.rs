@@ -0,0 +1,244 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! A custom constraint defined outside of the solver crate: a 1D spring that
+//! keeps the x variables of two objects at a given distance.
+//!
+//! The springs are added to a system directly with System::add_custom_constraint,
+//! or they are built from the input maps of build_constraints by registering
+//! their factory with System::register_constraint.
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use solver::{
+    Constraint, CustomConstraint, Footprint, HessianAccumulator, Residual, ResidualKind,
+    SolveStats, SolverOptions, System, SystemObject, VariableName as VN,
+};
+
+
+/// The constraint function is (x2 - x1 - length)^2
+#[derive(Debug, Clone)]
+struct Spring {
+    name: String,
+    length: f64,
+    /// value of x2 - x1 - length
+    residual: f64,
+    obj1_index: usize,
+    obj2_index: usize,
+}
+
+
+impl Spring {
+    fn new(
+        name: &str,
+        sys_objects: &mut [SystemObject],
+        length: f64,
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> Spring {
+        sys_objects[obj1_index].enable_variables(&["x"]);
+        sys_objects[obj2_index].enable_variables(&["x"]);
+        Spring {
+            name: name.to_string(),
+            length,
+            residual: 0.0,
+            obj1_index,
+            obj2_index,
+        }
+    }
+
+    /// Partial derivatives of the residual with respect to the solver
+    /// variables as (solver index, derivative). Locked variables have no index
+    /// and are skipped, and variables equal to other variables contribute
+    /// with their scale.
+    fn jacobian(&self, sys_objects: &[SystemObject]) -> Vec<(usize, f64)> {
+        let variables = [
+            sys_objects[self.obj1_index].get_variable(VN::x),
+            sys_objects[self.obj2_index].get_variable(VN::x),
+        ];
+        variables
+            .iter()
+            .zip(&JACOBIAN)
+            .filter_map(|(variable, jac)| variable.index.map(|k| (k, variable.scale * jac)))
+            .collect()
+    }
+}
+
+
+/// Partial derivatives of the residual with respect to x1 and x2
+const JACOBIAN: [f64; 2] = [-1.0, 1.0];
+
+
+impl Constraint for Spring {
+    fn evaluate(&mut self, sys_objects: &[SystemObject]) {
+        let x1 = sys_objects[self.obj1_index].get_variable(VN::x).value;
+        let x2 = sys_objects[self.obj2_index].get_variable(VN::x).value;
+        self.residual = x2 - x1 - self.length;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_grad[k] += 2.0 * self.residual * jac;
+        }
+    }
+
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        self.jacobian(sys_objects)
+            .iter()
+            .map(|(k, jac)| 2.0 * self.residual * jac * direction[*k])
+            .sum()
+    }
+
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        let jacobian = self.jacobian(sys_objects);
+        for (i, jac_i) in &jacobian {
+            for (j, jac_j) in &jacobian {
+                sys_hess.add(*i, *j, 2.0 * jac_i * jac_j);
+            }
+        }
+    }
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]) {
+        residuals.push(Residual {
+            value: self.residual,
+            jacobian: self.jacobian(sys_objects),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn residual_breakdown(&self, _sys_objects: &[SystemObject]) -> Vec<(&'static str, f64, &'static str)> {
+        vec![("stretch", self.residual, "length")]
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj1_index, self.obj2_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj1_index, Footprint::Variables), (self.obj2_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.obj1_index, VN::x), (self.obj2_index, VN::x)]
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj1_index = object_map[&self.obj1_index];
+        self.obj2_index = object_map[&self.obj2_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.length]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.length = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let x1 = sys_objects[self.obj1_index].get_variable(VN::x).value;
+        let x2 = sys_objects[self.obj2_index].get_variable(VN::x).value;
+        vec![x2 - x1]
+    }
+}
+
+
+/// Creates a spring from the input maps of a constraint. The objects have the
+/// roles "Object1" and "Object2" and the length is the parameter "length".
+fn spring_factory(
+    name: &str,
+    objects: &HashMap<&str, usize>,
+    sys_objects: &mut [SystemObject],
+    params: &HashMap<&str, f64>,
+) -> Box<dyn CustomConstraint> {
+    Box::new(Spring::new(name, sys_objects, params["length"], objects["Object1"], objects["Object2"]))
+}
+
+
+/// Prints the solved x variable of each object
+fn print_solution(system: &System, stats: &SolveStats) {
+    println!("solved: {} ({} iterations)", stats.success, stats.iterations);
+    for name in &["Ground", "Cart", "Trailer"] {
+        let x = system.sys_objects[system.sys_objects_idx[*name]].get_variable(VN::x).value;
+        println!("{}: x = {}", name, x);
+    }
+}
+
+
+fn main() {
+    let mut system = System::new();
+    for (name, x) in &[("Ground", 0.0), ("Cart", 3.0), ("Trailer", -2.0)] {
+        let placement: HashMap<&str, f64> = [
+            ("x", *x), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ].iter().cloned().collect();
+        system.add_object(name, &placement);
+    }
+    let ground = system.sys_objects_idx["Ground"];
+    let cart = system.sys_objects_idx["Cart"];
+    let trailer = system.sys_objects_idx["Trailer"];
+
+    // The ground does not move
+    system.sys_objects[ground].enable_variables(&["x"]);
+    system.sys_objects[ground].lock_variables(&["x"]);
+
+    let spring = Spring::new("Spring_1", &mut system.sys_objects, 1.5, ground, cart);
+    system.add_custom_constraint(Box::new(spring));
+    let spring = Spring::new("Spring_2", &mut system.sys_objects, 2.0, cart, trailer);
+    system.add_custom_constraint(Box::new(spring));
+
+    let stats = system.solve(&SolverOptions::new());
+    print_solution(&system, &stats);
+
+    // The same system built from input maps. Constraints of type "Spring"
+    // ("Spring_1", "Spring_2", ...) are springs.
+    let mut objects = HashMap::new();
+    for (name, x) in &[("Ground", 0.0), ("Cart", 3.0), ("Trailer", -2.0)] {
+        let placement: HashMap<&str, f64> = [
+            ("x", *x), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ].iter().cloned().collect();
+        objects.insert(*name, placement);
+    }
+    let mut names = HashMap::new();
+    let mut parameters = HashMap::new();
+    names.insert("Lock_1", [("Object", "Ground")].iter().cloned().collect());
+    parameters.insert("Lock_1", [("x", 0.0)].iter().cloned().collect());
+    for (spring, obj1, obj2, length) in &[
+        ("Spring_1", "Ground", "Cart", 1.5),
+        ("Spring_2", "Cart", "Trailer", 2.0),
+    ] {
+        names.insert(*spring, [("Object1", *obj1), ("Object2", *obj2)].iter().cloned().collect());
+        parameters.insert(*spring, [("length", *length)].iter().cloned().collect());
+    }
+
+    let mut system = System::new();
+    system.register_constraint("Spring", spring_factory).unwrap();
+    solver::build_constraints(&mut system, &objects, &names, &parameters).unwrap();
+    let stats = system.solve(&SolverOptions::new());
+    print_solution(&system, &stats);
+}
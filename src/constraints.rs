@@ -16,12 +16,106 @@
 mod fix_base_constraint;
 pub use fix_base_constraint::FixBaseConstraint;
 
+mod fix_rotation_constraint;
+pub use fix_rotation_constraint::FixRotationConstraint;
+
+mod point_coincident_constraint;
+pub use point_coincident_constraint::PointCoincidentConstraint;
+
+mod distance_constraint;
+pub use distance_constraint::DistanceConstraint;
+
+mod plane_constraint;
+pub use plane_constraint::PlaneConstraint;
+
+mod coaxial_constraint;
+pub use coaxial_constraint::CoaxialConstraint;
+
+mod coincident_constraint;
+pub use coincident_constraint::CoincidentConstraint;
+
+mod fixed_angle_constraint;
+pub use fixed_angle_constraint::FixedAngleConstraint;
+
+mod symmetry_constraint;
+pub use symmetry_constraint::SymmetryConstraint;
+
+mod point_on_line_constraint;
+pub use point_on_line_constraint::PointOnLineConstraint;
+
+mod point_on_plane_constraint;
+pub use point_on_plane_constraint::PointOnPlaneConstraint;
+
+mod parallel_constraint;
+pub use parallel_constraint::ParallelConstraint;
+
+mod perpendicular_constraint;
+pub use perpendicular_constraint::PerpendicularConstraint;
+
+mod ratio_constraint;
+pub use ratio_constraint::RatioConstraint;
+
+mod hinge_constraint;
+pub use hinge_constraint::HingeConstraint;
+
+mod slider_constraint;
+pub use slider_constraint::SliderConstraint;
+
+mod ball_constraint;
+pub use ball_constraint::BallConstraint;
+
+mod screw_constraint;
+pub use screw_constraint::ScrewConstraint;
+
+mod driven_angle_constraint;
+pub use driven_angle_constraint::DrivenAngleConstraint;
+
+mod midpoint_constraint;
+pub use midpoint_constraint::MidpointConstraint;
+
+mod plane_parallel_constraint;
+pub use plane_parallel_constraint::PlaneParallelConstraint;
+
+mod colinear_constraint;
+pub use colinear_constraint::ColinearConstraint;
+
+mod tangent_constraint;
+pub use tangent_constraint::TangentConstraint;
+
+mod centric_constraint;
+pub use centric_constraint::CentricConstraint;
+
+mod distance_range_constraint;
+pub use distance_range_constraint::DistanceRangeConstraint;
+
+mod coplanar_constraint;
+pub use coplanar_constraint::CoplanarConstraint;
+
+mod linear_constraint;
+pub use linear_constraint::LinearConstraint;
+
+mod fixed_position_constraint;
+pub use fixed_position_constraint::FixedPositionConstraint;
+
+mod weighted_constraint;
+pub use weighted_constraint::WeightedConstraint;
+
+mod residual_function;
+
 pub mod equality_constraint;
+pub use equality_constraint::EqualityConstraint;
 
 pub mod lock_constraint;
+pub use lock_constraint::LockConstraint;
 
 mod constraint_traits;
-use constraint_traits::Constraint;
+pub use constraint_traits::{Constraint, Footprint, Residual, ResidualKind};
+
+mod hessian_accumulator;
+pub use hessian_accumulator::{HessianAccumulator, SparseHessian};
+
+mod custom_constraint;
+pub use custom_constraint::{BUILT_IN_TYPES, ConstraintFactory, CustomConstraint};
 
 mod constraint_type;
 pub use constraint_type::ConstraintType;
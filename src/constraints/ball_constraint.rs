@@ -0,0 +1,186 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::constant_vector;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Pins the origin of an object to a point of a reference object with a ball
+/// joint
+///
+/// The point is given by its coordinates ("x", "y" and "z", 0 by default) in
+/// the local coordinate system of the reference, so it is rotated by the
+/// rotation of the reference before it is compared with the origin of the
+/// object. The residuals are the components of p - (rp + qr * o * qr^-1),
+/// where o is the offset of the point.
+///
+/// Unlike a FixBase constraint, the object is free to rotate in every
+/// direction, so only its position variables are used.
+#[derive(Debug, Clone)]
+pub struct BallConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// local coordinates of the point in the reference
+    offset: [f64; 3],
+}
+
+
+impl Constraint for BallConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let offset = self.offset;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, &offset));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let offset = self.offset;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, &offset));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let offset = self.offset;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, &offset));
+        vec![
+            ("x", error[0], "length"),
+            ("y", error[1], "length"),
+            ("z", error[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.offset.to_vec()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.offset = [parameters[0], parameters[1], parameters[2]];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The point is moved to the origin of the object
+        let object = Placement::current(&sys_objects[self.function.objects()[0]]);
+        let reference = Placement::current(&sys_objects[self.function.objects()[1]]);
+        let offset = reference.local_point(&object.position);
+        vec![offset.x.re, offset.y.re, offset.z.re]
+    }
+}
+
+
+impl BallConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> BallConstraint {
+        // The rotation of the object is free, but the point moves with the
+        // rotation of the reference
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z", "phi", "theta", "psi"]);
+
+        let coordinate = |key| constraint_parameters.get(key).copied().unwrap_or(0.0);
+
+        BallConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::with_variables(
+                vec![obj_index, ref_index],
+                vec![VN::get_position_iter().collect(), VN::get_variable_iter().collect()],
+            ),
+            offset: [coordinate("x"), coordinate("y"), coordinate("z")],
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the offset of the origin of the
+/// object from the point of the reference
+fn residuals(placements: &[Placement], offset: &[f64; 3]) -> Vec<HDual> {
+    let point = placements[1].point(&constant_vector(offset[0], offset[1], offset[2]));
+    let distance = placements[0].position - point;
+    vec![distance.x, distance.y, distance.z]
+}
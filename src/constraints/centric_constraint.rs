@@ -0,0 +1,186 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::constant_vector;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes the centers of two spherical or circular features coincide
+///
+/// The center of each feature is on the local Z-axis of its object, at the
+/// distance "offset1" or "offset2" from the origin (the protrusion of a shaft
+/// or the radius of a ball), so its global position is p + q * (0, 0, offset) * q^-1.
+/// Unlike an axis coincident mate the directions of the axes are free, only the
+/// components of the difference of both centers are residuals.
+#[derive(Debug, Clone)]
+pub struct CentricConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// offsets of the centers along the local Z-axis of both objects
+    offsets: [f64; 2],
+}
+
+
+impl Constraint for CentricConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let offsets = self.offsets;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, offsets));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let offsets = self.offsets;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, offsets));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let offsets = self.offsets;
+        let distance = self.function.residual_values(sys_objects, |placements| residuals(placements, offsets));
+        vec![
+            ("x", distance[0], "length"),
+            ("y", distance[1], "length"),
+            ("z", distance[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.offsets.to_vec()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.offsets.copy_from_slice(&parameters[0..2]);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The center of the first object is kept and the offset of the second
+        // object is the projection of that center on its Z-axis
+        let objects = self.function.objects();
+        let placement1 = Placement::current(&sys_objects[objects[0]]);
+        let placement2 = Placement::current(&sys_objects[objects[1]]);
+        let center = placement1.point(&constant_vector(0.0, 0.0, self.offsets[0]));
+        let local = placement2.local_point(&center);
+        vec![self.offsets[0], local.z.re]
+    }
+}
+
+
+impl CentricConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> CentricConstraint {
+        // The global positions of the centers depend on every variable of
+        // both objects
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj1_index].enable_variables(&all_variables);
+        system_objects[obj2_index].enable_variables(&all_variables);
+
+        // The missing offsets are set to a value of 0
+        let offset1 = constraint_parameters.get("offset1").cloned().unwrap_or(0.0);
+        let offset2 = constraint_parameters.get("offset2").cloned().unwrap_or(0.0);
+
+        CentricConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_variable_iter().collect()),
+            offsets: [offset1, offset2],
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the components of the distance
+/// vector between both centers
+fn residuals(placements: &[Placement], offsets: [f64; 2]) -> Vec<HDual> {
+    let center1 = placements[0].point(&constant_vector(0.0, 0.0, offsets[0]));
+    let center2 = placements[1].point(&constant_vector(0.0, 0.0, offsets[1]));
+    let distance = center1 - center2;
+    vec![distance.x, distance.y, distance.z]
+}
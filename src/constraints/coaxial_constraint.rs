@@ -0,0 +1,203 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{constant_vector, cross, dot};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{attachment_offset, Placement, ResidualFunction};
+
+
+/// Makes the local Z-axis of an object collinear with the local Z-axis of a
+/// reference object
+///
+/// The axes are parallel when the cross product of their directions in the
+/// global coordinate system is zero; its components are the first three
+/// residuals. The origin of the object is on the axis of the reference when the
+/// vector p - rp (where p and rp are the position vectors of the object and the
+/// reference) is parallel to that axis, so the last two residuals are the dot
+/// products of p - rp with the local X and Y axes of the reference.
+///
+/// The axes can also go through named attachment points of the objects instead
+/// of their origins; then p and rp are the global positions of those points.
+///
+/// The axes may point in opposite directions, and the object is free to slide
+/// along and rotate about the shared axis.
+#[derive(Debug, Clone)]
+pub struct CoaxialConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// points of the axes of the object and the reference in their local
+    /// coordinate systems
+    points: [[f64; 3]; 2],
+}
+
+
+impl Constraint for CoaxialConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let points = &self.points;
+        self.function.evaluate(sys_objects, |placements| residuals(points, placements));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let points = &self.points;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(points, placements));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        // The residuals of the axes are sines of angles, but the offset from
+        // the axis is a length
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let points = &self.points;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(points, placements));
+        let sine = (error[0].powi(2) + error[1].powi(2) + error[2].powi(2)).sqrt().min(1.0);
+        vec![
+            ("angle", sine.asin(), "angle"),
+            ("offset_x", error[3], "length"),
+            ("offset_y", error[4], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl CoaxialConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj_index: usize,
+        ref_index: usize,
+        obj_attachment: Option<&str>,
+        ref_attachment: Option<&str>,
+    ) -> CoaxialConstraint {
+        // The axes depend on the rotations and the offset from the axis on the
+        // positions, so every variable of both objects is enabled
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj_index].enable_variables(&all_variables);
+        system_objects[ref_index].enable_variables(&all_variables);
+
+        CoaxialConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_variable_iter().collect()),
+            points: [
+                attachment_offset(&system_objects[obj_index], obj_attachment),
+                attachment_offset(&system_objects[ref_index], ref_attachment),
+            ],
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the cross product of both axes
+/// and the offset of the axis point of the object from the axis of the
+/// reference along the X and Y axes of the reference
+fn residuals(points: &[[f64; 3]; 2], placements: &[Placement]) -> Vec<HDual> {
+    let object = &placements[0];
+    let reference = &placements[1];
+    let axis = object.direction(&constant_vector(0.0, 0.0, 1.0));
+    let ref_x = reference.direction(&constant_vector(1.0, 0.0, 0.0));
+    let ref_y = reference.direction(&constant_vector(0.0, 1.0, 0.0));
+    let ref_axis = reference.direction(&constant_vector(0.0, 0.0, 1.0));
+    let parallel = cross(&axis, &ref_axis);
+    let point = object.point(&constant_vector(points[0][0], points[0][1], points[0][2]));
+    let ref_point = reference.point(&constant_vector(points[1][0], points[1][1], points[1][2]));
+    let offset = point - ref_point;
+    vec![
+        parallel.x,
+        parallel.y,
+        parallel.z,
+        dot(&offset, &ref_x),
+        dot(&offset, &ref_y),
+    ]
+}
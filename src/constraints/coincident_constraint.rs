@@ -0,0 +1,159 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes the origin of an object coincide with the origin of another object
+///
+/// The residuals of the constraint are the components of p1 - p2 in the global
+/// coordinate system, where p1 and p2 are the position vectors of the objects.
+/// Unlike a FixBaseConstraint with zero offsets, the rotations of the objects
+/// are not used, so their rotation variables are not enabled.
+#[derive(Debug, Clone)]
+pub struct CoincidentConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+}
+
+
+impl Constraint for CoincidentConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.function.evaluate(sys_objects, residuals);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_residuals(residuals_vec, sys_objects, residuals);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let distance = self.function.residual_values(sys_objects, residuals);
+        vec![
+            ("x", distance[0], "length"),
+            ("y", distance[1], "length"),
+            ("z", distance[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl CoincidentConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> CoincidentConstraint {
+        system_objects[obj1_index].enable_variables(&["x", "y", "z"]);
+        system_objects[obj2_index].enable_variables(&["x", "y", "z"]);
+
+        CoincidentConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_position_iter().collect()),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the components of the distance
+/// vector between both origins
+fn residuals(placements: &[Placement]) -> Vec<HDual> {
+    let distance = placements[0].position - placements[1].position;
+    vec![distance.x, distance.y, distance.z]
+}
@@ -0,0 +1,176 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::cross;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes the origins of three objects colinear
+///
+/// The residuals are the components of (p2 - p1) x (p3 - p1), so the
+/// constraint function is the squared norm of the cross product and only the
+/// position variables of the three objects are used. The cross product is not
+/// normalized: when two of the points coincide it is zero and so are some of
+/// its derivatives, but the gradient and hessian stay finite.
+#[derive(Debug, Clone)]
+pub struct ColinearConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+}
+
+
+impl Constraint for ColinearConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.function.evaluate(sys_objects, residuals);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_residuals(residuals_vec, sys_objects, residuals);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        // The cross product is an area, so it is given as the distance of the
+        // third point from the line of the other two when they do not coincide
+        let objects = self.function.objects();
+        let p1 = Placement::current(&sys_objects[objects[0]]).position;
+        let p2 = Placement::current(&sys_objects[objects[1]]).position;
+        let error = self.function.residual_values(sys_objects, residuals);
+        let base = p2 - p1;
+        let length = (base.x.re.powi(2) + base.y.re.powi(2) + base.z.re.powi(2)).sqrt();
+        let scale = if length > 0.0 { 1.0 / length } else { 1.0 };
+        vec![
+            ("x", scale * error[0], "length"),
+            ("y", scale * error[1], "length"),
+            ("z", scale * error[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl ColinearConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj1_index: usize,
+        obj2_index: usize,
+        obj3_index: usize,
+    ) -> ColinearConstraint {
+        for index in &[obj1_index, obj2_index, obj3_index] {
+            system_objects[*index].enable_variables(&["x", "y", "z"]);
+        }
+
+        ColinearConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(
+                vec![obj1_index, obj2_index, obj3_index],
+                VN::get_position_iter().collect(),
+            ),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the cross product of the offsets
+/// of the second and third points from the first one
+fn residuals(placements: &[Placement]) -> Vec<HDual> {
+    let offset2 = placements[1].position - placements[0].position;
+    let offset3 = placements[2].position - placements[0].position;
+    let area = cross(&offset2, &offset3);
+    vec![area.x, area.y, area.z]
+}
@@ -13,8 +13,11 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
 
 
-use ndarray::{Array1, Array2};
-use crate::system_object::SystemObject;
+use std::collections::HashMap;
+
+use ndarray::Array1;
+use crate::constraints::HessianAccumulator;
+use crate::system_object::{SystemObject, VariableName};
 
 
 /// General constraint methods used by the solver.
@@ -22,26 +25,143 @@ use crate::system_object::SystemObject;
 /// This trait is used as a way to interface the distinct constraint functions
 /// with the solver. This way the constraint functions are free to choose any
 /// way to calculate the constraint errors.
+///
+/// Constraints defined outside of this crate implement this trait too (see
+/// CustomConstraint). Their derivatives are added to the system arrays at the
+/// solver indices of the variables they use (Variable::index); variables
+/// without an index are constants for the solver and must be skipped. The
+/// derivatives with respect to a variable are multiplied by its scale
+/// (Variable::scale, -1 for a variable linked with opposite sign by an
+/// equality), and the hessian entries by the scales of both variables.
 pub trait Constraint {
     /// Evaluates the square of the constraint function f(x)^2
     /// This method is intended to set the calculated gradients and hessians in
     /// internal variables that then are obtained by calling get_gradient and
     /// get_hessian. sys_variables should be the updated variables for the
     /// iteration.
-    fn evaluate(&mut self, sys_objects: &Vec<SystemObject>);
+    fn evaluate(&mut self, sys_objects: &[SystemObject]);
 
     /// Gets the real value of the square of constraint function
     fn get_value(&self) -> f64;
 
     /// Gets the gradient of the square of the constraint function. This method
     /// adds the gradient contribution of this constraint to the system gradient.
-    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_obects: &Vec<SystemObject>);
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_obects: &[SystemObject]);
 
-    /// Gets the one-dimensional first derivative of the constraint function
-    fn get_diff(&mut self) -> f64;
+    /// Gets the directional derivative of the square of the constraint function
+    /// along direction (a vector of the size of the system variables). The
+    /// constraint must be already evaluated.
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64;
 
     /// Gets the hessian matrix of the square of the constraint function. This
     /// method adds the hessian contribution of this constraint to the system
-    /// hessian.
-    fn get_hessian(&self, sys_hess: &mut Array2<f64>, sys_obects: &Vec<SystemObject>);
+    /// hessian, which may be a dense or a sparse matrix.
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_obects: &[SystemObject]);
+
+    /// Adds the residuals of this constraint to the residuals vector. The value
+    /// of the constraint function is the sum of the squares of its residuals.
+    /// Unlike evaluate, this method only finds the first partial derivatives
+    /// (with respect to the system variables) so it is much cheaper.
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]);
+
+    /// Gets the kind of error measured by the constraint function. It is used
+    /// to choose the tolerance that the constraint has to satisfy.
+    fn get_kind(&self) -> ResidualKind;
+
+    /// Converts the error of the constraint into physical quantities, for
+    /// example the remaining offset along each axis in length units. Each entry
+    /// is (label, value, unit). The objects must be already updated.
+    ///
+    /// By default the squared error is returned without a unit.
+    fn residual_breakdown(
+            &self,
+            _sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        vec![("squared_error", self.get_value(), "")]
+    }
+
+    /// Returns the indices (in the vector of system objects) of the objects
+    /// used by this constraint
+    fn affected_objects(&self) -> Vec<usize>;
+
+    /// Returns the variables that may be used by this constraint as pairs of
+    /// (object index, variable name). Only the enabled variables of these pairs
+    /// are actually used.
+    fn get_variables(&self) -> Vec<(usize, VariableName)>;
+
+    /// Returns the blocks of the objects read by this constraint as pairs of
+    /// (object index, block). It is used to update only the position vectors
+    /// and rotation quaternions that some constraint needs.
+    ///
+    /// By default every affected object needs both its position vector and its
+    /// rotation quaternion.
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.affected_objects()
+            .into_iter()
+            .flat_map(|obj_idx| vec![(obj_idx, Footprint::Position), (obj_idx, Footprint::Rotation)])
+            .collect()
+    }
+
+    /// Gets the name of the constraint (the name given to it in the assembly)
+    fn get_name(&self) -> &str;
+
+    /// Changes the indices of the objects used by this constraint. object_map
+    /// maps the old indices to the new ones. It is used when the constraint is
+    /// moved to another system.
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>);
+
+    /// Gets the parameters of the constraint, for example the offsets of a fix
+    /// constraint. The order of the parameters depends on the constraint.
+    fn get_parameters(&self) -> Vec<f64>;
+
+    /// Sets the parameters of the constraint. parameters has the same order as
+    /// the vector returned by get_parameters.
+    fn set_parameters(&mut self, parameters: &[f64]);
+
+    /// Gets the parameters that would make the constraint satisfied at the
+    /// current placements of the system objects (the objects must be already
+    /// updated). They have the same order as in get_parameters.
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64>;
+
+    /// Sets the values of the free variables that this constraint determines
+    /// on its own, for example when the other variables it uses are locked. It
+    /// is called before solving, once every constraint and lock is set up.
+    ///
+    /// By default nothing is done.
+    fn drive(&self, _sys_objects: &mut [SystemObject]) {}
+}
+
+
+/// The data of an object read by a constraint
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Footprint {
+    /// The position vector of the object (SystemObject::get_vector)
+    Position,
+    /// The rotation quaternion of the object (SystemObject::get_quaternion and
+    /// SystemObject::get_inverse_quaternion)
+    Rotation,
+    /// Only the values of the variables of the object
+    Variables,
+}
+
+
+/// The kind of error measured by a constraint function
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ResidualKind {
+    /// The constraint function is a squared length
+    Length,
+    /// The constraint function is a squared angle
+    Angle,
+}
+
+
+/// A residual r(x) of a constraint function together with its partial
+/// derivatives with respect to the solver variables (a row of the jacobian).
+#[derive(Debug)]
+pub struct Residual {
+    /// value of r(x)
+    pub value: f64,
+    /// pairs of (solver index, partial derivative of r(x)) for the variables
+    /// used by the residual
+    pub jacobian: Vec<(usize, f64)>,
 }
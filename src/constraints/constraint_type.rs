@@ -13,57 +13,198 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
 
 
-use ndarray::{Array1, Array2};
-use crate::constraints::{Constraint, fix_base_constraint};
-use crate::system_object::SystemObject;
+use std::collections::HashMap;
 
-// Used to group all types of constraints so they can be used in a single vector
-#[derive(Debug)]
+use ndarray::Array1;
+use crate::constraints::{
+    Constraint, CustomConstraint, Footprint, HessianAccumulator, Residual, ResidualKind,
+    ball_constraint, centric_constraint, coaxial_constraint, coincident_constraint,
+    colinear_constraint, coplanar_constraint, distance_constraint, distance_range_constraint,
+    driven_angle_constraint, equality_constraint, fix_base_constraint, fix_rotation_constraint,
+    fixed_angle_constraint, fixed_position_constraint, hinge_constraint, linear_constraint,
+    lock_constraint, midpoint_constraint, parallel_constraint, perpendicular_constraint,
+    plane_constraint, plane_parallel_constraint, point_coincident_constraint,
+    point_on_line_constraint, point_on_plane_constraint, ratio_constraint, screw_constraint,
+    slider_constraint, symmetry_constraint, tangent_constraint, weighted_constraint,
+};
+use crate::system_object::{SystemObject, VariableName};
+
+// Used to group all types of constraints so they can be used in a single vector.
+// The constraints that are much larger than the others are boxed.
+#[derive(Debug, Clone)]
 pub enum ConstraintType {
-    FixBaseConstraint(fix_base_constraint::FixBaseConstraint),
+    FixBaseConstraint(Box<fix_base_constraint::FixBaseConstraint>),
+    FixRotationConstraint(Box<fix_rotation_constraint::FixRotationConstraint>),
+    PointCoincidentConstraint(point_coincident_constraint::PointCoincidentConstraint),
+    DistanceConstraint(distance_constraint::DistanceConstraint),
+    PlaneConstraint(plane_constraint::PlaneConstraint),
+    CoaxialConstraint(coaxial_constraint::CoaxialConstraint),
+    CoincidentConstraint(coincident_constraint::CoincidentConstraint),
+    FixedAngleConstraint(fixed_angle_constraint::FixedAngleConstraint),
+    SymmetryConstraint(symmetry_constraint::SymmetryConstraint),
+    PointOnLineConstraint(point_on_line_constraint::PointOnLineConstraint),
+    PointOnPlaneConstraint(point_on_plane_constraint::PointOnPlaneConstraint),
+    ParallelConstraint(parallel_constraint::ParallelConstraint),
+    PerpendicularConstraint(perpendicular_constraint::PerpendicularConstraint),
+    RatioConstraint(ratio_constraint::RatioConstraint),
+    HingeConstraint(hinge_constraint::HingeConstraint),
+    SliderConstraint(slider_constraint::SliderConstraint),
+    BallConstraint(ball_constraint::BallConstraint),
+    ScrewConstraint(screw_constraint::ScrewConstraint),
+    DrivenAngleConstraint(driven_angle_constraint::DrivenAngleConstraint),
+    LockConstraint(lock_constraint::LockConstraint),
+    MidpointConstraint(midpoint_constraint::MidpointConstraint),
+    EqualityConstraint(Box<equality_constraint::EqualityConstraint>),
+    PlaneParallelConstraint(plane_parallel_constraint::PlaneParallelConstraint),
+    ColinearConstraint(colinear_constraint::ColinearConstraint),
+    TangentConstraint(tangent_constraint::TangentConstraint),
+    CentricConstraint(centric_constraint::CentricConstraint),
+    DistanceRangeConstraint(distance_range_constraint::DistanceRangeConstraint),
+    CoplanarConstraint(coplanar_constraint::CoplanarConstraint),
+    LinearConstraint(linear_constraint::LinearConstraint),
+    FixedPositionConstraint(fixed_position_constraint::FixedPositionConstraint),
+    /// A constraint with a weighted constraint function
+    WeightedConstraint(weighted_constraint::WeightedConstraint),
+    /// A constraint defined outside of this crate
+    Custom(Box<dyn CustomConstraint>),
+}
+
+/// Calls the same method on the constraint of any variant
+macro_rules! dispatch {
+    ($self:ident, $constraint:ident => $call:expr) => {
+        match $self {
+            Self::FixBaseConstraint($constraint) => $call,
+            Self::FixRotationConstraint($constraint) => $call,
+            Self::PointCoincidentConstraint($constraint) => $call,
+            Self::DistanceConstraint($constraint) => $call,
+            Self::PlaneConstraint($constraint) => $call,
+            Self::CoaxialConstraint($constraint) => $call,
+            Self::CoincidentConstraint($constraint) => $call,
+            Self::FixedAngleConstraint($constraint) => $call,
+            Self::SymmetryConstraint($constraint) => $call,
+            Self::PointOnLineConstraint($constraint) => $call,
+            Self::PointOnPlaneConstraint($constraint) => $call,
+            Self::ParallelConstraint($constraint) => $call,
+            Self::PerpendicularConstraint($constraint) => $call,
+            Self::RatioConstraint($constraint) => $call,
+            Self::HingeConstraint($constraint) => $call,
+            Self::SliderConstraint($constraint) => $call,
+            Self::BallConstraint($constraint) => $call,
+            Self::ScrewConstraint($constraint) => $call,
+            Self::DrivenAngleConstraint($constraint) => $call,
+            Self::LockConstraint($constraint) => $call,
+            Self::MidpointConstraint($constraint) => $call,
+            Self::EqualityConstraint($constraint) => $call,
+            Self::PlaneParallelConstraint($constraint) => $call,
+            Self::ColinearConstraint($constraint) => $call,
+            Self::TangentConstraint($constraint) => $call,
+            Self::CentricConstraint($constraint) => $call,
+            Self::DistanceRangeConstraint($constraint) => $call,
+            Self::CoplanarConstraint($constraint) => $call,
+            Self::LinearConstraint($constraint) => $call,
+            Self::FixedPositionConstraint($constraint) => $call,
+            Self::WeightedConstraint($constraint) => $call,
+            Self::Custom($constraint) => $call,
+        }
+    };
 }
 
 impl ConstraintType {
     pub fn evaluate(
             &mut self,
-            sys_objects: &Vec<SystemObject>
+            sys_objects: &[SystemObject]
     ) {
-        match self {
-            Self::FixBaseConstraint(fix) => fix.evaluate(sys_objects),
-        }
+        dispatch!(self, constraint => constraint.evaluate(sys_objects))
     }
 
     pub fn get_value(&self) -> f64 {
-        match self {
-            Self::FixBaseConstraint(fix) => fix.get_value()
-        }
+        dispatch!(self, constraint => constraint.get_value())
     }
 
     pub fn get_gradient(
             &self,
             sys_grad: &mut Array1<f64>,
-            sys_objects: &Vec<SystemObject>,
+            sys_objects: &[SystemObject],
     ) {
-        match self {
-            Self::FixBaseConstraint(fix) => fix.get_gradient(sys_grad, sys_objects)
-        }
+        dispatch!(self, constraint => constraint.get_gradient(sys_grad, sys_objects))
     }
 
     pub fn get_diff(
-            &mut self,
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
     ) -> f64 {
-        match self {
-            Self::FixBaseConstraint(fix) => fix.get_diff()
-        }
+        dispatch!(self, constraint => constraint.get_diff(direction, sys_objects))
     }
 
     pub fn get_hessian(
             &self,
-            sys_hess: &mut Array2<f64>,
-            sys_objects: &Vec<SystemObject>,
+            sys_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
     ) {
-        match self {
-            Self::FixBaseConstraint(fix) => fix.get_hessian(sys_hess, sys_objects)
-        }
+        dispatch!(self, constraint => constraint.get_hessian(sys_hess, sys_objects))
+    }
+
+    pub fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        dispatch!(self, constraint => constraint.get_residuals(residuals, sys_objects))
+    }
+
+    pub fn get_kind(&self) -> ResidualKind {
+        dispatch!(self, constraint => constraint.get_kind())
+    }
+
+    pub fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        dispatch!(self, constraint => constraint.residual_breakdown(sys_objects))
+    }
+
+    pub fn affected_objects(&self) -> Vec<usize> {
+        dispatch!(self, constraint => constraint.affected_objects())
+    }
+
+    pub fn footprint(&self) -> Vec<(usize, Footprint)> {
+        dispatch!(self, constraint => constraint.footprint())
+    }
+
+    pub fn get_variables(&self) -> Vec<(usize, VariableName)> {
+        dispatch!(self, constraint => constraint.get_variables())
+    }
+
+    pub fn get_name(&self) -> &str {
+        dispatch!(self, constraint => constraint.get_name())
+    }
+
+    pub fn drive(&self, sys_objects: &mut [SystemObject]) {
+        dispatch!(self, constraint => constraint.drive(sys_objects))
+    }
+
+    pub fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        dispatch!(self, constraint => constraint.remap_objects(object_map))
+    }
+
+    pub fn get_parameters(&self) -> Vec<f64> {
+        dispatch!(self, constraint => constraint.get_parameters())
+    }
+
+    pub fn set_parameters(&mut self, parameters: &[f64]) {
+        dispatch!(self, constraint => constraint.set_parameters(parameters))
+    }
+
+    /// Changes one of the parameters returned by get_parameters (for example
+    /// the target of a driven angle) without building the constraint again
+    pub fn set_parameter(&mut self, index: usize, value: f64) {
+        let mut parameters = self.get_parameters();
+        parameters[index] = value;
+        self.set_parameters(&parameters);
+    }
+
+    pub fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        dispatch!(self, constraint => constraint.current_parameters(sys_objects))
     }
 }
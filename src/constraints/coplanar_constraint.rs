@@ -0,0 +1,178 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{cross, dot, length, reciprocal};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes the origins of four or more objects coplanar
+///
+/// The plane passes through the origins of the first three objects, and there
+/// is one residual for each of the other objects: the signed distance of its
+/// origin from the plane. Only the position variables of the objects are used.
+///
+/// When the first three origins are on a line the plane is not defined. Then
+/// the residuals are the products of the offsets with the normal (p2 - p1) x
+/// (p3 - p1) without dividing by its length; they are zero, but their
+/// derivatives move the first three points off the line.
+#[derive(Debug, Clone)]
+pub struct CoplanarConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+}
+
+
+impl Constraint for CoplanarConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.function.evaluate(sys_objects, residuals);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_residuals(residuals_vec, sys_objects, residuals);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        // one distance for each object after the first three
+        self.function
+            .residual_values(sys_objects, residuals)
+            .into_iter()
+            .map(|distance| ("distance", distance, "length"))
+            .collect()
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl CoplanarConstraint {
+    /// Creates the constraint of the objects with the given indices. The first
+    /// three objects define the plane, so there must be at least four.
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj_indices: Vec<usize>,
+    ) -> CoplanarConstraint {
+        for index in &obj_indices {
+            system_objects[*index].enable_variables(&["x", "y", "z"]);
+        }
+
+        CoplanarConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(obj_indices, VN::get_position_iter().collect()),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the distances of the points after
+/// the first three from the plane through the first three points
+fn residuals(placements: &[Placement]) -> Vec<HDual> {
+    let origin = placements[0].position;
+    let normal = cross(&(placements[1].position - origin), &(placements[2].position - origin));
+    let normal_length = length(&normal);
+    placements[3..]
+        .iter()
+        .map(|placement| {
+            let offset = dot(&(placement.position - origin), &normal);
+            if normal_length.re > 0.0 {
+                offset * reciprocal(normal_length)
+            } else {
+                offset
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,78 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::constraints::Constraint;
+use crate::system_object::SystemObject;
+
+
+/// A constraint defined outside of this crate.
+///
+/// Any type implementing Constraint, Clone and Debug is a CustomConstraint, so
+/// custom constraints only have to implement the Constraint trait. They must be
+/// Send because systems can be solved on other threads.
+pub trait CustomConstraint: Constraint + Debug + Send {
+    /// Returns a boxed copy of the constraint
+    fn box_clone(&self) -> Box<dyn CustomConstraint>;
+}
+
+
+impl<T> CustomConstraint for T
+where
+    T: Constraint + Clone + Debug + Send + 'static,
+{
+    fn box_clone(&self) -> Box<dyn CustomConstraint> {
+        Box::new(self.clone())
+    }
+}
+
+
+impl Clone for Box<dyn CustomConstraint> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+
+/// The constraint types created by build_constraints, which can't be used as
+/// the type names of custom constraints
+pub const BUILT_IN_TYPES: [&str; 35] = [
+    "FixBase", "FixRotation", "FixGlobal", "FixedPosition", "PointCoincident", "AxisCoincident",
+    "PointOnPlane", "PlaneParallel", "Plane", "PlaneCoincident", "Coincident", "DistanceRange",
+    "Distance", "Coaxial", "FixedAngle", "Symmetry", "PointOnLine", "Parallel",
+    "Perpendicular", "AxisPerpendicular", "Ratio", "Hinge", "Slider", "Ball",
+    "Screw", "Driven", "Midpoint", "Colinear", "Tangent", "Centric",
+    "Coplanar", "Linear", "Lock", "Equality", "PlacementEqual",
+];
+
+
+/// Creates a custom constraint from the input maps of a constraint. The
+/// arguments are the name of the constraint, the indices of its objects in
+/// sys_objects (keyed by the role of the object in the constraint, for example
+/// "Object" or "Reference"), the system objects and the constraint parameters.
+///
+/// The objects are already added to the system, and the factory must enable the
+/// variables used by the constraint (like the constructors of the built-in
+/// constraints do). Factories are registered with System::register_constraint.
+pub type ConstraintFactory = fn(
+    &str,
+    &HashMap<&str, usize>,
+    &mut [SystemObject],
+    &HashMap<&str, f64>,
+) -> Box<dyn CustomConstraint>;
+
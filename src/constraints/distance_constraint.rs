@@ -0,0 +1,177 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::length;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Keeps the origin of an object at a fixed distance from the origin of a
+/// reference object
+///
+/// The residual of the constraint is |p - rp| - d, where p and rp are the
+/// position vectors of the object and the reference and d is the target
+/// distance. The direction of the distance is free, so only the positions of
+/// the objects are used.
+#[derive(Debug, Clone)]
+pub struct DistanceConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// target distance between both objects
+    distance: f64,
+}
+
+
+impl Constraint for DistanceConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let distance = self.distance;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, distance));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let distance = self.distance;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, distance));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let distance = self.distance;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, distance));
+        vec![("distance", error[0], "length")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.distance]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.distance = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let placements: Vec<Placement> = self.function
+            .objects()
+            .iter()
+            .map(|obj_idx| Placement::current(&sys_objects[*obj_idx]))
+            .collect();
+        vec![length(&(placements[0].position - placements[1].position)).re]
+    }
+}
+
+
+impl DistanceConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> DistanceConstraint {
+        // Only the positions are used, the rotations of the objects do not
+        // change the distance between their origins
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z"]);
+
+        // A missing distance is set to a value of 0
+        let distance = constraint_parameters.get("d").copied().unwrap_or(0.0);
+
+        DistanceConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_position_iter().collect()),
+            distance,
+        }
+    }
+}
+
+
+/// Evaluates the residual of the constraint: the difference between the
+/// distance of both objects and the target distance
+fn residuals(placements: &[Placement], distance: f64) -> Vec<HDual> {
+    let mut target = HDual::new();
+    target.re = distance;
+    vec![length(&(placements[0].position - placements[1].position)) - target]
+}
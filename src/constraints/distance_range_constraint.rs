@@ -0,0 +1,191 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{length, positive_excess};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Keeps the distance between the origins of an object and a reference object
+/// inside the band [min, max]
+///
+/// Inside the band the constraint is satisfied and its residuals, gradient and
+/// hessian are zero. Outside of it the residual is e^(3/2), where e is the
+/// distance to the nearest end of the band, so the constraint function is e^3
+/// and its hessian is continuous at both ends. A missing minimum is 0 and a
+/// missing maximum is infinite.
+#[derive(Debug, Clone)]
+pub struct DistanceRangeConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// minimum distance between both objects
+    min: f64,
+    /// maximum distance between both objects
+    max: f64,
+}
+
+
+impl Constraint for DistanceRangeConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let (min, max) = (self.min, self.max);
+        self.function.evaluate(sys_objects, |placements| residuals(placements, min, max));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let (min, max) = (self.min, self.max);
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, min, max));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        // The distances outside of the band (not the residuals)
+        let distance = self.current_distance(sys_objects);
+        vec![
+            ("below min", (self.min - distance).max(0.0), "length"),
+            ("above max", (distance - self.max).max(0.0), "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.min, self.max]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.min = parameters[0];
+        self.max = parameters[1];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The band is widened just enough to contain the current distance
+        let distance = self.current_distance(sys_objects);
+        vec![self.min.min(distance), self.max.max(distance)]
+    }
+}
+
+
+impl DistanceRangeConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        min: Option<f64>,
+        max: Option<f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> DistanceRangeConstraint {
+        // Only the positions are used, the rotations of the objects do not
+        // change the distance between their origins
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z"]);
+
+        DistanceRangeConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_position_iter().collect()),
+            min: min.unwrap_or(0.0),
+            max: max.unwrap_or(f64::INFINITY),
+        }
+    }
+
+    /// Returns the distance between both objects at their current positions
+    fn current_distance(&self, sys_objects: &[SystemObject]) -> f64 {
+        let objects = self.function.objects();
+        let placement = Placement::current(&sys_objects[objects[0]]);
+        let ref_placement = Placement::current(&sys_objects[objects[1]]);
+        length(&(placement.position - ref_placement.position)).re
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the one-sided penalties of the
+/// distance below the minimum and above the maximum
+fn residuals(placements: &[Placement], min: f64, max: f64) -> Vec<HDual> {
+    let distance = length(&(placements[0].position - placements[1].position));
+    let mut below = HDual::new();
+    below.re = min;
+    let mut above = HDual::new();
+    above.re = max;
+    vec![positive_excess(below - distance), positive_excess(distance - above)]
+}
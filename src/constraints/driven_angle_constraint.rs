@@ -0,0 +1,190 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use crate::system::Variable;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
+
+/// Pins one rotation variable (phi, theta or psi) of an object to a target
+/// angle: (angle - target)^2
+///
+/// The target is meant to be swept to animate an assembly, so it can be
+/// changed with set_target (or ConstraintType::set_parameter) without building
+/// the system again.
+#[derive(Debug, Clone)]
+pub struct DrivenAngleConstraint {
+    /// name of the constraint
+    name: String,
+    /// value of angle - target
+    residual: f64,
+    /// the driven rotation variable
+    variable: VN,
+    /// target value of the angle (in radians)
+    target: f64,
+    /// Index of the object in the vector of system objects
+    o_index: usize,
+}
+
+
+impl Constraint for DrivenAngleConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.residual = self.residual_value(sys_objects);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        let variable = self.driven_variable(sys_objects);
+        if let Some(k) = variable.index {
+            system_grad[k] += 2.0 * self.residual * variable.scale;
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let variable = self.driven_variable(sys_objects);
+        match variable.index {
+            Some(k) => 2.0 * self.residual * variable.scale * direction[k],
+            None => 0.0,
+        }
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        let variable = self.driven_variable(sys_objects);
+        if let Some(k) = variable.index {
+            system_hess.add(k, k, 2.0 * variable.scale.powi(2));
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        // The residuals are used without evaluating the constraint first
+        let mut residual = Residual {
+            value: self.residual_value(sys_objects),
+            jacobian: Vec::new(),
+        };
+        let variable = self.driven_variable(sys_objects);
+        if let Some(k) = variable.index {
+            residual.jacobian.push((k, variable.scale));
+        }
+        residuals.push(residual);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Angle
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.o_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        vec![(self.variable.as_str(), self.residual_value(sys_objects), "angle")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.o_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.o_index, self.variable)]
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.o_index = object_map[&self.o_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.target]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.set_target(parameters[0]);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        vec![self.driven_variable(sys_objects).value]
+    }
+}
+
+
+impl DrivenAngleConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        variable: VN,
+        target: f64,
+        o_index: usize,
+    ) -> DrivenAngleConstraint {
+        system_objects[o_index].enable_variables(&[variable.as_str()]);
+
+        DrivenAngleConstraint {
+            name: name.to_string(),
+            residual: 0.0,
+            variable,
+            target,
+            o_index,
+        }
+    }
+
+    /// Changes the target angle of the constraint. The new target is used the
+    /// next time the constraint is evaluated.
+    pub fn set_target(&mut self, target: f64) {
+        self.target = target;
+    }
+
+    /// Returns the rotation variable driven by this constraint
+    fn driven_variable<'a>(&self, sys_objects: &'a [SystemObject]) -> &'a Variable {
+        sys_objects[self.o_index].get_variable(self.variable)
+    }
+
+    /// Returns the value of the residual at the current values of the variables
+    fn residual_value(&self, sys_objects: &[SystemObject]) -> f64 {
+        self.driven_variable(sys_objects).value - self.target
+    }
+}
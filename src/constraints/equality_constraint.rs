@@ -15,43 +15,407 @@
 
 use std::collections::HashMap;
 
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::system::Variable;
 use crate::system_object::{SystemObject, VariableName};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
 
+/// Factor of the pairs of variables linked with opposite sign
+pub const OPPOSITE_SIGN: f64 = -1.0;
 
-/// This function adds the objects being constrained to the system and their corresponding
-/// variables. Then it adds the corresponding equality constraints to the variables of
-/// the objects.
+
+/// This function enables the variables of the pairs of equal variables in both
+/// objects. Then it makes the variable of the second object of each pair equal
+/// to the factor of the pair times the variable of the first object. Both
+/// objects may be the same object, in which case the pairs must not form a
+/// cycle (see find_equality_cycle).
 pub fn set_up_equalities<>(
-        c_params: &HashMap<&str, f64>,
+        pairs: &[(VariableName, VariableName, f64)],
         sys_object1_idx: usize,
         sys_object2_idx: usize,
-        sys_objects: &mut Vec<SystemObject>,
+        sys_objects: &mut [SystemObject],
 ) {
-    let mut var_idx: VariableName;
-    let mut equal_variables: Vec<&str> = Vec::new();
-    let mut equal_indices: Vec<(&str, (usize, VariableName))> = Vec::new();
     // now we add the indices of the equal variables
-    // NOTE: we assume that there are not chained equality constraints (they should
-    // be removed by the constraint front-end)
-    for variable in ["x", "y", "z", "phi", "theta", "psi"].iter() {
-        match c_params.get(variable) {
-            Some(_) => {
-                var_idx = VariableName::get_from_str(variable);
-                equal_variables.push(variable);
-                equal_indices.push((variable, (sys_object1_idx, var_idx)));
-            }
-            None => (),
-        }
-    }
-    sys_objects[sys_object1_idx].enable_variables(&equal_variables);
-    sys_objects[sys_object2_idx].enable_variables(&equal_variables);
-    sys_objects[sys_object2_idx].add_equal_indices(&equal_indices);
-
-    // WARNING: we are enabling both rotation quaternion and position vector of the
-    // system objects. This may cause unnecessary updates on the quaternion or the vector
-    // we only need to enable them if one of their variables are enabled
-    sys_objects[sys_object1_idx].q_enable = true;
-    sys_objects[sys_object1_idx].v_enable = true;
-    sys_objects[sys_object2_idx].q_enable = true;
-    sys_objects[sys_object2_idx].v_enable = true;
+    // NOTE: chained equality constraints are resolved to the root of the chain
+    // when the indices are added (see System::add_indices)
+    for (var_name1, var_name2, factor) in pairs {
+        sys_objects[sys_object1_idx].enable_variables(&[var_name1.as_str()]);
+        sys_objects[sys_object2_idx].enable_variables(&[var_name2.as_str()]);
+        sys_objects[sys_object2_idx].add_equal_indices(&[(var_name2.as_str(), (sys_object1_idx, *var_name1, *factor))]);
+    }
+}
+
+
+/// Returns the pairs of equal variables (variable of object1, variable of
+/// object2, factor) given by the parameters of an equality. A variable name
+/// ("x") makes the same variable of both objects equal, and "a=b" makes the
+/// variable a of object1 equal to the variable b of object2 (for example "y=z"
+/// on a single object). Other parameters (like "penalty") are skipped.
+///
+/// A parameter value of -1 links the variables with opposite sign (the
+/// variable of object2 is minus the variable of object1); any other value
+/// makes them equal.
+pub fn equality_pairs(c_params: &HashMap<&str, f64>) -> Result<Vec<(VariableName, VariableName, f64)>, String> {
+    let factor = |key: &str| if c_params[key] == OPPOSITE_SIGN { OPPOSITE_SIGN } else { 1.0 };
+    let mut pairs: Vec<(VariableName, VariableName, f64)> = VariableName::get_variable_iter()
+        .filter(|var_name| c_params.contains_key(var_name.as_str()))
+        .map(|var_name| (var_name, var_name, factor(var_name.as_str())))
+        .collect();
+
+    let mut keys: Vec<&&str> = c_params.keys().filter(|key| key.contains('=')).collect();
+    keys.sort();
+    for key in keys {
+        let mut names = key.splitn(2, '=').map(|name| {
+            VariableName::get_variable_iter().find(|var_name| var_name.as_str() == name.trim())
+        });
+        match (names.next().flatten(), names.next().flatten()) {
+            (Some(var_name1), Some(var_name2)) => pairs.push((var_name1, var_name2, factor(key))),
+            _ => return Err(format!("'{}' is not a pair of variable names", key)),
+        }
+    }
+    Ok(pairs)
+}
+
+
+/// Returns the first pair of variables whose equality would make a cycle of
+/// equal variables (a variable that is equal to itself through a chain of
+/// equalities), taking into account the pairs before it. A cycle has no root
+/// variable to take the solver index from (see System::add_indices), and it
+/// is only possible when object1 and object2 are the same object or when they
+/// are already linked by other equalities.
+pub fn find_equality_cycle(
+        sys_objects: &[SystemObject],
+        sys_object1_idx: usize,
+        sys_object2_idx: usize,
+        pairs: &[(VariableName, VariableName, f64)],
+) -> Option<(VariableName, VariableName)> {
+    let mut links: HashMap<(usize, VariableName), (usize, VariableName)> = HashMap::new();
+    let max_steps = 6 * sys_objects.len() + pairs.len();
+    for (var_name1, var_name2, _) in pairs {
+        let variable = (sys_object2_idx, *var_name2);
+        let mut current = Some((sys_object1_idx, *var_name1));
+        // follow the chain of the variable it is made equal to
+        for _ in 0..=max_steps {
+            match current {
+                Some(next) if next == variable => return Some((*var_name1, *var_name2)),
+                Some(next) => {
+                    current = links
+                        .get(&next)
+                        .copied()
+                        .or(sys_objects[next.0].get_variable(next.1).equal.map(|(j, var_name, _)| (j, var_name)));
+                }
+                None => break,
+            }
+        }
+        links.insert(variable, (sys_object1_idx, *var_name1));
+    }
+    None
+}
+
+
+/// Returns the first of the variables that can't be made equal between both
+/// objects without breaking an existing equality: set_up_equalities would
+/// overwrite the link of a variable of object2 that is already equal to another
+/// variable (or to the same variable with another factor), and a variable of
+/// object1 equal to the same variable of object2 would make a cycle.
+pub fn find_equality_conflict(
+        sys_objects: &[SystemObject],
+        sys_object1_idx: usize,
+        sys_object2_idx: usize,
+        variables: &[VariableName],
+) -> Option<VariableName> {
+    variables.iter().copied().find(|var_name| {
+        let equal1 = sys_objects[sys_object1_idx].get_variable(*var_name).equal;
+        let equal2 = sys_objects[sys_object2_idx].get_variable(*var_name).equal;
+        matches!(equal1, Some((j, j_var_name, _)) if j == sys_object2_idx && j_var_name == *var_name)
+            || equal2.is_some_and(|equal| equal != (sys_object1_idx, *var_name, 1.0))
+    })
+}
+
+
+/// Makes variables of two objects equal: sum_i (factor_i * var1_i - var2_i)^2
+///
+/// When use_penalty is false the equal variables share the same solver index
+/// (see set_up_equalities), so they always have the same value (up to the
+/// factor) and the constraint is only kept to report the distance between
+/// them. When it is
+/// true the variables are independent and the squared differences are
+/// minimized like any other constraint function.
+///
+/// NOTE: the indices of the local variables used in this constraint are the following:
+/// 0..6 -> object1.x, object1.y, object1.z, object1.phi, object1.theta, object1.psi
+/// 6..12 -> object2.x, object2.y, object2.z, object2.phi, object2.theta, object2.psi
+#[derive(Debug, Clone)]
+pub struct EqualityConstraint {
+    /// name of the constraint
+    name: String,
+    /// value of phi(y)^2
+    value: f64,
+    /// gradient vector of phi(y)^2
+    grad: [f64; 12],
+    /// hessian matrix of phi(y)^2
+    hess: [[f64; 12]; 12],
+    /// pairs of equal variables as (variable of object1, variable of object2,
+    /// factor), where the variable of object2 is factor times the variable of
+    /// object1
+    pairs: Vec<(VariableName, VariableName, f64)>,
+    /// whether the equality is a penalty instead of shared solver indices
+    use_penalty: bool,
+    /// Index of the first object in the vector of system objects
+    object1_index: usize,
+    /// Index of the second object in the vector of system objects
+    object2_index: usize,
+}
+
+
+impl Constraint for EqualityConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let values = self.local_values(sys_objects);
+        let mut fn_eval = HDual::new();
+        for i in 0..12 {
+            for j in i..12 {
+                fn_eval = self.eval(&values, i, j);
+                self.hess[i][j] = fn_eval.e1e2;
+                self.hess[j][i] = fn_eval.e1e2;
+                if i == j {
+                    self.grad[i] = fn_eval.e1;
+                }
+            }
+        }
+        self.value = fn_eval.re;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                system_grad[k] += var.scale * self.grad[i];
+            }
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let mut diff = 0.0;
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                diff += var.scale * self.grad[i] * direction[k];
+            }
+        }
+        diff
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        let variables = self.local_variables(sys_objects);
+        for (i, variable1) in variables.iter().enumerate() {
+            if let Some(k) = variable1.index {
+                for (j, variable2) in variables.iter().enumerate() {
+                    if let Some(l) = variable2.index {
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i][j]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        // Equal variables that share an index have derivatives that cancel
+        let object1 = &sys_objects[self.object1_index];
+        let object2 = &sys_objects[self.object2_index];
+        for (var_name1, var_name2, factor) in &self.pairs {
+            let variable1 = object1.get_variable(*var_name1);
+            let variable2 = object2.get_variable(*var_name2);
+            let mut residual = Residual {
+                value: factor * variable1.value - variable2.value,
+                jacobian: Vec::new(),
+            };
+            if let Some(k) = variable1.index {
+                residual.jacobian.push((k, factor * variable1.scale));
+            }
+            if let Some(k) = variable2.index {
+                residual.jacobian.push((k, -variable2.scale));
+            }
+            residuals.push(residual);
+        }
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.object1_index, self.object2_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let object1 = &sys_objects[self.object1_index];
+        let object2 = &sys_objects[self.object2_index];
+        self.pairs
+            .iter()
+            .map(|(var_name1, var_name2, factor)| {
+                let difference = factor * object1.get_variable(*var_name1).value - object2.get_variable(*var_name2).value;
+                let unit = if var_name1.is_rotation() { "angle" } else { "length" };
+                (var_name1.as_str(), difference, unit)
+            })
+            .collect()
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![
+            (self.object1_index, Footprint::Variables),
+            (self.object2_index, Footprint::Variables),
+        ]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VariableName)> {
+        // Shared indices are not variables of their own
+        if !self.use_penalty {
+            return Vec::new();
+        }
+        self.pairs
+            .iter()
+            .flat_map(|(var_name1, var_name2, _)| {
+                vec![(self.object1_index, *var_name1), (self.object2_index, *var_name2)]
+            })
+            .collect()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.object1_index = object_map[&self.object1_index];
+        self.object2_index = object_map[&self.object2_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl EqualityConstraint {
+    /// Creates the equality of the pairs of variables (see equality_pairs).
+    /// Without a penalty the equal variables of object2 take the solver indices
+    /// of object1.
+    pub fn new(
+        name: &str,
+        sys_objects: &mut [SystemObject],
+        pairs: Vec<(VariableName, VariableName, f64)>,
+        use_penalty: bool,
+        object1_index: usize,
+        object2_index: usize,
+    ) -> EqualityConstraint {
+        if use_penalty {
+            for (var_name1, var_name2, _) in &pairs {
+                sys_objects[object1_index].enable_variables(&[var_name1.as_str()]);
+                sys_objects[object2_index].enable_variables(&[var_name2.as_str()]);
+            }
+        } else {
+            set_up_equalities(&pairs, object1_index, object2_index, sys_objects);
+        }
+
+        EqualityConstraint {
+            name: name.to_string(),
+            value: 0.0,
+            grad: [0.0; 12],
+            hess: [[0.0; 12]; 12],
+            pairs,
+            use_penalty,
+            object1_index,
+            object2_index,
+        }
+    }
+
+    /// Returns true when the equality is a penalty instead of shared solver
+    /// indices
+    pub fn uses_penalty(&self) -> bool {
+        self.use_penalty
+    }
+
+    /// Returns the variables of both objects in the order of the local
+    /// variables
+    fn local_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        let object1 = &sys_objects[self.object1_index];
+        let object2 = &sys_objects[self.object2_index];
+        VariableName::get_variable_iter()
+            .map(|var_name| object1.get_variable(var_name))
+            .chain(VariableName::get_variable_iter().map(|var_name| object2.get_variable(var_name)))
+            .collect()
+    }
+
+    /// Returns the values of the local variables
+    fn local_values(&self, sys_objects: &[SystemObject]) -> [f64; 12] {
+        let mut values = [0.0; 12];
+        for (value, variable) in values.iter_mut().zip(self.local_variables(sys_objects)) {
+            *value = variable.value;
+        }
+        values
+    }
+
+    /// Evaluates the constraint function with the partial derivatives with
+    /// respect to the local variables i (in e1) and j (in e2)
+    fn eval(&self, values: &[f64; 12], i: usize, j: usize) -> HDual {
+        let local = |k: usize| {
+            let mut variable = HDual::new();
+            variable.re = values[k];
+            if k == i {
+                variable.e1 = 1.0;
+            }
+            if k == j {
+                variable.e2 = 1.0;
+            }
+            variable
+        };
+        let mut fn_eval = HDual::new();
+        for (var_name1, var_name2, factor) in &self.pairs {
+            let mut scale = HDual::new();
+            scale.re = *factor;
+            let difference = scale * local(local_index(*var_name1)) - local(6 + local_index(*var_name2));
+            fn_eval = fn_eval + difference.powi(2);
+        }
+        fn_eval
+    }
+}
+
+
+/// Returns the position of a variable among the variables of an object
+fn local_index(var_name: VariableName) -> usize {
+    VariableName::get_variable_iter().position(|name| name == var_name).unwrap()
 }
@@ -15,18 +15,18 @@
 
 use std::collections::HashMap;
 
-use ndarray::{Array1, Array2};
+use ndarray::Array1;
 
 use optimization::geometry::{HDQuaternion, HDVector};
 use optimization::number_system::HyperDualScalar as HDual;
 
 use crate::system::Variable;
 use crate::system_object::{SystemObject, VariableName as VN};
-use crate::constraints::Constraint;
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
 
 
 /// The values to fix the 3 axis of the object relative to the reference object
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct FixParameters {
     pub x: f64,
     pub y: f64,
@@ -72,8 +72,10 @@ impl FixParameters {
 /// 6 -> reference.phi
 /// 7 -> reference.theta
 /// 8 -> reference.psi
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FixBaseConstraint {
+    /// name of the constraint
+    name: String,
     /// value of phi(y)^2
     value: f64,
     /// gradient vector of phi(y)^2
@@ -95,7 +97,7 @@ impl Constraint for FixBaseConstraint {
 
     fn evaluate(
             &mut self,
-            sys_objects: &Vec<SystemObject>
+            sys_objects: &[SystemObject]
     ) {
         let object = &sys_objects[self.obj_index];
         let reference = &sys_objects[self.ref_index];
@@ -110,8 +112,8 @@ impl Constraint for FixBaseConstraint {
         // vector representing the position of the object and the reference
         let mut p: HDVector;
         let mut rp: HDVector;
-        // quaternion representing the rotation of the reference
-        let mut rq: HDQuaternion;
+        // inverse of the quaternion representing the rotation of the reference
+        let mut rq_inv: HDQuaternion;
 
 
         // Start with the partial derivatives with respect to only the object variables
@@ -121,14 +123,14 @@ impl Constraint for FixBaseConstraint {
         // for the evaluation of the partial derivatives with respect to only
         // the variables of the object being fixed
         rp = reference.get_vector(None, None); // this
-        rq = reference.get_quaternion(None, None); // no evaluate the reference variables
+        rq_inv = reference.get_inverse_quaternion(None, None); // no evaluate the reference variables
         for (i, var1) in VN::get_position_iter().enumerate() {
             // Now find the other partial derivatives with respect to the object
             // (we find the partial derivatives with respect to all the combinations
             // of x, y, z for the object)
             for (j, var2) in VN::get_position_iter().enumerate().skip(i) {
                 p = object.get_vector(Some(var1), Some(var2));
-                fn_eval = self.eval(object, p, rp, rq);
+                fn_eval = self.eval(object, p, rp, rq_inv);
                 self.hess[i][j] = fn_eval.e1e2;
                 self.hess[j][i] = fn_eval.e1e2;
             }
@@ -145,8 +147,8 @@ impl Constraint for FixBaseConstraint {
             for (j, var2) in VN::get_variable_iter().enumerate() {
                 // the second variable is a reference variable
                 rp = reference.get_vector(None, Some(var2));
-                rq = reference.get_quaternion(None, Some(var2));
-                fn_eval = self.eval(object, p, rp, rq);
+                rq_inv = reference.get_inverse_quaternion(None, Some(var2));
+                fn_eval = self.eval(object, p, rp, rq_inv);
                 self.hess[i][j+offset] = fn_eval.e1e2;
                 self.hess[j+offset][i] = fn_eval.e1e2;
             }
@@ -162,8 +164,8 @@ impl Constraint for FixBaseConstraint {
         for (i, var1) in VN::get_variable_iter().enumerate() {
             for (j, var2) in VN::get_variable_iter().enumerate().skip(i) {
                 rp = reference.get_vector(Some(var1), Some(var2));
-                rq = reference.get_quaternion(Some(var1), Some(var2));
-                fn_eval = self.eval(object, p, rp, rq);
+                rq_inv = reference.get_inverse_quaternion(Some(var1), Some(var2));
+                fn_eval = self.eval(object, p, rp, rq_inv);
                 self.hess[i+offset][j+offset] = fn_eval.e1e2;
                 self.hess[j+offset][i+offset] = fn_eval.e1e2;
             }
@@ -184,7 +186,7 @@ impl Constraint for FixBaseConstraint {
      fn get_gradient(
             &self,
             system_grad: &mut Array1<f64>,
-            sys_objects: &Vec<SystemObject>,
+            sys_objects: &[SystemObject],
     ) {
         let object = &sys_objects[self.obj_index];
         let reference = &sys_objects[self.ref_index];
@@ -194,28 +196,47 @@ impl Constraint for FixBaseConstraint {
         for (i, var_name) in VN::get_position_iter().enumerate() {
             var = object.get_variable(var_name);
             if let Some(k) = var.index {
-                system_grad[k] += self.grad[i];
+                system_grad[k] += var.scale * self.grad[i];
             }
         }
         // add the gradient values from the reference variables
         for (i, var_name) in VN::get_variable_iter().enumerate() {
             var = reference.get_variable(var_name);
             if let Some(k) = var.index {
-                system_grad[k] += self.grad[i+offset];
+                system_grad[k] += var.scale * self.grad[i+offset];
             }
         }
      }
 
      fn get_diff(
-            &mut self,
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
      ) -> f64 {
-        1.0
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        let mut var: &Variable;
+        let offset = 3; // offset between object variables and reference variables
+        let mut diff = 0.0;
+        for (i, var_name) in VN::get_position_iter().enumerate() {
+            var = object.get_variable(var_name);
+            if let Some(k) = var.index {
+                diff += var.scale * self.grad[i] * direction[k];
+            }
+        }
+        for (i, var_name) in VN::get_variable_iter().enumerate() {
+            var = reference.get_variable(var_name);
+            if let Some(k) = var.index {
+                diff += var.scale * self.grad[i+offset] * direction[k];
+            }
+        }
+        diff
      }
 
     fn get_hessian(
             &self,
-            system_hess: &mut Array2<f64>,
-            sys_objects: &Vec<SystemObject>,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
     ) {
         // system indices of the variables
         let object = &sys_objects[self.obj_index];
@@ -232,7 +253,7 @@ impl Constraint for FixBaseConstraint {
                 for (j, var2) in VN::get_position_iter().enumerate() {
                     variable2 = object.get_variable(var2);
                     if let Some(l) = variable2.index {
-                        system_hess[[k, l]] += self.hess[i][j];
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i][j]);
                     }
                 }
             }
@@ -246,8 +267,9 @@ impl Constraint for FixBaseConstraint {
                 for (j, var2) in VN::get_variable_iter().enumerate() {
                     variable2 = reference.get_variable(var2);
                     if let Some(l) = variable2.index {
-                        system_hess[[k, l]] += self.hess[i][j+offset];
-                        system_hess[[l, k]] += self.hess[j+offset][i];
+                        let scale = variable1.scale * variable2.scale;
+                        system_hess.add(k, l, scale * self.hess[i][j+offset]);
+                        system_hess.add(l, k, scale * self.hess[j+offset][i]);
                     }
                 }
             }
@@ -260,18 +282,143 @@ impl Constraint for FixBaseConstraint {
                 for (j, var2) in VN::get_variable_iter().enumerate() {
                     variable2 = reference.get_variable(var2);
                     if let Some(l) = variable2.index {
-                        system_hess[[k, l]] += self.hess[i+offset][j+offset];
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i+offset][j+offset]);
                     }
                 }
             }
         }
     }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+
+        // The residuals are the enabled components of the base evaluation. We
+        // only need first partial derivatives so only e1 is used.
+        let enabled = [
+            object.get_variable(VN::x).enabled,
+            object.get_variable(VN::y).enabled,
+            object.get_variable(VN::z).enabled,
+        ];
+        let p = object.get_vector(None, None);
+        let rp = reference.get_vector(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        let base_eval = self.eval_base(object, p, rp, rq_inv);
+        let first = residuals.len();
+        for (axis, component) in [base_eval.x, base_eval.y, base_eval.z].iter().enumerate() {
+            if enabled[axis] {
+                residuals.push(Residual {
+                    value: component.re,
+                    jacobian: Vec::new(),
+                });
+            }
+        }
+
+        let mut var: &Variable;
+        let mut base_eval: HDVector;
+        // partial derivatives with respect to the object variables
+        for var_name in VN::get_position_iter() {
+            var = object.get_variable(var_name);
+            if let Some(k) = var.index {
+                let p = object.get_vector(Some(var_name), None);
+                base_eval = self.eval_base(object, p, rp, rq_inv);
+                add_jacobian_entries(&mut residuals[first..], &enabled, &base_eval, k, var.scale);
+            }
+        }
+        // partial derivatives with respect to the reference variables
+        for var_name in VN::get_variable_iter() {
+            var = reference.get_variable(var_name);
+            if let Some(k) = var.index {
+                let rp = reference.get_vector(Some(var_name), None);
+                let rq_inv = reference.get_inverse_quaternion(Some(var_name), None);
+                base_eval = self.eval_base(object, p, rp, rq_inv);
+                add_jacobian_entries(&mut residuals[first..], &enabled, &base_eval, k, var.scale);
+            }
+        }
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj_index, self.ref_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        let p = object.get_vector(None, None);
+        let rp = reference.get_vector(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        // remaining offset along each fixed axis of the reference
+        let base_eval = self.eval_base(object, p, rp, rq_inv);
+        let mut breakdown = Vec::new();
+        for (var_name, component) in VN::get_position_iter().zip([base_eval.x, base_eval.y, base_eval.z].iter()) {
+            if object.get_variable(var_name).enabled {
+                breakdown.push((var_name.as_str(), component.re, "length"));
+            }
+        }
+        breakdown
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![
+            (self.obj_index, Footprint::Position),
+            (self.ref_index, Footprint::Position),
+            (self.ref_index, Footprint::Rotation),
+        ]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        let object_vars = VN::get_position_iter().map(|var| (self.obj_index, var));
+        let reference_vars = VN::get_variable_iter().map(|var| (self.ref_index, var));
+        object_vars.chain(reference_vars).collect()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj_index = object_map[&self.obj_index];
+        self.ref_index = object_map[&self.ref_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.parameters.x, self.parameters.y, self.parameters.z]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.parameters.x = parameters[0];
+        self.parameters.y = parameters[1];
+        self.parameters.z = parameters[2];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        let p = object.get_vector(None, None);
+        let rp = reference.get_vector(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        // position of the object in the coordinate system of the reference
+        let local = rq_inv.mul_vec(&(p - rp));
+        vec![local.x.re, local.y.re, local.z.re]
+    }
 }
 
 
 impl FixBaseConstraint {
     pub fn new(
-        system_objects: &mut Vec<SystemObject>,
+        name: &str,
+        system_objects: &mut [SystemObject],
         constraint_parameters: &HashMap<&str, f64>,
         obj_index: usize,
         ref_index: usize,
@@ -288,15 +435,12 @@ impl FixBaseConstraint {
         {
             let sys_object = &mut system_objects[obj_index];
             sys_object.enable_variables_from_params(constraint_parameters);
-            sys_object.v_enable = true;
         }
         {
             let sys_reference = &mut system_objects[ref_index];
             sys_reference.enable_variables_from_params(constraint_parameters);
             // make sure we enable the rotation angles of the reference object
             sys_reference.enable_variables(&["phi", "theta", "psi"]);
-            sys_reference.v_enable = true;
-            sys_reference.q_enable = true;
         }
 
         // Adds the "offset" values used in the constraint function. Note that
@@ -307,6 +451,7 @@ impl FixBaseConstraint {
         add_parameters(&mut parameters, constraint_parameters);
 
         FixBaseConstraint {
+            name: name.to_string(),
             value: 0.0,
             grad: [0.0; 9],
             hess: [[0.0; 9]; 9],
@@ -323,17 +468,13 @@ impl FixBaseConstraint {
             object: &SystemObject,
             p: HDVector,
             rp: HDVector,
-            rq: HDQuaternion,
+            rq_inv: HDQuaternion,
     ) -> HDual {
         let obj_px_enabled = object.get_variable(VN::x).enabled;
         let obj_py_enabled = object.get_variable(VN::y).enabled;
         let obj_pz_enabled = object.get_variable(VN::z).enabled;
 
-        let f_base = self.get_f_base(obj_px_enabled, obj_py_enabled, obj_pz_enabled, &p);
-
-        let v = p - rp;
-
-        let base_eval = rq.inv().mul_vec(&v) - f_base;
+        let base_eval = self.eval_base(object, p, rp, rq_inv);
 
         let mut result = HDual::new();
         //TODO: addasign operator
@@ -349,6 +490,28 @@ impl FixBaseConstraint {
         result
     }
 
+    /// Evaluates the vector of residuals of the constraint function. Only the
+    /// components of the enabled axes of the object are residuals; the other
+    /// components should be ignored. rq_inv is the inverse of the rotation
+    /// quaternion of the reference.
+    fn eval_base(
+            &self,
+            object: &SystemObject,
+            p: HDVector,
+            rp: HDVector,
+            rq_inv: HDQuaternion,
+    ) -> HDVector {
+        let obj_px_enabled = object.get_variable(VN::x).enabled;
+        let obj_py_enabled = object.get_variable(VN::y).enabled;
+        let obj_pz_enabled = object.get_variable(VN::z).enabled;
+
+        let f_base = self.get_f_base(obj_px_enabled, obj_py_enabled, obj_pz_enabled, &p);
+
+        let v = p - rp;
+
+        rq_inv.mul_vec(&v) - f_base
+    }
+
     /// Gets the vector f_base used in evaluating the constraint function.
     /// p is the position vector of the fixed object
     fn get_f_base(
@@ -390,9 +553,30 @@ fn add_parameters(
         constraint_parameters: &HashMap<&str, f64>,
 ) {
     for variable in ["x", "y", "z"].iter() {
-        match constraint_parameters.get(variable) {
-            Some(value) => parameters.set_parameter(variable, *value),
-            None => ()
+        if let Some(value) = constraint_parameters.get(variable) {
+            parameters.set_parameter(variable, *value);
+        }
+    }
+}
+
+
+/// Adds the partial derivatives (stored in e1) of the enabled components of
+/// base_eval to the jacobian of the residuals. k is the solver index of the
+/// variable and scale its derivative with respect to the solver variable (see
+/// Variable::scale).
+fn add_jacobian_entries(
+        residuals: &mut [Residual],
+        enabled: &[bool; 3],
+        base_eval: &HDVector,
+        k: usize,
+        scale: f64,
+) {
+    let components = [base_eval.x, base_eval.y, base_eval.z];
+    let mut i = 0;
+    for (axis, component) in components.iter().enumerate() {
+        if enabled[axis] {
+            residuals[i].jacobian.push((k, scale * component.e1));
+            i += 1;
         }
     }
 }
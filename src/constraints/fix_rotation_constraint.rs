@@ -0,0 +1,415 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::geometry::{HDQuaternion, HDVector};
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{euler, quaternion_product};
+use crate::system::Variable;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
+
+/// The angles of the rotation of the object relative to the reference object
+#[derive(Debug, Clone)]
+struct FixRotationParameters {
+    pub phi: f64,
+    pub theta: f64,
+    pub psi: f64,
+}
+
+impl FixRotationParameters {
+    pub fn new() -> FixRotationParameters {
+        FixRotationParameters {
+            phi: 0.0,
+            theta: 0.0,
+            psi: 0.0,
+        }
+    }
+
+    /// Adds value to the parameters
+    pub fn set_parameter(&mut self, variable: &str, value: f64) {
+        match variable {
+            "phi" => self.phi = value,
+            "theta" => self.theta = value,
+            "psi" => self.psi = value,
+            _ => ()
+        }
+    }
+}
+
+/// Fixes the rotation (no position) of one object with respect to another
+///
+/// The rotation of the object in the coordinate system of the reference is
+/// q_local = rq^-1 * q, where q and rq are the rotation quaternions of the
+/// object and the reference. The residuals of the constraint are the vector
+/// part of t^-1 * q_local, where t is the quaternion of the target rotation
+/// given by the parameters. They are zero when q_local is t or -t (both
+/// quaternions represent the same rotation), and the sum of their squares is
+/// sin(angle/2)^2 where angle is the angle of the remaining rotation.
+///
+/// Like FixBaseConstraint, the constraint function is evaluated as a function
+/// phi(y) of only the variables used by this constraint.
+///
+/// NOTE: the indices of the local variables used in this constraint are the following:
+/// 0 -> object.phi
+/// 1 -> object.theta
+/// 2 -> object.psi
+/// 3 -> reference.phi
+/// 4 -> reference.theta
+/// 5 -> reference.psi
+#[derive(Debug, Clone)]
+pub struct FixRotationConstraint {
+    /// name of the constraint
+    name: String,
+    /// value of phi(y)^2
+    value: f64,
+    /// gradient vector of phi(y)^2
+    grad: [f64; 6],
+    /// hessian matrix of phi(y)^2
+    hess: [[f64; 6]; 6],
+    /// Euler angles of the rotation of the object with respect to the local
+    /// coordinate system of the reference object
+    parameters: FixRotationParameters,
+    /// Index of the object in the vector of system objects
+    obj_index: usize,
+    /// Index of the reference in the vector of system objects
+    ref_index: usize,
+}
+
+
+impl Constraint for FixRotationConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+
+        // The first 3 variables are the object variables, then the next 3
+        // variables are the reference variables
+        let offset = 3;
+
+        let t_inv = self.target_inverse();
+        let mut fn_eval = HDual::new();
+        let mut q: HDQuaternion;
+        let mut rq_inv: HDQuaternion;
+
+        // Partial derivatives with respect to only the object variables
+        rq_inv = reference.get_inverse_quaternion(None, None);
+        for (i, var1) in VN::get_rotation_iter().enumerate() {
+            for (j, var2) in VN::get_rotation_iter().enumerate().skip(i) {
+                q = object.get_quaternion(Some(var1), Some(var2));
+                fn_eval = self.eval(q, rq_inv, t_inv);
+                self.hess[i][j] = fn_eval.e1e2;
+                self.hess[j][i] = fn_eval.e1e2;
+            }
+            self.grad[i] = fn_eval.e1;
+        }
+
+        // Partial derivatives with respect to the variables of both the object
+        // and the reference
+        for (i, var1) in VN::get_rotation_iter().enumerate() {
+            q = object.get_quaternion(Some(var1), None);
+            for (j, var2) in VN::get_rotation_iter().enumerate() {
+                rq_inv = reference.get_inverse_quaternion(None, Some(var2));
+                fn_eval = self.eval(q, rq_inv, t_inv);
+                self.hess[i][j+offset] = fn_eval.e1e2;
+                self.hess[j+offset][i] = fn_eval.e1e2;
+            }
+        }
+
+        // Partial derivatives with respect to only the reference variables
+        q = object.get_quaternion(None, None);
+        for (i, var1) in VN::get_rotation_iter().enumerate() {
+            for (j, var2) in VN::get_rotation_iter().enumerate().skip(i) {
+                rq_inv = reference.get_inverse_quaternion(Some(var1), Some(var2));
+                fn_eval = self.eval(q, rq_inv, t_inv);
+                self.hess[i+offset][j+offset] = fn_eval.e1e2;
+                self.hess[j+offset][i+offset] = fn_eval.e1e2;
+            }
+            self.grad[i+offset] = fn_eval.e1;
+        }
+
+        self.value = fn_eval.re;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                system_grad[k] += var.scale * self.grad[i];
+            }
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let mut diff = 0.0;
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                diff += var.scale * self.grad[i] * direction[k];
+            }
+        }
+        diff
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        let variables = self.local_variables(sys_objects);
+        for (i, variable1) in variables.iter().enumerate() {
+            if let Some(k) = variable1.index {
+                for (j, variable2) in variables.iter().enumerate() {
+                    if let Some(l) = variable2.index {
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i][j]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+
+        let t_inv = self.target_inverse();
+        let q = object.get_quaternion(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        let rotation_eval = self.eval_rotation(q, rq_inv, t_inv);
+        let first = residuals.len();
+        for component in [rotation_eval.x, rotation_eval.y, rotation_eval.z].iter() {
+            residuals.push(Residual {
+                value: component.re,
+                jacobian: Vec::new(),
+            });
+        }
+
+        let mut var: &Variable;
+        let mut rotation_eval: HDVector;
+        // partial derivatives with respect to the object variables
+        for var_name in VN::get_rotation_iter() {
+            var = object.get_variable(var_name);
+            if let Some(k) = var.index {
+                let q = object.get_quaternion(Some(var_name), None);
+                rotation_eval = self.eval_rotation(q, rq_inv, t_inv);
+                add_jacobian_entries(&mut residuals[first..], &rotation_eval, k, var.scale);
+            }
+        }
+        // partial derivatives with respect to the reference variables
+        for var_name in VN::get_rotation_iter() {
+            var = reference.get_variable(var_name);
+            if let Some(k) = var.index {
+                let rq_inv = reference.get_inverse_quaternion(Some(var_name), None);
+                rotation_eval = self.eval_rotation(q, rq_inv, t_inv);
+                add_jacobian_entries(&mut residuals[first..], &rotation_eval, k, var.scale);
+            }
+        }
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Angle
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj_index, self.ref_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        let q = object.get_quaternion(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        // for small rotations the components of the vector part are half the
+        // remaining angle about each axis of the target rotation
+        let rotation_eval = self.eval_rotation(q, rq_inv, self.target_inverse());
+        vec![
+            ("x", 2.0 * rotation_eval.x.re, "angle"),
+            ("y", 2.0 * rotation_eval.y.re, "angle"),
+            ("z", 2.0 * rotation_eval.z.re, "angle"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![
+            (self.obj_index, Footprint::Rotation),
+            (self.ref_index, Footprint::Rotation),
+        ]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        let object_vars = VN::get_rotation_iter().map(|var| (self.obj_index, var));
+        let reference_vars = VN::get_rotation_iter().map(|var| (self.ref_index, var));
+        object_vars.chain(reference_vars).collect()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj_index = object_map[&self.obj_index];
+        self.ref_index = object_map[&self.ref_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.parameters.phi, self.parameters.theta, self.parameters.psi]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.parameters.phi = parameters[0];
+        self.parameters.theta = parameters[1];
+        self.parameters.psi = parameters[2];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        let q = object.get_quaternion(None, None);
+        let rq_inv = reference.get_inverse_quaternion(None, None);
+        // rotation of the object in the coordinate system of the reference
+        let local = quaternion_product(&rq_inv, &q);
+        let (phi, theta, psi) = euler::quaternion_angles(local.q0.re, local.q1.re, local.q2.re, local.q3.re);
+        vec![phi, theta, psi]
+    }
+}
+
+
+impl FixRotationConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> FixRotationConstraint {
+        // The rotation quaternions depend on the 3 rotation angles, so all of
+        // them are enabled for both the object and the reference
+        system_objects[obj_index].enable_variables(&["phi", "theta", "psi"]);
+        system_objects[ref_index].enable_variables(&["phi", "theta", "psi"]);
+
+        // The missing angles are set to a value of 0
+        let mut parameters = FixRotationParameters::new();
+        for variable in ["phi", "theta", "psi"].iter() {
+            if let Some(value) = constraint_parameters.get(variable) {
+                parameters.set_parameter(variable, *value);
+            }
+        }
+
+        FixRotationConstraint {
+            name: name.to_string(),
+            value: 0.0,
+            grad: [0.0; 6],
+            hess: [[0.0; 6]; 6],
+            parameters,
+            obj_index,
+            ref_index,
+        }
+    }
+
+    /// Returns the variables used by this constraint in the order of the local
+    /// variables
+    fn local_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        let object = &sys_objects[self.obj_index];
+        let reference = &sys_objects[self.ref_index];
+        VN::get_rotation_iter()
+            .map(|var_name| object.get_variable(var_name))
+            .chain(VN::get_rotation_iter().map(|var_name| reference.get_variable(var_name)))
+            .collect()
+    }
+
+    /// Returns the inverse of the quaternion of the target rotation
+    fn target_inverse(&self) -> HDQuaternion {
+        let mut phi = HDual::new();
+        phi.re = self.parameters.phi;
+        let mut theta = HDual::new();
+        theta.re = self.parameters.theta;
+        let mut psi = HDual::new();
+        psi.re = self.parameters.psi;
+        HDQuaternion::from_angles(phi, theta, psi).inv()
+    }
+
+    /// This is the actual constraint function error. It is intended to be called
+    /// by the method evaluate() from the Constraint trait.
+    fn eval(
+            &self,
+            q: HDQuaternion,
+            rq_inv: HDQuaternion,
+            t_inv: HDQuaternion,
+    ) -> HDual {
+        let rotation_eval = self.eval_rotation(q, rq_inv, t_inv);
+        rotation_eval.x.powi(2) + rotation_eval.y.powi(2) + rotation_eval.z.powi(2)
+    }
+
+    /// Evaluates the vector of residuals of the constraint function: the vector
+    /// part of t_inv * rq_inv * q. rq_inv is the inverse of the rotation
+    /// quaternion of the reference and t_inv the inverse of the target
+    /// quaternion.
+    fn eval_rotation(
+            &self,
+            q: HDQuaternion,
+            rq_inv: HDQuaternion,
+            t_inv: HDQuaternion,
+    ) -> HDVector {
+        let error = quaternion_product(&t_inv, &quaternion_product(&rq_inv, &q));
+        let mut rotation_eval = HDVector::new();
+        rotation_eval.x = error.q1;
+        rotation_eval.y = error.q2;
+        rotation_eval.z = error.q3;
+        rotation_eval
+    }
+}
+
+
+/// Adds the partial derivatives (stored in e1) of the components of
+/// rotation_eval to the jacobian of the residuals. k is the solver index of
+/// the variable and scale its derivative with respect to the solver variable
+/// (see Variable::scale).
+fn add_jacobian_entries(
+        residuals: &mut [Residual],
+        rotation_eval: &HDVector,
+        k: usize,
+        scale: f64,
+) {
+    let components = [rotation_eval.x, rotation_eval.y, rotation_eval.z];
+    for (residual, component) in residuals.iter_mut().zip(components.iter()) {
+        residual.jacobian.push((k, scale * component.e1));
+    }
+}
@@ -0,0 +1,238 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{acos, constant_vector, dot};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// The axis of the objects and the target angle between them
+#[derive(Debug, Clone)]
+struct FixedAngleParameters {
+    /// direction of the axis in the local coordinate system of each object
+    /// (normalized)
+    pub axis: [f64; 3],
+    /// target angle (in radians)
+    pub angle: f64,
+}
+
+impl FixedAngleParameters {
+    pub fn new() -> FixedAngleParameters {
+        FixedAngleParameters {
+            axis: [0.0, 0.0, 1.0],
+            angle: 0.0,
+        }
+    }
+
+    /// Adds value to the parameters
+    pub fn set_parameter(&mut self, variable: &str, value: f64) {
+        match variable {
+            "x" => self.axis[0] = value,
+            "y" => self.axis[1] = value,
+            "z" => self.axis[2] = value,
+            "angle" => self.angle = value,
+            _ => ()
+        }
+    }
+
+    /// Normalizes the axis so the dot product of the axes of both objects is
+    /// the cosine of the angle between them. A zero axis is replaced by the
+    /// Z-axis.
+    pub fn normalize(&mut self) {
+        let norm = self.axis.iter().map(|a| a * a).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            for a in self.axis.iter_mut() {
+                *a /= norm;
+            }
+        } else {
+            self.axis = [0.0, 0.0, 1.0];
+        }
+    }
+
+    /// Evaluates the residual of the constraint: the difference between the
+    /// angle of the axes of both objects and the target angle
+    pub fn residuals(&self, placements: &[Placement]) -> Vec<HDual> {
+        let mut target = HDual::new();
+        target.re = self.angle;
+        vec![self.eval_angle(placements) - target]
+    }
+
+    /// Evaluates the angle between the axes of both objects
+    pub fn eval_angle(&self, placements: &[Placement]) -> HDual {
+        let axis = constant_vector(self.axis[0], self.axis[1], self.axis[2]);
+        let obj_axis = placements[0].direction(&axis);
+        let ref_axis = placements[1].direction(&axis);
+        acos(dot(&obj_axis, &ref_axis))
+    }
+}
+
+
+/// Keeps a fixed angle between the axes of two objects
+///
+/// The axis is given by its direction in the local coordinate system of the
+/// objects (the Z-axis by default), and both objects use the same direction.
+/// The residual of the constraint is acos(a . ra) - angle, where a and ra are
+/// the axis of the object and the reference in the global coordinate system.
+///
+/// The derivatives of acos are not defined when the axes are parallel (an
+/// angle of 0 or pi), so angles close to these values converge slowly; a
+/// FixRotation constraint is better for them.
+#[derive(Debug, Clone)]
+pub struct FixedAngleConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// axis of the objects and target angle
+    parameters: FixedAngleParameters,
+}
+
+
+impl Constraint for FixedAngleConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let parameters = &self.parameters;
+        self.function.evaluate(sys_objects, |placements| parameters.residuals(placements));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let parameters = &self.parameters;
+        self.function.add_residuals(residuals, sys_objects, |placements| parameters.residuals(placements));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Angle
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let parameters = &self.parameters;
+        let error = self.function.residual_values(sys_objects, |placements| parameters.residuals(placements));
+        vec![("angle", error[0], "angle")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.parameters.angle]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.parameters.angle = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let placements: Vec<Placement> = self.function
+            .objects()
+            .iter()
+            .map(|obj_idx| Placement::current(&sys_objects[*obj_idx]))
+            .collect();
+        vec![self.parameters.eval_angle(&placements).re]
+    }
+}
+
+
+impl FixedAngleConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> FixedAngleConstraint {
+        // The axes only depend on the rotations of the objects
+        system_objects[obj_index].enable_variables(&["phi", "theta", "psi"]);
+        system_objects[ref_index].enable_variables(&["phi", "theta", "psi"]);
+
+        // The missing axis components are taken from the Z-axis and a missing
+        // angle is set to a value of 0
+        let mut parameters = FixedAngleParameters::new();
+        for variable in ["x", "y", "z", "angle"].iter() {
+            if let Some(value) = constraint_parameters.get(variable) {
+                parameters.set_parameter(variable, *value);
+            }
+        }
+        parameters.normalize();
+
+        FixedAngleConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_rotation_iter().collect()),
+            parameters,
+        }
+    }
+}
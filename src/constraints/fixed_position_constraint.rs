@@ -0,0 +1,179 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::constant_vector;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Fixes the origin of an object at a position in the global coordinate
+/// system, without a reference object
+///
+/// The residuals of the constraint are the components of p - target, where p
+/// is the position vector of the object, so only the position variables of
+/// the object are used and the hessian is a 3x3 block. The object is still
+/// free to rotate about its origin.
+#[derive(Debug, Clone)]
+pub struct FixedPositionConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// position of the origin of the object in the global coordinate system
+    target: [f64; 3],
+}
+
+
+impl Constraint for FixedPositionConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let target = &self.target;
+        self.function.evaluate(sys_objects, |placements| residuals(target, placements));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let target = &self.target;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(target, placements));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let target = &self.target;
+        let offset = self.function.residual_values(sys_objects, |placements| residuals(target, placements));
+        vec![
+            ("x", offset[0], "length"),
+            ("y", offset[1], "length"),
+            ("z", offset[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.target.to_vec()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.target.copy_from_slice(&parameters[0..3]);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The target is moved to the current position of the object
+        let object = &sys_objects[self.function.objects()[0]];
+        VN::get_position_iter().map(|var_name| object.get_variable(var_name).value).collect()
+    }
+}
+
+
+impl FixedPositionConstraint {
+    /// Creates the constraint of the object with the given index. The missing
+    /// coordinates "x", "y" and "z" of the target are set to a value of 0.
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+    ) -> FixedPositionConstraint {
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+
+        let mut target = [0.0; 3];
+        for (coordinate, var_name) in target.iter_mut().zip(VN::get_position_iter()) {
+            if let Some(value) = constraint_parameters.get(var_name.as_str()) {
+                *coordinate = *value;
+            }
+        }
+
+        FixedPositionConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index], VN::get_position_iter().collect()),
+            target,
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the components of the offset of
+/// the origin of the object from the target
+fn residuals(target: &[f64; 3], placements: &[Placement]) -> Vec<HDual> {
+    let offset = placements[0].position - constant_vector(target[0], target[1], target[2]);
+    vec![offset.x, offset.y, offset.z]
+}
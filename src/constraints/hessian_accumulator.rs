@@ -0,0 +1,136 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+
+/// A matrix where the constraints add their contributions to the hessian of the
+/// system (see Constraint::get_hessian).
+///
+/// It is implemented by the dense Array2<f64> used by the minimizers and by
+/// SparseHessian, so constraints work the same way with both of them.
+pub trait HessianAccumulator {
+    /// Adds value to the entry of the matrix at (row, col)
+    fn add(&mut self, row: usize, col: usize, value: f64);
+
+    /// Returns the entry of the matrix at (row, col)
+    fn get(&self, row: usize, col: usize) -> f64;
+}
+
+
+impl HessianAccumulator for Array2<f64> {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        self[[row, col]] += value;
+    }
+
+    fn get(&self, row: usize, col: usize) -> f64 {
+        self[[row, col]]
+    }
+}
+
+
+/// A square matrix that only stores its nonzero entries.
+///
+/// Each constraint only uses the variables of a few objects, so most entries of
+/// the hessian of a system with many objects are zero. A sparse hessian uses
+/// memory proportional to the number of variables used together by some
+/// constraint instead of the square of the number of variables.
+///
+/// The entries are stored as (row, column, value) triplets in the order they
+/// are added, and an entry added more than once is stored once for each time
+/// it was added (the value of the entry is the sum of the values of its
+/// triplets). The diagonal is stored apart from the triplets so its entries can
+/// be read quickly.
+#[derive(Debug, Clone)]
+pub struct SparseHessian {
+    /// entries of the diagonal of the matrix
+    diagonal: Vec<f64>,
+    /// entries outside the diagonal as (row, column, value)
+    triplets: Vec<(usize, usize, f64)>,
+}
+
+
+impl HessianAccumulator for SparseHessian {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        if row == col {
+            self.diagonal[row] += value;
+        } else {
+            debug_assert!(row < self.size() && col < self.size());
+            self.triplets.push((row, col, value));
+        }
+    }
+
+    fn get(&self, row: usize, col: usize) -> f64 {
+        if row == col {
+            return self.diagonal[row];
+        }
+        self.triplets
+            .iter()
+            .filter(|(i, j, _)| *i == row && *j == col)
+            .map(|(_, _, value)| value)
+            .sum()
+    }
+}
+
+
+impl SparseHessian {
+    /// Creates a size x size matrix of zeros
+    pub fn new(size: usize) -> SparseHessian {
+        SparseHessian {
+            diagonal: vec![0.0; size],
+            triplets: Vec::new(),
+        }
+    }
+
+    /// Returns the number of rows (and columns) of the matrix
+    pub fn size(&self) -> usize {
+        self.diagonal.len()
+    }
+
+    /// Returns the number of stored entries of the matrix (the diagonal and
+    /// the triplets)
+    pub fn stored_entries(&self) -> usize {
+        self.diagonal.len() + self.triplets.len()
+    }
+
+    /// Returns the product of the matrix and the vector v
+    pub fn mul_vec(&self, v: &Array1<f64>) -> Array1<f64> {
+        let mut output: Array1<f64> = self.diagonal.iter().zip(v).map(|(d, x)| d * x).collect();
+        for (row, col, value) in &self.triplets {
+            output[*row] += value * v[*col];
+        }
+        output
+    }
+
+    /// Adds every entry of the matrix to output, a matrix of the same size
+    pub fn add_to(&self, output: &mut dyn HessianAccumulator) {
+        for (i, value) in self.diagonal.iter().enumerate() {
+            output.add(i, i, *value);
+        }
+        for (row, col, value) in &self.triplets {
+            output.add(*row, *col, *value);
+        }
+    }
+
+    /// Returns the matrix as a dense matrix
+    pub fn to_dense(&self) -> Array2<f64> {
+        let mut output = Array2::from_diag(&Array1::from(self.diagonal.clone()));
+        for (row, col, value) in &self.triplets {
+            output[[*row, *col]] += value;
+        }
+        output
+    }
+}
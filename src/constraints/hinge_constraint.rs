@@ -0,0 +1,180 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{cross, unit_axis};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Mates two objects with a hinge (revolute joint) about their local Z-axes
+///
+/// The origins of both objects are coincident and their Z-axes are parallel,
+/// so the only relative motion left is the rotation about the shared axis. The
+/// first three residuals are the components of p1 - p2 and the last three the
+/// components of z1 x z2, where z1 and z2 are the Z-axes in the global
+/// coordinate system.
+///
+/// Both parts are evaluated by the same function, so the 12 variables of the
+/// objects share one hessian block instead of the two blocks of a Coincident
+/// and a Parallel constraint.
+#[derive(Debug, Clone)]
+pub struct HingeConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+}
+
+
+impl Constraint for HingeConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.function.evaluate(sys_objects, residuals);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_residuals(residuals_vec, sys_objects, residuals);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let error = self.function.residual_values(sys_objects, residuals);
+        let sine = (error[3].powi(2) + error[4].powi(2) + error[5].powi(2)).sqrt().min(1.0);
+        vec![
+            ("x", error[0], "length"),
+            ("y", error[1], "length"),
+            ("z", error[2], "length"),
+            ("angle", sine.asin(), "angle"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl HingeConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> HingeConstraint {
+        // The origins depend on the positions and the axes on the rotations,
+        // so every variable of both objects is enabled
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj1_index].enable_variables(&all_variables);
+        system_objects[obj2_index].enable_variables(&all_variables);
+
+        HingeConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_variable_iter().collect()),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the offset between both origins
+/// and the cross product of both Z-axes
+fn residuals(placements: &[Placement]) -> Vec<HDual> {
+    let offset = placements[0].position - placements[1].position;
+    let axis1 = placements[0].direction(&unit_axis(2));
+    let axis2 = placements[1].direction(&unit_axis(2));
+    let parallel = cross(&axis1, &axis2);
+    vec![
+        offset.x,
+        offset.y,
+        offset.z,
+        parallel.x,
+        parallel.y,
+        parallel.z,
+    ]
+}
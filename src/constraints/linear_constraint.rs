@@ -0,0 +1,273 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use crate::system::Variable;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
+
+/// Keeps a linear combination of variables of several objects at a constant
+/// value: sum_i c_i * v_i = rhs
+///
+/// It generalizes couplings like rack and pinion, where a translation is
+/// proportional to a rotation. The residual of the constraint is
+/// sum_i c_i * v_i - rhs, which is linear in the variables: the gradient of its
+/// square is 2 * residual * c_i and the hessian is the constant matrix
+/// 2 * c_i * c_j, which is found once when the constraint is created.
+///
+/// NOTE: the local variables used in this constraint are the variables of the
+/// terms, in the order of the terms.
+#[derive(Debug, Clone)]
+pub struct LinearConstraint {
+    /// name of the constraint
+    name: String,
+    /// value of the residual
+    residual: f64,
+    /// terms of the linear combination as (index of the object in the vector
+    /// of system objects, variable, coefficient)
+    terms: Vec<(usize, VN, f64)>,
+    /// value of the linear combination
+    rhs: f64,
+    /// hessian of the square of the residual with respect to the local
+    /// variables
+    hess: Vec<Vec<f64>>,
+}
+
+
+impl Constraint for LinearConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.residual = self.residual_value(sys_objects);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        for (var, (_, _, coefficient)) in self.local_variables(sys_objects).iter().zip(&self.terms) {
+            if let Some(k) = var.index {
+                system_grad[k] += 2.0 * self.residual * var.scale * coefficient;
+            }
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let mut diff = 0.0;
+        for (var, (_, _, coefficient)) in self.local_variables(sys_objects).iter().zip(&self.terms) {
+            if let Some(k) = var.index {
+                diff += 2.0 * self.residual * var.scale * coefficient * direction[k];
+            }
+        }
+        diff
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        let variables = self.local_variables(sys_objects);
+        for (i, variable1) in variables.iter().enumerate() {
+            if let Some(k) = variable1.index {
+                for (j, variable2) in variables.iter().enumerate() {
+                    if let Some(l) = variable2.index {
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i][j]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        // The residuals are used without evaluating the constraint first
+        let mut residual = Residual {
+            value: self.residual_value(sys_objects),
+            jacobian: Vec::new(),
+        };
+        for (var, (_, _, coefficient)) in self.local_variables(sys_objects).iter().zip(&self.terms) {
+            if let Some(k) = var.index {
+                residual.jacobian.push((k, var.scale * coefficient));
+            }
+        }
+        residuals.push(residual);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        // A combination of only angles is an angle, anything else is taken as
+        // a length
+        if self.terms.iter().all(|(_, var_name, _)| var_name.is_rotation()) {
+            ResidualKind::Angle
+        } else {
+            ResidualKind::Length
+        }
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        let mut objects = Vec::new();
+        for (obj_idx, _, _) in &self.terms {
+            if !objects.contains(obj_idx) {
+                objects.push(*obj_idx);
+            }
+        }
+        objects
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let unit = match self.get_kind() {
+            ResidualKind::Angle => "angle",
+            ResidualKind::Length => "length",
+        };
+        vec![("combination", self.residual_value(sys_objects), unit)]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.affected_objects()
+            .into_iter()
+            .map(|obj_idx| (obj_idx, Footprint::Variables))
+            .collect()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.terms.iter().map(|(obj_idx, var_name, _)| (*obj_idx, *var_name)).collect()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        for (obj_idx, _, _) in self.terms.iter_mut() {
+            *obj_idx = object_map[obj_idx];
+        }
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        let mut parameters: Vec<f64> = self.terms.iter().map(|(_, _, coefficient)| *coefficient).collect();
+        parameters.push(self.rhs);
+        parameters
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        for (term, coefficient) in self.terms.iter_mut().zip(parameters) {
+            term.2 = *coefficient;
+        }
+        self.rhs = parameters[self.terms.len()];
+        self.hess = hessian(&self.terms);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The coefficients are kept and the value of the combination is changed
+        let mut parameters = self.get_parameters();
+        let n = self.terms.len();
+        parameters[n] = self.residual_value(sys_objects) + self.rhs;
+        parameters
+    }
+
+    fn drive(&self, sys_objects: &mut [SystemObject]) {
+        // When every variable but one is locked, the free one is solved for
+        let variables: Vec<Variable> = self.local_variables(sys_objects).into_iter().cloned().collect();
+        let mut free = variables.iter().enumerate().filter(|(_, var)| !var.locked);
+        let (i, var) = match (free.next(), free.next()) {
+            (Some(free_var), None) => free_var,
+            _ => return,
+        };
+        let (obj_idx, var_name, coefficient) = self.terms[i];
+        if var.equal.is_some() || coefficient == 0.0 {
+            return;
+        }
+        let others: f64 = variables
+            .iter()
+            .zip(&self.terms)
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, (var, (_, _, c)))| c * var.value)
+            .sum();
+        sys_objects[obj_idx].get_mut_variable(var_name).value = (self.rhs - others) / coefficient;
+    }
+}
+
+
+impl LinearConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        terms: Vec<(usize, VN, f64)>,
+        rhs: f64,
+    ) -> LinearConstraint {
+        for (obj_idx, var_name, _) in &terms {
+            system_objects[*obj_idx].enable_variables(&[var_name.as_str()]);
+        }
+
+        LinearConstraint {
+            name: name.to_string(),
+            residual: 0.0,
+            hess: hessian(&terms),
+            terms,
+            rhs,
+        }
+    }
+
+    /// Returns the variables used by this constraint in the order of the local
+    /// variables
+    fn local_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        self.terms
+            .iter()
+            .map(|(obj_idx, var_name, _)| sys_objects[*obj_idx].get_variable(*var_name))
+            .collect()
+    }
+
+    /// Returns the value of the residual at the current values of the variables
+    fn residual_value(&self, sys_objects: &[SystemObject]) -> f64 {
+        let combination: f64 = self.terms
+            .iter()
+            .map(|(obj_idx, var_name, coefficient)| coefficient * sys_objects[*obj_idx].get_variable(*var_name).value)
+            .sum();
+        combination - self.rhs
+    }
+}
+
+
+/// Returns the hessian of the square of the residual with respect to the local
+/// variables: 2 * c_i * c_j
+fn hessian(terms: &[(usize, VN, f64)]) -> Vec<Vec<f64>> {
+    terms
+        .iter()
+        .map(|(_, _, c_i)| terms.iter().map(|(_, _, c_j)| 2.0 * c_i * c_j).collect())
+        .collect()
+}
@@ -15,31 +15,267 @@
 
 use std::collections::HashMap;
 
+use ndarray::Array1;
+
+use crate::geometry::euler;
+use crate::system::Variable;
 use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
+
+/// The norm of a rotation quaternion given to a lock may differ from 1 by at
+/// most this value
+const QUATERNION_NORM_TOLERANCE: f64 = 1e-6;
+
 
 /// This function adds the lock constraints to the variables being locked.
+///
+/// The rotation can also be given as a unit quaternion with the "q0", "q1",
+/// "q2" and "q3" parameters. It is converted into the Euler angles used by the
+/// solver and the three rotation variables are locked (the quaternion takes
+/// precedence over the "phi", "theta" and "psi" parameters).
+///
+/// Returns the locked variables, or an error when the quaternion is incomplete
+/// or not normalized.
 pub fn set_up_locks(
         c_params: &HashMap<&str, f64>,
         sys_object: &mut SystemObject,
-) {
-    let mut locked_variables: Vec<&str> = Vec::new();
-    let var_names_str = ["x", "y", "z", "phi", "theta", "psi"];
-
-    for (variable_str, variable_name) in var_names_str.iter().zip(VN::get_variable_iter()) {
-        match c_params.get(variable_str) {
-            Some(value) => {
-                locked_variables.push(variable_str);
-                sys_object.get_mut_variable(variable_name).value = *value;
-            },
-            None => ()
-        }
+) -> Result<Vec<VN>, String> {
+    let targets = lock_targets(c_params)?;
+    let locked_variables: Vec<&str> = targets.iter().map(|(var_name, _)| var_name.as_str()).collect();
+    for (var_name, value) in &targets {
+        sys_object.get_mut_variable(*var_name).value = *value;
     }
     sys_object.lock_variables(&locked_variables);
     sys_object.enable_variables(&locked_variables);
+    Ok(targets.into_iter().map(|(var_name, _)| var_name).collect())
+}
+
+
+/// Returns the locked variables with their values from the parameters of a
+/// lock, in the order of VN::get_variable_iter
+pub fn lock_targets(c_params: &HashMap<&str, f64>) -> Result<Vec<(VN, f64)>, String> {
+    let rotation = quaternion_angles(c_params)?;
+    let mut targets = Vec::new();
+    for variable_name in VN::get_variable_iter() {
+        let value = match (rotation, variable_name) {
+            (Some((phi, _, _)), VN::phi) => Some(phi),
+            (Some((_, theta, _)), VN::theta) => Some(theta),
+            (Some((_, _, psi)), VN::psi) => Some(psi),
+            _ => c_params.get(variable_name.as_str()).copied(),
+        };
+        if let Some(value) = value {
+            targets.push((variable_name, value));
+        }
+    }
+    Ok(targets)
+}
+
+
+/// Returns the Euler angles of the rotation quaternion given by the "q0",
+/// "q1", "q2" and "q3" parameters, or None when the parameters do not have a
+/// quaternion
+fn quaternion_angles(c_params: &HashMap<&str, f64>) -> Result<Option<(f64, f64, f64)>, String> {
+    let keys = ["q0", "q1", "q2", "q3"];
+    if keys.iter().all(|key| !c_params.contains_key(key)) {
+        return Ok(None);
+    }
+    let mut q = [0.0; 4];
+    for (component, key) in q.iter_mut().zip(keys.iter()) {
+        *component = *c_params
+            .get(key)
+            .ok_or_else(|| format!("the rotation quaternion is missing '{}'", key))?;
+    }
+    let norm = q.iter().map(|component| component * component).sum::<f64>().sqrt();
+    if norm.is_nan() || (norm - 1.0).abs() > QUATERNION_NORM_TOLERANCE {
+        return Err(format!("the rotation quaternion is not normalized (its norm is {})", norm));
+    }
+    Ok(Some(euler::quaternion_angles(q[0], q[1], q[2], q[3])))
+}
+
+
+/// A soft lock: the locked variables of an object are pulled to their target
+/// values by the penalty weight * sum_i (x_i - target_i)^2
+///
+/// Unlike the locks made by set_up_locks the variables are still solver
+/// variables, so a large weight behaves like a lock while keeping the system
+/// solvable when the lock conflicts with other constraints.
+#[derive(Debug, Clone)]
+pub struct LockConstraint {
+    /// name of the constraint
+    name: String,
+    /// locked variables with their target values
+    targets: Vec<(VN, f64)>,
+    /// weight of the penalty
+    weight: f64,
+    /// values of x_i - target_i
+    residuals: Vec<f64>,
+    /// Index of the object in the vector of system objects
+    o_index: usize,
+}
+
+
+impl Constraint for LockConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.residuals = self.residual_values(sys_objects);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.weight * self.residuals.iter().map(|r| r.powi(2)).sum::<f64>()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        for (variable, residual) in self.locked_variables(sys_objects).iter().zip(&self.residuals) {
+            if let Some(k) = variable.index {
+                system_grad[k] += 2.0 * self.weight * residual * variable.scale;
+            }
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let mut diff = 0.0;
+        for (variable, residual) in self.locked_variables(sys_objects).iter().zip(&self.residuals) {
+            if let Some(k) = variable.index {
+                diff += 2.0 * self.weight * residual * variable.scale * direction[k];
+            }
+        }
+        diff
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        // The hessian block of the locked variables is 2 * weight * identity
+        // (scaled by the square of the scale of each variable)
+        for variable in self.locked_variables(sys_objects) {
+            if let Some(k) = variable.index {
+                system_hess.add(k, k, 2.0 * self.weight * variable.scale.powi(2));
+            }
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        // The residuals are used without evaluating the constraint first
+        let scale = self.weight.sqrt();
+        let variables = self.locked_variables(sys_objects);
+        for (variable, residual) in variables.iter().zip(self.residual_values(sys_objects)) {
+            residuals.push(Residual {
+                value: scale * residual,
+                jacobian: variable.index.map(|k| (k, scale * variable.scale)).into_iter().collect(),
+            });
+        }
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.o_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        self.targets
+            .iter()
+            .zip(self.residual_values(sys_objects))
+            .map(|((var_name, _), residual)| {
+                let unit = match var_name {
+                    VN::x | VN::y | VN::z => "length",
+                    _ => "angle",
+                };
+                (var_name.as_str(), residual, unit)
+            })
+            .collect()
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.o_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.targets.iter().map(|(var_name, _)| (self.o_index, *var_name)).collect()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.o_index = object_map[&self.o_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.targets.iter().map(|(_, target)| *target).collect()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        for ((_, target), value) in self.targets.iter_mut().zip(parameters) {
+            *target = *value;
+        }
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        self.locked_variables(sys_objects).iter().map(|variable| variable.value).collect()
+    }
+}
 
-    // WARNING: we are enabling both the rotation quaternion and position vector
-    // of the object, in some cases we should not enable them (it may slow things down
-    // by making unnecessary updates to the quaternion and the vector)
-    sys_object.q_enable = true;
-    sys_object.v_enable = true;
+
+impl LockConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        targets: Vec<(VN, f64)>,
+        weight: f64,
+        o_index: usize,
+    ) -> LockConstraint {
+        let locked_variables: Vec<&str> = targets.iter().map(|(var_name, _)| var_name.as_str()).collect();
+        system_objects[o_index].enable_variables(&locked_variables);
+
+        LockConstraint {
+            name: name.to_string(),
+            residuals: vec![0.0; targets.len()],
+            targets,
+            weight,
+            o_index,
+        }
+    }
+
+    /// Returns the locked variables in the order of the targets
+    fn locked_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        self.targets
+            .iter()
+            .map(|(var_name, _)| sys_objects[self.o_index].get_variable(*var_name))
+            .collect()
+    }
+
+    /// Returns the values of x_i - target_i at the current values of the
+    /// variables
+    fn residual_values(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        self.locked_variables(sys_objects)
+            .iter()
+            .zip(&self.targets)
+            .map(|(variable, (_, target))| variable.value - target)
+            .collect()
+    }
 }
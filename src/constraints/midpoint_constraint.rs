@@ -0,0 +1,194 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Places the origin of an object at the midpoint of the origins of two
+/// reference objects
+///
+/// The point can also be at any fraction "t" of the segment from the first
+/// reference (t = 0) to the second one (t = 1), 0.5 by default. The residuals
+/// are the components of p - (1 - t) * r1 - t * r2, so only the position
+/// variables of the three objects are used.
+#[derive(Debug, Clone)]
+pub struct MidpointConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// fraction of the segment between the references
+    t: f64,
+}
+
+
+impl Constraint for MidpointConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let t = self.t;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, t));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let t = self.t;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, t));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let t = self.t;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, t));
+        vec![
+            ("x", error[0], "length"),
+            ("y", error[1], "length"),
+            ("z", error[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.t]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.t = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The fraction of the projection of the object on the segment. It is
+        // not changed when the references are at the same position.
+        let objects = self.function.objects();
+        let object = Placement::current(&sys_objects[objects[0]]).position;
+        let reference1 = Placement::current(&sys_objects[objects[1]]).position;
+        let reference2 = Placement::current(&sys_objects[objects[2]]).position;
+        let offset = object - reference1;
+        let segment = reference2 - reference1;
+        let length2 = segment.x.re.powi(2) + segment.y.re.powi(2) + segment.z.re.powi(2);
+        if length2 == 0.0 {
+            return vec![self.t];
+        }
+        let projection = offset.x.re * segment.x.re + offset.y.re * segment.y.re + offset.z.re * segment.z.re;
+        vec![projection / length2]
+    }
+}
+
+
+impl MidpointConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref1_index: usize,
+        ref2_index: usize,
+    ) -> MidpointConstraint {
+        for index in &[obj_index, ref1_index, ref2_index] {
+            system_objects[*index].enable_variables(&["x", "y", "z"]);
+        }
+
+        MidpointConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(
+                vec![obj_index, ref1_index, ref2_index],
+                VN::get_position_iter().collect(),
+            ),
+            t: constraint_parameters.get("t").copied().unwrap_or(0.5),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the offset of the origin of the
+/// object from the point p - r1 - t * (r2 - r1) of the segment
+fn residuals(placements: &[Placement], t: f64) -> Vec<HDual> {
+    let mut fraction = HDual::new();
+    fraction.re = t;
+    let offset = placements[0].position - placements[1].position;
+    let segment = placements[2].position - placements[1].position;
+    vec![
+        offset.x - fraction * segment.x,
+        offset.y - fraction * segment.y,
+        offset.z - fraction * segment.z,
+    ]
+}
@@ -0,0 +1,167 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{dot, unit_axis};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::parallel_constraint::axis_parameters;
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes a local axis of an object perpendicular to a local axis of another
+/// object
+///
+/// The axes are selected like in ParallelConstraint. The residual of the
+/// constraint is a1 . a2, where a1 and a2 are the axes in the global
+/// coordinate system.
+#[derive(Debug, Clone)]
+pub struct PerpendicularConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// indices of the local axes of both objects
+    axes: [usize; 2],
+}
+
+
+impl Constraint for PerpendicularConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let axes = self.axes;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, &axes));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let axes = self.axes;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, &axes));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Angle
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let axes = self.axes;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, &axes));
+        // the cosine of the angle is the sine of its difference from pi/2
+        vec![("angle", error[0].clamp(-1.0, 1.0).asin(), "angle")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl PerpendicularConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> PerpendicularConstraint {
+        // The axes only depend on the rotations of the objects
+        system_objects[obj1_index].enable_variables(&["phi", "theta", "psi"]);
+        system_objects[obj2_index].enable_variables(&["phi", "theta", "psi"]);
+
+        PerpendicularConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_rotation_iter().collect()),
+            axes: axis_parameters(constraint_parameters),
+        }
+    }
+}
+
+
+
+/// Evaluates the residual of the constraint: the dot product of both axes
+fn residuals(placements: &[Placement], axes: &[usize; 2]) -> Vec<HDual> {
+    let axis1 = placements[0].direction(&unit_axis(axes[0]));
+    let axis2 = placements[1].direction(&unit_axis(axes[1]));
+    vec![dot(&axis1, &axis2)]
+}
@@ -0,0 +1,196 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{constant_vector, dot};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Keeps the local XY-plane of an object coincident with the local XY-plane of
+/// a reference object
+///
+/// The planes are parallel when the normal of the object (its local Z-axis in
+/// the global coordinate system) is perpendicular to the local X and Y axes of
+/// the reference, so the first two residuals are the dot products of the
+/// normal with these axes. The third residual is the distance of the origin of
+/// the object to the plane of the reference minus the offset: the dot product
+/// of p - rp with the normal of the reference minus offset, where p and rp are
+/// the position vectors of the object and the reference. The offset is signed
+/// and measured along the normal of the reference.
+///
+/// Both normals may point in opposite directions (as two faces in contact do).
+#[derive(Debug, Clone)]
+pub struct PlaneConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// distance between both planes along the normal of the reference
+    offset: f64,
+}
+
+
+impl Constraint for PlaneConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let offset = self.offset;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, offset));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let offset = self.offset;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, offset));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        // The residuals of the normals are sines of angles, but the distance
+        // to the plane is a length
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let offset = self.offset;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, offset));
+        vec![
+            ("normal_x", error[0].asin(), "angle"),
+            ("normal_y", error[1].asin(), "angle"),
+            ("distance", error[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.offset]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.offset = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // the offset that makes the last residual zero
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, 0.0));
+        vec![error[2]]
+    }
+}
+
+
+impl PlaneConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> PlaneConstraint {
+        // The normals depend on the rotations and the distance to the plane on
+        // the positions, so every variable of both objects is enabled
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj_index].enable_variables(&all_variables);
+        system_objects[ref_index].enable_variables(&all_variables);
+
+        PlaneConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_variable_iter().collect()),
+            // A missing offset is set to a value of 0
+            offset: constraint_parameters.get("offset").copied().unwrap_or(0.0),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the components of the normal of
+/// the object along the X and Y axes of the reference and the distance of the
+/// object to the plane of the reference minus the offset
+fn residuals(placements: &[Placement], offset: f64) -> Vec<HDual> {
+    let object = &placements[0];
+    let reference = &placements[1];
+    let normal = object.direction(&constant_vector(0.0, 0.0, 1.0));
+    let ref_x = reference.direction(&constant_vector(1.0, 0.0, 0.0));
+    let ref_y = reference.direction(&constant_vector(0.0, 1.0, 0.0));
+    let ref_normal = reference.direction(&constant_vector(0.0, 0.0, 1.0));
+    let mut offset_value = HDual::new();
+    offset_value.re = offset;
+    vec![
+        dot(&normal, &ref_x),
+        dot(&normal, &ref_y),
+        dot(&(object.position - reference.position), &ref_normal) - offset_value,
+    ]
+}
@@ -0,0 +1,180 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{cross, dot, unit_axis};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes the local XY planes of two objects parallel at any distance
+///
+/// Only the normals of the planes (the local Z-axes n1 and n2) are compared,
+/// so the constraint uses the rotation variables of both objects and none of
+/// their position variables. The residuals are the components of n1 x n2 and
+/// 1 - s * n1 · n2, where s is 1 when the normals point in the same direction
+/// and -1 when the "flip" parameter is not 0 (anti-parallel normals). The sum
+/// of their squares is |n1 - s * n2|^2.
+#[derive(Debug, Clone)]
+pub struct PlaneParallelConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// 1 for parallel normals and -1 for anti-parallel normals
+    sign: f64,
+}
+
+
+impl Constraint for PlaneParallelConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let sign = self.sign;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, sign));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let sign = self.sign;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, sign));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Angle
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let sign = self.sign;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, sign));
+        vec![
+            ("normal x", error[0], "angle"),
+            ("normal y", error[1], "angle"),
+            ("normal z", error[2], "angle"),
+            ("direction", error[3], "angle"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl PlaneParallelConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> PlaneParallelConstraint {
+        // The normals only depend on the rotations of the objects, so the
+        // position vectors are never updated for this constraint
+        system_objects[obj1_index].enable_variables(&["phi", "theta", "psi"]);
+        system_objects[obj2_index].enable_variables(&["phi", "theta", "psi"]);
+
+        let flip = constraint_parameters.get("flip").is_some_and(|flip| *flip != 0.0);
+
+        PlaneParallelConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_rotation_iter().collect()),
+            sign: if flip { -1.0 } else { 1.0 },
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the cross product of the normals
+/// and the difference of their dot product from the sign
+fn residuals(placements: &[Placement], sign: f64) -> Vec<HDual> {
+    let normal1 = placements[0].direction(&unit_axis(2));
+    let normal2 = placements[1].direction(&unit_axis(2));
+    let parallel = cross(&normal1, &normal2);
+    let mut one = HDual::new();
+    one.re = 1.0;
+    let mut s = HDual::new();
+    s.re = sign;
+    vec![parallel.x, parallel.y, parallel.z, one - s * dot(&normal1, &normal2)]
+}
@@ -0,0 +1,232 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::constant_vector;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{attachment_offset, Placement, ResidualFunction};
+
+
+/// The positions of the attachment points in the local coordinate systems of
+/// their objects
+#[derive(Debug, Clone)]
+struct PointCoincidentParameters {
+    pub point1: [f64; 3],
+    pub point2: [f64; 3],
+}
+
+impl PointCoincidentParameters {
+    pub fn new() -> PointCoincidentParameters {
+        PointCoincidentParameters {
+            point1: [0.0; 3],
+            point2: [0.0; 3],
+        }
+    }
+
+    /// Adds value to the parameters
+    pub fn set_parameter(&mut self, variable: &str, value: f64) {
+        match variable {
+            "x1" => self.point1[0] = value,
+            "y1" => self.point1[1] = value,
+            "z1" => self.point1[2] = value,
+            "x2" => self.point2[0] = value,
+            "y2" => self.point2[1] = value,
+            "z2" => self.point2[2] = value,
+            _ => ()
+        }
+    }
+
+    /// Evaluates the residuals of the constraint: the components of the
+    /// distance vector between both attachment points
+    pub fn residuals(&self, placements: &[Placement]) -> Vec<HDual> {
+        let p1 = placements[0].point(&constant_vector(self.point1[0], self.point1[1], self.point1[2]));
+        let p2 = placements[1].point(&constant_vector(self.point2[0], self.point2[1], self.point2[2]));
+        let distance = p1 - p2;
+        vec![distance.x, distance.y, distance.z]
+    }
+}
+
+
+/// Makes an attachment point of one object occupy the same position as an
+/// attachment point of another object
+///
+/// The points are given in the local coordinate systems of their objects, so
+/// their global positions are p + q * point * q^-1 where p and q are the
+/// position vector and rotation quaternion of the object. The residuals of the
+/// constraint are the components of the difference of both global positions.
+#[derive(Debug, Clone)]
+pub struct PointCoincidentConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// attachment points of both objects
+    parameters: PointCoincidentParameters,
+}
+
+
+impl Constraint for PointCoincidentConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let parameters = &self.parameters;
+        self.function.evaluate(sys_objects, |placements| parameters.residuals(placements));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let parameters = &self.parameters;
+        self.function.add_residuals(residuals, sys_objects, |placements| parameters.residuals(placements));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let parameters = &self.parameters;
+        let distance = self.function.residual_values(sys_objects, |placements| parameters.residuals(placements));
+        vec![
+            ("x", distance[0], "length"),
+            ("y", distance[1], "length"),
+            ("z", distance[2], "length"),
+        ]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.parameters.point1.iter().chain(self.parameters.point2.iter()).cloned().collect()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.parameters.point1.copy_from_slice(&parameters[0..3]);
+        self.parameters.point2.copy_from_slice(&parameters[3..6]);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The point of the first object is kept and the point of the second
+        // object is moved to its position
+        let objects = self.function.objects();
+        let placement1 = Placement::current(&sys_objects[objects[0]]);
+        let placement2 = Placement::current(&sys_objects[objects[1]]);
+        let point1 = self.parameters.point1;
+        let global = placement1.point(&constant_vector(point1[0], point1[1], point1[2]));
+        let point2 = placement2.local_point(&global);
+        vec![point1[0], point1[1], point1[2], point2.x.re, point2.y.re, point2.z.re]
+    }
+}
+
+
+impl PointCoincidentConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj1_index: usize,
+        obj2_index: usize,
+        attachment1: Option<&str>,
+        attachment2: Option<&str>,
+    ) -> PointCoincidentConstraint {
+        // The global positions of the points depend on every variable of both
+        // objects
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj1_index].enable_variables(&all_variables);
+        system_objects[obj2_index].enable_variables(&all_variables);
+
+        // The missing coordinates are set to a value of 0
+        let mut parameters = PointCoincidentParameters::new();
+        for variable in ["x1", "y1", "z1", "x2", "y2", "z2"].iter() {
+            if let Some(value) = constraint_parameters.get(variable) {
+                parameters.set_parameter(variable, *value);
+            }
+        }
+        // The coordinates are offsets from the named attachment points of the
+        // objects (their origins if not given)
+        let offset1 = attachment_offset(&system_objects[obj1_index], attachment1);
+        let offset2 = attachment_offset(&system_objects[obj2_index], attachment2);
+        for k in 0..3 {
+            parameters.point1[k] += offset1[k];
+            parameters.point2[k] += offset2[k];
+        }
+
+        PointCoincidentConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj1_index, obj2_index], VN::get_variable_iter().collect()),
+            parameters,
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{constant_vector, dot};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Keeps the origin of an object on a line through the origin of a reference
+/// object along one of the local axes of the reference
+///
+/// The component of p - rp perpendicular to the line (where p and rp are the
+/// position vectors of the object and the reference) is zero when p - rp has
+/// no component along the other two local axes of the reference, so the
+/// residuals are the dot products of p - rp with these axes. The sum of their
+/// squares is |(p - rp) - ((p - rp) . a) a|^2 where a is the direction of the
+/// line.
+///
+/// The object is free to rotate and to slide along the line, so only its
+/// position variables are used.
+#[derive(Debug, Clone)]
+pub struct PointOnLineConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// index (0 for x, 1 for y and 2 for z) of the local axis of the reference
+    /// along the line
+    axis: usize,
+}
+
+
+impl Constraint for PointOnLineConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let axis = self.axis;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, axis));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let axis = self.axis;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, axis));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let axis = self.axis;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, axis));
+        vec![("distance", (error[0].powi(2) + error[1].powi(2)).sqrt(), "length")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl PointOnLineConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> PointOnLineConstraint {
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z", "phi", "theta", "psi"]);
+
+        // The axis is selected by its name in the parameters (like the axes of
+        // a lock constraint), the Z-axis is used by default
+        let axis = ["x", "y", "z"]
+            .iter()
+            .position(|axis| constraint_parameters.contains_key(axis))
+            .unwrap_or(2);
+
+        PointOnLineConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::with_variables(
+                vec![obj_index, ref_index],
+                vec![VN::get_position_iter().collect(), VN::get_variable_iter().collect()],
+            ),
+            axis,
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the components of the offset of
+/// the object from the reference along the two local axes of the reference
+/// that are perpendicular to the line
+fn residuals(placements: &[Placement], axis: usize) -> Vec<HDual> {
+    let object = &placements[0];
+    let reference = &placements[1];
+    let offset = object.position - reference.position;
+    let unit_axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    unit_axes
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != axis)
+        .map(|(_, a)| dot(&offset, &reference.direction(&constant_vector(a[0], a[1], a[2]))))
+        .collect()
+}
@@ -0,0 +1,182 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{constant_vector, dot};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Keeps the origin of an object on one of the local planes (XY, XZ or YZ) of
+/// a reference object
+///
+/// The residual of the constraint is the distance of the origin of the object
+/// to the plane: (p - rp) . n, where p and rp are the position vectors of the
+/// object and the reference and n is the normal of the plane (the local axis of
+/// the reference that is not in the plane).
+///
+/// The object is free to rotate and to slide on the plane, so only its
+/// position variables are used.
+#[derive(Debug, Clone)]
+pub struct PointOnPlaneConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// normal of the plane in the local coordinate system of the reference
+    normal: [f64; 3],
+}
+
+
+impl Constraint for PointOnPlaneConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let normal = self.normal;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, &normal));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let normal = self.normal;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, &normal));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let normal = self.normal;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, &normal));
+        vec![("distance", error[0], "length")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl PointOnPlaneConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> PointOnPlaneConstraint {
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z", "phi", "theta", "psi"]);
+
+        // The plane is selected by its name in the parameters (like the axes of
+        // a lock constraint), the XY-plane is used by default
+        let normal = if constraint_parameters.contains_key("YZ") {
+            [1.0, 0.0, 0.0]
+        } else if constraint_parameters.contains_key("XZ") {
+            [0.0, 1.0, 0.0]
+        } else {
+            [0.0, 0.0, 1.0]
+        };
+
+        PointOnPlaneConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::with_variables(
+                vec![obj_index, ref_index],
+                vec![VN::get_position_iter().collect(), VN::get_variable_iter().collect()],
+            ),
+            normal,
+        }
+    }
+}
+
+
+/// Evaluates the residual of the constraint: the distance of the origin of the
+/// object to the plane of the reference
+fn residuals(placements: &[Placement], normal: &[f64; 3]) -> Vec<HDual> {
+    let object = &placements[0];
+    let reference = &placements[1];
+    let normal = reference.direction(&constant_vector(normal[0], normal[1], normal[2]));
+    vec![dot(&(object.position - reference.position), &normal)]
+}
@@ -0,0 +1,341 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::geometry::{HDQuaternion, HDVector};
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::constraints::{Footprint, HessianAccumulator, Residual};
+use crate::system::Variable;
+use crate::system_object::{SystemObject, VariableName as VN};
+
+
+/// The placement of an object with the partial derivatives with respect to
+/// (at most) two of the variables of the system, as passed to the residuals of
+/// a ResidualFunction
+#[derive(Debug, Copy, Clone)]
+pub struct Placement {
+    /// position vector of the object
+    pub position: HDVector,
+    /// rotation quaternion of the object
+    pub rotation: HDQuaternion,
+    /// inverse of the rotation quaternion of the object
+    pub inverse_rotation: HDQuaternion,
+}
+
+impl Placement {
+    /// Returns the placement of an object without partial derivatives
+    pub fn current(object: &SystemObject) -> Placement {
+        Placement {
+            position: object.get_vector(None, None),
+            rotation: object.get_quaternion(None, None),
+            inverse_rotation: object.get_inverse_quaternion(None, None),
+        }
+    }
+
+    /// Returns the position in the global coordinate system of a point given
+    /// by its local coordinates in the object
+    pub fn point(&self, local: &HDVector) -> HDVector {
+        self.position + self.rotation.mul_vec(local)
+    }
+
+    /// Returns the direction in the global coordinate system of a direction
+    /// given in the local coordinate system of the object
+    pub fn direction(&self, local: &HDVector) -> HDVector {
+        self.rotation.mul_vec(local)
+    }
+
+    /// Returns the local coordinates in the object of a point given in the
+    /// global coordinate system
+    pub fn local_point(&self, point: &HDVector) -> HDVector {
+        self.inverse_rotation.mul_vec(&(*point - self.position))
+    }
+}
+
+
+/// Returns the offset of a named attachment point of an object in its local
+/// coordinate system, or the origin if no attachment is given. The attachment
+/// must exist (see build_constraints).
+pub fn attachment_offset(object: &SystemObject, attachment: Option<&str>) -> [f64; 3] {
+    match attachment {
+        Some(name) => object
+            .get_attachment(name)
+            .unwrap_or_else(|| panic!("the object has no attachment point '{}'", name)),
+        None => [0.0; 3],
+    }
+}
+
+
+/// Value and derivatives of a constraint function that is the sum of the
+/// squares of some residuals of the placements of a few objects.
+///
+/// The residuals are given by a function of the placements of the objects, so
+/// a new constraint only needs to find its residuals; the gradient, hessian and
+/// jacobian are found here from the hyper-dual partial derivatives. Like in
+/// FixBaseConstraint, the function is evaluated as a function phi(y) of only
+/// the variables used by the residuals: the local variables are the used
+/// variables of the first object, then the ones of the second object and so
+/// on.
+#[derive(Debug, Clone)]
+pub struct ResidualFunction {
+    /// Indices of the objects in the vector of system objects
+    objects: Vec<usize>,
+    /// Local variables as pairs of (position of the object in objects,
+    /// variable name)
+    variables: Vec<(usize, VN)>,
+    /// value of phi(y)^2
+    value: f64,
+    /// gradient vector of phi(y)^2
+    grad: Vec<f64>,
+    /// hessian matrix of phi(y)^2
+    hess: Vec<Vec<f64>>,
+}
+
+
+impl ResidualFunction {
+    /// Creates the function of the residuals of objects that use the same
+    /// variables of every object
+    pub fn new(objects: Vec<usize>, variables: Vec<VN>) -> ResidualFunction {
+        let object_variables = objects.iter().map(|_| variables.clone()).collect();
+        ResidualFunction::with_variables(objects, object_variables)
+    }
+
+    /// Creates the function of the residuals of objects that use different
+    /// variables of each object. object_variables has the variables of each
+    /// object in the same order as objects.
+    pub fn with_variables(objects: Vec<usize>, object_variables: Vec<Vec<VN>>) -> ResidualFunction {
+        let variables: Vec<(usize, VN)> = object_variables
+            .into_iter()
+            .enumerate()
+            .flat_map(|(k, var_names)| var_names.into_iter().map(move |var_name| (k, var_name)))
+            .collect();
+        let n = variables.len();
+        ResidualFunction {
+            objects,
+            variables,
+            value: 0.0,
+            grad: vec![0.0; n],
+            hess: vec![vec![0.0; n]; n],
+        }
+    }
+
+    /// Evaluates the value, gradient and hessian of the sum of the squares of
+    /// the residuals. Only the derivatives with respect to variables with a
+    /// solver index are evaluated; the others are left at zero.
+    pub fn evaluate<F>(&mut self, sys_objects: &[SystemObject], residuals: F)
+    where
+        F: Fn(&[Placement]) -> Vec<HDual>,
+    {
+        let variables = self.local_variables(sys_objects);
+        let placements = self.placements(sys_objects, None, None);
+        self.value = sum_squares(&residuals(&placements)).re;
+        for g in self.grad.iter_mut() {
+            *g = 0.0;
+        }
+        for row in self.hess.iter_mut() {
+            for h in row.iter_mut() {
+                *h = 0.0;
+            }
+        }
+
+        for (i, var_i) in variables.iter().enumerate() {
+            if var_i.index.is_none() {
+                continue;
+            }
+            for (j, var_j) in variables.iter().enumerate().skip(i) {
+                if var_j.index.is_none() {
+                    continue;
+                }
+                let placements = self.placements(sys_objects, Some(i), Some(j));
+                let fn_eval = sum_squares(&residuals(&placements));
+                if i == j {
+                    self.grad[i] = fn_eval.e1;
+                }
+                self.hess[i][j] = fn_eval.e1e2;
+                self.hess[j][i] = fn_eval.e1e2;
+            }
+        }
+    }
+
+    /// Gets the real value of the sum of the squares of the residuals
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    /// Adds the gradient to the system gradient
+    pub fn add_gradient(&self, system_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                system_grad[k] += var.scale * self.grad[i];
+            }
+        }
+    }
+
+    /// Returns the directional derivative along direction
+    pub fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        let mut diff = 0.0;
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                diff += var.scale * self.grad[i] * direction[k];
+            }
+        }
+        diff
+    }
+
+    /// Adds the hessian to the system hessian
+    pub fn add_hessian(&self, system_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        let variables = self.local_variables(sys_objects);
+        for (i, variable1) in variables.iter().enumerate() {
+            if let Some(k) = variable1.index {
+                for (j, variable2) in variables.iter().enumerate() {
+                    if let Some(l) = variable2.index {
+                        system_hess.add(k, l, variable1.scale * variable2.scale * self.hess[i][j]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds the residuals with their first partial derivatives (a row of the
+    /// jacobian) to the residuals vector
+    pub fn add_residuals<F>(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+            residual_fn: F,
+    )
+    where
+        F: Fn(&[Placement]) -> Vec<HDual>,
+    {
+        let first = residuals.len();
+        let placements = self.placements(sys_objects, None, None);
+        for value in residual_fn(&placements) {
+            residuals.push(Residual {
+                value: value.re,
+                jacobian: Vec::new(),
+            });
+        }
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                let placements = self.placements(sys_objects, Some(i), None);
+                for (residual, value) in residuals[first..].iter_mut().zip(residual_fn(&placements)) {
+                    residual.jacobian.push((k, var.scale * value.e1));
+                }
+            }
+        }
+    }
+
+    /// Returns the residuals at the current placements (without derivatives)
+    pub fn residual_values<F>(&self, sys_objects: &[SystemObject], residual_fn: F) -> Vec<f64>
+    where
+        F: Fn(&[Placement]) -> Vec<HDual>,
+    {
+        let placements = self.placements(sys_objects, None, None);
+        residual_fn(&placements).iter().map(|value| value.re).collect()
+    }
+
+    /// Returns the indices of the objects in the vector of system objects
+    pub fn objects(&self) -> &[usize] {
+        &self.objects
+    }
+
+    /// Returns the variables used by the residuals as pairs of (object index,
+    /// variable name)
+    pub fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.variables
+            .iter()
+            .map(|(k, var_name)| (self.objects[*k], *var_name))
+            .collect()
+    }
+
+    /// Returns the blocks of the objects read by the residuals: the position
+    /// vector of an object when some of its position variables is used and the
+    /// rotation quaternion when some of its rotation angles is used
+    pub fn footprint(&self) -> Vec<(usize, Footprint)> {
+        let mut footprint = Vec::new();
+        for (k, obj_idx) in self.objects.iter().enumerate() {
+            let mut var_names = self.variables.iter().filter(|(l, _)| *l == k).map(|(_, var_name)| var_name);
+            if var_names.clone().any(|var_name| !var_name.is_rotation()) {
+                footprint.push((*obj_idx, Footprint::Position));
+            }
+            if var_names.any(|var_name| var_name.is_rotation()) {
+                footprint.push((*obj_idx, Footprint::Rotation));
+            }
+        }
+        footprint
+    }
+
+    /// Changes the indices of the objects (see Constraint::remap_objects)
+    pub fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        for obj_idx in self.objects.iter_mut() {
+            *obj_idx = object_map[obj_idx];
+        }
+    }
+
+    /// Returns the variables of the objects in the order of the local variables
+    fn local_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        self.variables
+            .iter()
+            .map(|(k, var_name)| sys_objects[self.objects[*k]].get_variable(*var_name))
+            .collect()
+    }
+
+    /// Returns the placements of the objects with the partial derivatives with
+    /// respect to the local variables i (in e1) and j (in e2)
+    fn placements(
+            &self,
+            sys_objects: &[SystemObject],
+            i: Option<usize>,
+            j: Option<usize>,
+    ) -> Vec<Placement> {
+        self.objects
+            .iter()
+            .enumerate()
+            .map(|(k, obj_idx)| {
+                let object = &sys_objects[*obj_idx];
+                let var1 = self.local_variable_name(k, i);
+                let var2 = self.local_variable_name(k, j);
+                Placement {
+                    position: object.get_vector(var1, var2),
+                    rotation: object.get_quaternion(var1, var2),
+                    inverse_rotation: object.get_inverse_quaternion(var1, var2),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the name of the local variable i if it is a variable of the k-th
+    /// object
+    fn local_variable_name(&self, k: usize, i: Option<usize>) -> Option<VN> {
+        match i {
+            Some(i) if self.variables[i].0 == k => Some(self.variables[i].1),
+            _ => None,
+        }
+    }
+}
+
+
+/// Returns the sum of the squares of the residuals
+fn sum_squares(residuals: &[HDual]) -> HDual {
+    let mut result = HDual::new();
+    for residual in residuals {
+        result = result + residual.powi(2);
+    }
+    result
+}
@@ -0,0 +1,241 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use ndarray::Array1;
+
+use crate::system::Variable;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+
+
+/// Couples the translation along and the rotation about the Z-axis of two
+/// objects like a screw: z_a - z_b = pitch / (2 pi) * (psi_a - psi_b)
+///
+/// The objects must already share their axis (for example with a Coaxial
+/// constraint) along the global Z-axis. A negative pitch is a left-hand
+/// thread. The residual of the constraint is linear in the variables, so the
+/// gradient and hessian of its square are found directly. The hessian has
+/// entries between the position and the rotation variables of both objects.
+///
+/// NOTE: the indices of the local variables used in this constraint are the following:
+/// 0 -> object_a.z
+/// 1 -> object_a.psi
+/// 2 -> object_b.z
+/// 3 -> object_b.psi
+#[derive(Debug, Clone)]
+pub struct ScrewConstraint {
+    /// name of the constraint
+    name: String,
+    /// value of the residual
+    residual: f64,
+    /// translation along the axis in one turn
+    pitch: f64,
+    /// Index of the first object in the vector of system objects
+    a_index: usize,
+    /// Index of the second object in the vector of system objects
+    b_index: usize,
+}
+
+
+impl Constraint for ScrewConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.residual = self.residual_value(sys_objects);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        let jacobian = self.jacobian();
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                system_grad[k] += 2.0 * self.residual * var.scale * jacobian[i];
+            }
+        }
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        let jacobian = self.jacobian();
+        let mut diff = 0.0;
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                diff += 2.0 * self.residual * var.scale * jacobian[i] * direction[k];
+            }
+        }
+        diff
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        // Locked and disabled variables do not have an index, so only the
+        // entries between solver variables are added
+        let jacobian = self.jacobian();
+        let variables = self.local_variables(sys_objects);
+        for (i, variable1) in variables.iter().enumerate() {
+            if let Some(k) = variable1.index {
+                for (j, variable2) in variables.iter().enumerate() {
+                    if let Some(l) = variable2.index {
+                        system_hess.add(k, l, 2.0 * variable1.scale * jacobian[i] * variable2.scale * jacobian[j]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        // The residuals are used without evaluating the constraint first
+        let jacobian = self.jacobian();
+        let mut residual = Residual {
+            value: self.residual_value(sys_objects),
+            jacobian: Vec::new(),
+        };
+        for (i, var) in self.local_variables(sys_objects).iter().enumerate() {
+            if let Some(k) = var.index {
+                residual.jacobian.push((k, var.scale * jacobian[i]));
+            }
+        }
+        residuals.push(residual);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.a_index, self.b_index]
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        vec![("z", self.residual_value(sys_objects), "length")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![
+            (self.a_index, Footprint::Variables),
+            (self.b_index, Footprint::Variables),
+        ]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![
+            (self.a_index, VN::z),
+            (self.a_index, VN::psi),
+            (self.b_index, VN::z),
+            (self.b_index, VN::psi),
+        ]
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.a_index = object_map[&self.a_index];
+        self.b_index = object_map[&self.b_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.pitch]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.pitch = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The pitch is not changed when the objects have the same angle
+        let variables = self.local_variables(sys_objects);
+        let translation = variables[0].value - variables[2].value;
+        let rotation = variables[1].value - variables[3].value;
+        if rotation == 0.0 {
+            return vec![self.pitch];
+        }
+        vec![2.0 * PI * translation / rotation]
+    }
+}
+
+
+impl ScrewConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        pitch: f64,
+        a_index: usize,
+        b_index: usize,
+    ) -> ScrewConstraint {
+        system_objects[a_index].enable_variables(&["z", "psi"]);
+        system_objects[b_index].enable_variables(&["z", "psi"]);
+
+        ScrewConstraint {
+            name: name.to_string(),
+            residual: 0.0,
+            pitch,
+            a_index,
+            b_index,
+        }
+    }
+
+    /// Returns the variables used by this constraint in the order of the local
+    /// variables
+    fn local_variables<'a>(&self, sys_objects: &'a [SystemObject]) -> Vec<&'a Variable> {
+        vec![
+            sys_objects[self.a_index].get_variable(VN::z),
+            sys_objects[self.a_index].get_variable(VN::psi),
+            sys_objects[self.b_index].get_variable(VN::z),
+            sys_objects[self.b_index].get_variable(VN::psi),
+        ]
+    }
+
+    /// Returns the value of the residual at the current values of the variables
+    fn residual_value(&self, sys_objects: &[SystemObject]) -> f64 {
+        let variables = self.local_variables(sys_objects);
+        let jacobian = self.jacobian();
+        variables.iter().zip(&jacobian).map(|(variable, derivative)| variable.value * derivative).sum()
+    }
+
+    /// Returns the partial derivatives of the residual with respect to the
+    /// local variables
+    fn jacobian(&self) -> [f64; 4] {
+        let lead = self.pitch / (2.0 * PI);
+        [1.0, -lead, -1.0, lead]
+    }
+}
@@ -0,0 +1,219 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{dot, quaternion_product, unit_axis};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Mates an object with a reference by a slider (prismatic joint) along the
+/// local Z-axis of the reference
+///
+/// The object keeps the rotation of the reference and its origin stays on the
+/// Z-axis of the reference, so the only motion left is the translation along
+/// that axis. The first two residuals are the dot products of p - rp (where p
+/// and rp are the position vectors of the object and the reference) with the
+/// local X and Y axes of the reference, and the next three are the vector part
+/// of qr^-1 * q, the rotation of the object relative to the reference.
+///
+/// The travel t = (p - rp) . rz can be limited by the "min" and "max"
+/// parameters. With a limit there is a sixth residual, the distance from t to
+/// the range [min, max], which is zero inside the range.
+#[derive(Debug, Clone)]
+pub struct SliderConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// smallest and largest travel along the axis (infinite without a limit)
+    limits: [f64; 2],
+}
+
+
+impl Constraint for SliderConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let limits = self.limits;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, &limits));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let limits = self.limits;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, &limits));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        // The rotation residuals are sines of half angles, but the position
+        // residuals are lengths
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let limits = self.limits;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, &limits));
+        let mut breakdown = vec![
+            ("offset_x", error[0], "length"),
+            ("offset_y", error[1], "length"),
+            ("phi", 2.0 * error[2], "angle"),
+            ("theta", 2.0 * error[3], "angle"),
+            ("psi", 2.0 * error[4], "angle"),
+        ];
+        if let Some(travel) = error.get(5) {
+            breakdown.push(("travel", *travel, "length"));
+        }
+        breakdown
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.limits.to_vec()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.limits = [parameters[0], parameters[1]];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The range is extended to the current travel
+        let object = Placement::current(&sys_objects[self.function.objects()[0]]);
+        let reference = Placement::current(&sys_objects[self.function.objects()[1]]);
+        let travel = dot(&(object.position - reference.position), &reference.direction(&unit_axis(2))).re;
+        vec![self.limits[0].min(travel), self.limits[1].max(travel)]
+    }
+}
+
+
+impl SliderConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        constraint_parameters: &HashMap<&str, f64>,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> SliderConstraint {
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        system_objects[obj_index].enable_variables(&all_variables);
+        system_objects[ref_index].enable_variables(&all_variables);
+
+        // A missing limit leaves the travel free on that side
+        let min = constraint_parameters.get("min").copied().unwrap_or(f64::NEG_INFINITY);
+        let max = constraint_parameters.get("max").copied().unwrap_or(f64::INFINITY);
+
+        SliderConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(vec![obj_index, ref_index], VN::get_variable_iter().collect()),
+            limits: [min, max],
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the offset of the object from
+/// the axis of the reference along the X and Y axes of the reference, the
+/// vector part of the relative rotation and the distance of the travel from
+/// its range when the travel is limited
+fn residuals(placements: &[Placement], limits: &[f64; 2]) -> Vec<HDual> {
+    let object = &placements[0];
+    let reference = &placements[1];
+    let offset = object.position - reference.position;
+    let rotation = quaternion_product(&reference.inverse_rotation, &object.rotation);
+    let mut residuals = vec![
+        dot(&offset, &reference.direction(&unit_axis(0))),
+        dot(&offset, &reference.direction(&unit_axis(1))),
+        rotation.q1,
+        rotation.q2,
+        rotation.q3,
+    ];
+
+    if limits[0].is_finite() || limits[1].is_finite() {
+        let travel = dot(&offset, &reference.direction(&unit_axis(2)));
+        let mut limit = HDual::new();
+        residuals.push(if travel.re < limits[0] {
+            limit.re = limits[0];
+            limit - travel
+        } else if travel.re > limits[1] {
+            limit.re = limits[1];
+            travel - limit
+        } else {
+            HDual::new()
+        });
+    }
+    residuals
+}
@@ -0,0 +1,210 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::geometry::{HDQuaternion, HDVector};
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{constant_vector, dot, quaternion_product};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes an object the mirror image of a source object across the local
+/// XY-plane of a reference object
+///
+/// The position of the object must be the reflection of the position of the
+/// source: ps - 2 ((ps - rp) . n) n, where ps and rp are the position vectors
+/// of the source and the reference and n is the normal of the mirror plane (the
+/// Z-axis of the reference). These are the first three residuals.
+///
+/// The reflection of a rotation is not a rotation, so the local coordinate
+/// system of the object is also reflected across its own XY-plane: the
+/// rotation of the object must be n * qs * k, where qs is the rotation
+/// quaternion of the source, and n and k are the pure quaternions of the
+/// normal and of the local Z-axis. Like in FixRotationConstraint, the last
+/// three residuals are the vector part of (n * qs * k)^-1 * q = k * qs^-1 * n * q.
+///
+/// NOTE: the objects are ordered as object, source and reference.
+#[derive(Debug, Clone)]
+pub struct SymmetryConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+}
+
+
+impl Constraint for SymmetryConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.function.evaluate(sys_objects, residuals);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_residuals(residuals_vec, sys_objects, residuals);
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        // The rotation residuals are sines of half angles, but the position
+        // residuals are lengths
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let error = self.function.residual_values(sys_objects, residuals);
+        vec![
+            ("x", error[0], "length"),
+            ("y", error[1], "length"),
+            ("z", error[2], "length"),
+            ("phi", 2.0 * error[3], "angle"),
+            ("theta", 2.0 * error[4], "angle"),
+            ("psi", 2.0 * error[5], "angle"),
+        ]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+impl SymmetryConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        obj_index: usize,
+        src_index: usize,
+        ref_index: usize,
+    ) -> SymmetryConstraint {
+        let all_variables = ["x", "y", "z", "phi", "theta", "psi"];
+        for obj_idx in [obj_index, src_index, ref_index].iter() {
+            system_objects[*obj_idx].enable_variables(&all_variables);
+        }
+
+        SymmetryConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::new(
+                vec![obj_index, src_index, ref_index],
+                VN::get_variable_iter().collect(),
+            ),
+        }
+    }
+}
+
+
+/// Evaluates the residuals of the constraint: the offset of the object from
+/// the reflected position of the source and the vector part of the rotation
+/// from the reflected rotation of the source to the rotation of the object
+fn residuals(placements: &[Placement]) -> Vec<HDual> {
+    let object = &placements[0];
+    let source = &placements[1];
+    let reference = &placements[2];
+
+    let normal = reference.direction(&constant_vector(0.0, 0.0, 1.0));
+    let mut twice_distance = dot(&(source.position - reference.position), &normal);
+    twice_distance = twice_distance + twice_distance;
+    let mut reflected = source.position;
+    reflected.x = reflected.x - twice_distance * normal.x;
+    reflected.y = reflected.y - twice_distance * normal.y;
+    reflected.z = reflected.z - twice_distance * normal.z;
+    let offset = object.position - reflected;
+
+    let k = pure_quaternion(&constant_vector(0.0, 0.0, 1.0));
+    let n = pure_quaternion(&normal);
+    let error = quaternion_product(
+        &k,
+        &quaternion_product(&source.inverse_rotation, &quaternion_product(&n, &object.rotation)),
+    );
+
+    vec![offset.x, offset.y, offset.z, error.q1, error.q2, error.q3]
+}
+
+
+/// Returns the quaternion with a zero scalar part and the vector v as its
+/// vector part
+fn pure_quaternion(v: &HDVector) -> HDQuaternion {
+    let mut q = HDQuaternion::new();
+    q.q0 = HDual::new();
+    q.q1 = v.x;
+    q.q2 = v.y;
+    q.q3 = v.z;
+    q
+}
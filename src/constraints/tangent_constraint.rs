@@ -0,0 +1,176 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::{dot, unit_axis};
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::constraints::residual_function::{Placement, ResidualFunction};
+
+
+/// Makes a circle tangent to a plane: the center of the circle (the origin of
+/// the object) is at a distance of "radius" from the local XY plane of the
+/// reference
+///
+/// The normal of the plane is the local Z-axis of the reference rotated to the
+/// global coordinate system, and the residual is
+/// (p_circle - p_plane) · normal - radius, so the circle is on the side the
+/// normal points to. Only the position variables of the circle are used.
+#[derive(Debug, Clone)]
+pub struct TangentConstraint {
+    /// name of the constraint
+    name: String,
+    /// value and derivatives of the constraint function
+    function: ResidualFunction,
+    /// radius of the circle
+    radius: f64,
+}
+
+
+impl Constraint for TangentConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        let radius = self.radius;
+        self.function.evaluate(sys_objects, |placements| residuals(placements, radius));
+    }
+
+    fn get_value(&self) -> f64 {
+        self.function.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_gradient(system_grad, sys_objects);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.function.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        self.function.add_hessian(system_hess, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals_vec: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let radius = self.radius;
+        self.function.add_residuals(residuals_vec, sys_objects, |placements| residuals(placements, radius));
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.function.objects().to_vec()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        let radius = self.radius;
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, radius));
+        vec![("distance", error[0], "length")]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.function.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.function.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.function.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.radius]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.radius = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        // The current distance of the center from the plane
+        let error = self.function.residual_values(sys_objects, |placements| residuals(placements, 0.0));
+        vec![error[0]]
+    }
+}
+
+
+impl TangentConstraint {
+    pub fn new(
+        name: &str,
+        system_objects: &mut [SystemObject],
+        radius: f64,
+        obj_index: usize,
+        ref_index: usize,
+    ) -> TangentConstraint {
+        // The plane moves with the rotation of the reference
+        system_objects[obj_index].enable_variables(&["x", "y", "z"]);
+        system_objects[ref_index].enable_variables(&["x", "y", "z", "phi", "theta", "psi"]);
+
+        TangentConstraint {
+            name: name.to_string(),
+            function: ResidualFunction::with_variables(
+                vec![obj_index, ref_index],
+                vec![VN::get_position_iter().collect(), VN::get_variable_iter().collect()],
+            ),
+            radius,
+        }
+    }
+}
+
+
+/// Evaluates the residual of the constraint: the distance of the center of the
+/// circle from the plane minus the radius
+fn residuals(placements: &[Placement], radius: f64) -> Vec<HDual> {
+    let normal = placements[1].direction(&unit_axis(2));
+    let offset = placements[0].position - placements[1].position;
+    let mut r = HDual::new();
+    r.re = radius;
+    vec![dot(&offset, &normal) - r]
+}
@@ -0,0 +1,188 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use ndarray::Array1;
+
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::constraints::{
+    Constraint, ConstraintType, Footprint, HessianAccumulator, Residual, ResidualKind,
+};
+
+
+/// A constraint with its constraint function multiplied by a weight
+///
+/// The value, gradient and hessian of the wrapped constraint are multiplied by
+/// the weight, and its residuals by the square root of the weight, so a
+/// constraint with a small weight behaves like an objective that gives way to
+/// the other constraints when they can not all be satisfied. The residual
+/// breakdown is not weighted because it is given in physical quantities.
+#[derive(Debug, Clone)]
+pub struct WeightedConstraint {
+    /// the wrapped constraint
+    constraint: Box<ConstraintType>,
+    /// factor of the constraint function
+    weight: f64,
+}
+
+
+impl Constraint for WeightedConstraint {
+
+    fn evaluate(
+            &mut self,
+            sys_objects: &[SystemObject]
+    ) {
+        self.constraint.evaluate(sys_objects);
+    }
+
+    fn get_value(&self) -> f64 {
+        self.weight * self.constraint.get_value()
+    }
+
+    fn get_gradient(
+            &self,
+            system_grad: &mut Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) {
+        // The constraint adds its gradient to the system gradient, so it is
+        // found apart before it is weighted
+        let mut grad = Array1::zeros(system_grad.len());
+        self.constraint.get_gradient(&mut grad, sys_objects);
+        system_grad.scaled_add(self.weight, &grad);
+    }
+
+    fn get_diff(
+            &self,
+            direction: &Array1<f64>,
+            sys_objects: &[SystemObject],
+    ) -> f64 {
+        self.weight * self.constraint.get_diff(direction, sys_objects)
+    }
+
+    fn get_hessian(
+            &self,
+            system_hess: &mut dyn HessianAccumulator,
+            sys_objects: &[SystemObject],
+    ) {
+        let mut weighted = WeightedHessian {
+            hessian: system_hess,
+            weight: self.weight,
+        };
+        self.constraint.get_hessian(&mut weighted, sys_objects);
+    }
+
+    fn get_residuals(
+            &self,
+            residuals: &mut Vec<Residual>,
+            sys_objects: &[SystemObject],
+    ) {
+        let first = residuals.len();
+        self.constraint.get_residuals(residuals, sys_objects);
+        let scale = self.weight.sqrt();
+        for residual in &mut residuals[first..] {
+            residual.value *= scale;
+            for (_, derivative) in &mut residual.jacobian {
+                *derivative *= scale;
+            }
+        }
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        self.constraint.get_kind()
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        self.constraint.affected_objects()
+    }
+
+    fn residual_breakdown(
+            &self,
+            sys_objects: &[SystemObject],
+    ) -> Vec<(&'static str, f64, &'static str)> {
+        self.constraint.residual_breakdown(sys_objects)
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        self.constraint.footprint()
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        self.constraint.get_variables()
+    }
+
+    fn get_name(&self) -> &str {
+        self.constraint.get_name()
+    }
+
+    fn drive(&self, sys_objects: &mut [SystemObject]) {
+        self.constraint.drive(sys_objects);
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.constraint.remap_objects(object_map);
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        self.constraint.get_parameters()
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.constraint.set_parameters(parameters);
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        self.constraint.current_parameters(sys_objects)
+    }
+}
+
+
+impl WeightedConstraint {
+    pub fn new(constraint: ConstraintType, weight: f64) -> WeightedConstraint {
+        WeightedConstraint {
+            constraint: Box::new(constraint),
+            weight,
+        }
+    }
+
+    /// Returns the weight of the constraint function
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Returns the wrapped constraint
+    pub fn inner(&self) -> &ConstraintType {
+        &self.constraint
+    }
+}
+
+
+/// Adds the hessian entries of a constraint multiplied by a weight
+struct WeightedHessian<'a> {
+    hessian: &'a mut dyn HessianAccumulator,
+    weight: f64,
+}
+
+
+impl HessianAccumulator for WeightedHessian<'_> {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        self.hessian.add(row, col, self.weight * value);
+    }
+
+    fn get(&self, row: usize, col: usize) -> f64 {
+        self.hessian.get(row, col)
+    }
+}
@@ -14,7 +14,15 @@
 
 
 mod quaternion;
-pub use quaternion::Quaternion;
+pub use quaternion::{Quaternion, quaternion_product};
 
 mod vector;
-pub use vector::Vector;
+pub use vector::{Vector, constant_vector, cross, dot, length, unit_axis};
+
+mod scalar;
+pub use scalar::{acos, positive_excess, reciprocal};
+
+pub mod euler;
+
+#[cfg(test)]
+mod tests;
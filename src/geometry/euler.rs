@@ -0,0 +1,85 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::f64::consts::PI;
+
+
+/// Returns the angle wrapped to the interval (-pi, pi]
+pub fn wrap_angle(angle: f64) -> f64 {
+    let mut wrapped = angle % (2.0 * PI);
+    if wrapped > PI {
+        wrapped -= 2.0 * PI;
+    } else if wrapped <= -PI {
+        wrapped += 2.0 * PI;
+    }
+    wrapped
+}
+
+
+/// Returns the Euler angles (phi, theta, psi) of the rotation of a unit
+/// quaternion (q0, q1, q2, q3). The angles are used as in
+/// HDQuaternion::from_angles, and theta is in the interval [-pi/2, pi/2].
+pub fn quaternion_angles(q0: f64, q1: f64, q2: f64, q3: f64) -> (f64, f64, f64) {
+    let phi = (2.0 * (q0 * q1 + q2 * q3)).atan2(1.0 - 2.0 * (q1 * q1 + q2 * q2));
+    let theta = (2.0 * (q0 * q2 - q3 * q1)).clamp(-1.0, 1.0).asin();
+    let psi = (2.0 * (q0 * q3 + q1 * q2)).atan2(1.0 - 2.0 * (q2 * q2 + q3 * q3));
+    (phi, theta, psi)
+}
+
+
+/// Returns the other set of Euler angles (phi, theta, psi) that represents the
+/// same rotation as the given angles. The angles are used as in
+/// HDQuaternion::from_angles (rotation phi about x, then theta about y and
+/// then psi about z).
+pub fn equivalent_angles(phi: f64, theta: f64, psi: f64) -> (f64, f64, f64) {
+    (
+        wrap_angle(phi + PI),
+        wrap_angle(PI - theta),
+        wrap_angle(psi + PI),
+    )
+}
+
+
+/// Returns a measure of how far the angles are from the gimbal lock (theta
+/// equal to pi/2 or -pi/2), where phi and psi rotate about the same axis. The
+/// value goes from 0 at the gimbal lock to 1 when theta is 0 or pi.
+pub fn gimbal_lock_distance(theta: f64) -> f64 {
+    theta.cos().abs()
+}
+
+
+/// Returns Euler angles away from the gimbal lock for a rotation close to it.
+///
+/// Both sets of angles of a rotation have the same distance to the gimbal
+/// lock, so when the rotation itself is closer than min_distance, theta is
+/// moved away from the lock until the distance is min_distance. In that case
+/// the angles represent a slightly different rotation, and the second value
+/// returned is false.
+pub fn reseed_angles(phi: f64, theta: f64, psi: f64, min_distance: f64) -> ((f64, f64, f64), bool) {
+    let (phi, theta, psi) = equivalent_angles(phi, theta, psi);
+    if gimbal_lock_distance(theta) >= min_distance {
+        return ((phi, theta, psi), true);
+    }
+    // move theta towards 0 (or towards pi when it is past pi/2)
+    let lock = (PI / 2.0).copysign(theta);
+    let offset = min_distance.asin();
+    let new_theta = if theta.abs() <= PI / 2.0 {
+        lock - offset.copysign(theta)
+    } else {
+        lock + offset.copysign(theta)
+    };
+    ((phi, new_theta, psi), false)
+}
@@ -21,6 +21,10 @@ use optimization::{
 use crate::system::Variable;
 
 
+/// Quaternions whose norm differs from 1 by more than this value are normalized
+const NORM_TOLERANCE: f64 = 1e-9;
+
+
 
 /// This object holds a quaternion with its partial derivatives.
 ///
@@ -28,7 +32,7 @@ use crate::system::Variable;
 /// themselves. This is useful since different constraints may require the same quaternion
 /// and building it outside of the constraint allows the reuse of the quaternion computation.
 /// Moreover, it also helps to reduce the number of calculations of partial derivatives.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Quaternion {
     // These are the quaternions containing all of the different partial derivatives
     // with respect to the variables phi, theta, and psi
@@ -174,15 +178,51 @@ impl Quaternion {
         // Now find the partial derivatives with respect to psi and only psi since
         // all the other partial derivatives are already found.
         // We find psi-psi
-        if psi_var.locked || !phi_var.enabled {
+        if psi_var.locked || !psi_var.enabled {
             self.psi_psi = const_const;
         }
         else {
             psi.e1 = 1.0;
             psi.e2 = 1.0;
             self.psi_psi = HDQuaternion::from_angles(phi, theta, psi);
-            psi.e1 = 0.0;
-            psi.e2 = 0.0;
+        }
+    }
+
+    /// Normalizes the quaternions if they are not unit quaternions. This can only
+    /// happen because of rounding errors, for example with very large angles.
+    /// The partial derivatives are those of the normalized quaternion. Returns
+    /// the norm before normalizing, or None if the quaternions are already
+    /// normalized.
+    pub fn normalize(&mut self) -> Option<f64> {
+        let q = &self.phi_phi;
+        let norm = (q.q0.re.powi(2) + q.q1.re.powi(2) + q.q2.re.powi(2) + q.q3.re.powi(2)).sqrt();
+        if (norm - 1.0).abs() <= NORM_TOLERANCE {
+            return None;
+        }
+        for q in [
+            &mut self.phi_phi,
+            &mut self.phi_theta,
+            &mut self.phi_psi,
+            &mut self.theta_theta,
+            &mut self.theta_psi,
+            &mut self.psi_psi,
+        ].iter_mut() {
+            normalize(q);
+        }
+        Some(norm)
+    }
+
+    /// Returns the inverses of the quaternions. The partial derivatives of the
+    /// returned quaternions are those of the inverse rotation, and they are
+    /// obtained with the same getters.
+    pub fn inverse(&self) -> Quaternion {
+        Quaternion {
+            phi_phi: self.phi_phi.inv(),
+            phi_theta: self.phi_theta.inv(),
+            phi_psi: self.phi_psi.inv(),
+            theta_theta: self.theta_theta.inv(),
+            theta_psi: self.theta_psi.inv(),
+            psi_psi: self.psi_psi.inv(),
         }
     }
 
@@ -330,6 +370,42 @@ impl Quaternion {
     }
 }
 
+/// Helper function that divides a quaternion by its norm
+///
+/// The norm is a hyper-dual number, so the partial derivatives of the result are
+/// the partial derivatives of the normalized quaternion.
+fn normalize(q: &mut HDQuaternion) {
+    let s = q.q0.powi(2) + q.q1.powi(2) + q.q2.powi(2) + q.q3.powi(2);
+    // 1/sqrt(s) and its first and second derivatives
+    let f = s.re.powf(-0.5);
+    let df = -0.5 * s.re.powf(-1.5);
+    let ddf = 0.75 * s.re.powf(-2.5);
+    let mut inv_norm = HDual::new();
+    inv_norm.re = f;
+    inv_norm.e1 = df * s.e1;
+    inv_norm.e2 = df * s.e2;
+    inv_norm.e1e2 = df * s.e1e2 + ddf * s.e1 * s.e2;
+
+    q.q0 = q.q0 * inv_norm;
+    q.q1 = q.q1 * inv_norm;
+    q.q2 = q.q2 * inv_norm;
+    q.q3 = q.q3 * inv_norm;
+}
+
+
+/// Returns the product a * b of two quaternions with their partial derivatives.
+/// The rotation of the product is the rotation of b followed by the rotation
+/// of a.
+pub fn quaternion_product(a: &HDQuaternion, b: &HDQuaternion) -> HDQuaternion {
+    let mut q = HDQuaternion::new();
+    q.q0 = a.q0 * b.q0 - a.q1 * b.q1 - a.q2 * b.q2 - a.q3 * b.q3;
+    q.q1 = a.q0 * b.q1 + a.q1 * b.q0 + a.q2 * b.q3 - a.q3 * b.q2;
+    q.q2 = a.q0 * b.q2 - a.q1 * b.q3 + a.q2 * b.q0 + a.q3 * b.q1;
+    q.q3 = a.q0 * b.q3 + a.q1 * b.q2 - a.q2 * b.q1 + a.q3 * b.q0;
+    q
+}
+
+
 ///  Helper function that removes the components of the partial derivatives of e1
 ///
 /// This function sets e1 and e1e2 to zero for the input quaternion. It is used
@@ -0,0 +1,63 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use optimization::number_system::HyperDualScalar as HDual;
+
+
+/// Applies a scalar function to a hyper-dual number with the chain rule. f is
+/// the value of the function at u.re, and df and ddf are its first and second
+/// derivatives there.
+fn chain_rule(u: HDual, f: f64, df: f64, ddf: f64) -> HDual {
+    let mut result = HDual::new();
+    result.re = f;
+    result.e1 = df * u.e1;
+    result.e2 = df * u.e2;
+    result.e1e2 = df * u.e1e2 + ddf * u.e1 * u.e2;
+    result
+}
+
+
+/// Returns the arccosine of a hyper-dual number with its partial derivatives.
+/// The value is clamped to [-1, 1], and the derivatives are not defined at the
+/// ends of the interval so they are set to 0 there.
+pub fn acos(u: HDual) -> HDual {
+    let x = u.re.clamp(-1.0, 1.0);
+    let s = 1.0 - x * x;
+    if s <= 0.0 {
+        return chain_rule(u, x.acos(), 0.0, 0.0);
+    }
+    chain_rule(u, x.acos(), -1.0 / s.sqrt(), -x / (s * s.sqrt()))
+}
+
+
+/// Returns max(u, 0)^(3/2) with its partial derivatives. Its square is
+/// max(u, 0)^3, which is zero for u <= 0 and has continuous first and second
+/// derivatives, so it can be used as a one-sided residual.
+pub fn positive_excess(u: HDual) -> HDual {
+    if u.re <= 0.0 {
+        return HDual::new();
+    }
+    let root = u.re.sqrt();
+    chain_rule(u, u.re * root, 1.5 * root, 0.75 / root)
+}
+
+
+/// Returns 1 / u with its partial derivatives. The reciprocal is not defined
+/// for u = 0, so callers must check the value of u first.
+pub fn reciprocal(u: HDual) -> HDual {
+    let inverse = 1.0 / u.re;
+    chain_rule(u, inverse, -inverse * inverse, 2.0 * inverse * inverse * inverse)
+}
@@ -0,0 +1,171 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::f64::consts::PI;
+
+use optimization::geometry::{HDQuaternion, HDVector};
+use optimization::number_system::HyperDualScalar as HDual;
+
+use crate::geometry::euler::*;
+use crate::geometry::{Quaternion, Vector};
+use crate::system::Variable;
+
+
+/// Returns the unit quaternion (q0, q1, q2, q3) of the rotation of the Euler
+/// angles (phi, theta, psi), used as in HDQuaternion::from_angles
+fn angles_quaternion(phi: f64, theta: f64, psi: f64) -> [f64; 4] {
+    let (c1, s1) = ((phi / 2.0).cos(), (phi / 2.0).sin());
+    let (c2, s2) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+    let (c3, s3) = ((psi / 2.0).cos(), (psi / 2.0).sin());
+    [
+        c1 * c2 * c3 + s1 * s2 * s3,
+        s1 * c2 * c3 - c1 * s2 * s3,
+        c1 * s2 * c3 + s1 * c2 * s3,
+        c1 * c2 * s3 - s1 * s2 * c3,
+    ]
+}
+
+
+/// Returns true when two sets of Euler angles represent the same rotation (the
+/// quaternions q and -q are the same rotation)
+fn same_rotation(a: (f64, f64, f64), b: (f64, f64, f64)) -> bool {
+    let qa = angles_quaternion(a.0, a.1, a.2);
+    let qb = angles_quaternion(b.0, b.1, b.2);
+    let dot: f64 = qa.iter().zip(&qb).map(|(a, b)| a * b).sum();
+    (dot.abs() - 1.0).abs() < 1e-12
+}
+
+
+/// Euler angles covering the interval (-pi, pi] for phi and psi and [-pi/2,
+/// pi/2] for theta, including the gimbal lock
+fn sample_angles() -> Vec<(f64, f64, f64)> {
+    let mut angles = Vec::new();
+    for i in -3..=3 {
+        for j in -2..=2 {
+            for k in -3..=3 {
+                angles.push((i as f64 * 1.0, j as f64 * PI / 4.0, k as f64 * 0.9 + 0.1));
+            }
+        }
+    }
+    angles
+}
+
+
+#[test]
+fn wrap_angle_interval() {
+    for k in -20..=20 {
+        let angle = k as f64 * 0.7;
+        let wrapped = wrap_angle(angle);
+        assert!(wrapped > -PI && wrapped <= PI, "{}", angle);
+        assert!(same_rotation((angle, 0.0, 0.0), (wrapped, 0.0, 0.0)));
+    }
+    assert_eq!(wrap_angle(PI), PI);
+    assert_eq!(wrap_angle(-PI), PI);
+}
+
+
+#[test]
+fn equivalent_angles_are_the_same_rotation() {
+    for (phi, theta, psi) in sample_angles() {
+        let equivalent = equivalent_angles(phi, theta, psi);
+        assert!(same_rotation((phi, theta, psi), equivalent), "{:?}", (phi, theta, psi));
+        assert!((gimbal_lock_distance(equivalent.1) - gimbal_lock_distance(theta)).abs() < 1e-12);
+        // Going back gives the first angles
+        let back = equivalent_angles(equivalent.0, equivalent.1, equivalent.2);
+        assert!((wrap_angle(back.0 - phi)).abs() < 1e-12);
+        assert!((wrap_angle(back.1 - theta)).abs() < 1e-12);
+        assert!((wrap_angle(back.2 - psi)).abs() < 1e-12);
+    }
+}
+
+
+#[test]
+fn reseed_angles_leave_the_gimbal_lock() {
+    let min_distance = 1e-2;
+
+    // Far from the lock the other angles of the same rotation are returned
+    let (angles, exact) = reseed_angles(0.3, 0.5, -0.4, min_distance);
+    assert!(exact);
+    assert_eq!(angles, equivalent_angles(0.3, 0.5, -0.4));
+
+    // At the lock theta is moved just far enough from it
+    for theta in [PI / 2.0, -PI / 2.0, PI / 2.0 - 1e-4, -PI / 2.0 + 1e-4].iter().cloned() {
+        let (angles, exact) = reseed_angles(0.3, theta, -0.4, min_distance);
+        assert!(!exact);
+        assert!((gimbal_lock_distance(angles.1) - min_distance).abs() < 1e-12, "{}", theta);
+        let (phi, _, psi) = equivalent_angles(0.3, theta, -0.4);
+        assert_eq!((angles.0, angles.2), (phi, psi));
+    }
+}
+
+
+/// Returns the (re, e1, e2, e1e2) parts of the x, y and z components of a
+/// vector
+fn vector_parts(v: &HDVector) -> [[f64; 4]; 3] {
+    let part = |c: &HDual| [c.re, c.e1, c.e2, c.e1e2];
+    [part(&v.x), part(&v.y), part(&v.z)]
+}
+
+
+#[test]
+fn z_derivatives_of_vectors_are_flipped() {
+    let variables: Vec<Variable> = [1.0, 2.0, 3.0]
+        .iter()
+        .map(|value| Variable {
+            value: *value,
+            enabled: true,
+            ..Variable::new()
+        })
+        .collect();
+    let mut vector = Vector::new();
+    vector.evaluate_vector(&variables[0], &variables[1], &variables[2]);
+
+    // e1 is the derivative with respect to z and e2 with respect to x or y
+    assert_eq!(vector_parts(&vector.get_z_x()), [
+        [1.0, 0.0, 1.0, 0.0], [2.0, 0.0, 0.0, 0.0], [3.0, 1.0, 0.0, 0.0],
+    ]);
+    assert_eq!(vector_parts(&vector.get_z_y()), [
+        [1.0, 0.0, 0.0, 0.0], [2.0, 0.0, 1.0, 0.0], [3.0, 1.0, 0.0, 0.0],
+    ]);
+}
+
+
+#[test]
+fn psi_derivatives_need_only_psi() {
+    // phi and theta are not enabled, so only the psi derivatives are not zero
+    let mut variables: Vec<Variable> = [0.3, 0.2, 0.5]
+        .iter()
+        .map(|value| Variable {
+            value: *value,
+            ..Variable::new()
+        })
+        .collect();
+    variables[2].enabled = true;
+    let mut quaternion = Quaternion::new();
+    quaternion.evaluate_quaternion(&variables[0], &variables[1], &variables[2]);
+
+    let components = |q: HDQuaternion| [q.q0, q.q1, q.q2, q.q3];
+    // every component is a product with the cosine or the sine of psi/2, so its
+    // second derivative is -1/4 of its value
+    for c in components(quaternion.get_psi_psi()).iter() {
+        assert!(c.e1e2 != 0.0);
+        assert!((c.e1e2 + c.re / 4.0).abs() < 1e-15, "{:?}", c);
+        assert_eq!(c.e1, c.e2);
+    }
+    for c in components(quaternion.get_theta_theta()).iter() {
+        assert_eq!([c.e1, c.e2, c.e1e2], [0.0; 3]);
+    }
+}
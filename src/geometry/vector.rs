@@ -28,7 +28,7 @@ use crate::system::Variable;
 /// of the position of the object. This object is useful for reusing the same position vectors
 /// across all constraint functions. This way, constraint functions don't have to
 /// to create their own vectors; they can retrieve them from this object.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Vector {
     // These are the vectors containing all of the different partial derivatives
     // with respect to the variables x, y, and z
@@ -190,8 +190,6 @@ impl Vector {
             z.e1 = 1.0;
             z.e2 = 1.0;
             self.z_z = HDVector{x, y, z};
-            z.e1 = 0.0;
-            z.e2 = 0.0;
         }
     }
 
@@ -379,7 +377,8 @@ fn remove_e1_e2(v: &mut HDVector) {
 /// Helper function that flips the values of e1 and e2 for the input vector.
 ///
 /// This function is useful when the partial derivatives with respect to e1 should
-/// we moved to e2 or vice-versa.
+/// we moved to e2 or vice-versa. Every component is flipped; e1e2 is left as it
+/// is since the mixed partial derivatives do not depend on their order.
 fn flip_e1_e2(v: &mut HDVector) {
     let mut old_e1: f64;
     old_e1 = v.x.e1;
@@ -393,3 +392,56 @@ fn flip_e1_e2(v: &mut HDVector) {
     v.z.e2 = old_e1;
 }
 
+
+/// Returns a vector with constant components (without partial derivatives)
+pub fn constant_vector(x: f64, y: f64, z: f64) -> HDVector {
+    let mut v = HDVector::new();
+    v.x.re = x;
+    v.y.re = y;
+    v.z.re = z;
+    v
+}
+
+
+/// Returns the dot product of two vectors with their partial derivatives
+pub fn dot(a: &HDVector, b: &HDVector) -> HDual {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+
+/// Returns the cross product a x b of two vectors with their partial derivatives
+pub fn cross(a: &HDVector, b: &HDVector) -> HDVector {
+    let mut c = HDVector::new();
+    c.x = a.y * b.z - a.z * b.y;
+    c.y = a.z * b.x - a.x * b.z;
+    c.z = a.x * b.y - a.y * b.x;
+    c
+}
+
+
+/// Returns the length of a vector with its partial derivatives. The partial
+/// derivatives of a zero vector are not defined, they are set to 0.
+pub fn length(v: &HDVector) -> HDual {
+    let squared = v.x.powi(2) + v.y.powi(2) + v.z.powi(2);
+    let mut result = HDual::new();
+    result.re = squared.re.sqrt();
+    if result.re > 0.0 {
+        // chain rule of sqrt(u) for the hyper-dual parts of u
+        result.e1 = squared.e1 / (2.0 * result.re);
+        result.e2 = squared.e2 / (2.0 * result.re);
+        result.e1e2 = squared.e1e2 / (2.0 * result.re)
+            - squared.e1 * squared.e2 / (4.0 * result.re.powi(3));
+    }
+    result
+}
+
+
+/// Returns the unit vector of the axis given by its index (0 for x, 1 for y
+/// and 2 for z). Any other index is the Z-axis.
+pub fn unit_axis(index: usize) -> HDVector {
+    match index {
+        0 => constant_vector(1.0, 0.0, 0.0),
+        1 => constant_vector(0.0, 1.0, 0.0),
+        _ => constant_vector(0.0, 0.0, 1.0),
+    }
+}
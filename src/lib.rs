@@ -14,56 +14,134 @@
 
 use std::collections::HashMap;
 
-use pyo3::prelude::*;
-use pyo3::wrap_pyfunction;
-
 mod constraints;
 use constraints::ConstraintType;
+pub use constraints::{
+    Constraint, ConstraintFactory, CustomConstraint, Footprint, HessianAccumulator, Residual,
+    ResidualKind, SparseHessian,
+};
 
 mod geometry;
 mod system;
-use system::System;
+pub use system::{Plan, System, SystemSnapshot, Variable};
 mod system_object;
+pub use system_object::{SystemObject, VariableName};
 use system_object::VariableName as VN;
+mod minimizer;
+pub use minimizer::{Method, SolveStats, SolverOptions, Termination};
+#[cfg(feature = "python")]
+mod solver_system;
+mod solver_result;
+pub use solver_result::SolverResult;
+mod solver_error;
+pub use solver_error::SolverError;
+mod testing;
+pub use testing::{DerivativeMismatch, DerivativeReport, verify_constraint_derivatives};
+#[cfg(test)]
+mod test_fixtures;
+#[cfg(feature = "python")]
+mod python;
 
-use optimization::TrustNCG;
 
+/// A constraint system solved by solve
+#[derive(Debug, Clone)]
+pub struct SolvedSystem<'a> {
+    /// The objects passed to solve with the solved placements. Objects that are
+    /// not used by any constraint keep their placements.
+    pub objects: HashMap<&'a str, HashMap<&'a str, f64>>,
+    /// Whether the solver converged and the details of the solve
+    pub result: SolverResult,
+}
 
-#[pymodule]
-fn solver(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
-    m.add_wrapped(wrap_pyfunction!(solve_constraint_system))?;
 
-    Ok(())
+/// Solves a constraint system without Python. The maps are the same maps
+/// passed to the solve_constraint_system function of the Python module, and
+/// options are the solver options (SolverOptions::new() uses the "auto"
+/// method).
+///
+/// A SolverError is returned when some object, placement or parameter used by
+/// a constraint is missing. A solve stopped by the memory limit of the options
+/// is not an error; its result has the MemoryLimit termination.
+pub fn solve<'a>(
+    objects: &HashMap<&'a str, HashMap<&'a str, f64>>,
+    constraint_names: &HashMap<&str, HashMap<&str, &str>>,
+    constraint_parameters: &HashMap<&str, HashMap<&str, f64>>,
+    options: &SolverOptions,
+) -> Result<SolvedSystem<'a>, SolverError> {
+    let mut system = System::new();
+    build_constraints(&mut system, objects, constraint_names, constraint_parameters)?;
+
+    let stats = system.solve(options);
+
+    let mut objects = objects.clone();
+    system.write_placements(&mut objects);
+    Ok(SolvedSystem {
+        result: SolverResult::new(&stats, &system),
+        objects,
+    })
 }
 
-/// Set-up the constraints functions
+
+/// Adds the constrained objects and the constraints to the system.
 ///
-/// objects: map of all objects in the system with their current placement values.
-///     This map is returned with the resulting values after solving the system.
-/// constraint_names: map of all constraints with the name of constrained objects
-/// constraint_parameters: map of all constraints parameters. For example the
-///     values of the axis to lock for a Lock constraint. Axis not enabled in a
-///     constraint will be omitted in this map (if a lock constraint does not
-///     lock the x-axis, then it will not be included in constraint_parameters)
-#[pyfunction]
-fn solve_constraint_system<'a>(
-    mut objects: HashMap<&'a str, HashMap<&'a str, f64>>,
-    constraint_names: HashMap<&'a str, HashMap<&'a str, &str>>,
-    constraint_parameters: HashMap<&'a str, HashMap<&'a str, f64>>,
-) -> (HashMap<&'a str, HashMap<&'a str, f64>>, bool) {
-    // Here we store the system information.
-    let mut system = System::new();
+/// The constraints are added in the order of their names.
+///
+/// The arguments are the same maps passed to solve (and to
+/// solve_constraint_system from Python). Objects already in the system are
+/// reused with their current placements, for example the objects of a
+/// restored SystemSnapshot. A SolverError is returned when some object,
+/// placement or parameter used by a constraint is missing.
+pub fn build_constraints(
+    system: &mut System,
+    objects: &HashMap<&str, HashMap<&str, f64>>,
+    constraint_names: &HashMap<&str, HashMap<&str, &str>>,
+    constraint_parameters: &HashMap<&str, HashMap<&str, f64>>,
+) -> Result<(), SolverError> {
+    // The constraints are added in the order of their names so the objects and
+    // the solver variables always have the same order for the same input. This
+    // makes the solutions reproducible (the iteration order of a HashMap
+    // changes between runs).
+    let mut sorted_names: Vec<(&&str, &HashMap<&str, &str>)> = constraint_names.iter().collect();
+    sorted_names.sort_by(|a, b| a.0.cmp(b.0));
 
-    for (c, object_names) in &constraint_names {
+    for (c, object_names) in sorted_names {
+        let factory = c.split('_').next().and_then(|type_name| system.factories.get(type_name));
+        if let Some(factory) = factory.copied() {
+            // A custom type replaces the built-in constraints whose names are
+            // part of its name ("GearRatio_1" is not a Ratio)
+            // The objects are added in the order of their roles so the objects
+            // always have the same order for the same input
+            let mut roles: Vec<(&&str, &&str)> = object_names.iter().collect();
+            roles.sort();
+            let mut obj_indices = HashMap::new();
+            for (role, obj_name) in roles {
+                let obj_params = get_placement(c, objects, obj_name)?;
+                system.add_object(obj_name, obj_params);
+                obj_indices.insert(*role, system.sys_objects_idx[*obj_name]);
+            }
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let constraint = factory(c, &obj_indices, &mut system.sys_objects, c_params);
+            system.add_constraint(ConstraintType::Custom(constraint));
+            continue;
+        }
         if c.contains("FixBase") {
-            let obj_name = object_names.get("Object").unwrap();
-            let ref_name = object_names.get("Reference").unwrap();
-
-            let obj_params = objects.get(obj_name).unwrap();
-            let ref_params = objects.get(ref_name).unwrap();
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let obj_params = get_placement(c, objects, obj_name)?;
 
             // constraint parameters of this fix constraint
-            let c_params = constraint_parameters.get(c).unwrap();
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            // without a reference the position is fixed in the global
+            // coordinate system
+            let ref_name = match get_reference_name(objects, object_names) {
+                Some(ref_name) => ref_name,
+                None => {
+                    let targets = global_targets(c_params, VN::get_position_iter());
+                    add_global_fix(system, c, obj_name, obj_params, targets);
+                    continue;
+                }
+            };
+            let ref_params = get_placement(c, objects, ref_name)?;
 
             // we add object to be fixed and the reference object to the system
             // and create variables
@@ -71,44 +149,850 @@ fn solve_constraint_system<'a>(
             system.add_object(ref_name, ref_params);
 
             // indices of the reference and object in the SystemObject vector
-            let ref_idx = *system.sys_objects_idx.get(ref_name).unwrap();
-            let obj_idx = *system.sys_objects_idx.get(obj_name).unwrap();
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
 
             // Finally, add the fix constraint. Note that a Fix constraint is
-            // broken into fix base and fix rotation
+            // broken into fix base and fix rotation (see the FixRotation
+            // constraint below)
             let fix_base_constraint =
                 constraints::FixBaseConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::FixBaseConstraint(Box::new(fix_base_constraint)));
+        }
+        if c.contains("FixRotation") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let obj_params = get_placement(c, objects, obj_name)?;
+
+            // constraint parameters of this fix constraint (the angles of the
+            // rotation relative to the reference)
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            // without a reference the angles are fixed in the global
+            // coordinate system
+            let ref_name = match get_reference_name(objects, object_names) {
+                Some(ref_name) => ref_name,
+                None => {
+                    let targets = global_targets(c_params, VN::get_rotation_iter());
+                    add_global_fix(system, c, obj_name, obj_params, targets);
+                    continue;
+                }
+            };
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let fix_rotation_constraint =
+                constraints::FixRotationConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::FixRotationConstraint(Box::new(fix_rotation_constraint)));
+        }
+        if c.contains("FixGlobal") {
+            // the variables given in the parameters (or the rotation given as
+            // a quaternion) are fixed in the global coordinate system
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let obj_params = get_placement(c, objects, obj_name)?;
+
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let targets = constraints::lock_constraint::lock_targets(c_params)
+                .map_err(|message| SolverError::new(format!("constraint '{}': {}", c, message)))?;
+            add_global_fix(system, c, obj_name, obj_params, targets);
+        }
+        if c.contains("FixedPosition") {
+            // the position is always given in the global coordinate system,
+            // FixBase fixes it relative to a reference
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            if let Some(ref_name) = get_reference_name(objects, object_names) {
+                return Err(SolverError::new(format!(
+                    "constraint '{}' fixes a global position but has the reference '{}'", c, ref_name,
+                )));
+            }
+            let obj_params = get_placement(c, objects, obj_name)?;
+
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            system.add_object(obj_name, obj_params);
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let fixed_position_constraint =
+                constraints::FixedPositionConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx
+                );
+            system.add_constraint(ConstraintType::FixedPositionConstraint(fixed_position_constraint));
+        }
+        if c.contains("PointCoincident") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // constraint parameters of this constraint (the attachment points
+            // in the local coordinate system of each object)
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            // the points can be offsets from named attachment points
+            let attachment1 = get_attachment_name(c, object_names, "Attachment1", system, obj1_name)?;
+            let attachment2 = get_attachment_name(c, object_names, "Attachment2", system, obj2_name)?;
+
+            let point_coincident_constraint =
+                constraints::PointCoincidentConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj1_idx,
+                    obj2_idx,
+                    attachment1,
+                    attachment2
+                );
+            system.add_constraint(ConstraintType::PointCoincidentConstraint(point_coincident_constraint));
+        } else if c.contains("AxisCoincident") {
+            // An axis coincident mate is built as a Coaxial constraint with
+            // the first object as the object and the second one as the
+            // reference
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let attachment1 = get_attachment_name(c, object_names, "Attachment1", system, obj1_name)?;
+            let attachment2 = get_attachment_name(c, object_names, "Attachment2", system, obj2_name)?;
+
+            let coaxial_constraint =
+                constraints::CoaxialConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj1_idx,
+                    obj2_idx,
+                    attachment1,
+                    attachment2
+                );
+            system.add_constraint(ConstraintType::CoaxialConstraint(coaxial_constraint));
+        } else if c.contains("PointOnPlane") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // The plane of a point on plane constraint is optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let point_on_plane_constraint =
+                constraints::PointOnPlaneConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::PointOnPlaneConstraint(point_on_plane_constraint));
+        } else if c.contains("PlaneParallel") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // The "flip" of a plane parallel constraint is optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let plane_parallel_constraint =
+                constraints::PlaneParallelConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::PlaneParallelConstraint(plane_parallel_constraint));
+        } else if c.contains("Plane") {
+            // Plane and PlaneCoincident constraints are the same constraint
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // The offset of a plane constraint is optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let plane_constraint =
+                constraints::PlaneConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::PlaneConstraint(plane_constraint));
+        } else if c.contains("Coincident") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let coincident_constraint =
+                constraints::CoincidentConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::CoincidentConstraint(coincident_constraint));
+        }
+        if c.contains("DistanceRange") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the ends "min" and
+            // "max" of the band), either of them may be missing
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+            let min = c_params.get("min").copied();
+            let max = c_params.get("max").copied();
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    return Err(SolverError::new(format!(
+                        "constraint '{}' has a minimum distance greater than its maximum", c
+                    )));
+                }
+            }
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let distance_range_constraint =
+                constraints::DistanceRangeConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    min,
+                    max,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::DistanceRangeConstraint(distance_range_constraint));
+        } else if c.contains("Distance") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the distance "d")
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let distance_constraint =
+                constraints::DistanceConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::DistanceConstraint(distance_constraint));
+        }
+        if c.contains("Coaxial") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            // the axes can go through named attachment points
+            let obj_attachment = get_attachment_name(c, object_names, "ObjectAttachment", system, obj_name)?;
+            let ref_attachment = get_attachment_name(c, object_names, "ReferenceAttachment", system, ref_name)?;
+
+            let coaxial_constraint =
+                constraints::CoaxialConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj_idx,
+                    ref_idx,
+                    obj_attachment,
+                    ref_attachment
+                );
+            system.add_constraint(ConstraintType::CoaxialConstraint(coaxial_constraint));
+        }
+        if c.contains("FixedAngle") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the axis and the angle)
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let fixed_angle_constraint =
+                constraints::FixedAngleConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::FixedAngleConstraint(fixed_angle_constraint));
+        }
+        if c.contains("Symmetry") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let src_name = get_object_name(c, object_names, "Source")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let src_params = get_placement(c, objects, src_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(src_name, src_params);
+            system.add_object(ref_name, ref_params);
+
+            let obj_idx = system.sys_objects_idx[obj_name];
+            let src_idx = system.sys_objects_idx[src_name];
+            let ref_idx = system.sys_objects_idx[ref_name];
+
+            let symmetry_constraint =
+                constraints::SymmetryConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj_idx,
+                    src_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::SymmetryConstraint(symmetry_constraint));
+        }
+        if c.contains("PointOnLine") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the axis of the line)
+            let c_params = get_parameters(c, constraint_parameters)?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let ref_idx = system.sys_objects_idx[ref_name];
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let point_on_line_constraint =
+                constraints::PointOnLineConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::PointOnLineConstraint(point_on_line_constraint));
+        }
+        // PlaneParallel constraints are added with the plane constraints
+        if c.contains("Parallel") && !c.contains("PlaneParallel") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // The axes of a parallel constraint are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let parallel_constraint =
+                constraints::ParallelConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::ParallelConstraint(parallel_constraint));
+        }
+        if c.contains("Perpendicular") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // The axes of a perpendicular constraint are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let perpendicular_constraint =
+                constraints::PerpendicularConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::PerpendicularConstraint(perpendicular_constraint));
+        }
+        if c.contains("Ratio") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // constraint parameters of this constraint (the "ratio" and the
+            // "phase"), they are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let ratio_constraint =
+                constraints::RatioConstraint::new(
+                    c,
                     &mut system.sys_objects,
                     c_params,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::RatioConstraint(ratio_constraint));
+        }
+        if c.contains("Hinge") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let hinge_constraint =
+                constraints::HingeConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::HingeConstraint(hinge_constraint));
+        }
+        if c.contains("Slider") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the "min" and "max"
+            // travel), they are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let obj_idx = system.sys_objects_idx[obj_name];
+            let ref_idx = system.sys_objects_idx[ref_name];
+
+            let slider_constraint =
+                constraints::SliderConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::SliderConstraint(slider_constraint));
+        }
+        if c.contains("Ball") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // constraint parameters of this constraint (the "x", "y" and "z"
+            // coordinates of the point), they are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let obj_idx = system.sys_objects_idx[obj_name];
+            let ref_idx = system.sys_objects_idx[ref_name];
+
+            let ball_constraint =
+                constraints::BallConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref_idx
+                );
+            system.add_constraint(ConstraintType::BallConstraint(ball_constraint));
+        }
+        if c.contains("Screw") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // the "pitch" of the screw, negative for left-hand threads
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let pitch = *c_params.get("pitch").ok_or_else(|| {
+                SolverError::new(format!("constraint '{}' is missing the parameter 'pitch'", c))
+            })?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let screw_constraint =
+                constraints::ScrewConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    pitch,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::ScrewConstraint(screw_constraint));
+        }
+        if c.contains("Driven") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let obj_params = get_placement(c, objects, obj_name)?;
+
+            // the parameters have a single rotation variable ("phi", "theta"
+            // or "psi") with the target angle
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let mut driven = VN::get_rotation_iter()
+                .filter_map(|var_name| c_params.get(var_name.as_str()).map(|target| (var_name, *target)));
+            let (variable, target) = match (driven.next(), driven.next()) {
+                (Some(driven), None) => driven,
+                _ => return Err(SolverError::new(format!(
+                    "constraint '{}' must have exactly one of the parameters 'phi', 'theta' or 'psi'", c,
+                ))),
+            };
+
+            system.add_object(obj_name, obj_params);
+            let obj_idx = system.sys_objects_idx[obj_name];
+
+            let driven_angle_constraint =
+                constraints::DrivenAngleConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    variable,
+                    target,
+                    obj_idx
+                );
+            system.add_constraint(ConstraintType::DrivenAngleConstraint(driven_angle_constraint));
+        }
+        if c.contains("Midpoint") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref1_name = get_object_name(c, object_names, "Reference1")?;
+            let ref2_name = get_object_name(c, object_names, "Reference2")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref1_params = get_placement(c, objects, ref1_name)?;
+            let ref2_params = get_placement(c, objects, ref2_name)?;
+
+            // constraint parameters of this constraint (the fraction "t" of
+            // the segment), they are optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref1_name, ref1_params);
+            system.add_object(ref2_name, ref2_params);
+
+            let obj_idx = system.sys_objects_idx[obj_name];
+            let ref1_idx = system.sys_objects_idx[ref1_name];
+            let ref2_idx = system.sys_objects_idx[ref2_name];
+
+            let midpoint_constraint =
+                constraints::MidpointConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj_idx,
+                    ref1_idx,
+                    ref2_idx
+                );
+            system.add_constraint(ConstraintType::MidpointConstraint(midpoint_constraint));
+        }
+        if c.contains("Colinear") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+            let obj3_name = get_object_name(c, object_names, "Object3")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+            let obj3_params = get_placement(c, objects, obj3_name)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+            system.add_object(obj3_name, obj3_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+            let obj3_idx = system.sys_objects_idx[obj3_name];
+
+            let colinear_constraint =
+                constraints::ColinearConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj1_idx,
+                    obj2_idx,
+                    obj3_idx
+                );
+            system.add_constraint(ConstraintType::ColinearConstraint(colinear_constraint));
+        }
+        if c.contains("Tangent") {
+            let obj_name = get_object_name(c, object_names, "Object")?;
+            let ref_name = get_object_name(c, object_names, "Reference")?;
+
+            let obj_params = get_placement(c, objects, obj_name)?;
+            let ref_params = get_placement(c, objects, ref_name)?;
+
+            // the "radius" of the circle
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let radius = *c_params.get("radius").ok_or_else(|| {
+                SolverError::new(format!("constraint '{}' is missing the parameter 'radius'", c))
+            })?;
+
+            system.add_object(obj_name, obj_params);
+            system.add_object(ref_name, ref_params);
+
+            let obj_idx = system.sys_objects_idx[obj_name];
+            let ref_idx = system.sys_objects_idx[ref_name];
+
+            let tangent_constraint =
+                constraints::TangentConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    radius,
                     obj_idx,
                     ref_idx
                 );
-            system
-                .constraints
-                .push(ConstraintType::FixBaseConstraint(fix_base_constraint));
+            system.add_constraint(ConstraintType::TangentConstraint(tangent_constraint));
+        }
+        if c.contains("Centric") {
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            // constraint parameters of this constraint (the offsets "offset1"
+            // and "offset2" of the centers along the local Z-axes), they are
+            // optional
+            let no_parameters = HashMap::new();
+            let c_params = constraint_parameters.get(c).unwrap_or(&no_parameters);
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let obj1_idx = system.sys_objects_idx[obj1_name];
+            let obj2_idx = system.sys_objects_idx[obj2_name];
+
+            let centric_constraint =
+                constraints::CentricConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    c_params,
+                    obj1_idx,
+                    obj2_idx
+                );
+            system.add_constraint(ConstraintType::CentricConstraint(centric_constraint));
+        }
+        if c.contains("Coplanar") {
+            // any number of objects "Object1" ... "ObjectN", the first three
+            // define the plane
+            let obj_names = get_numbered_object_names(c, object_names)?;
+            if obj_names.len() < 4 {
+                return Err(SolverError::new(format!(
+                    "constraint '{}' needs at least four objects but has {}", c, obj_names.len(),
+                )));
+            }
+
+            let mut obj_indices = Vec::with_capacity(obj_names.len());
+            for obj_name in obj_names {
+                let obj_params = get_placement(c, objects, obj_name)?;
+                system.add_object(obj_name, obj_params);
+                obj_indices.push(system.sys_objects_idx[obj_name]);
+            }
+
+            let coplanar_constraint =
+                constraints::CoplanarConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    obj_indices
+                );
+            system.add_constraint(ConstraintType::CoplanarConstraint(coplanar_constraint));
+        }
+        if c.contains("Linear") {
+            // the coefficients are given by the dotted keys "<role>.<variable>"
+            // of the parameters and the value of the combination by "rhs"
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let coefficients = get_variable_coefficients(c, object_names, c_params)?;
+            if coefficients.is_empty() {
+                return Err(SolverError::new(format!("constraint '{}' has no coefficients", c)));
+            }
+
+            let mut terms = Vec::with_capacity(coefficients.len());
+            for (obj_name, var_name, coefficient) in coefficients {
+                let obj_params = get_placement(c, objects, obj_name)?;
+                system.add_object(obj_name, obj_params);
+                terms.push((system.sys_objects_idx[obj_name], var_name, coefficient));
+            }
+
+            let linear_constraint =
+                constraints::LinearConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    terms,
+                    c_params.get("rhs").copied().unwrap_or(0.0)
+                );
+            system.add_constraint(ConstraintType::LinearConstraint(linear_constraint));
         }
-        // TODO: make a fix_rotation_constraint
-        // TODO: make a lock_constraint
         if c.contains("Lock") {
             // WARNING: It is assumed that at this point any chained equality
             // constraints with some locked constraint applied to any of the
             // chained variables is already decomposed into multiple simple locked
             // constraints.
-            let obj_name = object_names.get("Object").unwrap();
+            let obj_name = get_object_name(c, object_names, "Object")?;
 
-            let obj_params = objects.get(obj_name).unwrap();
+            let obj_params = get_placement(c, objects, obj_name)?;
             system.add_object(obj_name, obj_params);
 
-            let sys_obj_idx = *system.sys_objects_idx.get(obj_name).unwrap();
-            let sys_object = &mut system.sys_objects[sys_obj_idx];
+            let sys_obj_idx = system.sys_objects_idx[obj_name];
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let lock_error = |message| SolverError::new(format!("constraint '{}': {}", c, message));
 
-            let c_params = constraint_parameters.get(c).unwrap();
-            constraints::lock_constraint::set_up_locks(
-                &c_params,
-                sys_object,
-            );
+            // A lock with a "weight" is a soft lock: a penalty constraint
+            // instead of locked variables
+            if let Some(weight) = c_params.get("weight") {
+                let targets = constraints::lock_constraint::lock_targets(c_params).map_err(lock_error)?;
+                let lock_constraint =
+                    constraints::LockConstraint::new(
+                        c,
+                        &mut system.sys_objects,
+                        targets,
+                        *weight,
+                        sys_obj_idx
+                    );
+                system.add_constraint(ConstraintType::LockConstraint(lock_constraint));
+            } else {
+                let sys_object = &mut system.sys_objects[sys_obj_idx];
+                let locked = constraints::lock_constraint::set_up_locks(
+                    c_params,
+                    sys_object,
+                ).map_err(lock_error)?;
+                system.locks.push((c.to_string(), sys_obj_idx, locked));
+                system.revision += 1;
+            }
         }
-        // TODO: make a equality_constraint
         if c.contains("Equality") {
             // Now we have to have a way of stating which variables are equal
             // NOTE: equal system variables point to the same solver variables. This
@@ -116,52 +1000,298 @@ fn solve_constraint_system<'a>(
             // to use any equal variable without any extra work (they would end up
             // updating the correct gradient and hessian indices). Basically,
             // equal variables are treated as only one variable.
+            // An equality with a non-zero "penalty" parameter keeps the
+            // variables independent and minimizes their squared differences
+            // instead.
+            // Both objects may be the same object, then the pairs of equal
+            // variables are given as "a=b" parameters (see equality_pairs).
 
-            let obj1_name = object_names.get("Object1").unwrap();
-            let obj2_name = object_names.get("Object2").unwrap();
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
 
-            let obj1_params = objects.get(obj1_name).unwrap();
-            let obj2_params = objects.get(obj2_name).unwrap();
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
 
             system.add_object(obj1_name, obj1_params);
             system.add_object(obj2_name, obj2_params);
 
-            let object1_idx = *system.sys_objects_idx.get(obj1_name).unwrap();
-            let object2_idx = *system.sys_objects_idx.get(obj2_name).unwrap();
-            let c_params = constraint_parameters.get(c).unwrap();
-            constraints::equality_constraint::set_up_equalities(
-                &c_params,
-                object1_idx,
-                object2_idx,
-                &mut system.sys_objects,
-            );
+            let object1_idx = system.sys_objects_idx[obj1_name];
+            let object2_idx = system.sys_objects_idx[obj2_name];
+            let c_params = get_parameters(c, constraint_parameters)?;
+            let use_penalty = c_params.get("penalty").is_some_and(|penalty| *penalty != 0.0);
+            let pairs = constraints::equality_constraint::equality_pairs(c_params)
+                .map_err(|message| SolverError::new(format!("constraint '{}': {}", c, message)))?;
+            if object1_idx == object2_idx {
+                if let Some((var_name, _, _)) = pairs.iter().find(|(var_name1, var_name2, _)| var_name1 == var_name2) {
+                    return Err(SolverError::new(format!(
+                        "constraint '{}': variable '{}' of '{}' can't be equal to itself",
+                        c, var_name.as_str(), obj1_name
+                    )));
+                }
+            }
+            if !use_penalty {
+                if let Some((var_name1, var_name2)) = constraints::equality_constraint::find_equality_cycle(
+                    &system.sys_objects, object1_idx, object2_idx, &pairs
+                ) {
+                    return Err(SolverError::new(format!(
+                        "constraint '{}': making '{}' of '{}' equal to '{}' of '{}' makes a cycle of equal variables",
+                        c, var_name2.as_str(), obj2_name, var_name1.as_str(), obj1_name
+                    )));
+                }
+            }
+            let equality_constraint =
+                constraints::EqualityConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    pairs,
+                    use_penalty,
+                    object1_idx,
+                    object2_idx
+                );
+            system.add_constraint(ConstraintType::EqualityConstraint(Box::new(equality_constraint)));
+        }
+        if c.contains("PlacementEqual") {
+            // The same as an equality of the six variables: the placement of
+            // the second object takes the solver variables of the first one
+            let obj1_name = get_object_name(c, object_names, "Object1")?;
+            let obj2_name = get_object_name(c, object_names, "Object2")?;
+
+            let obj1_params = get_placement(c, objects, obj1_name)?;
+            let obj2_params = get_placement(c, objects, obj2_name)?;
+
+            system.add_object(obj1_name, obj1_params);
+            system.add_object(obj2_name, obj2_params);
+
+            let object1_idx = system.sys_objects_idx[obj1_name];
+            let object2_idx = system.sys_objects_idx[obj2_name];
+            if object1_idx == object2_idx {
+                return Err(SolverError::new(format!(
+                    "constraint '{}': the placement of '{}' can't be equal to itself", c, obj1_name
+                )));
+            }
+
+            // an equality made by an earlier constraint is not overwritten
+            let variables: Vec<VN> = VN::get_variable_iter().collect();
+            if let Some(var_name) = constraints::equality_constraint::find_equality_conflict(
+                &system.sys_objects, object1_idx, object2_idx, &variables
+            ) {
+                return Err(SolverError::new(format!(
+                    "constraint '{}': variable '{}' of '{}' and '{}' is already used by another equality",
+                    c, var_name.as_str(), obj1_name, obj2_name
+                )));
+            }
+
+            let pairs = variables.iter().map(|var_name| (*var_name, *var_name, 1.0)).collect();
+            let equality_constraint =
+                constraints::EqualityConstraint::new(
+                    c,
+                    &mut system.sys_objects,
+                    pairs,
+                    false,
+                    object1_idx,
+                    object2_idx
+                );
+            system.add_constraint(ConstraintType::EqualityConstraint(Box::new(equality_constraint)));
         }
     }
+    // an optional "weight" parameter multiplies the constraint function (a
+    // Lock uses its weight as the penalty of a soft lock instead)
+    let mut weighted = Vec::with_capacity(system.constraints.len());
+    for constraint in system.constraints.drain(..) {
+        let weight = constraint_parameters
+            .get(constraint.get_name())
+            .and_then(|c_params| c_params.get("weight"))
+            .filter(|_| !constraint.get_name().contains("Lock"));
+        match weight {
+            Some(weight) if !(weight.is_finite() && *weight > 0.0) => {
+                return Err(SolverError::new(format!(
+                    "the weight of constraint '{}' must be a positive number", constraint.get_name(),
+                )));
+            },
+            Some(weight) => weighted.push(ConstraintType::WeightedConstraint(
+                constraints::WeightedConstraint::new(constraint, *weight)
+            )),
+            None => weighted.push(constraint),
+        }
+    }
+    system.constraints = weighted;
 
-    // Un-comment this part in order to solve the problem (it is faster than the
-    // implementation in python
-        system.add_indices();
-        let x0 = system.start_position();
+    // equality chains are allowed, but a cycle of equalities has no variable
+    // to take the index from (see System::validate for the other checks)
+    system.validate().map_err(|errors| {
+        let messages: Vec<&str> = errors.iter().map(|error| error.message()).collect();
+        SolverError::new(messages.join("; "))
+    })?;
+    system.finalize();
+    Ok(())
+}
 
-        let mut min = TrustNCG::new();
 
-        let sol = min.minimize(&x0, &mut system);
+/// Returns the name of the object with the given role (for example "Object" or
+/// "Reference") in the constraint c
+fn get_object_name<'m>(
+    c: &str,
+    object_names: &'m HashMap<&str, &str>,
+    role: &str,
+) -> Result<&'m str, SolverError> {
+    object_names
+        .get(role)
+        .copied()
+        .ok_or_else(|| SolverError::new(format!("constraint '{}' is missing its '{}' entry", c, role)))
+}
 
-//         println!("Solution succeeded?: {}, iterations: {}, function evaluations: {}, \
-//         gradient evaluations: {}", sol.success, sol.iter_num, sol.f_evals, sol.f_grad_evals);
-//         println!("solution x: {}", sol.x);
 
+/// Returns the names of the objects of a constraint with any number of objects,
+/// given by the roles "Object1", "Object2", ... "ObjectN" in this order. Every
+/// role up to the largest number must be present.
+fn get_numbered_object_names<'m>(
+    c: &str,
+    object_names: &'m HashMap<&str, &str>,
+) -> Result<Vec<&'m str>, SolverError> {
+    let count = object_names
+        .keys()
+        .filter_map(|role| role.strip_prefix("Object").and_then(|k| k.parse::<usize>().ok()))
+        .max()
+        .unwrap_or(0);
+    (1..=count)
+        .map(|k| get_object_name(c, object_names, &format!("Object{}", k)))
+        .collect()
+}
 
-        let mut obj_idx: usize;
-        let mut sys_object: &system_object::SystemObject;
-        let mut var_name: VN;
-        for (obj, vars) in objects.iter_mut() {
-            obj_idx = *system.sys_objects_idx.get(obj).unwrap();
-            sys_object = &system.sys_objects[obj_idx];
-            for (var_name_str, var_value ) in vars.iter_mut() {
-                var_name = VN::get_from_str(var_name_str);
-                *var_value = sys_object.get_variable(var_name).value;
-            }
+
+/// Returns the name of the attachment point given by the role (for example
+/// "Attachment1") of a constraint, or None when the constraint uses the origin
+/// of the object. The object must be already added to the system and have an
+/// attachment point with that name.
+fn get_attachment_name<'m>(
+    c: &str,
+    object_names: &'m HashMap<&str, &str>,
+    role: &str,
+    system: &System,
+    obj_name: &str,
+) -> Result<Option<&'m str>, SolverError> {
+    let attachment = match object_names.get(role) {
+        Some(attachment) => *attachment,
+        None => return Ok(None),
+    };
+    let object = &system.sys_objects[system.sys_objects_idx[obj_name]];
+    if object.get_attachment(attachment).is_none() {
+        return Err(SolverError::new(format!(
+            "constraint '{}' references attachment point '{}' which object '{}' does not have",
+            c, attachment, obj_name,
+        )));
+    }
+    Ok(Some(attachment))
+}
+
+
+/// Returns the coefficients of the variables of the objects of a constraint
+/// as triples of (object name, variable, coefficient). They are given by the
+/// parameters with dotted keys "<role>.<variable>", for example "Object1.x",
+/// where the role is one of the constraint names of the constraint. The other
+/// parameters are skipped, and the coefficients are sorted by their keys.
+fn get_variable_coefficients<'m>(
+    c: &str,
+    object_names: &'m HashMap<&str, &str>,
+    c_params: &HashMap<&str, f64>,
+) -> Result<Vec<(&'m str, VN, f64)>, SolverError> {
+    let mut keys: Vec<&&str> = c_params.keys().filter(|key| key.contains('.')).collect();
+    keys.sort();
+    let mut coefficients = Vec::with_capacity(keys.len());
+    for key in keys {
+        let (role, var_name_str) = key.rsplit_once('.').unwrap();
+        let obj_name = get_object_name(c, object_names, role)?;
+        let var_name = VN::parse(var_name_str).ok_or_else(|| {
+            SolverError::new(format!(
+                "constraint '{}' has the parameter '{}' of the unknown variable '{}'", c, key, var_name_str,
+            ))
+        })?;
+        coefficients.push((obj_name, var_name, c_params[*key]));
+    }
+    Ok(coefficients)
+}
+
+
+/// Returns the name of the "Reference" of a constraint, or None when the
+/// constraint is relative to the global coordinate system: it has no reference
+/// or its reference is "Origin" (and no object has that name)
+fn get_reference_name<'m>(
+    objects: &HashMap<&str, HashMap<&str, f64>>,
+    object_names: &'m HashMap<&str, &str>,
+) -> Option<&'m str> {
+    match object_names.get("Reference") {
+        Some(&"Origin") if !objects.contains_key("Origin") => None,
+        Some(ref_name) => Some(*ref_name),
+        None => None,
+    }
+}
+
+
+/// Returns the values of the variables var_names in the parameters of a fix
+/// constraint. The missing values are 0.
+fn global_targets<I>(c_params: &HashMap<&str, f64>, var_names: I) -> Vec<(VN, f64)>
+where
+    I: Iterator<Item = VN>,
+{
+    var_names
+        .map(|var_name| (var_name, c_params.get(var_name.as_str()).copied().unwrap_or(0.0)))
+        .collect()
+}
+
+
+/// Adds a constraint that fixes variables of an object to absolute values:
+/// (x - target)^2 for each fixed variable. It does not need a reference object.
+fn add_global_fix(
+    system: &mut System,
+    c: &str,
+    obj_name: &str,
+    obj_params: &HashMap<&str, f64>,
+    targets: Vec<(VN, f64)>,
+) {
+    system.add_object(obj_name, obj_params);
+    let obj_idx = system.sys_objects_idx[obj_name];
+    let fix_constraint =
+        constraints::LockConstraint::new(
+            c,
+            &mut system.sys_objects,
+            targets,
+            1.0,
+            obj_idx
+        );
+    system.add_constraint(ConstraintType::LockConstraint(fix_constraint));
+}
+
+
+/// Returns the placement of an object used by the constraint c. Every
+/// placement variable (x, y, z, phi, theta and psi) must be given.
+fn get_placement<'m>(
+    c: &str,
+    objects: &'m HashMap<&str, HashMap<&str, f64>>,
+    obj_name: &str,
+) -> Result<&'m HashMap<&'m str, f64>, SolverError> {
+    let placement = objects.get(obj_name).ok_or_else(|| {
+        SolverError::new(format!(
+            "constraint '{}' references object '{}' which is not in the objects map", c, obj_name,
+        ))
+    })?;
+    for var_name in VN::get_variable_iter() {
+        if !placement.contains_key(var_name.as_str()) {
+            return Err(SolverError::new(format!(
+                "object '{}' (used by constraint '{}') is missing the placement key '{}'",
+                obj_name, c, var_name.as_str(),
+            )));
         }
-        (objects, sol.success)
+    }
+    Ok(placement)
+}
+
+
+/// Returns the parameters of the constraint c
+fn get_parameters<'m>(
+    c: &str,
+    constraint_parameters: &'m HashMap<&str, HashMap<&str, f64>>,
+) -> Result<&'m HashMap<&'m str, f64>, SolverError> {
+    constraint_parameters
+        .get(c)
+        .ok_or_else(|| SolverError::new(format!("constraint '{}' is not in the constraint parameters map", c)))
 }
@@ -0,0 +1,727 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::f64::consts::PI;
+use std::time::Instant;
+
+use ndarray::Array1;
+
+use optimization::TrustNCG;
+use optimization::problem::Objective;
+
+use crate::geometry::euler;
+use crate::system::System;
+use crate::system_object::VariableName as VN;
+
+mod linalg;
+pub(crate) use linalg::rank;
+
+mod levenberg_marquardt;
+use levenberg_marquardt::LevenbergMarquardt;
+
+mod gauss_newton;
+use gauss_newton::{GaussNewton, GaussNewtonResult};
+
+mod lbfgs;
+use lbfgs::Lbfgs;
+
+mod newton;
+use newton::{Newton, NewtonResult, newton_applicable};
+
+mod newton_cg;
+use newton_cg::NewtonCg;
+
+mod solver_options;
+pub use solver_options::{Method, SolverOptions, Strategy};
+
+mod monitor;
+pub use monitor::{Monitor, NonFiniteRecord, StagnationWindow, StopReason};
+
+mod scaled_problem;
+use scaled_problem::{ScaledProblem, variable_scales};
+
+mod polish;
+pub use polish::Polish;
+
+mod step_limit;
+use step_limit::StepLimit;
+
+mod random;
+use random::Random;
+
+mod report;
+pub use report::{
+    Attempt, ComponentReport, ConstraintAttribution, ConstraintError, ContinuationFailure,
+    GradientContribution, LockShadow, Reseed, SolveReport,
+};
+
+mod stats;
+pub use stats::{SolveStats, Termination};
+
+mod memory;
+pub use memory::memory_estimate;
+
+#[cfg(test)]
+mod tests;
+
+
+/// Relative decrease of the objective function that a run of the minimization
+/// method must achieve to be considered as making progress
+const PROGRESS_TOLERANCE: f64 = 1e-6;
+
+/// Factor applied to the gradient tolerance each time the minimization method
+/// is run again because it stopped on its gradient criterion with some
+/// constraint still unsatisfied
+const GRADIENT_TOLERANCE_REDUCTION: f64 = 1e-3;
+
+/// Objects with a rotation closer than this value to the gimbal lock are
+/// reseeded (see euler::gimbal_lock_distance)
+const GIMBAL_LOCK_TOLERANCE: f64 = 1e-2;
+
+
+/// Result of minimizing the constraint system
+#[derive(Debug)]
+pub struct Solution {
+    /// Values of the solver variables at the end of the minimization
+    pub x: Array1<f64>,
+    /// States whether the minimization converged
+    pub success: bool,
+    /// Number of iterations
+    pub iter_num: usize,
+    /// Number of evaluations of the objective function
+    pub f_evals: usize,
+    /// Number of evaluations of the gradient (or jacobian)
+    pub f_grad_evals: usize,
+}
+
+
+/// Minimizes the system with the method and strategy selected in the options.
+/// The solved values are stored in the variables of the system objects.
+///
+/// The returned statistics are also stored in the report of the system.
+pub fn solve(system: &mut System, options: &SolverOptions) -> SolveStats {
+    let start = Instant::now();
+
+    // The system is not solved when it would need too much memory
+    system.update_indices();
+    let memory = memory_estimate(system, options);
+    if options.max_memory_bytes.is_some_and(|max_memory| memory > max_memory) {
+        let mut stats = SolveStats::unsolved(Termination::MemoryLimit);
+        stats.wall_time = start.elapsed();
+        stats.memory_estimate = memory;
+        system.report = SolveReport::new();
+        system.report.stats = Some(stats.clone());
+        system.report.preset = options.preset.map(|preset| preset.to_string());
+        return stats;
+    }
+
+    let initial = if options.attribution {
+        Some(system.attribution_snapshot())
+    } else {
+        None
+    };
+    system.f_evals = 0;
+    system.grad_evals = 0;
+    system.hess_evals = 0;
+
+    let floating_locks = if options.regularization > 0.0 {
+        lock_floating_references(system)
+    } else {
+        Vec::new()
+    };
+
+    let sol = if options.stay_near_start_weight > 0.0 {
+        solve_near_start(system, options)
+    } else if options.continuation_steps > 0 {
+        solve_continuation(system, options)
+    } else {
+        solve_strategy(system, options)
+    };
+
+    if !floating_locks.is_empty() {
+        unlock(system, &floating_locks);
+        system.update_indices();
+        for (obj_idx, var_name) in floating_locks {
+            let name = (system.get_object_name(obj_idx).to_string(), var_name.as_str().to_string());
+            if !system.report.regularized.contains(&name) {
+                system.report.regularized.push(name);
+            }
+        }
+        system.report.regularized.sort();
+    }
+
+    let stats = SolveStats {
+        success: sol.success,
+        iterations: sol.iter_num,
+        f_evals: system.f_evals,
+        f_grad_evals: system.grad_evals,
+        hessian_evals: system.hess_evals,
+        wall_time: start.elapsed(),
+        memory_estimate: memory,
+        termination: Termination::from_report(sol.success, &system.report, options.max_iterations),
+    };
+    system.report.stats = Some(stats.clone());
+    system.report.preset = options.preset.map(|preset| preset.to_string());
+    if !stats.success {
+        system.report.worst_constraints = system.worst_constraints();
+    }
+    if options.gradient_breakdown {
+        system.report.gradient_breakdown = system.gradient_breakdown();
+    }
+    if options.lock_shadows {
+        system.report.lock_shadows = system.lock_shadows();
+    }
+    if let Some(initial) = initial {
+        system.report.attribution = system.attribution(&initial);
+    }
+    stats
+}
+
+
+/// Locks the free variables of the reference object of each floating component
+/// of the system (see System::floating_references) so the component can't
+/// drift as a whole. Returns the locked variables, which must be unlocked after
+/// the solve.
+fn lock_floating_references(system: &mut System) -> Vec<(usize, VN)> {
+    let mut locks = Vec::new();
+    for obj_idx in system.floating_references() {
+        let object = &mut system.sys_objects[obj_idx];
+        for var_name in VN::get_variable_iter() {
+            let variable = object.get_mut_variable(var_name);
+            if variable.enabled && !variable.locked && variable.equal.is_none() {
+                variable.locked = true;
+                locks.push((obj_idx, var_name));
+            }
+        }
+    }
+    if !locks.is_empty() {
+        system.indexed = false;
+    }
+    locks
+}
+
+
+/// Unlocks the variables locked during a solve
+fn unlock(system: &mut System, locks: &[(usize, VN)]) {
+    for (obj_idx, var_name) in locks {
+        system.sys_objects[*obj_idx].get_mut_variable(*var_name).locked = false;
+    }
+    system.indexed = false;
+}
+
+
+/// Solves the system with the strategy selected in the options
+fn solve_strategy(system: &mut System, options: &SolverOptions) -> Solution {
+    match options.strategy {
+        Strategy::Single => solve_single(system, options),
+        Strategy::Staged => solve_staged(system, options),
+    }
+}
+
+
+/// Solves the system with a penalty pulling the solver variables towards their
+/// initial values (see SolverOptions::stay_near_start_weight), and then solves
+/// it again without the penalty starting from that solution. The first solve
+/// keeps the variables that the constraints leave free near their initial
+/// values, and the second one removes the small error that the penalty leaves
+/// in the constraints.
+fn solve_near_start(system: &mut System, options: &SolverOptions) -> Solution {
+    system.set_start(options.stay_near_start_weight);
+    let near_start = if options.continuation_steps > 0 {
+        solve_continuation(system, options)
+    } else {
+        solve_strategy(system, options)
+    };
+    system.set_start(0.0);
+    let mut sol = solve_strategy(system, options);
+    sol.iter_num += near_start.iter_num;
+    sol.f_evals += near_start.f_evals;
+    sol.f_grad_evals += near_start.f_grad_evals;
+    sol
+}
+
+
+/// Solves a sequence of systems where the constraint parameters go linearly
+/// from the values satisfied by the initial placements to the values given by
+/// the user. Each system starts from the solution of the previous one.
+///
+/// The first intermediate system that fails is recorded in the report, but the
+/// sequence continues until the parameters given by the user are solved.
+fn solve_continuation(system: &mut System, options: &SolverOptions) -> Solution {
+    // The objects must be updated with the initial placements to find the
+    // parameters satisfied by them.
+    system.update_indices();
+    let x0 = system.get_position();
+    system.update_x(&x0);
+    let start: Vec<Vec<f64>> = system.constraints
+        .iter()
+        .map(|c| c.current_parameters(&system.sys_objects))
+        .collect();
+    let target: Vec<Vec<f64>> = system.constraints
+        .iter()
+        .map(|c| c.get_parameters())
+        .collect();
+
+    let steps = options.continuation_steps;
+    let mut failure = None;
+    let mut iter_num = 0;
+    let mut f_evals = 0;
+    let mut f_grad_evals = 0;
+    let mut sol = None;
+    for step in 1..=steps {
+        let t = step as f64 / steps as f64;
+        for (constraint, (p_start, p_target)) in system.constraints
+            .iter_mut()
+            .zip(start.iter().zip(&target))
+        {
+            if step == steps {
+                constraint.set_parameters(p_target);
+            } else {
+                let parameters: Vec<f64> = p_start
+                    .iter()
+                    .zip(p_target)
+                    .map(|(a, b)| a + t * (b - a))
+                    .collect();
+                constraint.set_parameters(&parameters);
+            }
+        }
+
+        let step_sol = solve_strategy(system, options);
+        iter_num += step_sol.iter_num;
+        f_evals += step_sol.f_evals;
+        f_grad_evals += step_sol.f_grad_evals;
+        if !step_sol.success && step < steps && failure.is_none() {
+            let constraints = system.unsatisfied_constraints()
+                .iter()
+                .map(|i| system.constraints[*i].get_name().to_string())
+                .collect();
+            failure = Some(ContinuationFailure {
+                step,
+                constraints,
+            });
+        }
+        sol = Some(step_sol);
+    }
+
+    system.report.continuation_failure = failure;
+    let mut sol = sol.unwrap();
+    sol.iter_num = iter_num;
+    sol.f_evals = f_evals;
+    sol.f_grad_evals = f_grad_evals;
+    sol
+}
+
+
+/// Solves the system in two stages. The first stage solves the system with
+/// every rotation variable locked at its initial value, and the second stage
+/// solves all the variables starting from the solution of the first stage.
+///
+/// The report of the system contains the reports of both stages.
+fn solve_staged(system: &mut System, options: &SolverOptions) -> Solution {
+    // Only the rotation variables that are not already locked by the user are
+    // locked (and unlocked after the first stage).
+    let mut stage_locks = Vec::new();
+    for (obj_idx, object) in system.sys_objects.iter_mut().enumerate() {
+        for var_name in VN::get_rotation_iter() {
+            let variable = object.get_mut_variable(var_name);
+            if variable.enabled && !variable.locked {
+                variable.locked = true;
+                stage_locks.push((obj_idx, var_name));
+            }
+        }
+    }
+    system.indexed = false;
+    let first = solve_single(system, options);
+    let first_report = std::mem::replace(&mut system.report, SolveReport::new());
+
+    unlock(system, &stage_locks);
+    let mut sol = solve_single(system, options);
+
+    let second_report = system.report.clone();
+    system.report.stages = vec![first_report, second_report];
+    sol.iter_num += first.iter_num;
+    sol.f_evals += first.f_evals;
+    sol.f_grad_evals += first.f_grad_evals;
+    sol
+}
+
+
+/// Solves all the variables of the system at the same time.
+///
+/// The system is solved when every constraint is satisfied within its residual
+/// tolerance. If the first attempt fails, the system is solved again up to
+/// `options.restarts` times starting from random perturbations of the objects
+/// of the unsatisfied constraints, and the best attempt is kept. Every attempt
+/// is recorded in the report of the system.
+///
+/// A successful solution is then polished with `options.polish_iterations`
+/// Newton steps.
+fn solve_single(system: &mut System, options: &SolverOptions) -> Solution {
+    system.update_indices();
+    system.report = SolveReport::new();
+    let mut random = Random::new(options.seed);
+
+    let x0 = system.get_position();
+    let evals_start = (system.f_evals, system.grad_evals);
+    let mut best = solve_attempt(system, x0, options);
+    let mut best_value = system.eval_real();
+    record_attempt(system, &best, best_value, evals_start);
+
+    let mut iter_num = best.iter_num;
+    let mut f_evals = best.f_evals;
+    let mut f_grad_evals = best.f_grad_evals;
+
+    for _ in 0..options.restarts {
+        if best.success || system.monitor.budget_exhausted(system.f_evals, system.grad_evals) {
+            break;
+        }
+        // The system variables hold the best solution so far
+        system.update_x(&best.x);
+        system.eval_real();
+        let x0 = perturbed_position(system, &best.x, &mut random, options);
+
+        let evals_start = (system.f_evals, system.grad_evals);
+        let sol = solve_attempt(system, x0, options);
+        let value = system.eval_real();
+        record_attempt(system, &sol, value, evals_start);
+        iter_num += sol.iter_num;
+        f_evals += sol.f_evals;
+        f_grad_evals += sol.f_grad_evals;
+        if sol.success || value < best_value {
+            best = sol;
+            best_value = value;
+        }
+    }
+
+    system.update_x(&best.x);
+    system.eval_real();
+    if best.success && options.polish_iterations > 0 {
+        let polish = polish::polish(system, &mut best.x, options.polish_iterations);
+        system.report.polish = Some(polish);
+    }
+    best.iter_num = iter_num;
+    best.f_evals = f_evals;
+    best.f_grad_evals = f_grad_evals;
+    best
+}
+
+
+/// Solves the system once starting from x0.
+///
+/// The minimization method stops as soon as every constraint is satisfied, and
+/// if the method stops before (while it is still reducing the objective
+/// function) it is run again from the point where it stopped until
+/// max_iterations is reached. Each new run uses a smaller gradient tolerance so
+/// a method that stopped on its gradient criterion keeps iterating.
+///
+/// If the attempt stagnates with an object close to the gimbal lock, the
+/// rotation angles of the object are reseeded once (see reseed_gimbal_locks)
+/// and the attempt continues.
+///
+/// When the attempt fails, the best point seen by the monitor is returned
+/// instead of the last one. If the objective function was growing at the end
+/// of the attempt, the attempt is marked as diverged.
+fn solve_attempt(system: &mut System, x0: Array1<f64>, options: &SolverOptions) -> Solution {
+    system.monitor = Monitor::new(options);
+    let mut sol = Solution {
+        x: x0,
+        success: false,
+        iter_num: 0,
+        f_evals: 0,
+        f_grad_evals: 0,
+    };
+
+    let mut run_options = options.clone();
+    let mut reseeded = false;
+    loop {
+        system.update_x(&sol.x);
+        let f_start = system.eval_real();
+        let run = minimize(system, &sol.x, &run_options);
+        sol.x = run.x;
+        sol.iter_num += run.iter_num;
+        sol.f_evals += run.f_evals;
+        sol.f_grad_evals += run.f_grad_evals;
+
+        // The last point evaluated by the minimizer may not be the solution (for
+        // example a rejected trial step) so we make sure the system variables
+        // hold the solution values.
+        system.update_x(&sol.x);
+        let f_end = system.eval_real();
+
+        if system.within_tolerance() {
+            sol.success = true;
+            system.monitor.stop = Some(StopReason::Converged);
+            break;
+        }
+        // Some constraint is not satisfied; only keep going while the method is
+        // making progress.
+        let progress = f_end < f_start * (1.0 - PROGRESS_TOLERANCE);
+        let stagnated = !progress || system.monitor.stop == Some(StopReason::Stagnated);
+        if stagnated && !reseeded && sol.iter_num < options.max_iterations {
+            reseeded = true;
+            if reseed_gimbal_locks(system) {
+                sol.x = system.get_position();
+                system.monitor.stop = None;
+                continue;
+            }
+        }
+        if !progress || sol.iter_num >= options.max_iterations {
+            break;
+        }
+        match system.monitor.stop {
+            Some(StopReason::Diverged)
+            | Some(StopReason::Stagnated)
+            | Some(StopReason::NonFinite)
+            | Some(StopReason::BudgetExhausted) => break,
+            _ => (),
+        }
+        system.monitor.stop = None;
+        run_options.gradient_tolerance *= GRADIENT_TOLERANCE_REDUCTION;
+    }
+
+    if !sol.success {
+        let f_end = system.eval_real();
+        if let Some(best_x) = system.monitor.best_x.clone() {
+            if system.monitor.best_value < f_end || !f_end.is_finite() {
+                sol.x = best_x;
+                system.update_x(&sol.x);
+                system.eval_real();
+                match system.monitor.stop {
+                    Some(StopReason::Stagnated)
+                    | Some(StopReason::NonFinite)
+                    | Some(StopReason::BudgetExhausted) => (),
+                    _ => system.monitor.stop = Some(StopReason::Diverged),
+                }
+            }
+        }
+    }
+    sol
+}
+
+
+/// Adds the outcome of an attempt to the report of the system. evals_start
+/// are the numbers of function and gradient evaluations of the system when the
+/// attempt started.
+fn record_attempt(system: &mut System, sol: &Solution, value: f64, evals_start: (usize, usize)) {
+    system.report.attempts.push(Attempt {
+        success: sol.success,
+        value,
+        iter_num: sol.iter_num,
+        reason: system.monitor.stop,
+        best_iteration: system.monitor.best_iteration,
+        stagnation: system.monitor.stagnation,
+        non_finite: system.monitor.non_finite.clone(),
+        f_evals: system.f_evals - evals_start.0,
+        f_grad_evals: system.grad_evals - evals_start.1,
+    });
+
+    let mut regularized = Vec::new();
+    for index in &system.monitor.regularized {
+        for (obj_name, var_name) in system.get_variable_names(*index) {
+            regularized.push((obj_name, var_name.as_str().to_string()));
+        }
+    }
+    for name in regularized {
+        if !system.report.regularized.contains(&name) {
+            system.report.regularized.push(name);
+        }
+    }
+    system.report.regularized.sort();
+}
+
+
+/// Changes the rotation angles of the objects close to the gimbal lock to
+/// angles away from it. Only the objects whose 3 rotation angles are solver
+/// variables (not locked or equal to other variables) are reseeded. Returns
+/// true if any object was reseeded.
+///
+/// The reseeded objects are recorded in the report of the system.
+fn reseed_gimbal_locks(system: &mut System) -> bool {
+    let mut reseeds = Vec::new();
+    for (obj_idx, object) in system.sys_objects.iter_mut().enumerate() {
+        let free = VN::get_rotation_iter().all(|var_name| {
+            let variable = object.get_variable(var_name);
+            variable.index.is_some() && variable.equal.is_none()
+        });
+        let theta = object.get_variable(VN::theta).value;
+        if !free || euler::gimbal_lock_distance(theta) >= GIMBAL_LOCK_TOLERANCE {
+            continue;
+        }
+        let phi = object.get_variable(VN::phi).value;
+        let psi = object.get_variable(VN::psi).value;
+        let (after, exact) = euler::reseed_angles(phi, theta, psi, GIMBAL_LOCK_TOLERANCE);
+        object.get_mut_variable(VN::phi).value = after.0;
+        object.get_mut_variable(VN::theta).value = after.1;
+        object.get_mut_variable(VN::psi).value = after.2;
+        reseeds.push((obj_idx, [phi, theta, psi], [after.0, after.1, after.2], exact));
+    }
+
+    let reseeded = !reseeds.is_empty();
+    for (obj_idx, before, after, exact) in reseeds {
+        let object = system.get_object_name(obj_idx).to_string();
+        system.report.reseeded.push(Reseed {
+            object,
+            before,
+            after,
+            exact,
+        });
+    }
+    reseeded
+}
+
+
+/// Returns a copy of x where the free variables of the objects used by the
+/// unsatisfied constraints are moved by a random offset. The constraints must
+/// be already evaluated at x.
+fn perturbed_position(
+        system: &System,
+        x: &Array1<f64>,
+        random: &mut Random,
+        options: &SolverOptions,
+) -> Array1<f64> {
+    let mut new_x = x.clone();
+    // Equal variables share the same index so we make sure each index is only
+    // perturbed once.
+    let mut perturbed = vec![false; x.len()];
+    let max_angle = options.restart_angle_fraction * PI;
+
+    for c_idx in system.unsatisfied_constraints() {
+        for obj_index in system.constraints[c_idx].affected_objects() {
+            let object = &system.sys_objects[obj_index];
+            let variables = VN::get_position_iter()
+                .map(|var| (var, options.restart_magnitude))
+                .chain(VN::get_rotation_iter().map(|var| (var, max_angle)));
+            for (var_name, magnitude) in variables {
+                if let Some(k) = object.get_variable(var_name).index {
+                    if !perturbed[k] {
+                        new_x[k] += magnitude * random.next_symmetric();
+                        perturbed[k] = true;
+                    }
+                }
+            }
+        }
+    }
+    new_x
+}
+
+
+/// Runs the minimization method once starting from x0
+fn minimize(system: &mut System, x0: &Array1<f64>, options: &SolverOptions) -> Solution {
+    match options.method {
+        Method::Auto => minimize_auto(system, x0, options),
+        Method::TrustNCG => minimize_trust_ncg(system, x0, options),
+        Method::Newton => {
+            let mut min = Newton::new(options);
+            match min.minimize(x0, system) {
+                NewtonResult::Done(sol) => sol,
+                NewtonResult::IllConditioned(sol) => continue_trust_ncg(system, sol, options),
+            }
+        },
+        Method::NewtonCg => {
+            let mut min = NewtonCg::new(options);
+            min.minimize(x0, system)
+        },
+        Method::LevenbergMarquardt => {
+            let mut min = LevenbergMarquardt::new(options);
+            min.minimize(x0, system)
+        },
+        Method::GaussNewton => {
+            let mut min = GaussNewton::new(options);
+            match min.minimize(x0, system) {
+                GaussNewtonResult::Done(sol) => sol,
+                GaussNewtonResult::RankDeficient(sol) => continue_levenberg_marquardt(system, sol, options),
+            }
+        },
+        Method::Lbfgs => {
+            let mut min = Lbfgs::new(options);
+            min.minimize(x0, system)
+        },
+        Method::LbfgsNewton => {
+            let mut min = Lbfgs::new(options);
+            let sol = min.minimize(x0, system);
+            if system.monitor.stop.is_none() {
+                continue_trust_ncg(system, sol, options)
+            } else {
+                sol
+            }
+        },
+    }
+}
+
+
+/// Runs the Newton method on small systems whose hessian at x0 is positive
+/// definite and well conditioned, and TrustNCG on the rest. A Newton run that
+/// stops without converging (and without being stopped by the monitor) is
+/// continued with TrustNCG.
+fn minimize_auto(system: &mut System, x0: &Array1<f64>, options: &SolverOptions) -> Solution {
+    if x0.len() > options.newton_max_dimension || !newton_applicable(system, x0) {
+        return minimize_trust_ncg(system, x0, options);
+    }
+    let mut min = Newton::new(options);
+    match min.minimize(x0, system) {
+        NewtonResult::Done(sol) if sol.success || system.monitor.stop.is_some() => sol,
+        NewtonResult::Done(sol) | NewtonResult::IllConditioned(sol) => {
+            continue_trust_ncg(system, sol, options)
+        },
+    }
+}
+
+
+/// Runs TrustNCG starting from the solution of another method. The returned
+/// solution includes the iterations and evaluations of both methods.
+fn continue_trust_ncg(system: &mut System, sol: Solution, options: &SolverOptions) -> Solution {
+    let trust_sol = minimize_trust_ncg(system, &sol.x, options);
+    Solution {
+        x: trust_sol.x,
+        success: trust_sol.success,
+        iter_num: sol.iter_num + trust_sol.iter_num,
+        f_evals: sol.f_evals + trust_sol.f_evals,
+        f_grad_evals: sol.f_grad_evals + trust_sol.f_grad_evals,
+    }
+}
+
+
+/// Runs Levenberg-Marquardt starting from the solution of another method. The
+/// returned solution includes the iterations and evaluations of both methods.
+fn continue_levenberg_marquardt(system: &mut System, sol: Solution, options: &SolverOptions) -> Solution {
+    let mut min = LevenbergMarquardt::new(options);
+    let lm_sol = min.minimize(&sol.x, system);
+    Solution {
+        x: lm_sol.x,
+        success: lm_sol.success,
+        iter_num: sol.iter_num + lm_sol.iter_num,
+        f_evals: sol.f_evals + lm_sol.f_evals,
+        f_grad_evals: sol.f_grad_evals + lm_sol.f_grad_evals,
+    }
+}
+
+
+/// Runs TrustNCG once starting from x0. The variables are scaled so the trust
+/// region treats angles and lengths differently.
+fn minimize_trust_ncg(system: &mut System, x0: &Array1<f64>, options: &SolverOptions) -> Solution {
+    let scale = variable_scales(system, options);
+    let mut problem = ScaledProblem::new(system, scale);
+    let y0 = problem.scaled(x0);
+    let mut min = TrustNCG::new();
+    let sol = min.minimize(&y0, &mut problem);
+    Solution {
+        x: problem.unscaled(&sol.x),
+        success: sol.success,
+        iter_num: sol.iter_num as usize,
+        f_evals: sol.f_evals as usize,
+        f_grad_evals: sol.f_grad_evals as usize,
+    }
+}
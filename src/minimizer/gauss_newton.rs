@@ -0,0 +1,167 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::Array1;
+
+use optimization::problem::Objective;
+
+use crate::minimizer::levenberg_marquardt::{max_abs, normal_equations, sum_squares, total_sum_squares};
+use crate::minimizer::linalg::{cholesky, cholesky_condition, cholesky_substitution};
+use crate::minimizer::{Solution, SolverOptions, StopReason};
+use crate::minimizer::StepLimit;
+use crate::system::System;
+
+
+/// Sufficient decrease parameter of the Armijo condition
+const ARMIJO: f64 = 1e-4;
+/// The line search fails when the step length becomes smaller than this value
+const MIN_STEP_LENGTH: f64 = 1e-20;
+/// Largest estimated condition number of J^T J accepted by the method
+const MAX_CONDITION: f64 = 1e12;
+
+
+/// Result of the Gauss-Newton method
+pub enum GaussNewtonResult {
+    /// The minimization finished (it may have failed to converge)
+    Done(Solution),
+    /// The matrix J^T J at the last point of the solution is singular or
+    /// ill-conditioned (the jacobian does not have full rank), so a
+    /// Gauss-Newton step cannot be trusted
+    RankDeficient(Solution),
+}
+
+
+/// Gauss-Newton method with a backtracking line search.
+///
+/// The objective function of the system is the sum of the squares of the
+/// constraint residuals f(x) = r(x)^T r(x). Each step solves J^T J p = -J^T r,
+/// where J is the jacobian of the residuals, so only first derivatives are
+/// needed. Without the damping of Levenberg-Marquardt the steps are full
+/// Gauss-Newton steps, which converge fast on overdetermined systems (many
+/// more residuals than variables) whose residuals vanish at the solution. The
+/// method stops when J^T J can't be used and the caller should continue with
+/// a method that handles rank deficient jacobians.
+pub struct GaussNewton {
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Tolerance of the largest component of the gradient
+    gradient_tolerance: f64,
+    /// Largest change of a position variable in a single step
+    max_translation_step: f64,
+    /// Largest change of a rotation variable in a single step
+    max_rotation_step: f64,
+}
+
+
+impl GaussNewton {
+    pub fn new(options: &SolverOptions) -> GaussNewton {
+        GaussNewton {
+            max_iterations: options.max_iterations,
+            gradient_tolerance: options.gradient_tolerance,
+            max_translation_step: options.max_translation_step,
+            max_rotation_step: options.max_rotation_step,
+        }
+    }
+
+    /// Minimizes the system starting from x0
+    pub fn minimize(&mut self, x0: &Array1<f64>, system: &mut System) -> GaussNewtonResult {
+        let n = x0.len();
+        let mut x = x0.clone();
+        let step_limit = StepLimit::new(system, self.max_translation_step, self.max_rotation_step);
+        system.update_x(&x);
+        let mut residuals = system.get_residuals();
+        let mut f = total_sum_squares(&residuals);
+        let mut f_evals = 1;
+        let mut f_grad_evals = 0;
+        let mut success = false;
+        let mut iter_num = 0;
+        let mut rank_deficient = false;
+
+        while iter_num < self.max_iterations {
+            let values = system.constraints
+                .iter()
+                .zip(&residuals)
+                .map(|(c, r)| (c.get_kind(), sum_squares(r)));
+            let penalty = system.start_penalty();
+            if system.monitor.iteration(values, penalty, &x) {
+                success = system.monitor.stop == Some(StopReason::Converged);
+                break;
+            }
+
+            let (jtj, jtr) = normal_equations(&residuals, n);
+            f_grad_evals += 1;
+
+            // The gradient of f(x) is 2 J^T r
+            if 2.0 * max_abs(&jtr) < self.gradient_tolerance {
+                success = true;
+                break;
+            }
+
+            let l = match cholesky(&jtj) {
+                Some(l) if cholesky_condition(&l) < MAX_CONDITION => l,
+                _ => {
+                    rank_deficient = true;
+                    break;
+                },
+            };
+            iter_num += 1;
+            let mut p = cholesky_substitution(&l, &(-&jtr));
+            step_limit.apply(&mut p);
+            let slope = 2.0 * jtr.dot(&p);
+
+            // backtracking line search
+            let mut alpha = 1.0;
+            let mut x_new;
+            let mut new_residuals;
+            let mut f_new;
+            loop {
+                x_new = &x + &(alpha * &p);
+                system.update_x(&x_new);
+                new_residuals = system.get_residuals();
+                f_new = total_sum_squares(&new_residuals);
+                f_evals += 1;
+                // A non-finite value or the evaluation budget stops the search
+                if system.monitor.stop.is_some() {
+                    break;
+                }
+                if f_new <= f + ARMIJO * alpha * slope || alpha < MIN_STEP_LENGTH {
+                    break;
+                }
+                alpha *= 0.5;
+            }
+            if alpha < MIN_STEP_LENGTH || system.monitor.stop.is_some() {
+                break;
+            }
+            x = x_new;
+            f = f_new;
+            residuals = new_residuals;
+        }
+
+        system.update_x(&x);
+        let sol = Solution {
+            x,
+            success,
+            iter_num,
+            f_evals,
+            f_grad_evals,
+        };
+        if rank_deficient {
+            GaussNewtonResult::RankDeficient(sol)
+        } else {
+            GaussNewtonResult::Done(sol)
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::VecDeque;
+
+use ndarray::Array1;
+
+use optimization::problem::{Objective, Gradient};
+
+use crate::minimizer::{Solution, SolverOptions, StopReason};
+use crate::minimizer::StepLimit;
+use crate::system::System;
+
+
+/// Number of correction pairs stored by the method
+pub const MEMORY: usize = 10;
+/// Sufficient decrease parameter of the Armijo condition
+const ARMIJO: f64 = 1e-4;
+/// The line search fails when the step length becomes smaller than this value
+const MIN_STEP_LENGTH: f64 = 1e-20;
+
+
+/// Limited memory BFGS minimizer.
+///
+/// This method only needs the objective function and its gradient, which makes
+/// it useful far from the solution where the hessian of the system may be
+/// indefinite. The step length is found with a backtracking line search.
+pub struct Lbfgs {
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Tolerance of the largest component of the gradient
+    gradient_tolerance: f64,
+    /// Largest change of a position variable in a single step
+    max_translation_step: f64,
+    /// Largest change of a rotation variable in a single step
+    max_rotation_step: f64,
+}
+
+
+impl Lbfgs {
+    pub fn new(options: &SolverOptions) -> Lbfgs {
+        Lbfgs {
+            max_iterations: options.max_iterations,
+            gradient_tolerance: options.gradient_tolerance,
+            max_translation_step: options.max_translation_step,
+            max_rotation_step: options.max_rotation_step,
+        }
+    }
+
+    /// Minimizes the system starting from x0
+    pub fn minimize(&mut self, x0: &Array1<f64>, system: &mut System) -> Solution {
+        let n = x0.len();
+        let mut x = x0.clone();
+        let step_limit = StepLimit::new(system, self.max_translation_step, self.max_rotation_step);
+        let mut g = Array1::zeros(n);
+        // correction pairs (s, y, 1/(y^T s))
+        let mut pairs: VecDeque<(Array1<f64>, Array1<f64>, f64)> = VecDeque::with_capacity(MEMORY);
+
+        system.update_x(&x);
+        let mut f = system.eval_real();
+        system.grad(&mut g);
+        let mut f_evals = 1;
+        let mut f_grad_evals = 1;
+        let mut success = false;
+        let mut iter_num = 0;
+
+        while iter_num < self.max_iterations {
+            if let Some(reason) = system.monitor.stop {
+                success = reason == StopReason::Converged;
+                break;
+            }
+            if max_abs(&g) < self.gradient_tolerance {
+                success = true;
+                break;
+            }
+            iter_num += 1;
+
+            let mut d = search_direction(&g, &pairs);
+            let mut slope = g.dot(&d);
+            if slope.is_nan() || slope >= 0.0 {
+                // The stored curvature information doesn't give a descent
+                // direction, so we start again from steepest descent.
+                pairs.clear();
+                d = -&g;
+                slope = g.dot(&d);
+            }
+            if step_limit.apply(&mut d) < 1.0 {
+                slope = g.dot(&d);
+            }
+
+            // backtracking line search
+            let mut alpha = 1.0;
+            let mut f_new;
+            loop {
+                system.move_step(&x, &d, alpha);
+                f_new = system.eval_real();
+                f_evals += 1;
+                // A non-finite value or the evaluation budget stops the search
+                if system.monitor.stop.is_some() {
+                    break;
+                }
+                if f_new <= f + ARMIJO * alpha * slope || alpha < MIN_STEP_LENGTH {
+                    break;
+                }
+                alpha *= 0.5;
+            }
+            if alpha < MIN_STEP_LENGTH || system.monitor.stop.is_some() {
+                break;
+            }
+            let x_new = &x + &(alpha * &d);
+
+            let mut g_new = Array1::zeros(n);
+            system.grad(&mut g_new);
+            f_grad_evals += 1;
+
+            let s = &x_new - &x;
+            let y = &g_new - &g;
+            let ys = y.dot(&s);
+            if ys > f64::EPSILON * y.dot(&y) {
+                if pairs.len() == MEMORY {
+                    pairs.pop_front();
+                }
+                pairs.push_back((s, y, 1.0 / ys));
+            }
+            x = x_new;
+            f = f_new;
+            g = g_new;
+        }
+
+        system.update_x(&x);
+        Solution {
+            x,
+            success,
+            iter_num,
+            f_evals,
+            f_grad_evals,
+        }
+    }
+}
+
+
+/// Finds the search direction -H g with the two-loop recursion, where H is the
+/// approximation of the inverse hessian given by the correction pairs.
+fn search_direction(
+        g: &Array1<f64>,
+        pairs: &VecDeque<(Array1<f64>, Array1<f64>, f64)>,
+) -> Array1<f64> {
+    let mut q = g.clone();
+    let mut alphas = Vec::with_capacity(pairs.len());
+    for (s, y, rho) in pairs.iter().rev() {
+        let alpha = rho * s.dot(&q);
+        q.scaled_add(-alpha, y);
+        alphas.push(alpha);
+    }
+    // initial inverse hessian approximation gamma*I
+    if let Some((s, y, _)) = pairs.back() {
+        q *= s.dot(y) / y.dot(y);
+    }
+    for ((s, y, rho), alpha) in pairs.iter().zip(alphas.iter().rev()) {
+        let beta = rho * y.dot(&q);
+        q.scaled_add(alpha - beta, s);
+    }
+    -q
+}
+
+
+/// Returns the largest absolute value of the components of a vector
+fn max_abs(v: &Array1<f64>) -> f64 {
+    v.iter().fold(0.0, |max, x| x.abs().max(max))
+}
@@ -0,0 +1,190 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+use optimization::problem::Objective;
+
+use crate::constraints::Residual;
+use crate::minimizer::{Solution, SolverOptions, StopReason};
+use crate::minimizer::linalg::cholesky_solve;
+use crate::minimizer::StepLimit;
+use crate::system::System;
+
+
+/// Factor used to increase or decrease the damping parameter
+const DAMPING_FACTOR: f64 = 10.0;
+/// Smallest damping parameter
+const MIN_DAMPING: f64 = 1e-12;
+/// When the damping parameter grows beyond this value no descent step can be
+/// found and the minimization stops
+const MAX_DAMPING: f64 = 1e16;
+
+
+/// Levenberg-Marquardt minimizer.
+///
+/// The objective function of the system is the sum of the squares of the
+/// constraint residuals f(x) = r(x)^T r(x). At each iteration the step p is found
+/// by solving (J^T J + lambda I) p = -J^T r where J is the jacobian of the
+/// residuals. The damping parameter lambda is decreased when a step reduces f(x)
+/// and increased when it does not, so the method moves between Gauss-Newton
+/// (small lambda) and gradient descent (large lambda).
+pub struct LevenbergMarquardt {
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Tolerance of the largest component of the gradient
+    gradient_tolerance: f64,
+    /// Largest change of a position variable in a single step
+    max_translation_step: f64,
+    /// Largest change of a rotation variable in a single step
+    max_rotation_step: f64,
+    /// Damping parameter lambda
+    damping: f64,
+}
+
+
+impl LevenbergMarquardt {
+    pub fn new(options: &SolverOptions) -> LevenbergMarquardt {
+        LevenbergMarquardt {
+            max_iterations: options.max_iterations,
+            gradient_tolerance: options.gradient_tolerance,
+            max_translation_step: options.max_translation_step,
+            max_rotation_step: options.max_rotation_step,
+            damping: 1e-3,
+        }
+    }
+
+    /// Minimizes the system starting from x0
+    pub fn minimize(&mut self, x0: &Array1<f64>, system: &mut System) -> Solution {
+        let n = x0.len();
+        let mut x = x0.clone();
+        let step_limit = StepLimit::new(system, self.max_translation_step, self.max_rotation_step);
+        system.update_x(&x);
+        let mut residuals = system.get_residuals();
+        let mut f = total_sum_squares(&residuals);
+        let mut f_evals = 1;
+        let mut f_grad_evals = 0;
+        let mut success = false;
+        let mut iter_num = 0;
+
+        while iter_num < self.max_iterations {
+            let values = system.constraints
+                .iter()
+                .zip(&residuals)
+                .map(|(c, r)| (c.get_kind(), sum_squares(r)));
+            let penalty = system.start_penalty();
+            if system.monitor.iteration(values, penalty, &x) {
+                success = system.monitor.stop == Some(StopReason::Converged);
+                break;
+            }
+
+            let (jtj, jtr) = normal_equations(&residuals, n);
+            f_grad_evals += 1;
+
+            // The gradient of f(x) is 2 J^T r
+            if 2.0 * max_abs(&jtr) < self.gradient_tolerance {
+                success = true;
+                break;
+            }
+            iter_num += 1;
+
+            // Find a step that reduces f(x)
+            let mut accepted = false;
+            while self.damping < MAX_DAMPING {
+                let mut a = jtj.clone();
+                for i in 0..n {
+                    a[[i, i]] += self.damping;
+                }
+                let mut step = match cholesky_solve(&a, &(-&jtr)) {
+                    Some(step) => step,
+                    None => {
+                        self.damping *= DAMPING_FACTOR;
+                        continue;
+                    },
+                };
+                step_limit.apply(&mut step);
+                let x_new = &x + &step;
+                system.update_x(&x_new);
+                let new_residuals = system.get_residuals();
+                let f_new = total_sum_squares(&new_residuals);
+                f_evals += 1;
+                // the evaluation budget stops the search
+                if system.monitor.stop.is_some() {
+                    break;
+                }
+                if f_new < f {
+                    x = x_new;
+                    f = f_new;
+                    residuals = new_residuals;
+                    self.damping = (self.damping / DAMPING_FACTOR).max(MIN_DAMPING);
+                    accepted = true;
+                    break;
+                }
+                self.damping *= DAMPING_FACTOR;
+            }
+
+            if !accepted {
+                // There is no descent step left. This only means success if the
+                // residuals are already zero.
+                success = f == 0.0;
+                break;
+            }
+        }
+
+        system.update_x(&x);
+        Solution {
+            x,
+            success,
+            iter_num,
+            f_evals,
+            f_grad_evals,
+        }
+    }
+}
+
+
+/// Returns the sum of the squares of the residuals
+pub(super) fn sum_squares(residuals: &[Residual]) -> f64 {
+    residuals.iter().map(|r| r.value * r.value).sum()
+}
+
+
+/// Returns the sum of the squares of the residuals of all the constraints
+pub(super) fn total_sum_squares(residuals: &[Vec<Residual>]) -> f64 {
+    residuals.iter().map(|r| sum_squares(r)).sum()
+}
+
+
+/// Returns the largest absolute value of the components of a vector
+pub(super) fn max_abs(v: &Array1<f64>) -> f64 {
+    v.iter().fold(0.0, |max, x| x.abs().max(max))
+}
+
+
+/// Assembles the matrix J^T J and the vector J^T r of the normal equations
+pub(super) fn normal_equations(residuals: &[Vec<Residual>], n: usize) -> (Array2<f64>, Array1<f64>) {
+    let mut jtj = Array2::zeros((n, n));
+    let mut jtr = Array1::zeros(n);
+    for residual in residuals.iter().flatten() {
+        for &(k, dk) in &residual.jacobian {
+            jtr[k] += dk * residual.value;
+            for &(l, dl) in &residual.jacobian {
+                jtj[[k, l]] += dk * dl;
+            }
+        }
+    }
+    (jtj, jtr)
+}
@@ -0,0 +1,137 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+
+/// Solves the linear system a*x = b where a is a symmetric positive definite
+/// matrix using the Cholesky decomposition of a.
+///
+/// Returns None when a is not positive definite.
+pub fn cholesky_solve(a: &Array2<f64>, b: &Array1<f64>) -> Option<Array1<f64>> {
+    let l = cholesky(a)?;
+    Some(cholesky_substitution(&l, b))
+}
+
+
+/// Finds the Cholesky decomposition a = l*l^T of a symmetric positive definite
+/// matrix a, where l is a lower triangular matrix.
+///
+/// Returns None when a is not positive definite.
+pub fn cholesky(a: &Array2<f64>) -> Option<Array2<f64>> {
+    let n = a.nrows();
+    let mut l: Array2<f64> = Array2::zeros((n, n));
+    let mut sum: f64;
+
+    for j in 0..n {
+        sum = a[[j, j]];
+        for k in 0..j {
+            sum -= l[[j, k]] * l[[j, k]];
+        }
+        if !sum.is_finite() || sum <= 0.0 {
+            return None;
+        }
+        l[[j, j]] = sum.sqrt();
+        for i in (j+1)..n {
+            sum = a[[i, j]];
+            for k in 0..j {
+                sum -= l[[i, k]] * l[[j, k]];
+            }
+            l[[i, j]] = sum / l[[j, j]];
+        }
+    }
+    Some(l)
+}
+
+
+/// Solves the linear system l*l^T*x = b where l is the Cholesky decomposition
+/// of the matrix of the system.
+pub fn cholesky_substitution(l: &Array2<f64>, b: &Array1<f64>) -> Array1<f64> {
+    let n = b.len();
+    let mut sum: f64;
+
+    // forward substitution l*y = b
+    let mut y: Array1<f64> = Array1::zeros(n);
+    for i in 0..n {
+        sum = b[i];
+        for k in 0..i {
+            sum -= l[[i, k]] * y[k];
+        }
+        y[i] = sum / l[[i, i]];
+    }
+
+    // back substitution l^T*x = y
+    let mut x: Array1<f64> = Array1::zeros(n);
+    for i in (0..n).rev() {
+        sum = y[i];
+        for k in (i+1)..n {
+            sum -= l[[k, i]] * x[k];
+        }
+        x[i] = sum / l[[i, i]];
+    }
+    x
+}
+
+
+/// Estimates the condition number of a matrix from its Cholesky decomposition
+/// l. The estimate is a lower bound of the actual condition number.
+pub fn cholesky_condition(l: &Array2<f64>) -> f64 {
+    let mut min = f64::INFINITY;
+    let mut max: f64 = 0.0;
+    for i in 0..l.nrows() {
+        min = min.min(l[[i, i]]);
+        max = max.max(l[[i, i]]);
+    }
+    (max / min).powi(2)
+}
+
+
+/// Entries of a reduced matrix smaller than this value times the largest
+/// entry (in absolute value) of the matrix are considered to be zero
+const RANK_TOLERANCE: f64 = 1e-9;
+
+
+/// Finds the rank of a matrix (the number of its linearly independent rows)
+/// by Gaussian elimination with partial pivoting.
+pub fn rank(a: &Array2<f64>) -> usize {
+    let mut a = a.clone();
+    let (m, n) = a.dim();
+    let tolerance = RANK_TOLERANCE * a.iter().fold(0.0, |max: f64, value| max.max(value.abs()));
+    let mut rank = 0;
+    for j in 0..n {
+        if rank == m {
+            break;
+        }
+        // the largest entry of the column below the rows already reduced
+        let pivot = (rank..m)
+            .max_by(|i, k| a[[*i, j]].abs().partial_cmp(&a[[*k, j]].abs()).unwrap())
+            .unwrap();
+        if a[[pivot, j]].abs() <= tolerance {
+            continue;
+        }
+        for k in j..n {
+            a.swap([rank, k], [pivot, k]);
+        }
+        for i in (rank+1)..m {
+            let factor = a[[i, j]] / a[[rank, j]];
+            for k in j..n {
+                a[[i, k]] -= factor * a[[rank, k]];
+            }
+        }
+        rank += 1;
+    }
+    rank
+}
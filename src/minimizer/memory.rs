@@ -0,0 +1,97 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+
+use std::mem::size_of;
+
+use crate::constraints::{ConstraintType, Residual};
+use crate::minimizer::{Method, SolverOptions};
+use crate::minimizer::lbfgs;
+use crate::system::System;
+use crate::system_object::SystemObject;
+
+
+/// Number of vectors of the size of the system used by the minimization
+/// methods besides their matrices (gradients, steps, trial points...). It is an
+/// upper bound of the vectors used by any of the methods.
+const WORK_VECTORS: usize = 12;
+
+/// Number of residuals of a constraint for each variable it uses. Constraints
+/// have fewer residuals than variables, so this is an upper bound.
+const RESIDUALS_PER_VARIABLE: usize = 1;
+
+
+/// Estimates the peak memory (in bytes) used to solve the system with the
+/// given options. The indices of the system variables must be up to date.
+///
+/// The dense matrices of the minimization methods dominate the estimate: a
+/// system with n variables needs n^2 values for the hessian and more for its
+/// factorization.
+pub fn memory_estimate(system: &System, options: &SolverOptions) -> usize {
+    let n = system.get_enabled_size();
+    let float = size_of::<f64>();
+    let matrix = n * n * float;
+    let vector = n * float;
+
+    // Jacobian entries of the residuals (used by Levenberg-Marquardt and
+    // Gauss-Newton)
+    let jacobian_entries: usize = system.constraints
+        .iter()
+        .map(|constraint| {
+            let variables = constraint.get_variables().len();
+            RESIDUALS_PER_VARIABLE * variables * (size_of::<Residual>() + variables * size_of::<(usize, f64)>())
+        })
+        .sum();
+
+    // Entries of the sparse hessian: the diagonal and a triplet for each pair
+    // of different variables of a constraint
+    let sparse_hessian: usize = vector + system.constraints
+        .iter()
+        .map(|constraint| {
+            let variables = constraint.get_variables().len();
+            variables * variables.saturating_sub(1) * size_of::<(usize, usize, f64)>()
+        })
+        .sum::<usize>();
+
+    // hessian and its cholesky factor
+    let newton = 2 * matrix;
+    // hessian (the crate keeps a single copy)
+    let trust_ncg = matrix;
+    // normal equations, their damped copy and the cholesky factor
+    let levenberg_marquardt = 3 * matrix + jacobian_entries;
+    // normal equations and the cholesky factor
+    let gauss_newton = 2 * matrix + jacobian_entries;
+    // pairs of steps and gradient changes
+    let lbfgs = 2 * lbfgs::MEMORY * vector;
+    let method = match options.method {
+        Method::Auto if n <= options.newton_max_dimension => newton,
+        Method::Auto => trust_ncg,
+        Method::Newton => newton,
+        Method::NewtonCg => sparse_hessian,
+        Method::TrustNCG => trust_ncg,
+        Method::LevenbergMarquardt => levenberg_marquardt,
+        Method::GaussNewton => gauss_newton.max(levenberg_marquardt),
+        Method::Lbfgs => lbfgs,
+        Method::LbfgsNewton => lbfgs.max(trust_ncg),
+    };
+    // the polish uses Newton steps
+    let polish = if options.polish_iterations > 0 { newton } else { 0 };
+
+    let system_data = system.constraints.len() * size_of::<ConstraintType>()
+        + system.sys_objects.len() * size_of::<SystemObject>();
+
+    system_data + method.max(polish) + WORK_VECTORS * vector
+}
@@ -0,0 +1,290 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::{BTreeSet, VecDeque};
+
+use ndarray::Array1;
+
+use crate::constraints::ResidualKind;
+use crate::minimizer::SolverOptions;
+
+
+/// Reason why the monitor stopped the minimization
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopReason {
+    /// Every constraint is satisfied within its tolerance
+    Converged,
+    /// The objective function became non-finite or grew too much with respect
+    /// to the best value found
+    Diverged,
+    /// The minimization stopped making progress before satisfying the
+    /// constraints
+    Stagnated,
+    /// A constraint function returned a non-finite value
+    NonFinite,
+    /// The maximum number of function or gradient evaluations was reached
+    BudgetExhausted,
+}
+
+
+impl StopReason {
+    /// Returns the name of the reason used in the solve report
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::Converged => "converged",
+            StopReason::Diverged => "diverged",
+            StopReason::Stagnated => "stagnated",
+            StopReason::NonFinite => "non_finite",
+            StopReason::BudgetExhausted => "budget_exhausted",
+        }
+    }
+}
+
+
+/// Progress of the minimization over the last iterations, used to detect when
+/// the minimization stagnates
+#[derive(Debug, Copy, Clone)]
+pub struct StagnationWindow {
+    /// Number of iterations in the window
+    pub iterations: usize,
+    /// Decrease of the objective function over the window relative to its value
+    /// at the start of the window
+    pub relative_decrease: f64,
+    /// Largest step norm over the window
+    pub max_step_norm: f64,
+}
+
+
+/// Information about the first constraint that returned a non-finite value
+#[derive(Debug, Clone)]
+pub struct NonFiniteRecord {
+    /// Name of the constraint
+    pub constraint: String,
+    /// Value returned by the constraint
+    pub value: f64,
+    /// Iteration of the monitor when the value was found
+    pub iteration: usize,
+    /// Object name, variable name and value of the variables used by the
+    /// constraint when the value was found
+    pub variables: Vec<(String, String, f64)>,
+}
+
+
+/// Follows the progress of the minimization.
+///
+/// The minimizers report each new iterate to the monitor (TrustNCG does it
+/// through System::grad since the gradient is evaluated once at each iterate)
+/// and the monitor decides whether the minimization should stop early.
+#[derive(Debug)]
+pub struct Monitor {
+    /// Tolerance of the constraints measuring squared lengths
+    length_tolerance: f64,
+    /// Tolerance of the constraints measuring squared angles
+    angle_tolerance: f64,
+    /// Number of iterates seen by the monitor
+    pub iterations: usize,
+    /// Set when the minimization should stop
+    pub stop: Option<StopReason>,
+    /// Value added to the zero diagonal entries of the hessian
+    pub regularization: f64,
+    /// Solver indices of the variables whose hessian diagonal was regularized
+    pub regularized: BTreeSet<usize>,
+    /// The minimization diverges when the objective function is larger than
+    /// the best value times this factor
+    divergence_factor: f64,
+    /// Number of iterations after the best iterate that the objective function
+    /// can stay above the divergence factor before the minimization stops
+    divergence_window: usize,
+    /// Smallest value of the objective function seen by the monitor
+    pub best_value: f64,
+    /// Iterate with the smallest value of the objective function
+    pub best_x: Option<Array1<f64>>,
+    /// Iteration number of the best iterate
+    pub best_iteration: usize,
+    /// Number of consecutive iterations where the objective function was above
+    /// the divergence factor
+    diverging_iterations: usize,
+    /// Number of iterations used to detect stagnation
+    stagnation_window: usize,
+    /// The minimization stagnates when the relative decrease of the objective
+    /// function over the window is smaller than this value...
+    stagnation_tolerance: f64,
+    /// ...and every step in the window is shorter than this value
+    stagnation_step: f64,
+    /// Values of the objective function and step norms of the last iterations
+    window: VecDeque<(f64, f64)>,
+    /// Last iterate reported to the monitor
+    last_x: Option<Array1<f64>>,
+    /// Statistics of the window when the minimization stagnated
+    pub stagnation: Option<StagnationWindow>,
+    /// First constraint that returned a non-finite value
+    pub non_finite: Option<NonFiniteRecord>,
+    /// Maximum number of evaluations of the objective function
+    max_function_evals: Option<usize>,
+    /// Maximum number of evaluations of the gradient
+    max_gradient_evals: Option<usize>,
+}
+
+
+impl Monitor {
+    pub fn new(options: &SolverOptions) -> Monitor {
+        Monitor {
+            length_tolerance: options.residual_tolerance,
+            angle_tolerance: options.angle_residual_tolerance,
+            iterations: 0,
+            stop: None,
+            regularization: options.regularization,
+            regularized: BTreeSet::new(),
+            divergence_factor: options.divergence_factor,
+            divergence_window: options.divergence_window,
+            best_value: f64::INFINITY,
+            best_x: None,
+            best_iteration: 0,
+            diverging_iterations: 0,
+            stagnation_window: options.stagnation_window,
+            stagnation_tolerance: options.stagnation_tolerance,
+            stagnation_step: options.stagnation_step,
+            window: VecDeque::with_capacity(options.stagnation_window + 1),
+            last_x: None,
+            stagnation: None,
+            non_finite: None,
+            max_function_evals: options.max_function_evals,
+            max_gradient_evals: options.max_gradient_evals,
+        }
+    }
+
+    /// Returns true when every constraint value is within the tolerance of its
+    /// kind. values contains the kind and the value of each constraint.
+    pub fn within_tolerance<I>(&self, values: I) -> bool
+    where
+        I: Iterator<Item = (ResidualKind, f64)>,
+    {
+        for (kind, value) in values {
+            let tolerance = match kind {
+                ResidualKind::Length => self.length_tolerance,
+                ResidualKind::Angle => self.angle_tolerance,
+            };
+            if value.is_nan() || value > tolerance {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Records the first constraint that returned a non-finite value and stops
+    /// the minimization
+    pub fn non_finite_value(&mut self, record: NonFiniteRecord) {
+        if self.non_finite.is_none() {
+            self.non_finite = Some(record);
+        }
+        self.stop = Some(StopReason::NonFinite);
+    }
+
+    /// Returns true when the number of function or gradient evaluations reached
+    /// its maximum
+    pub fn budget_exhausted(&self, f_evals: usize, grad_evals: usize) -> bool {
+        let f_exhausted = self.max_function_evals.is_some_and(|max| f_evals >= max);
+        let grad_exhausted = self.max_gradient_evals.is_some_and(|max| grad_evals >= max);
+        f_exhausted || grad_exhausted
+    }
+
+    /// Stops the minimization when the number of evaluations reached its maximum
+    pub fn check_budget(&mut self, f_evals: usize, grad_evals: usize) {
+        if self.budget_exhausted(f_evals, grad_evals) && self.stop.is_none() {
+            self.stop = Some(StopReason::BudgetExhausted);
+        }
+    }
+
+    /// Reports a new iterate x with the constraint values evaluated at it.
+    /// penalty is the part of the objective function that does not come from
+    /// the constraints (it is only used to follow the progress, not to check
+    /// the tolerances). Returns true when the minimization should stop at this
+    /// iterate.
+    pub fn iteration<I>(&mut self, values: I, penalty: f64, x: &Array1<f64>) -> bool
+    where
+        I: Iterator<Item = (ResidualKind, f64)> + Clone,
+    {
+        self.iterations += 1;
+        let value: f64 = values.clone().map(|(_, value)| value).sum::<f64>() + penalty;
+
+        if !value.is_finite() {
+            if self.stop.is_none() {
+                self.stop = Some(StopReason::Diverged);
+            }
+            return true;
+        }
+        if value < self.best_value {
+            self.best_value = value;
+            self.best_x = Some(x.clone());
+            self.best_iteration = self.iterations;
+            self.diverging_iterations = 0;
+        } else if value > self.divergence_factor * self.best_value {
+            self.diverging_iterations += 1;
+            if self.diverging_iterations >= self.divergence_window {
+                self.stop = Some(StopReason::Diverged);
+                return true;
+            }
+        } else {
+            self.diverging_iterations = 0;
+        }
+
+        let step_norm = match &self.last_x {
+            Some(last_x) => (x - last_x).dot(&(x - last_x)).sqrt(),
+            None => 0.0,
+        };
+        self.last_x = Some(x.clone());
+        self.window.push_back((value, step_norm));
+        if self.window.len() > self.stagnation_window {
+            self.window.pop_front();
+        }
+
+        if self.within_tolerance(values) {
+            self.stop = Some(StopReason::Converged);
+        } else if let Some(stats) = self.stagnated() {
+            self.stagnation = Some(stats);
+            self.stop = Some(StopReason::Stagnated);
+        }
+        self.stop.is_some()
+    }
+
+    /// Returns the statistics of the window when the minimization stagnated
+    /// over it, or None if it is still making progress (or the window isn't
+    /// full yet).
+    fn stagnated(&self) -> Option<StagnationWindow> {
+        if self.stagnation_window == 0 || self.window.len() < self.stagnation_window {
+            return None;
+        }
+        let first_value = self.window.front()?.0;
+        let last_value = self.window.back()?.0;
+        let relative_decrease = (first_value - last_value) / first_value;
+        // The step of the first iterate in the window was taken before the
+        // window started.
+        let max_step_norm = self.window
+            .iter()
+            .skip(1)
+            .fold(0.0, |max: f64, (_, step_norm)| max.max(*step_norm));
+
+        if relative_decrease < self.stagnation_tolerance && max_step_norm < self.stagnation_step {
+            Some(StagnationWindow {
+                iterations: self.window.len(),
+                relative_decrease,
+                max_step_norm,
+            })
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+use optimization::problem::{Objective, Gradient, Hessian};
+
+use crate::minimizer::linalg::{cholesky, cholesky_condition, cholesky_substitution};
+use crate::minimizer::{Solution, SolverOptions, StopReason};
+use crate::minimizer::StepLimit;
+use crate::system::System;
+
+
+/// Sufficient decrease parameter of the Armijo condition
+const ARMIJO: f64 = 1e-4;
+/// The line search fails when the step length becomes smaller than this value
+const MIN_STEP_LENGTH: f64 = 1e-20;
+/// Largest estimated condition number of the hessian accepted by the method
+const MAX_CONDITION: f64 = 1e12;
+
+
+/// Result of the Newton method
+pub enum NewtonResult {
+    /// The minimization finished (it may have failed to converge)
+    Done(Solution),
+    /// The hessian at the last point of the solution is not positive definite
+    /// or it is ill-conditioned, so a Newton step cannot be trusted
+    IllConditioned(Solution),
+}
+
+
+/// Newton method with a backtracking line search.
+///
+/// Each step solves the system with the dense hessian, which is cheap for small
+/// systems and avoids the overhead of the trust region method. The method
+/// stops when the hessian can't be used and the caller should continue with a
+/// method that handles indefinite or singular hessians.
+pub struct Newton {
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Tolerance of the largest component of the gradient
+    gradient_tolerance: f64,
+    /// Largest change of a position variable in a single step
+    max_translation_step: f64,
+    /// Largest change of a rotation variable in a single step
+    max_rotation_step: f64,
+}
+
+
+impl Newton {
+    pub fn new(options: &SolverOptions) -> Newton {
+        Newton {
+            max_iterations: options.max_iterations,
+            gradient_tolerance: options.gradient_tolerance,
+            max_translation_step: options.max_translation_step,
+            max_rotation_step: options.max_rotation_step,
+        }
+    }
+
+    /// Minimizes the system starting from x0
+    pub fn minimize(&mut self, x0: &Array1<f64>, system: &mut System) -> NewtonResult {
+        let n = x0.len();
+        let mut x = x0.clone();
+        let step_limit = StepLimit::new(system, self.max_translation_step, self.max_rotation_step);
+        let mut g = Array1::zeros(n);
+        let mut h = Array2::zeros((n, n));
+
+        system.update_x(&x);
+        let mut f = system.eval_real();
+        let mut f_evals = 1;
+        let mut f_grad_evals = 0;
+        let mut success = false;
+        let mut iter_num = 0;
+        let mut ill_conditioned = false;
+
+        while iter_num < self.max_iterations {
+            system.grad(&mut g);
+            f_grad_evals += 1;
+            if let Some(reason) = system.monitor.stop {
+                success = reason == StopReason::Converged;
+                break;
+            }
+            if max_abs(&g) < self.gradient_tolerance {
+                success = true;
+                break;
+            }
+
+            system.hess(&mut h);
+            let l = match cholesky(&h) {
+                Some(l) if cholesky_condition(&l) < MAX_CONDITION => l,
+                _ => {
+                    ill_conditioned = true;
+                    break;
+                },
+            };
+            iter_num += 1;
+            let mut p = cholesky_substitution(&l, &(-&g));
+            step_limit.apply(&mut p);
+            let slope = g.dot(&p);
+
+            // backtracking line search
+            let mut alpha = 1.0;
+            let mut f_new;
+            loop {
+                system.move_step(&x, &p, alpha);
+                f_new = system.eval_real();
+                f_evals += 1;
+                // A non-finite value or the evaluation budget stops the search
+                if system.monitor.stop.is_some() {
+                    break;
+                }
+                if f_new <= f + ARMIJO * alpha * slope || alpha < MIN_STEP_LENGTH {
+                    break;
+                }
+                alpha *= 0.5;
+            }
+            if alpha < MIN_STEP_LENGTH || system.monitor.stop.is_some() {
+                break;
+            }
+            let x_new = &x + &(alpha * &p);
+            x = x_new;
+            f = f_new;
+        }
+
+        system.update_x(&x);
+        system.eval();
+        let sol = Solution {
+            x,
+            success,
+            iter_num,
+            f_evals,
+            f_grad_evals,
+        };
+        if ill_conditioned {
+            NewtonResult::IllConditioned(sol)
+        } else {
+            NewtonResult::Done(sol)
+        }
+    }
+}
+
+
+/// Returns true when the hessian of the system at x is positive definite and
+/// well conditioned, so the Newton method can start from x
+pub fn newton_applicable(system: &mut System, x: &Array1<f64>) -> bool {
+    let n = x.len();
+    let mut h = Array2::zeros((n, n));
+    system.update_x(x);
+    system.eval();
+    system.hess(&mut h);
+    match cholesky(&h) {
+        Some(l) => cholesky_condition(&l) < MAX_CONDITION,
+        None => false,
+    }
+}
+
+
+/// Returns the largest absolute value of the components of a vector
+fn max_abs(v: &Array1<f64>) -> f64 {
+    v.iter().fold(0.0, |max, x| x.abs().max(max))
+}
@@ -0,0 +1,192 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::Array1;
+
+use optimization::problem::{Objective, Gradient};
+
+use crate::constraints::{HessianAccumulator, SparseHessian};
+use crate::minimizer::{Solution, SolverOptions, StopReason};
+use crate::minimizer::StepLimit;
+use crate::system::System;
+
+
+/// Sufficient decrease parameter of the Armijo condition
+const ARMIJO: f64 = 1e-4;
+/// The line search fails when the step length becomes smaller than this value
+const MIN_STEP_LENGTH: f64 = 1e-20;
+/// Largest relative residual of the Newton system accepted by the conjugate
+/// gradients
+const MAX_FORCING: f64 = 0.5;
+/// Largest number of conjugate gradient iterations of a step for each variable.
+/// Rounding errors make the iterations converge slower than in exact
+/// arithmetic (at most one iteration for each variable) on ill-conditioned
+/// hessians.
+const CG_ITERATIONS_PER_VARIABLE: usize = 5;
+
+
+/// Truncated Newton method with a backtracking line search.
+///
+/// Each step solves the Newton system approximately with conjugate gradients on
+/// the sparse hessian of the system (see System::sparse_hessian), so the method
+/// never builds a dense matrix. Its memory grows with the number of variables
+/// used together by some constraint instead of the square of the number of
+/// variables, which makes it the method for systems with many objects.
+pub struct NewtonCg {
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Tolerance of the largest component of the gradient
+    gradient_tolerance: f64,
+    /// Largest change of a position variable in a single step
+    max_translation_step: f64,
+    /// Largest change of a rotation variable in a single step
+    max_rotation_step: f64,
+}
+
+
+impl NewtonCg {
+    pub fn new(options: &SolverOptions) -> NewtonCg {
+        NewtonCg {
+            max_iterations: options.max_iterations,
+            gradient_tolerance: options.gradient_tolerance,
+            max_translation_step: options.max_translation_step,
+            max_rotation_step: options.max_rotation_step,
+        }
+    }
+
+    /// Minimizes the system starting from x0
+    pub fn minimize(&mut self, x0: &Array1<f64>, system: &mut System) -> Solution {
+        let n = x0.len();
+        let mut x = x0.clone();
+        let step_limit = StepLimit::new(system, self.max_translation_step, self.max_rotation_step);
+        let mut g = Array1::zeros(n);
+
+        system.update_x(&x);
+        let mut f = system.eval_real();
+        let mut f_evals = 1;
+        let mut f_grad_evals = 0;
+        let mut success = false;
+        let mut iter_num = 0;
+
+        while iter_num < self.max_iterations {
+            system.grad(&mut g);
+            f_grad_evals += 1;
+            if let Some(reason) = system.monitor.stop {
+                success = reason == StopReason::Converged;
+                break;
+            }
+            if max_abs(&g) < self.gradient_tolerance {
+                success = true;
+                break;
+            }
+
+            let h = system.sparse_hessian();
+            iter_num += 1;
+            let mut p = conjugate_gradient_step(&h, &g);
+            step_limit.apply(&mut p);
+            let slope = g.dot(&p);
+
+            // backtracking line search
+            let mut alpha = 1.0;
+            let mut f_new;
+            loop {
+                system.move_step(&x, &p, alpha);
+                f_new = system.eval_real();
+                f_evals += 1;
+                // A non-finite value or the evaluation budget stops the search
+                if system.monitor.stop.is_some() {
+                    break;
+                }
+                if f_new <= f + ARMIJO * alpha * slope || alpha < MIN_STEP_LENGTH {
+                    break;
+                }
+                alpha *= 0.5;
+            }
+            if alpha < MIN_STEP_LENGTH || system.monitor.stop.is_some() {
+                break;
+            }
+            let x_new = &x + &(alpha * &p);
+            x = x_new;
+            f = f_new;
+        }
+
+        system.update_x(&x);
+        system.eval();
+        Solution {
+            x,
+            success,
+            iter_num,
+            f_evals,
+            f_grad_evals,
+        }
+    }
+}
+
+
+/// Returns an approximate solution p of h p = -g found with conjugate
+/// gradients starting from p = 0, preconditioned with the diagonal of h. The
+/// iterations stop when the residual is small relative to the gradient, or
+/// when a direction of negative curvature is found (h is not positive definite
+/// far from the solution). The returned step is always a descent direction:
+/// the first direction of the iterations when it already has negative
+/// curvature.
+fn conjugate_gradient_step(h: &SparseHessian, g: &Array1<f64>) -> Array1<f64> {
+    let n = g.len();
+    let g_norm = g.dot(g).sqrt();
+    let tolerance = MAX_FORCING.min(g_norm.sqrt()) * g_norm;
+    // Jacobi preconditioner. Variables without positive curvature are not
+    // scaled.
+    let inverse_diagonal: Array1<f64> = (0..n)
+        .map(|i| {
+            let d = h.get(i, i);
+            if d > 0.0 { 1.0 / d } else { 1.0 }
+        })
+        .collect();
+    let mut p = Array1::zeros(n);
+    // residual h p + g, preconditioned residual and search direction
+    let mut r = g.clone();
+    let mut z = &r * &inverse_diagonal;
+    let mut d = -&z;
+    let mut rz = r.dot(&z);
+
+    for k in 0..CG_ITERATIONS_PER_VARIABLE * n {
+        let hd = h.mul_vec(&d);
+        let curvature = d.dot(&hd);
+        if curvature <= 0.0 {
+            if k == 0 {
+                return d;
+            }
+            break;
+        }
+        let alpha = rz / curvature;
+        p.scaled_add(alpha, &d);
+        r.scaled_add(alpha, &hd);
+        if r.dot(&r).sqrt() <= tolerance {
+            break;
+        }
+        z = &r * &inverse_diagonal;
+        let new_rz = r.dot(&z);
+        d = &d * (new_rz / rz) - &z;
+        rz = new_rz;
+    }
+    p
+}
+
+
+/// Returns the largest absolute value of the components of a vector
+fn max_abs(v: &Array1<f64>) -> f64 {
+    v.iter().fold(0.0, |max, x| x.abs().max(max))
+}
@@ -0,0 +1,80 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+use optimization::problem::{Objective, Hessian};
+
+use crate::minimizer::linalg::cholesky_solve;
+use crate::system::System;
+
+
+/// Result of polishing a solution
+#[derive(Debug, Copy, Clone)]
+pub struct Polish {
+    /// Number of Newton steps accepted
+    pub iterations: usize,
+    /// Value of the objective function before polishing
+    pub value_before: f64,
+    /// Value of the objective function after polishing
+    pub value_after: f64,
+}
+
+
+/// Improves a solution x with up to max_iterations full Newton steps using the
+/// exact hessian of the system. A step is only accepted when it decreases the
+/// objective function, so polishing never makes the solution worse. The system
+/// variables hold the polished solution at the end.
+pub fn polish(system: &mut System, x: &mut Array1<f64>, max_iterations: usize) -> Polish {
+    let n = x.len();
+    system.update_x(x);
+    let value_before = system.eval_real();
+    let mut value = value_before;
+    let mut iterations = 0;
+
+    let mut grad = Array1::zeros(n);
+    let mut hess = Array2::zeros((n, n));
+    for _ in 0..max_iterations {
+        if value == 0.0 {
+            break;
+        }
+        // The monitor would stop a converged system so the gradient is taken
+        // directly from the constraints.
+        system.constraint_gradient(&mut grad);
+        system.hess(&mut hess);
+        let step = match cholesky_solve(&hess, &(-&grad)) {
+            Some(step) => step,
+            None => break,
+        };
+        let x_new = &*x + &step;
+        system.update_x(&x_new);
+        let value_new = system.eval_real();
+        if value_new.is_nan() || value_new >= value {
+            break;
+        }
+        *x = x_new;
+        value = value_new;
+        iterations += 1;
+    }
+
+    system.update_x(x);
+    system.eval_real();
+    Polish {
+        iterations,
+        value_before,
+        value_after: value,
+    }
+}
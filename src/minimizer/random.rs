@@ -0,0 +1,50 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+/// Small pseudo-random number generator (SplitMix64).
+///
+/// It is only used to perturb the starting point of the solver, so it doesn't
+/// need to be cryptographically secure, but it must give the same numbers for
+/// the same seed on every platform so the solutions are reproducible.
+#[derive(Debug)]
+pub struct Random {
+    state: u64,
+}
+
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random {
+            state: seed,
+        }
+    }
+
+    /// Returns the next random integer
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number uniformly distributed in [-1, 1)
+    pub fn next_symmetric(&mut self) -> f64 {
+        // the 53 most significant bits give a number in [0, 1)
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        2.0 * unit - 1.0
+    }
+}
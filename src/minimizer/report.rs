@@ -0,0 +1,347 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::{PyDict, PyList};
+
+use crate::minimizer::{NonFiniteRecord, Polish, SolveStats, StagnationWindow, StopReason};
+
+
+/// Outcome of a single attempt to solve the system
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    /// States whether every constraint was satisfied at the end of the attempt
+    pub success: bool,
+    /// Value of the objective function at the end of the attempt
+    pub value: f64,
+    /// Number of iterations used by the attempt
+    pub iter_num: usize,
+    /// Reason why the attempt stopped, if the monitor stopped it
+    pub reason: Option<StopReason>,
+    /// Iteration (as counted by the monitor) where the best point was found.
+    /// The attempt returns this point.
+    pub best_iteration: usize,
+    /// Statistics of the last iterations when the attempt stagnated
+    pub stagnation: Option<StagnationWindow>,
+    /// First constraint that returned a non-finite value during the attempt
+    pub non_finite: Option<NonFiniteRecord>,
+    /// Number of evaluations of the objective function (or of the residuals)
+    /// made by the attempt
+    pub f_evals: usize,
+    /// Number of evaluations of the gradient made by the attempt
+    pub f_grad_evals: usize,
+}
+
+
+/// Intermediate system of a continuation that could not be solved
+#[derive(Debug, Clone)]
+pub struct ContinuationFailure {
+    /// Number of the continuation step (starting from 1)
+    pub step: usize,
+    /// Names of the constraints that were not satisfied
+    pub constraints: Vec<String>,
+}
+
+
+/// Rotation angles of an object that were reseeded because they were close to
+/// the gimbal lock
+#[derive(Debug, Clone)]
+pub struct Reseed {
+    /// Name of the object
+    pub object: String,
+    /// Angles (phi, theta, psi) before reseeding
+    pub before: [f64; 3],
+    /// Angles (phi, theta, psi) after reseeding
+    pub after: [f64; 3],
+    /// States whether the new angles represent exactly the same rotation. When
+    /// the rotation itself is at the gimbal lock, theta has to be moved.
+    pub exact: bool,
+}
+
+
+/// Gradient contribution of a single constraint at the final point of the solve
+#[derive(Debug, Clone)]
+pub struct GradientContribution {
+    /// Name of the constraint
+    pub constraint: String,
+    /// Object name, variable name and partial derivative of the constraint
+    /// function for each solver variable with a nonzero derivative. A positive
+    /// value means that the constraint pulls the variable towards smaller
+    /// values.
+    pub gradient: Vec<(String, String, f64)>,
+}
+
+
+/// Error of a constraint that is not satisfied at the end of the solve
+#[derive(Debug, Clone)]
+pub struct ConstraintError {
+    /// Name of the constraint
+    pub constraint: String,
+    /// Value of the constraint function (the squared error)
+    pub value: f64,
+    /// The error converted into physical quantities as (label, value, unit)
+    pub residuals: Vec<(String, f64, String)>,
+}
+
+
+/// Disagreement between a lock and the rest of the constraints at the end of
+/// the solve
+#[derive(Debug, Clone)]
+pub struct LockShadow {
+    /// Name of the lock constraint
+    pub lock: String,
+    /// Name of the locked object
+    pub object: String,
+    /// Name of the locked variable
+    pub variable: String,
+    /// Locked value of the variable
+    pub value: f64,
+    /// Derivative of the objective function with respect to the locked variable
+    pub gradient: f64,
+    /// Change of the variable that would minimize the objective function if
+    /// only this variable were free (None when the constraints do not curve
+    /// the variable)
+    pub shift: Option<f64>,
+    /// Name and derivative of each constraint pulling the variable
+    pub opponents: Vec<(String, f64)>,
+}
+
+
+/// Variables moved by a constraint during the solve
+#[derive(Debug, Clone)]
+pub struct ConstraintAttribution {
+    /// Name of the constraint
+    pub constraint: String,
+    /// States whether the constraint positioned its objects: some of its
+    /// variables moved and its value decreased materially
+    pub active: bool,
+    /// Value of the constraint function before solving
+    pub initial_value: f64,
+    /// Value of the constraint function after solving
+    pub final_value: f64,
+    /// Object and variable names of the variables of the constraint that moved
+    pub moved: Vec<(String, String)>,
+}
+
+
+/// Report of an independent component of the system
+#[derive(Debug, Clone)]
+pub struct ComponentReport {
+    /// Names of the objects of the component
+    pub objects: Vec<String>,
+    /// States whether the component converged
+    pub success: bool,
+    /// Report of solving the component
+    pub report: SolveReport,
+}
+
+
+/// Information about the last time the system was solved
+#[derive(Debug, Clone)]
+pub struct SolveReport {
+    /// Name of the preset of the solver options, if any
+    pub preset: Option<String>,
+    /// Every attempt made to solve the system. The first attempt starts from the
+    /// initial placements and the rest are restarts from perturbed placements.
+    pub attempts: Vec<Attempt>,
+    /// Object and variable names of the variables whose hessian diagonal was
+    /// regularized because no constraint curves them, and of the variables
+    /// locked during the solve because their component was free to move as a
+    /// whole
+    pub regularized: Vec<(String, String)>,
+    /// Result of polishing the solution, if it was polished
+    pub polish: Option<Polish>,
+    /// Reports of each stage when the system is solved in stages. The rest of
+    /// the fields are the same as in the report of the last stage.
+    pub stages: Vec<SolveReport>,
+    /// First intermediate system that failed when the system is solved by
+    /// continuation
+    pub continuation_failure: Option<ContinuationFailure>,
+    /// Reports of each component when the independent components of the system
+    /// are solved separately. The rest of the fields are empty in this case.
+    pub components: Vec<ComponentReport>,
+    /// Objects whose rotation angles were reseeded during the solve
+    pub reseeded: Vec<Reseed>,
+    /// Statistics of the whole solve
+    pub stats: Option<SolveStats>,
+    /// Gradient contribution of each constraint at the final point, when it is
+    /// requested in the solver options
+    pub gradient_breakdown: Vec<GradientContribution>,
+    /// Constraints that are not satisfied at the end of a failed solve, from
+    /// the largest to the smallest error
+    pub worst_constraints: Vec<ConstraintError>,
+    /// How much each lock fights the rest of the constraints, when it is
+    /// requested in the solver options
+    pub lock_shadows: Vec<LockShadow>,
+    /// Variables moved by each constraint, when it is requested in the solver
+    /// options
+    pub attribution: Vec<ConstraintAttribution>,
+}
+
+
+impl SolveReport {
+    pub fn new() -> SolveReport {
+        SolveReport {
+            preset: None,
+            attempts: Vec::new(),
+            regularized: Vec::new(),
+            polish: None,
+            stages: Vec::new(),
+            continuation_failure: None,
+            components: Vec::new(),
+            reseeded: Vec::new(),
+            stats: None,
+            gradient_breakdown: Vec::new(),
+            worst_constraints: Vec::new(),
+            lock_shadows: Vec::new(),
+            attribution: Vec::new(),
+        }
+    }
+
+    /// Converts the report into a Python dictionary
+    #[cfg(feature = "python")]
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let attempts = PyList::empty(py);
+        for attempt in &self.attempts {
+            let attempt_dict = PyDict::new(py);
+            attempt_dict.set_item("success", attempt.success)?;
+            attempt_dict.set_item("value", attempt.value)?;
+            attempt_dict.set_item("iterations", attempt.iter_num)?;
+            attempt_dict.set_item("reason", attempt.reason.map(|r| r.as_str()))?;
+            attempt_dict.set_item("best_iteration", attempt.best_iteration)?;
+            attempt_dict.set_item("f_evals", attempt.f_evals)?;
+            attempt_dict.set_item("f_grad_evals", attempt.f_grad_evals)?;
+            if let Some(stagnation) = attempt.stagnation {
+                let stagnation_dict = PyDict::new(py);
+                stagnation_dict.set_item("iterations", stagnation.iterations)?;
+                stagnation_dict.set_item("relative_decrease", stagnation.relative_decrease)?;
+                stagnation_dict.set_item("max_step_norm", stagnation.max_step_norm)?;
+                attempt_dict.set_item("stagnation", stagnation_dict)?;
+            }
+            if let Some(non_finite) = &attempt.non_finite {
+                let non_finite_dict = PyDict::new(py);
+                non_finite_dict.set_item("constraint", non_finite.constraint.clone())?;
+                non_finite_dict.set_item("value", non_finite.value)?;
+                non_finite_dict.set_item("iteration", non_finite.iteration)?;
+                non_finite_dict.set_item("variables", non_finite.variables.clone())?;
+                attempt_dict.set_item("non_finite", non_finite_dict)?;
+            }
+            attempts.append(attempt_dict)?;
+        }
+        let report = PyDict::new(py);
+        report.set_item("preset", self.preset.clone())?;
+        report.set_item("attempts", attempts)?;
+        report.set_item("regularized", self.regularized.clone())?;
+        if let Some(stats) = &self.stats {
+            report.set_item("stats", stats.to_dict(py)?)?;
+        }
+        if let Some(polish) = self.polish {
+            let polish_dict = PyDict::new(py);
+            polish_dict.set_item("iterations", polish.iterations)?;
+            polish_dict.set_item("value_before", polish.value_before)?;
+            polish_dict.set_item("value_after", polish.value_after)?;
+            report.set_item("polish", polish_dict)?;
+        }
+        if !self.stages.is_empty() {
+            let stages = PyList::empty(py);
+            for stage in &self.stages {
+                stages.append(stage.to_dict(py)?)?;
+            }
+            report.set_item("stages", stages)?;
+        }
+        if let Some(failure) = &self.continuation_failure {
+            let failure_dict = PyDict::new(py);
+            failure_dict.set_item("step", failure.step)?;
+            failure_dict.set_item("constraints", failure.constraints.clone())?;
+            report.set_item("continuation_failure", failure_dict)?;
+        }
+        if !self.reseeded.is_empty() {
+            let reseeded = PyList::empty(py);
+            for reseed in &self.reseeded {
+                let reseed_dict = PyDict::new(py);
+                reseed_dict.set_item("object", reseed.object.clone())?;
+                reseed_dict.set_item("before", reseed.before.to_vec())?;
+                reseed_dict.set_item("after", reseed.after.to_vec())?;
+                reseed_dict.set_item("exact", reseed.exact)?;
+                reseeded.append(reseed_dict)?;
+            }
+            report.set_item("reseeded", reseeded)?;
+        }
+        if !self.gradient_breakdown.is_empty() {
+            let breakdown = PyDict::new(py);
+            for contribution in &self.gradient_breakdown {
+                let gradient = PyDict::new(py);
+                for (obj_name, var_name, value) in &contribution.gradient {
+                    gradient.set_item((obj_name.clone(), var_name.clone()), *value)?;
+                }
+                breakdown.set_item(contribution.constraint.clone(), gradient)?;
+            }
+            report.set_item("gradient_breakdown", breakdown)?;
+        }
+        if !self.worst_constraints.is_empty() {
+            let worst_constraints = PyList::empty(py);
+            for error in &self.worst_constraints {
+                let error_dict = PyDict::new(py);
+                error_dict.set_item("constraint", error.constraint.clone())?;
+                error_dict.set_item("value", error.value)?;
+                error_dict.set_item("residuals", error.residuals.clone())?;
+                worst_constraints.append(error_dict)?;
+            }
+            report.set_item("worst_constraints", worst_constraints)?;
+        }
+        if !self.lock_shadows.is_empty() {
+            let lock_shadows = PyList::empty(py);
+            for shadow in &self.lock_shadows {
+                let shadow_dict = PyDict::new(py);
+                shadow_dict.set_item("lock", shadow.lock.clone())?;
+                shadow_dict.set_item("object", shadow.object.clone())?;
+                shadow_dict.set_item("variable", shadow.variable.clone())?;
+                shadow_dict.set_item("value", shadow.value)?;
+                shadow_dict.set_item("gradient", shadow.gradient)?;
+                shadow_dict.set_item("shift", shadow.shift)?;
+                shadow_dict.set_item("opponents", shadow.opponents.clone())?;
+                lock_shadows.append(shadow_dict)?;
+            }
+            report.set_item("lock_shadows", lock_shadows)?;
+        }
+        if !self.attribution.is_empty() {
+            let attribution = PyDict::new(py);
+            for constraint in &self.attribution {
+                let constraint_dict = PyDict::new(py);
+                constraint_dict.set_item("active", constraint.active)?;
+                constraint_dict.set_item("initial_value", constraint.initial_value)?;
+                constraint_dict.set_item("final_value", constraint.final_value)?;
+                constraint_dict.set_item("moved", constraint.moved.clone())?;
+                attribution.set_item(constraint.constraint.clone(), constraint_dict)?;
+            }
+            report.set_item("attribution", attribution)?;
+        }
+        if !self.components.is_empty() {
+            let components = PyList::empty(py);
+            for component in &self.components {
+                let component_dict = PyDict::new(py);
+                component_dict.set_item("objects", component.objects.clone())?;
+                component_dict.set_item("success", component.success)?;
+                component_dict.set_item("report", component.report.to_dict(py)?)?;
+                components.append(component_dict)?;
+            }
+            report.set_item("components", components)?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,149 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::{Array1, Array2};
+
+use optimization::problem::{Objective, Gradient, Hessian};
+
+use crate::minimizer::SolverOptions;
+use crate::system::System;
+use crate::system_object::VariableName as VN;
+
+
+/// The system with its variables scaled by a diagonal matrix.
+///
+/// The minimizer works with the scaled variables y = x/s, where s is the scale
+/// of each variable. Angles and lengths can then have different scales, which
+/// is important for methods using a single trust region radius for all the
+/// variables. The gradient and hessian are transformed by the chain rule.
+pub struct ScaledProblem<'a> {
+    /// The system being minimized
+    system: &'a mut System,
+    /// Scale of each solver variable
+    scale: Array1<f64>,
+}
+
+
+impl<'a> ScaledProblem<'a> {
+    pub fn new(system: &'a mut System, scale: Array1<f64>) -> ScaledProblem<'a> {
+        ScaledProblem {
+            system,
+            scale,
+        }
+    }
+
+    /// Returns the scaled variables of x
+    pub fn scaled(&self, x: &Array1<f64>) -> Array1<f64> {
+        x / &self.scale
+    }
+
+    /// Returns the system variables of the scaled variables y
+    pub fn unscaled(&self, y: &Array1<f64>) -> Array1<f64> {
+        y * &self.scale
+    }
+}
+
+
+impl<'a> Objective for ScaledProblem<'a> {
+    fn eval(&mut self) {
+        self.system.eval();
+    }
+
+    fn eval_real(&mut self) -> f64 {
+        self.system.eval_real()
+    }
+
+    fn update_x(&mut self, y: &Array1<f64>) {
+        let x = self.unscaled(y);
+        self.system.update_x(&x);
+    }
+
+    fn move_step(&mut self, y: &Array1<f64>, p: &Array1<f64>, alpha: f64) {
+        let x = self.unscaled(y);
+        let p = self.unscaled(p);
+        self.system.move_step(&x, &p, alpha);
+    }
+}
+
+
+impl<'a> Gradient for ScaledProblem<'a> {
+    fn grad(&mut self, output: &mut Array1<f64>) {
+        self.system.grad(output);
+        *output *= &self.scale;
+    }
+
+    fn diff(&mut self) -> f64 {
+        self.system.diff()
+    }
+}
+
+
+impl<'a> Hessian for ScaledProblem<'a> {
+    fn hess(&mut self, output: &mut Array2<f64>) {
+        self.system.hess(output);
+        for ((i, j), value) in output.indexed_iter_mut() {
+            *value *= self.scale[i] * self.scale[j];
+        }
+    }
+}
+
+
+/// Returns the scale of each solver variable. Rotation variables use the
+/// rotation scale and position variables the translation scale. When the
+/// translation scale is not given, it is the size of the box containing the
+/// initial positions of all the objects.
+pub fn variable_scales(system: &System, options: &SolverOptions) -> Array1<f64> {
+    let translation_scale = match options.translation_scale {
+        Some(scale) => scale,
+        None => bounding_box_size(system),
+    };
+    let mut scale = Array1::ones(system.get_enabled_size());
+    for object in &system.sys_objects {
+        let variables = VN::get_position_iter()
+            .map(|var| (var, translation_scale))
+            .chain(VN::get_rotation_iter().map(|var| (var, options.rotation_scale)));
+        for (var_name, var_scale) in variables {
+            if let Some(k) = object.get_variable(var_name).index {
+                scale[k] = var_scale;
+            }
+        }
+    }
+    scale
+}
+
+
+/// Returns the length of the diagonal of the box containing the positions of
+/// the system objects, or 1 if all the objects are at the same position.
+fn bounding_box_size(system: &System) -> f64 {
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for object in &system.sys_objects {
+        for (i, var_name) in VN::get_position_iter().enumerate() {
+            let value = object.get_variable(var_name).value;
+            min[i] = min[i].min(value);
+            max[i] = max[i].max(value);
+        }
+    }
+    let size = (0..3)
+        .map(|i| (max[i] - min[i]).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    if size > 0.0 && size.is_finite() {
+        size
+    } else {
+        1.0
+    }
+}
@@ -0,0 +1,401 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+
+
+/// Method used to minimize the constraint system
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Method {
+    /// Newton for small systems (up to newton_max_dimension variables) whose
+    /// hessian is well conditioned at the starting point, and TrustNCG for the
+    /// rest. Newton runs that fail are continued with TrustNCG.
+    Auto,
+    /// Trust region Newton-CG method from the optimization crate. It uses the
+    /// exact hessian of the system.
+    TrustNCG,
+    /// Levenberg-Marquardt method. It takes advantage of the constraint functions
+    /// being sums of squared residuals, so it only needs the jacobian of the
+    /// residuals.
+    LevenbergMarquardt,
+    /// Gauss-Newton method with a line search. Like LevenbergMarquardt it
+    /// only needs the jacobian of the residuals, and its undamped steps
+    /// converge faster on overdetermined systems. When the jacobian does not
+    /// have full rank it continues with LevenbergMarquardt.
+    GaussNewton,
+    /// Newton method with a line search. It solves a dense linear system on
+    /// each iteration, so it is only fast for small systems. When the hessian is
+    /// ill-conditioned it continues with TrustNCG.
+    Newton,
+    /// Truncated Newton method with a line search. The steps are found with
+    /// conjugate gradients on the sparse hessian, so it needs much less memory
+    /// than the other Newton methods on systems with many objects.
+    NewtonCg,
+    /// Limited memory BFGS method. It only uses the gradient of the system.
+    Lbfgs,
+    /// LBFGS followed by TrustNCG. The LBFGS solution is used as the starting
+    /// point of TrustNCG.
+    LbfgsNewton,
+}
+
+
+impl Method {
+    /// Returns a Method from its name used in the solver options. Returns None
+    /// if the name is not a known method.
+    pub fn get_from_str(method: &str) -> Option<Method> {
+        match method {
+            "auto" => Some(Method::Auto),
+            "trust-ncg" => Some(Method::TrustNCG),
+            "newton" => Some(Method::Newton),
+            "newton-cg" => Some(Method::NewtonCg),
+            "lm" => Some(Method::LevenbergMarquardt),
+            "gauss-newton" => Some(Method::GaussNewton),
+            "lbfgs" => Some(Method::Lbfgs),
+            "lbfgs+newton" => Some(Method::LbfgsNewton),
+            _ => None,
+        }
+    }
+}
+
+
+/// Strategy used to solve the constraint system
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Strategy {
+    /// Solve all the variables at the same time
+    Single,
+    /// Solve first with the rotation variables locked at their initial values
+    /// and then solve all the variables starting from that solution
+    Staged,
+}
+
+
+impl Strategy {
+    /// Returns a Strategy from its name used in the solver options. Returns
+    /// None if the name is not a known strategy.
+    pub fn get_from_str(strategy: &str) -> Option<Strategy> {
+        match strategy {
+            "single" => Some(Strategy::Single),
+            "staged" => Some(Strategy::Staged),
+            _ => None,
+        }
+    }
+}
+
+
+/// Options used to solve the constraint system.
+#[derive(Debug, Clone)]
+pub struct SolverOptions {
+    /// Name of the preset the options started from (None for the default
+    /// options)
+    pub preset: Option<&'static str>,
+    /// Minimization method
+    pub method: Method,
+    /// Solving strategy
+    pub strategy: Strategy,
+    /// Largest number of variables solved with the Newton method when the
+    /// method is Auto
+    pub newton_max_dimension: usize,
+    /// Maximum number of iterations. TrustNCG uses its own limit on each run,
+    /// but it is not run again once this number of iterations is reached.
+    pub max_iterations: usize,
+    /// The minimization converges when the largest component of the gradient
+    /// is smaller than this value. Only used by the methods implemented in
+    /// this crate.
+    pub gradient_tolerance: f64,
+    /// The system is solved when the value of every constraint measuring a
+    /// squared length is smaller than this value
+    pub residual_tolerance: f64,
+    /// The system is solved when the value of every constraint measuring a
+    /// squared angle is smaller than this value
+    pub angle_residual_tolerance: f64,
+    /// Number of times the system is solved again from a perturbed starting
+    /// point when the first attempt fails
+    pub restarts: usize,
+    /// Seed of the random perturbations used by the restarts
+    pub seed: u64,
+    /// Largest offset added to the position variables on a restart
+    pub restart_magnitude: f64,
+    /// Largest offset added to the rotation variables on a restart as a
+    /// fraction of pi
+    pub restart_angle_fraction: f64,
+    /// Value added to the (near) zero diagonal entries of the hessian. These
+    /// entries belong to variables that no constraint curves, which makes the
+    /// hessian singular. A value of 0 disables the regularization.
+    ///
+    /// The variables of the reference object of each component that is free to
+    /// move as a whole are also locked during the solve (unless the value is
+    /// 0), and they are reported with the regularized variables.
+    pub regularization: f64,
+    /// The minimization diverges when the objective function stays larger than
+    /// the best value found times this factor for divergence_window iterations
+    pub divergence_factor: f64,
+    /// Number of iterations used to detect the divergence of the minimization
+    pub divergence_window: usize,
+    /// Largest change of a position variable in a single step. Steps with a
+    /// larger change are scaled down. Only used by the methods implemented in
+    /// this crate (TrustNCG limits its steps with its own trust region).
+    pub max_translation_step: f64,
+    /// Largest change (in radians) of a rotation variable in a single step.
+    /// Only used by the methods implemented in this crate.
+    pub max_rotation_step: f64,
+    /// Number of iterations of the window used to detect stagnation. A value of
+    /// 0 disables the detection.
+    pub stagnation_window: usize,
+    /// The minimization stagnates when the objective function decreases less
+    /// than this fraction over the window...
+    pub stagnation_tolerance: f64,
+    /// ...and the norm of every step in the window is smaller than this value
+    pub stagnation_step: f64,
+    /// Maximum number of Newton steps used to polish a successful solution
+    pub polish_iterations: usize,
+    /// Number of steps used to move the constraint parameters from the values
+    /// satisfied by the initial placements to the values given by the user. A
+    /// value of 0 solves the system directly.
+    pub continuation_steps: usize,
+    /// Scale of the rotation variables used by TrustNCG
+    pub rotation_scale: f64,
+    /// Scale of the position variables used by TrustNCG. When it is None, the
+    /// scale is found from the initial placements of the objects ("auto" in
+    /// the options dictionary).
+    pub translation_scale: Option<f64>,
+    /// Solve the independent components of the system separately (at the same
+    /// time when the crate is built with the parallel feature)
+    pub split_components: bool,
+    /// Maximum number of evaluations of the objective function (None means no
+    /// limit). The solve stops at the best point found when it is reached.
+    pub max_function_evals: Option<usize>,
+    /// Maximum number of evaluations of the gradient (None means no limit)
+    pub max_gradient_evals: Option<usize>,
+    /// Add the gradient contribution of each constraint at the final point to
+    /// the solve report
+    pub gradient_breakdown: bool,
+    /// Add to the solve report how much each lock fights the rest of the
+    /// constraints at the final point
+    pub lock_shadows: bool,
+    /// The system is not solved when the estimated memory needed to solve it is
+    /// larger than this number of bytes (None means no limit)
+    pub max_memory_bytes: Option<usize>,
+    /// Add to the solve report which constraints moved which variables
+    pub attribution: bool,
+    /// Weight of a quadratic penalty pulling every solver variable towards its
+    /// initial value, so the parts left free by the constraints stay where they
+    /// were. A value of 0 (the default) disables the penalty.
+    ///
+    /// The penalty also pulls the constrained variables: a variable that the
+    /// constraints move by a distance d ends up about weight * d short of its
+    /// target. This error is removed by solving the system again without the
+    /// penalty, which moves the free variables by about the same amount. The
+    /// weight should be small compared to the curvature of the constraints
+    /// (about 1); values between 1e-4 and 1e-2 work well.
+    pub stay_near_start_weight: f64,
+}
+
+
+impl SolverOptions {
+    pub fn new() -> SolverOptions {
+        SolverOptions {
+            preset: None,
+            method: Method::Auto,
+            strategy: Strategy::Single,
+            newton_max_dimension: 30,
+            max_iterations: 200,
+            gradient_tolerance: 1e-10,
+            residual_tolerance: 1e-12,
+            angle_residual_tolerance: 1e-14,
+            restarts: 0,
+            seed: 0,
+            restart_magnitude: 1.0,
+            restart_angle_fraction: 0.5,
+            regularization: 1e-8,
+            divergence_factor: 1e3,
+            divergence_window: 10,
+            max_translation_step: f64::INFINITY,
+            max_rotation_step: 0.5,
+            stagnation_window: 20,
+            stagnation_tolerance: 1e-6,
+            stagnation_step: 1e-8,
+            polish_iterations: 0,
+            continuation_steps: 0,
+            rotation_scale: 1.0,
+            translation_scale: Some(1.0),
+            split_components: false,
+            max_function_evals: None,
+            max_gradient_evals: None,
+            gradient_breakdown: false,
+            lock_shadows: false,
+            max_memory_bytes: None,
+            attribution: false,
+            stay_near_start_weight: 0.0,
+        }
+    }
+
+    /// Options for interactive use (for example while dragging a part): the
+    /// solve stops early with a moderate tolerance, and it starts from the last
+    /// solution.
+    pub fn interactive() -> SolverOptions {
+        SolverOptions {
+            preset: Some("interactive"),
+            max_iterations: 50,
+            residual_tolerance: 1e-10,
+            angle_residual_tolerance: 1e-12,
+            stagnation_window: 10,
+            max_function_evals: Some(500),
+            max_gradient_evals: Some(200),
+            ..SolverOptions::new()
+        }
+    }
+
+    /// Options for accurate solutions: tight tolerances, many iterations,
+    /// restarts and a polish of the solution.
+    pub fn accurate() -> SolverOptions {
+        SolverOptions {
+            preset: Some("accurate"),
+            max_iterations: 1000,
+            gradient_tolerance: 1e-14,
+            residual_tolerance: 1e-16,
+            angle_residual_tolerance: 1e-18,
+            restarts: 2,
+            polish_iterations: 3,
+            ..SolverOptions::new()
+        }
+    }
+
+    /// Options for a quick draft solution with a loose tolerance
+    pub fn draft() -> SolverOptions {
+        SolverOptions {
+            preset: Some("draft"),
+            max_iterations: 20,
+            residual_tolerance: 1e-6,
+            angle_residual_tolerance: 1e-8,
+            ..SolverOptions::new()
+        }
+    }
+
+    /// Returns the options of a preset from its name. Returns None if the name
+    /// is not a known preset.
+    pub fn from_preset(preset: &str) -> Option<SolverOptions> {
+        match preset {
+            "interactive" => Some(SolverOptions::interactive()),
+            "accurate" => Some(SolverOptions::accurate()),
+            "draft" => Some(SolverOptions::draft()),
+            _ => None,
+        }
+    }
+
+    /// Creates the solver options from the options dictionary passed from
+    /// Python. Options not found in the dictionary keep their default values,
+    /// or the values of the preset given by the "preset" key.
+    #[cfg(feature = "python")]
+    pub fn from_dict(options: Option<&PyDict>) -> PyResult<SolverOptions> {
+        let mut solver_options = SolverOptions::new();
+        if let Some(options) = options {
+            // The preset is applied first so the other keys override it
+            if let Some(preset) = options.get_item("preset") {
+                let preset: &str = preset.extract()?;
+                solver_options = SolverOptions::from_preset(preset).ok_or_else(|| {
+                    PyValueError::new_err(format!("unknown solver preset '{}'", preset))
+                })?;
+            }
+            for (key, value) in options.iter() {
+                let key: &str = key.extract()?;
+                if key != "preset" {
+                    solver_options.set_option(key, value)?;
+                }
+            }
+        }
+        Ok(solver_options)
+    }
+
+    /// Sets the value of a single option
+    #[cfg(feature = "python")]
+    fn set_option(&mut self, key: &str, value: &PyAny) -> PyResult<()> {
+        match key {
+            "method" => {
+                let method: &str = value.extract()?;
+                self.method = Method::get_from_str(method).ok_or_else(|| {
+                    PyValueError::new_err(format!("unknown solver method '{}'", method))
+                })?;
+            },
+            "strategy" => {
+                let strategy: &str = value.extract()?;
+                self.strategy = Strategy::get_from_str(strategy).ok_or_else(|| {
+                    PyValueError::new_err(format!("unknown solver strategy '{}'", strategy))
+                })?;
+            },
+            "newton_max_dimension" => self.newton_max_dimension = value.extract()?,
+            "max_iterations" => self.max_iterations = value.extract()?,
+            "gradient_tolerance" => self.gradient_tolerance = value.extract()?,
+            "residual_tolerance" => self.residual_tolerance = value.extract()?,
+            "angle_residual_tolerance" => self.angle_residual_tolerance = value.extract()?,
+            "restarts" => self.restarts = value.extract()?,
+            "seed" => self.seed = value.extract()?,
+            "restart_magnitude" => self.restart_magnitude = value.extract()?,
+            "restart_angle_fraction" => self.restart_angle_fraction = value.extract()?,
+            "regularization" => self.regularization = value.extract()?,
+            "divergence_factor" => self.divergence_factor = value.extract()?,
+            "divergence_window" => self.divergence_window = value.extract()?,
+            "max_translation_step" => self.max_translation_step = value.extract()?,
+            "max_rotation_step" => self.max_rotation_step = value.extract()?,
+            "stagnation_window" => self.stagnation_window = value.extract()?,
+            "stagnation_tolerance" => self.stagnation_tolerance = value.extract()?,
+            "stagnation_step" => self.stagnation_step = value.extract()?,
+            "polish_iterations" => self.polish_iterations = value.extract()?,
+            "continuation_steps" => self.continuation_steps = value.extract()?,
+            "rotation_scale" => self.rotation_scale = value.extract()?,
+            "split_components" => self.split_components = value.extract()?,
+            "max_function_evals" => self.max_function_evals = value.extract()?,
+            "max_gradient_evals" => self.max_gradient_evals = value.extract()?,
+            "gradient_breakdown" => self.gradient_breakdown = value.extract()?,
+            "lock_shadows" => self.lock_shadows = value.extract()?,
+            "max_memory_bytes" => self.max_memory_bytes = value.extract()?,
+            "attribution" => self.attribution = value.extract()?,
+            "stay_near_start_weight" => {
+                let weight: f64 = value.extract()?;
+                if weight.is_nan() || weight < 0.0 {
+                    return Err(PyValueError::new_err("stay_near_start_weight must not be negative"));
+                }
+                self.stay_near_start_weight = weight;
+            },
+            "translation_scale" => {
+                self.translation_scale = match value.extract::<&str>() {
+                    Ok("auto") => None,
+                    Ok(scale) => {
+                        return Err(PyValueError::new_err(format!(
+                            "unknown translation scale '{}'", scale
+                        )));
+                    },
+                    Err(_) => Some(value.extract()?),
+                };
+            },
+            _ => {
+                return Err(PyValueError::new_err(format!("unknown solver option '{}'", key)));
+            },
+        }
+        Ok(())
+    }
+}
+
+
+impl Default for SolverOptions {
+    fn default() -> SolverOptions {
+        SolverOptions::new()
+    }
+}
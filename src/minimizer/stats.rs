@@ -0,0 +1,166 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+
+use std::time::Duration;
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyMemoryError;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+
+use crate::minimizer::{SolveReport, StopReason};
+
+
+/// Reason why the solver stopped
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Termination {
+    /// Every constraint is satisfied
+    Converged,
+    /// The maximum number of iterations was reached
+    MaxIterations,
+    /// The minimization method stopped reducing the objective function
+    NoProgress,
+    /// The objective function grew too much (see StopReason::Diverged)
+    Diverged,
+    /// The objective function stagnated (see StopReason::Stagnated)
+    Stagnated,
+    /// A constraint returned a non-finite value
+    NonFinite,
+    /// The evaluation budget was exhausted
+    BudgetExhausted,
+    /// The system was not solved because it would need more memory than
+    /// allowed by the solver options
+    MemoryLimit,
+    /// The system was not solved because some constraints use objects removed
+    /// from the system
+    InvalidConstraints,
+    /// The system was not solved because the starting position given to
+    /// System::solve_from does not have one value for each solver variable
+    InvalidStart,
+}
+
+
+impl Termination {
+    /// Returns the name of the termination reason used in the solve report
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Converged => "converged",
+            Termination::MaxIterations => "max_iterations",
+            Termination::NoProgress => "no_progress",
+            Termination::Diverged => "diverged",
+            Termination::Stagnated => "stagnated",
+            Termination::NonFinite => "non_finite",
+            Termination::BudgetExhausted => "budget_exhausted",
+            Termination::MemoryLimit => "memory_limit",
+            Termination::InvalidConstraints => "invalid_constraints",
+            Termination::InvalidStart => "invalid_start",
+        }
+    }
+
+    /// Finds the termination reason of a solve from its report. When the solve
+    /// failed, the reason is the one of its last attempt.
+    pub fn from_report(success: bool, report: &SolveReport, max_iterations: usize) -> Termination {
+        if success {
+            return Termination::Converged;
+        }
+        let attempt = match report.attempts.last() {
+            Some(attempt) => attempt,
+            None => return Termination::NoProgress,
+        };
+        match attempt.reason {
+            Some(StopReason::Diverged) => Termination::Diverged,
+            Some(StopReason::Stagnated) => Termination::Stagnated,
+            Some(StopReason::NonFinite) => Termination::NonFinite,
+            Some(StopReason::BudgetExhausted) => Termination::BudgetExhausted,
+            _ if attempt.iter_num >= max_iterations => Termination::MaxIterations,
+            _ => Termination::NoProgress,
+        }
+    }
+}
+
+
+/// Statistics of solving the system. Unlike the rest of the solve report, the
+/// statistics cover the whole solve (every attempt, stage and continuation
+/// step).
+#[derive(Debug, Clone)]
+pub struct SolveStats {
+    /// States whether every constraint was satisfied
+    pub success: bool,
+    /// Number of iterations of the minimization methods
+    pub iterations: usize,
+    /// Number of evaluations of the objective function (or of the residuals)
+    pub f_evals: usize,
+    /// Number of evaluations of the gradient
+    pub f_grad_evals: usize,
+    /// Number of evaluations of the hessian
+    pub hessian_evals: usize,
+    /// Time spent solving the system
+    pub wall_time: Duration,
+    /// Estimated peak memory (in bytes) needed to solve the system
+    pub memory_estimate: usize,
+    /// Reason why the solver stopped
+    pub termination: Termination,
+}
+
+
+impl SolveStats {
+    /// Returns the statistics of a system that was not solved
+    pub fn unsolved(termination: Termination) -> SolveStats {
+        SolveStats {
+            success: false,
+            iterations: 0,
+            f_evals: 0,
+            f_grad_evals: 0,
+            hessian_evals: 0,
+            wall_time: Duration::default(),
+            memory_estimate: 0,
+            termination,
+        }
+    }
+
+    /// Converts the statistics into a Python dictionary. The wall time is given
+    /// in seconds.
+    #[cfg(feature = "python")]
+    pub fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let stats = PyDict::new(py);
+        stats.set_item("success", self.success)?;
+        stats.set_item("iterations", self.iterations)?;
+        stats.set_item("f_evals", self.f_evals)?;
+        stats.set_item("f_grad_evals", self.f_grad_evals)?;
+        stats.set_item("hessian_evals", self.hessian_evals)?;
+        stats.set_item("wall_time", self.wall_time.as_secs_f64())?;
+        stats.set_item("memory_estimate", self.memory_estimate)?;
+        stats.set_item("termination", self.termination.as_str())?;
+        Ok(stats)
+    }
+
+    /// Returns a MemoryError if the system was not solved because it would
+    /// need too much memory
+    #[cfg(feature = "python")]
+    pub fn check_memory(&self) -> PyResult<()> {
+        if self.termination == Termination::MemoryLimit {
+            return Err(PyMemoryError::new_err(format!(
+                "solving the system needs about {} bytes of memory, more than allowed by \
+                max_memory_bytes. The lbfgs method needs much less memory for large systems.",
+                self.memory_estimate,
+            )));
+        }
+        Ok(())
+    }
+}
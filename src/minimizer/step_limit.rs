@@ -0,0 +1,70 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::Array1;
+
+use crate::system::System;
+use crate::system_object::VariableName as VN;
+
+
+/// Limits the change of the solver variables in a single iteration.
+///
+/// A step far from the solution can propose a huge rotation that jumps across
+/// the nonlinearity of the constraints. When any component of a step is larger
+/// than the limit of its variable, the whole step is scaled down so its
+/// direction doesn't change.
+#[derive(Debug)]
+pub struct StepLimit {
+    /// Largest change allowed for each solver variable
+    limits: Array1<f64>,
+}
+
+
+impl StepLimit {
+    /// Creates the limits of the variables of the system. Position variables
+    /// are limited by max_translation and rotation variables by max_rotation.
+    pub fn new(system: &System, max_translation: f64, max_rotation: f64) -> StepLimit {
+        let mut limits = Array1::from_elem(system.get_enabled_size(), f64::INFINITY);
+        for object in &system.sys_objects {
+            let variables = VN::get_position_iter()
+                .map(|var| (var, max_translation))
+                .chain(VN::get_rotation_iter().map(|var| (var, max_rotation)));
+            for (var_name, limit) in variables {
+                if let Some(k) = object.get_variable(var_name).index {
+                    limits[k] = limit;
+                }
+            }
+        }
+        StepLimit {
+            limits,
+        }
+    }
+
+    /// Scales down the step when it exceeds the limits. Returns the scale factor
+    /// applied to the step.
+    pub fn apply(&self, step: &mut Array1<f64>) -> f64 {
+        let mut scale: f64 = 1.0;
+        for (p, limit) in step.iter().zip(self.limits.iter()) {
+            if p.abs() > *limit {
+                scale = scale.min(limit / p.abs());
+            }
+        }
+        if scale < 1.0 {
+            *step *= scale;
+        }
+        scale
+    }
+}
@@ -0,0 +1,1097 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::mem::size_of;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use ndarray::{Array1, Array2};
+
+use crate::geometry::euler;
+use crate::minimizer::{Method, Monitor, SolveStats, SolverOptions, StopReason, Strategy, Termination};
+use crate::minimizer::{memory_estimate, minimize, PROGRESS_TOLERANCE};
+use crate::minimizer::levenberg_marquardt::LevenbergMarquardt;
+use crate::minimizer::newton::{Newton, newton_applicable};
+use optimization::problem::{Gradient, Hessian, Objective};
+
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::system::System;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::test_fixtures::*;
+
+
+/// Largest difference allowed between the placements found by two methods
+const SOLUTION_TOLERANCE: f64 = 1e-5;
+
+
+/// Solves a fixture with the given options. Panics if the solve fails.
+fn solved(fixture: &Fixture, options: &SolverOptions) -> System {
+    let mut system = fixture.system();
+    let stats = system.solve(options);
+    assert!(stats.success, "{:?} failed: {:?}", options.method, stats.termination);
+    system
+}
+
+
+fn with_method(method: Method) -> SolverOptions {
+    SolverOptions {
+        method,
+        ..SolverOptions::new()
+    }
+}
+
+
+#[test]
+fn levenberg_marquardt_matches_newton() {
+    for (name, fixture) in corpus() {
+        let newton = solved(&fixture, &with_method(Method::Newton));
+        let lm = solved(&fixture, &with_method(Method::LevenbergMarquardt));
+        assert!(system_distance(&newton, &lm) < SOLUTION_TOLERANCE, "{}", name);
+    }
+}
+
+
+#[test]
+fn residual_criterion_keeps_iterating_after_gradient_stop() {
+    let options = SolverOptions {
+        method: Method::Newton,
+        gradient_tolerance: 1e-3,
+        ..SolverOptions::new()
+    };
+    let fixture = two_part(5.0);
+
+    // The Newton method alone stops on its gradient criterion while the
+    // constraints are still visibly unsatisfied
+    let mut system = fixture.system();
+    system.update_indices();
+    let x0 = system.get_position();
+    Newton::new(&options).minimize(&x0, &mut system);
+    system.eval_real();
+    assert!(!system.within_tolerance());
+
+    // The solver runs the method again until every constraint is satisfied
+
+    let mut system = fixture.system();
+    assert!(system.solve(&options).success);
+    assert!(system.within_tolerance());
+}
+
+
+#[test]
+fn lbfgs_matches_newton() {
+    for (name, fixture) in corpus() {
+        let newton = solved(&fixture, &with_method(Method::Newton));
+        // L-BFGS needs many more iterations than Newton
+        let lbfgs = solved(&fixture, &SolverOptions {
+            max_iterations: 5000,
+            ..with_method(Method::Lbfgs)
+        });
+        assert!(system_distance(&newton, &lbfgs) < SOLUTION_TOLERANCE, "{}", name);
+    }
+}
+
+
+#[test]
+fn newton_cg_matches_newton() {
+    for (name, fixture) in corpus() {
+        let newton = solved(&fixture, &with_method(Method::Newton));
+        let newton_cg = solved(&fixture, &with_method(Method::NewtonCg));
+        assert!(system_distance(&newton, &newton_cg) < SOLUTION_TOLERANCE, "{}", name);
+    }
+}
+
+
+#[test]
+fn line_search_methods_match_trust_ncg() {
+    for (name, fixture) in corpus() {
+        let trust_ncg = solved(&fixture, &with_method(Method::TrustNCG));
+        for method in [Method::Newton, Method::GaussNewton, Method::Lbfgs].iter().cloned() {
+            let line_search = solved(&fixture, &SolverOptions {
+                max_iterations: 5000,
+                ..with_method(method)
+            });
+            assert!(
+                system_distance(&trust_ncg, &line_search) < SOLUTION_TOLERANCE,
+                "{} with {:?}", name, method,
+            );
+        }
+    }
+}
+
+
+#[test]
+fn directional_derivative_matches_the_gradient() {
+    let mut system = chain(4, 0.3).system();
+    system.update_indices();
+    let x = system.get_position();
+    let n = x.len();
+    let p = Array1::from_iter((0..n).map(|i| (i as f64 * 0.7).sin()));
+    for alpha in [0.0, 0.1, 0.5].iter().cloned() {
+        system.move_step(&x, &p, alpha);
+        system.eval();
+        let mut grad = Array1::zeros(n);
+        system.grad(&mut grad);
+        let expected = grad.dot(&p);
+        assert!((system.diff() - expected).abs() < 1e-10 * expected.abs().max(1.0), "{}", alpha);
+    }
+}
+
+
+#[test]
+fn lbfgs_newton_is_never_worse() {
+    // Value of the objective function after solving with a small budget
+    let value = |fixture: &Fixture, method, max_iterations| {
+        let mut system = fixture.system();
+        system.solve(&SolverOptions {
+            method,
+            max_iterations,
+            ..SolverOptions::new()
+        });
+        system.eval_real()
+    };
+    for (name, fixture) in corpus() {
+        for max_iterations in [2, 5, 10].iter().cloned() {
+            let hybrid = value(&fixture, Method::LbfgsNewton, max_iterations);
+            let lbfgs = value(&fixture, Method::Lbfgs, max_iterations);
+            let newton = value(&fixture, Method::Newton, max_iterations);
+            // values below the residual tolerance are all solutions
+            let best = lbfgs.min(newton).max(SolverOptions::new().residual_tolerance);
+            assert!(hybrid <= best, "{} with {} iterations", name, max_iterations);
+        }
+    }
+}
+
+
+#[test]
+fn restart_escapes_anti_parallel_trap() {
+    // The normal of "B" starts exactly opposite to the normal of the locked
+    // "A", which is a stationary point of the plane parallel constraint
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [0.0, 0.0, 0.0, 0.0, PI, 0.0])
+        .constraint("PlaneParallel_1", &[("Object1", "A"), ("Object2", "B")], &[])
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ]);
+    let options = with_method(Method::LevenbergMarquardt);
+
+    let mut system = fixture.system();
+    assert!(!system.solve(&options).success);
+    assert_eq!(system.report.attempts.len(), 1);
+
+    for seed in 0..3 {
+        let mut system = fixture.system();
+        let stats = system.solve(&SolverOptions {
+            restarts: 3,
+            seed,
+            ..options.clone()
+        });
+        assert!(stats.success, "seed {}", seed);
+        let attempts = &system.report.attempts;
+        assert!(!attempts[0].success);
+        assert!(attempts.last().unwrap().success);
+        assert!(system.within_tolerance());
+    }
+}
+
+
+#[test]
+fn free_reference_is_locked_during_the_solve() {
+    // Nothing ties "A" or "B" to the global coordinate system
+    let fixture = Fixture::new()
+        .object("A", [0.5, -1.0, 2.0, 0.1, 0.0, 0.0])
+        .object("B", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 5.0), ("y", 1.0), ("z", 0.0),
+        ]);
+    let mut system = fixture.system();
+    assert!(system.solve(&with_method(Method::Newton)).success);
+    assert_eq!(placement_of(&system, "A"), [0.5, -1.0, 2.0, 0.1, 0.0, 0.0]);
+    assert!(!system.report.regularized.is_empty());
+    for (obj_name, _) in &system.report.regularized {
+        assert_eq!(obj_name, "A");
+    }
+
+    // The variables are only locked during the solve
+    for variable in system.sys_objects[system.sys_objects_idx["A"]].get_variables_iter() {
+        assert!(!variable.locked);
+    }
+}
+
+
+/// A constraint pulling the x variable of an object to 1 from a target that
+/// moves further away each time the constraint is evaluated, so the minimizer
+/// can never settle
+#[derive(Debug, Clone)]
+struct Drift {
+    obj_index: usize,
+    /// offset of the target, doubled at each evaluation
+    drift: f64,
+    residual: f64,
+}
+
+
+impl Drift {
+    fn jacobian(&self, sys_objects: &[SystemObject]) -> Vec<(usize, f64)> {
+        let variable = sys_objects[self.obj_index].get_variable(VN::x);
+        variable.index.map(|k| (k, variable.scale)).into_iter().collect()
+    }
+}
+
+
+impl Constraint for Drift {
+    fn evaluate(&mut self, sys_objects: &[SystemObject]) {
+        self.drift *= 2.0;
+        self.residual = sys_objects[self.obj_index].get_variable(VN::x).value - 1.0 + self.drift;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_grad[k] += 2.0 * self.residual * jac;
+        }
+    }
+
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        self.jacobian(sys_objects)
+            .iter()
+            .map(|(k, jac)| 2.0 * self.residual * jac * direction[*k])
+            .sum()
+    }
+
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_hess.add(k, k, 2.0 * jac * jac);
+        }
+    }
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]) {
+        residuals.push(Residual {
+            value: self.residual,
+            jacobian: self.jacobian(sys_objects),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.obj_index, VN::x)]
+    }
+
+    fn get_name(&self) -> &str {
+        "Drift"
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj_index = object_map[&self.obj_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+#[test]
+fn diverging_solve_returns_the_best_point() {
+    for method in [Method::LevenbergMarquardt, Method::Newton].iter().cloned() {
+        let mut system = System::new();
+        system.add_object("P", &to_placement([3.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        let obj_index = system.sys_objects_idx["P"];
+        system.sys_objects[obj_index].enable_variables(&["x"]);
+        system.add_custom_constraint(Box::new(Drift {
+            obj_index,
+            drift: 1e-3,
+            residual: 0.0,
+        }));
+
+        let stats = system.solve(&SolverOptions {
+            max_iterations: 20,
+            ..with_method(method)
+        });
+        assert!(!stats.success);
+        assert_eq!(stats.termination, Termination::Diverged, "{:?}", method);
+        let attempt = &system.report.attempts[0];
+        assert_eq!(attempt.reason, Some(StopReason::Diverged));
+        assert_eq!(attempt.best_iteration, system.monitor.best_iteration);
+
+        // The placement written back is the best point, not the last one
+        let best_x = system.monitor.best_x.clone().unwrap();
+        assert_eq!(placement_of(&system, "P")[0], best_x[0], "{:?}", method);
+        assert!(system.monitor.best_value < attempt.value);
+    }
+}
+
+
+/// A constraint pulling the x variable of an object to 1 that returns NaN from
+/// its evaluation number nan_after on, like a constraint with a bug
+#[derive(Debug, Clone)]
+struct Broken {
+    obj_index: usize,
+    nan_after: usize,
+    evaluations: usize,
+    residual: f64,
+}
+
+
+impl Broken {
+    fn jacobian(&self, sys_objects: &[SystemObject]) -> Vec<(usize, f64)> {
+        let variable = sys_objects[self.obj_index].get_variable(VN::x);
+        variable.index.map(|k| (k, variable.scale)).into_iter().collect()
+    }
+}
+
+
+impl Constraint for Broken {
+    fn evaluate(&mut self, sys_objects: &[SystemObject]) {
+        self.evaluations += 1;
+        self.residual = if self.evaluations >= self.nan_after {
+            f64::NAN
+        } else {
+            sys_objects[self.obj_index].get_variable(VN::x).value - 1.0
+        };
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_grad[k] += 2.0 * self.residual * jac;
+        }
+    }
+
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        self.jacobian(sys_objects)
+            .iter()
+            .map(|(k, jac)| 2.0 * self.residual * jac * direction[*k])
+            .sum()
+    }
+
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_hess.add(k, k, 2.0 * jac * jac);
+        }
+    }
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]) {
+        residuals.push(Residual {
+            value: self.residual,
+            jacobian: self.jacobian(sys_objects),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.obj_index, VN::x)]
+    }
+
+    fn get_name(&self) -> &str {
+        "Broken"
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj_index = object_map[&self.obj_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+#[test]
+fn non_finite_constraint_is_named_in_the_report() {
+    for method in [Method::LevenbergMarquardt, Method::Newton, Method::TrustNCG].iter().cloned() {
+        let mut system = two_part(5.0).system();
+        let obj_index = system.sys_objects_idx["B"];
+        system.add_custom_constraint(Box::new(Broken {
+            obj_index,
+            nan_after: 3,
+            evaluations: 0,
+            residual: 0.0,
+        }));
+
+        let stats = system.solve(&with_method(method));
+        assert!(!stats.success);
+        assert_eq!(stats.termination, Termination::NonFinite, "{:?}", method);
+        let attempt = &system.report.attempts[0];
+        assert_eq!(attempt.reason, Some(StopReason::NonFinite));
+        let record = attempt.non_finite.as_ref().unwrap();
+        assert_eq!(record.constraint, "Broken");
+        assert!(record.value.is_nan());
+
+        // The snapshot has the six variables of "B" with finite values
+        assert_eq!(record.variables.len(), 6, "{:?}", method);
+        for (obj_name, _, value) in &record.variables {
+            assert_eq!(obj_name, "B");
+            assert!(value.is_finite());
+        }
+        assert!(record.variables.iter().any(|(_, var_name, _)| var_name == "x"));
+    }
+}
+
+
+#[test]
+fn evaluation_budget_counts_match_the_calls() {
+    let methods = [
+        Method::TrustNCG, Method::Newton, Method::Lbfgs, Method::LevenbergMarquardt, Method::GaussNewton,
+    ];
+    for method in methods.iter().cloned() {
+        // Levenberg-Marquardt and Gauss-Newton only evaluate residuals
+        let budgets: &[(Option<usize>, Option<usize>)] = match method {
+            Method::LevenbergMarquardt | Method::GaussNewton => &[(Some(4), None)],
+            _ => &[(Some(4), None), (None, Some(3))],
+        };
+        for (max_function_evals, max_gradient_evals) in budgets.iter().cloned() {
+            let mut system = two_part(5.0).object("B", [1.0, 2.0, 3.0, 1.0, 1.2, 0.3]).system();
+            let counter = Counter::new(system.sys_objects_idx["B"]);
+            system.add_custom_constraint(Box::new(counter.clone()));
+
+            let stats = system.solve(&SolverOptions {
+                max_function_evals,
+                max_gradient_evals,
+                ..with_method(method)
+            });
+            let label = format!("{:?} {:?} {:?}", method, max_function_evals, max_gradient_evals);
+            assert!(!stats.success, "{}", label);
+            assert_eq!(stats.termination, Termination::BudgetExhausted, "{}", label);
+            if let Some(max) = max_gradient_evals {
+                assert_eq!(stats.f_grad_evals, max, "{}", label);
+            }
+
+            // The failed system is evaluated once more for the worst
+            // constraints of the report, after the statistics are taken
+            assert_eq!(counter.evaluations.load(Ordering::SeqCst), stats.f_evals + 1, "{}", label);
+            assert_eq!(counter.gradients.load(Ordering::SeqCst), stats.f_grad_evals, "{}", label);
+        }
+    }
+}
+
+
+#[test]
+fn rotation_step_limit_avoids_rejected_steps() {
+    // Number of steps rejected by Levenberg-Marquardt, which evaluates the
+    // residuals once at the start and once for each trial step
+    let rejected = |start: [f64; 6], max_rotation_step| {
+        let mut system = two_part(5.0).object("B", start).system();
+        system.update_indices();
+        let x0 = system.get_position();
+        let options = SolverOptions {
+            max_rotation_step,
+            ..with_method(Method::LevenbergMarquardt)
+        };
+        system.monitor = Monitor::new(&options);
+        let sol = LevenbergMarquardt::new(&options).minimize(&x0, &mut system);
+        assert!(sol.success);
+        sol.f_evals - 1 - sol.iter_num
+    };
+
+    // The rotation of "B" starts 170 degrees away from the solution
+    let off = 170f64.to_radians();
+    let starts = [
+        [1.0, 2.0, 3.0, 0.5, 0.3, off],
+        [1.0, 2.0, 3.0, 0.5 + off / 2.0, 0.3, off / 2.0],
+    ];
+    for start in starts.iter().cloned() {
+        let unlimited = rejected(start, f64::INFINITY);
+        let limited = rejected(start, 0.5);
+        assert!(limited < unlimited, "{:?}: {} >= {}", start, limited, unlimited);
+    }
+}
+
+
+#[test]
+fn rotation_step_limit_solves_the_corpus() {
+    for (name, fixture) in corpus() {
+        let unlimited = solved(&fixture, &SolverOptions {
+            max_rotation_step: f64::INFINITY,
+            ..SolverOptions::new()
+        });
+        let limited = solved(&fixture, &SolverOptions {
+            max_rotation_step: 0.5,
+            ..SolverOptions::new()
+        });
+        assert!(system_distance(&unlimited, &limited) < SOLUTION_TOLERANCE, "{}", name);
+    }
+}
+
+
+#[test]
+fn stagnation_stops_before_max_iterations() {
+    // The equality keeps "B" at the x of "A" while the fix wants it 5 away
+    let fixture = two_part(5.0)
+        .constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], &[
+            ("x", 1.0), ("penalty", 1.0),
+        ]);
+    for method in [Method::Newton, Method::Lbfgs, Method::GaussNewton].iter().cloned() {
+        // Without a gradient tolerance only the stagnation criterion stops the
+        // minimization at the best compromise
+        let options = SolverOptions {
+            max_iterations: 1000,
+            gradient_tolerance: 0.0,
+            ..with_method(method)
+        };
+        let mut system = fixture.system();
+        let stats = system.solve(&options);
+        assert!(!stats.success);
+        assert_eq!(stats.termination, Termination::Stagnated, "{:?}", method);
+        assert!(stats.iterations < 100, "{:?}: {} iterations", method, stats.iterations);
+
+        let window = system.report.attempts[0].stagnation.unwrap();
+        assert_eq!(window.iterations, options.stagnation_window);
+        assert!(window.relative_decrease < options.stagnation_tolerance);
+        assert!(window.max_step_norm < options.stagnation_step);
+    }
+}
+
+
+#[test]
+fn polish_reduces_the_residuals() {
+    let fixture = two_part(5.0);
+    let mut last_value = f64::INFINITY;
+    for polish_iterations in 0..5 {
+        let mut system = solved(&fixture, &SolverOptions {
+            polish_iterations,
+            ..with_method(Method::Newton)
+        });
+        let value = system.eval_real();
+        // Every extra Newton step can only make the solution better
+        assert!(value <= last_value, "{} polish iterations", polish_iterations);
+        last_value = value;
+
+        if let Some(polish) = system.report.polish {
+            assert!(polish.iterations <= polish_iterations);
+            assert!(polish.value_after <= polish.value_before);
+            assert_eq!(polish.value_after, value);
+        }
+    }
+
+    let polish = solved(&fixture, &SolverOptions {
+        polish_iterations: 5,
+        ..with_method(Method::Newton)
+    }).report.polish.unwrap();
+    assert!(polish.value_before > 0.0);
+    assert!(polish.value_after < 1e-6 * polish.value_before);
+}
+
+
+/// Chains of n objects whose placements start far from the solution: every
+/// object is turned by rotation around each axis and moved by distance
+fn misplaced_chain(n: usize, rotation: f64, distance: f64) -> Fixture {
+    const OBJECTS: [&str; 8] = ["P0", "P1", "P2", "P3", "P4", "P5", "P6", "P7"];
+    let mut fixture = chain(n, 0.0);
+    for (i, name) in OBJECTS.iter().take(n).enumerate() {
+        let k = i as f64 + 1.0;
+        fixture = fixture.object(name, [
+            distance * k, -distance, 0.5 * distance, rotation, 0.3 * rotation, -rotation,
+        ]);
+    }
+    fixture
+}
+
+
+#[test]
+fn staged_strategy_handles_large_rotation_errors() {
+    let mut cases = 0;
+    let mut single_successes = 0;
+    let mut staged_successes = 0;
+    let mut single_iterations = 0;
+    let mut staged_iterations = 0;
+    for rotation in [2.0, 2.5, 3.0].iter().cloned() {
+        for distance in [10.0, 100.0].iter().cloned() {
+            for n in [1, 3].iter().cloned() {
+                let fixture = misplaced_chain(n, rotation, distance);
+                let solve = |strategy| {
+                    let mut system = fixture.system();
+                    let stats = system.solve(&SolverOptions {
+                        strategy,
+                        ..with_method(Method::Newton)
+                    });
+                    (stats, system.report.stages.len())
+                };
+                let (single, _) = solve(Strategy::Single);
+                let (staged, stages) = solve(Strategy::Staged);
+                assert_eq!(stages, 2);
+
+                cases += 1;
+                single_successes += single.success as usize;
+                staged_successes += staged.success as usize;
+                if single.success && staged.success {
+                    single_iterations += single.iterations;
+                    staged_iterations += staged.iterations;
+                }
+            }
+        }
+    }
+    // Every system is solved in stages, and some of them are not solved in a
+    // single stage
+    assert_eq!(staged_successes, cases);
+    assert!(single_successes < cases);
+    assert!(staged_iterations <= single_iterations, "{} > {}", staged_iterations, single_iterations);
+}
+
+
+#[test]
+fn continuation_solves_half_turn() {
+    for axis in ["phi", "theta", "psi"].iter().cloned() {
+        // "B" starts in place but its rotation must turn by 180 degrees
+        let rotation: Vec<(&'static str, f64)> = ["phi", "theta", "psi"]
+            .iter()
+            .map(|var_name| (*var_name, if *var_name == axis { PI } else { 0.0 }))
+            .collect();
+        let fixture = two_part(5.0)
+            .object("B", [5.0, 1.0, 0.0, 0.5, 0.0, 0.0])
+            .constraint("FixRotation_1", &[("Object", "B"), ("Reference", "A")], &rotation);
+
+        let mut system = fixture.system();
+        assert!(!system.solve(&with_method(Method::Newton)).success, "{}", axis);
+
+        let mut system = fixture.system();
+        let stats = system.solve(&SolverOptions {
+            continuation_steps: 4,
+            ..with_method(Method::Newton)
+        });
+        assert!(stats.success, "{}", axis);
+        assert!(system.report.continuation_failure.is_none());
+        assert_eq!(system.report.attempts.len(), 1);
+    }
+}
+
+
+#[test]
+fn auto_matches_trust_ncg() {
+    for (name, fixture) in corpus() {
+        let trust_ncg = solved(&fixture, &with_method(Method::TrustNCG));
+        let auto = solved(&fixture, &with_method(Method::Auto));
+        assert!(system_distance(&trust_ncg, &auto) < SOLUTION_TOLERANCE, "{}", name);
+    }
+}
+
+
+#[test]
+fn auto_only_uses_newton_from_well_conditioned_starts() {
+    let mut system = two_part(5.0).system();
+    system.update_indices();
+    let x0 = system.get_position();
+    assert!(newton_applicable(&mut system, &x0));
+
+    // Moving both objects together doesn't change the fix, so the hessian is
+    // singular when nothing holds the reference in place
+    let mut system = Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 5.0), ("y", 1.0), ("z", 0.0),
+        ])
+        .system();
+    system.update_indices();
+    let x0 = system.get_position();
+    assert!(!newton_applicable(&mut system, &x0));
+}
+
+
+
+
+#[test]
+fn auto_translation_scale_on_large_assemblies() {
+    // Meter-scale assemblies in millimeters
+    let mut fixed_iterations = 0;
+    let mut auto_iterations = 0;
+    for distance in [1000.0, 5000.0].iter().cloned() {
+        for n in [4, 6, 8].iter().cloned() {
+            let fixture = misplaced_chain(n, 0.5, distance);
+            let solve = |translation_scale| {
+                let mut system = fixture.system();
+                let stats = system.solve(&SolverOptions {
+                    translation_scale,
+                    ..with_method(Method::TrustNCG)
+                });
+                assert!(stats.success);
+                (system, stats.iterations)
+            };
+            let (fixed, iterations) = solve(Some(1.0));
+            fixed_iterations += iterations;
+            let (auto, iterations) = solve(None);
+            auto_iterations += iterations;
+            assert!(system_distance(&fixed, &auto) < SOLUTION_TOLERANCE, "{} {}", distance, n);
+        }
+    }
+    assert!(auto_iterations <= fixed_iterations, "{} > {}", auto_iterations, fixed_iterations);
+}
+
+
+#[test]
+fn gimbal_lock_reference_is_reseeded() {
+    for delta in [0.3f64, 1.0, 2.0].iter().cloned() {
+        // "A" starts at the gimbal lock, where its angles can't turn it about
+        // the global x axis. The locked "B" has the rotation of "A" turned by
+        // delta about the x axis, so the gradient is zero at the start.
+        let (c, s) = ((delta / 2.0).cos(), (delta / 2.0).sin());
+        let h = (PI / 4.0).cos();
+        let (phi, theta, psi) = euler::quaternion_angles(c * h, s * h, c * h, s * h);
+        let fixture = Fixture::new()
+            .object("A", [0.0, 0.0, 0.0, 0.0, PI / 2.0, 0.0])
+            .object("B", [1.0, 2.0, 3.0, phi, theta, psi])
+            .constraint("FixRotation_1", &[("Object", "B"), ("Reference", "A")], &[
+                ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+            ])
+            .constraint("Lock_1", &[("Object", "B")], &[
+                ("x", 1.0), ("y", 2.0), ("z", 3.0), ("phi", phi), ("theta", theta), ("psi", psi),
+            ]);
+
+        for method in [Method::Newton, Method::TrustNCG, Method::LevenbergMarquardt].iter().cloned() {
+            let options = with_method(method);
+
+            // The method alone can't leave the starting point
+            let mut system = fixture.system();
+            system.update_indices();
+            let x0 = system.get_position();
+            let f_start = system.eval_real();
+            system.monitor = Monitor::new(&options);
+            let run = minimize(&mut system, &x0, &options);
+            system.update_x(&run.x);
+            assert!(system.eval_real() > f_start * (1.0 - PROGRESS_TOLERANCE), "{:?}", method);
+            assert!(!system.within_tolerance());
+
+            // The solve reseeds the angles of "A" and converges
+            let mut system = fixture.system();
+            let stats = system.solve(&options);
+            assert!(stats.success, "{:?} {}", method, delta);
+            assert_eq!(system.report.reseeded.len(), 1);
+            let reseed = &system.report.reseeded[0];
+            assert_eq!(reseed.object, "A");
+            assert_eq!(reseed.before[1], PI / 2.0);
+            assert!(!reseed.exact);
+        }
+    }
+}
+
+
+#[test]
+fn memory_estimate_matches_the_allocations() {
+    let mut system = chain(4, 0.3).system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    let n = system.get_enabled_size();
+    let mut hessian = Array2::zeros((n, n));
+    system.hess(&mut hessian);
+    let sparse = system.sparse_hessian();
+    assert_eq!(sparse.to_dense(), hessian);
+    let matrix = hessian.len() * size_of::<f64>();
+    let residuals: usize = system
+        .get_residuals()
+        .iter()
+        .flatten()
+        .map(|residual| size_of::<Residual>() + residual.jacobian.len() * size_of::<(usize, f64)>())
+        .sum();
+    let estimate = |method| memory_estimate(&system, &SolverOptions {
+        polish_iterations: 0,
+        ..with_method(method)
+    });
+
+    // Newton keeps the cholesky factor of the hessian besides the hessian
+    assert_eq!(estimate(Method::Newton) - estimate(Method::TrustNCG), matrix);
+
+    // Levenberg-Marquardt keeps one more matrix and the residuals
+    let jacobian = estimate(Method::LevenbergMarquardt) - estimate(Method::Newton) - matrix;
+    assert!(residuals > 0);
+    assert!(residuals <= jacobian, "{} > {}", residuals, jacobian);
+
+    // L-BFGS keeps pairs of vectors, so it needs less memory than the hessian
+    assert!(estimate(Method::Lbfgs) < estimate(Method::TrustNCG));
+
+    // Newton-CG keeps the entries of the sparse hessian
+    let entries = n * size_of::<f64>() + (sparse.stored_entries() - n) * size_of::<(usize, usize, f64)>();
+    let sparse_estimate = estimate(Method::NewtonCg) + matrix - estimate(Method::TrustNCG);
+    assert!(entries <= sparse_estimate, "{} > {}", entries, sparse_estimate);
+
+    // the polish uses Newton steps
+    let options = SolverOptions {
+        polish_iterations: 3,
+        ..with_method(Method::Lbfgs)
+    };
+    assert_eq!(memory_estimate(&system, &options), estimate(Method::Newton));
+}
+
+
+#[test]
+fn solve_is_refused_above_the_memory_limit() {
+    let mut system = chain(4, 0.3).system();
+    let plan = system.analyze(&SolverOptions::new());
+    let before = system.get_position();
+    let stats = system.solve(&SolverOptions {
+        max_memory_bytes: Some(plan.memory_estimate - 1),
+        ..SolverOptions::new()
+    });
+    assert_eq!(stats.termination, Termination::MemoryLimit);
+    assert_eq!(stats.memory_estimate, plan.memory_estimate);
+    assert_eq!(system.get_position(), before);
+
+    let stats = system.solve(&SolverOptions {
+        max_memory_bytes: Some(plan.memory_estimate),
+        ..SolverOptions::new()
+    });
+    assert!(stats.success);
+}
+
+
+#[test]
+fn presets_solve_within_their_tolerances() {
+    for preset in &["interactive", "accurate", "draft"] {
+        let options = SolverOptions::from_preset(preset).unwrap();
+        let mut system = chain(4, 0.3).system();
+        let stats = system.solve(&options);
+        assert!(stats.success, "{}: {:?}", preset, stats.termination);
+        assert_eq!(system.report.preset.as_deref(), Some(*preset));
+
+        let x = system.get_position();
+        system.update_x(&x);
+        system.eval();
+        for constraint in &system.constraints {
+            let tolerance = match constraint.get_kind() {
+                ResidualKind::Length => options.residual_tolerance,
+                ResidualKind::Angle => options.angle_residual_tolerance,
+            };
+            assert!(
+                constraint.get_value() <= tolerance,
+                "{}: {} = {} > {}", preset, constraint.get_name(), constraint.get_value(), tolerance,
+            );
+        }
+    }
+    assert!(SolverOptions::from_preset("fast").is_none());
+}
+
+
+#[test]
+fn options_override_their_preset() {
+    let preset = SolverOptions::accurate();
+    let options = SolverOptions {
+        method: Method::Lbfgs,
+        max_iterations: 2,
+        ..SolverOptions::accurate()
+    };
+    assert_eq!(options.preset, Some("accurate"));
+    assert_eq!(
+        (options.residual_tolerance, options.restarts, options.polish_iterations),
+        (preset.residual_tolerance, preset.restarts, preset.polish_iterations),
+    );
+
+    let mut system = chain(6, 0.4).system();
+    system.solve(&options);
+    assert_eq!(system.report.preset.as_deref(), Some("accurate"));
+    assert!(!system.report.attempts.is_empty());
+    assert!(system.report.attempts.iter().all(|attempt| attempt.iter_num <= 2));
+}
+
+
+#[test]
+fn free_parts_stay_near_their_start() {
+    // B is fixed relative to A, but A is only fixed in position, so the
+    // rotation of A is free. The fix is solved either by moving B or by turning
+    // A, and the penalty finds the solution closest to the start.
+    let fixture = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("A", [1.0, 0.0, 0.0, 0.3, 0.2, -0.1])
+        .object("B", [4.0, 2.0, 0.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_0", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("x", 1.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("FixBase_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 2.0), ("y", 0.0), ("z", 0.0),
+        ]);
+    let start = fixture.system();
+    // squared length of the change of all the variables
+    let change = |system: &System| -> f64 {
+        ["A", "B"]
+            .iter()
+            .flat_map(|name| {
+                let before = placement_of(&start, name);
+                let after = placement_of(system, name);
+                (0..6).map(move |k| (after[k] - before[k]).powi(2))
+            })
+            .sum()
+    };
+
+    let mut changes = Vec::new();
+    for weight in &[0.0, 1e-3] {
+        let mut system = fixture.system();
+        let stats = system.solve(&SolverOptions {
+            stay_near_start_weight: *weight,
+            ..SolverOptions::new()
+        });
+        assert!(stats.success, "{}", weight);
+        assert!((placement_of(&system, "A")[0] - 1.0).abs() < 1e-6);
+        // the penalty is removed before the end of the solve
+        assert!(system.start_weight == 0.0);
+        changes.push(change(&system));
+    }
+    assert!(changes[1] < changes[0], "{:?}", changes);
+}
+
+
+#[test]
+fn stay_near_start_keeps_constrained_solutions() {
+    // Fully constrained systems have the same solution with and without the
+    // penalty (the options of the accurate preset solve them to round-off)
+    for (name, fixture) in corpus() {
+        let solution = solved(&fixture, &SolverOptions::accurate());
+        let near_start = solved(&fixture, &SolverOptions {
+            stay_near_start_weight: 1e-3,
+            ..SolverOptions::accurate()
+        });
+        let distance = system_distance(&solution, &near_start);
+        assert!(distance < 1e-9, "{}: {}", name, distance);
+    }
+}
+
+
+/// Checks that the statistics of a solve are populated and consistent with each
+/// other and with the report of the system
+fn check_stats(system: &System, stats: &SolveStats, termination: Termination) {
+    assert_eq!(stats.termination, termination);
+    assert_eq!(stats.success, termination == Termination::Converged);
+    assert!(stats.f_evals > 0, "{:?}", termination);
+    assert!(stats.f_evals >= stats.iterations, "{:?}", termination);
+    assert!(stats.memory_estimate > 0);
+    assert!(stats.wall_time > Duration::default());
+    let attempts = &system.report.attempts;
+    assert_eq!(stats.iterations, attempts.iter().map(|attempt| attempt.iter_num).sum::<usize>());
+    assert!(stats.f_evals >= attempts.iter().map(|attempt| attempt.f_evals).sum::<usize>());
+    assert!(stats.f_grad_evals >= attempts.iter().map(|attempt| attempt.f_grad_evals).sum::<usize>());
+    let report_stats = system.report.stats.as_ref().unwrap();
+    assert_eq!(report_stats.termination, stats.termination);
+    assert_eq!((report_stats.iterations, report_stats.f_evals), (stats.iterations, stats.f_evals));
+}
+
+
+#[test]
+fn stats_are_populated_on_every_termination() {
+    // A normal solve uses the gradient and the hessian
+    let mut system = two_part(5.0).system();
+    let stats = system.solve(&with_method(Method::Newton));
+    check_stats(&system, &stats, Termination::Converged);
+    assert!(stats.iterations > 0);
+    assert!(stats.f_grad_evals >= stats.iterations);
+    assert!(stats.hessian_evals > 0);
+
+    let mut system = chain(6, 0.4).system();
+    let stats = system.solve(&SolverOptions {
+        max_iterations: 1,
+        ..with_method(Method::Lbfgs)
+    });
+    check_stats(&system, &stats, Termination::MaxIterations);
+    assert_eq!(stats.iterations, 1);
+
+    let mut system = two_part(5.0).system();
+    let stats = system.solve(&SolverOptions {
+        max_function_evals: Some(3),
+        ..with_method(Method::Newton)
+    });
+    check_stats(&system, &stats, Termination::BudgetExhausted);
+
+    let mut system = two_part(5.0)
+        .constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], &[
+            ("x", 1.0), ("penalty", 1.0),
+        ])
+        .system();
+    let stats = system.solve(&SolverOptions {
+        max_iterations: 1000,
+        gradient_tolerance: 0.0,
+        ..with_method(Method::Newton)
+    });
+    check_stats(&system, &stats, Termination::Stagnated);
+
+    let mut system = System::new();
+    system.add_object("P", &to_placement([3.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+    let obj_index = system.sys_objects_idx["P"];
+    system.sys_objects[obj_index].enable_variables(&["x"]);
+    system.add_custom_constraint(Box::new(Drift {
+        obj_index,
+        drift: 1e-3,
+        residual: 0.0,
+    }));
+    let stats = system.solve(&SolverOptions {
+        max_iterations: 20,
+        ..with_method(Method::Newton)
+    });
+    check_stats(&system, &stats, Termination::Diverged);
+
+    let mut system = two_part(5.0).system();
+    let obj_index = system.sys_objects_idx["B"];
+    system.add_custom_constraint(Box::new(Broken {
+        obj_index,
+        nan_after: 3,
+        evaluations: 0,
+        residual: 0.0,
+    }));
+    let stats = system.solve(&with_method(Method::Newton));
+    check_stats(&system, &stats, Termination::NonFinite);
+
+    // A system that is not solved has no evaluations
+    let mut system = two_part(5.0).system();
+    let stats = system.solve(&SolverOptions {
+        max_memory_bytes: Some(1),
+        ..with_method(Method::Newton)
+    });
+    assert_eq!(stats.termination, Termination::MemoryLimit);
+    assert_eq!((stats.iterations, stats.f_evals, stats.f_grad_evals, stats.hessian_evals), (0, 0, 0, 0));
+    assert!(stats.memory_estimate > 1);
+}
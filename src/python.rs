@@ -0,0 +1,331 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! The Python module of the solver.
+//!
+//! The constraints of a system are given to solve_constraint_system (and to the
+//! other functions and classes of the module) by name, and the type of a
+//! constraint is the one contained in its name (for example "FixBase_1" is a
+//! FixBase constraint). The objects and parameters of each type are:
+//!
+//! A Fix constraint of the assembly is given as two constraints: a FixBase
+//! constraint with the "x", "y" and "z" offsets of the object in the
+//! coordinate system of the reference, and a FixRotation constraint with the
+//! "phi", "theta" and "psi" angles of the rotation of the object relative to
+//! the reference. Both use the "Object" and "Reference" names.
+//! Without a "Reference" (or with the reference "Origin") they fix the
+//! position and the angles of the object in the global coordinate system.
+//! A FixGlobal constraint fixes only the variables of "Object" given as
+//! parameters to those absolute values.
+//! A FixedPosition constraint fixes the origin of "Object" at the position
+//! ("x", "y", "z") in the global coordinate system (the missing coordinates
+//! are 0) and leaves its rotation free; it does not take a "Reference".
+//! A PointCoincident constraint makes the point ("x1", "y1", "z1") of
+//! "Object1" and the point ("x2", "y2", "z2") of "Object2" occupy the same
+//! position; the points are given in the local coordinate system of each
+//! object and the missing coordinates are 0. With the optional names
+//! "Attachment1" and "Attachment2" the points are offsets from those
+//! attachment points of the objects instead of their origins.
+//! An AxisCoincident constraint is built as a Coaxial constraint with
+//! "Object1" as the object and "Object2" as the reference; the axes go through
+//! the optional "Attachment1" and "Attachment2" points.
+//! A Coincident constraint (any other name containing "Coincident") makes the
+//! origins of "Object1" and "Object2" coincide; it does not have parameters.
+//! A Distance constraint keeps the origins of "Object" and "Reference" at the
+//! distance "d".
+//! A Plane constraint keeps the local XY-plane of "Object" on the local
+//! XY-plane of "Reference". A PlaneCoincident constraint does the same with the
+//! planes separated by the signed "offset" along the normal of "Reference"
+//! (optional, 0 by default).
+//! A Coaxial constraint makes the local Z-axes of "Object" and "Reference"
+//! collinear; it does not have parameters. The axes go through the origins of
+//! the objects, or through the attachment points given by the optional names
+//! "ObjectAttachment" and "ReferenceAttachment".
+//! A FixedAngle constraint keeps the "angle" (in radians) between the axes of
+//! "Object" and "Reference"; the axis is given by its "x", "y" and "z"
+//! components in the local coordinate system of both objects (the Z-axis by
+//! default).
+//! A Symmetry constraint makes "Object" the mirror image of "Source" across the
+//! local XY-plane of "Reference"; it does not have parameters.
+//! A PointOnLine constraint keeps the origin of "Object" on the line through
+//! the origin of "Reference" along its local "x", "y" or "z" axis (the axis
+//! given in the parameters, the Z-axis by default).
+//! A PointOnPlane constraint keeps the origin of "Object" on the local "XY",
+//! "XZ" or "YZ" plane of "Reference" (the plane given in the parameters, the
+//! XY-plane by default).
+//! A Parallel constraint makes the local axis "axis1" of "Object1" parallel to
+//! the local axis "axis2" of "Object2" (0 for x, 1 for y and 2 for z, the
+//! Z-axis by default).
+//! A Perpendicular constraint makes the same axes perpendicular.
+//! A Ratio constraint links the psi angles of "Object1" and "Object2" as
+//! psi1 = "ratio" * psi2 + "phase" (1 and 0 by default). When one of the
+//! angles is locked the other one is set from it before solving.
+//! A Hinge constraint makes the origins of "Object1" and "Object2" coincident
+//! and their local Z-axes parallel, so they can only rotate about that axis.
+//! A Slider constraint keeps the rotation of "Object" equal to the rotation of
+//! "Reference" and its origin on the local Z-axis of "Reference", so it can
+//! only slide along that axis. The optional "min" and "max" limit the travel
+//! along the axis.
+//! A Ball constraint pins the origin of "Object" to the point ("x", "y", "z")
+//! of "Reference", given in its local coordinate system (the origin by
+//! default); "Object" is free to rotate about the point.
+//! A Screw constraint couples the "z" and "psi" variables of "Object1" and
+//! "Object2" as z1 - z2 = "pitch" / (2 pi) * (psi1 - psi2); a negative pitch
+//! is a left-hand thread.
+//! A Driven constraint pins one rotation variable of "Object" to a target
+//! angle, given as the only parameter with the name of the variable ("phi",
+//! "theta" or "psi").
+//! A Midpoint constraint places the origin of "Object" at the midpoint of the
+//! origins of "Reference1" and "Reference2", or at the optional fraction "t"
+//! of the segment from "Reference1" (t = 0) to "Reference2" (t = 1).
+//! An Equality constraint makes the variables of "Object1" and "Object2"
+//! given as parameters equal. A parameter value of -1 links the variables with
+//! opposite sign instead (the variable of "Object2" is minus the variable of
+//! "Object1"), for symmetric motion; other values are not used. The equal
+//! variables are solved as a single variable, unless the parameter "penalty"
+//! is not 0; then the squared differences are minimized instead.
+//! A parameter "a=b" makes the variable a of "Object1" equal to the variable b
+//! of "Object2", for example {"y=z": 1} with the same object as "Object1" and
+//! "Object2" keeps its y and z equal.
+//! A PlaneParallel constraint makes the local XY planes of "Object1" and
+//! "Object2" parallel at any distance. Their normals point in the same
+//! direction, or in opposite directions when the parameter "flip" is not 0.
+//! A Colinear constraint makes the origins of "Object1", "Object2" and
+//! "Object3" lie on a line.
+//! A Tangent constraint makes a circle with the center at the origin of
+//! "Object" tangent to the local XY plane of "Reference": the center is at the
+//! distance "radius" from the plane, on the side of its normal.
+//! A Centric constraint makes the points at the distances "offset1" and
+//! "offset2" along the local Z-axes of "Object1" and "Object2" coincide.
+//! A DistanceRange constraint keeps the distance between the origins of
+//! "Object" and "Reference" between "min" and "max" (either may be missing).
+//! A PlacementEqual constraint is an Equality of the six variables of "Object1"
+//! and "Object2". It is an error when one of these variables is already made
+//! equal to another variable by an earlier constraint.
+//! A Coplanar constraint keeps the origins of "Object1", "Object2", ...
+//! "ObjectN" (four or more objects) on the plane through the origins of the
+//! first three; it does not have parameters.
+//! A Linear constraint keeps the linear combination sum_i c_i * v_i of
+//! variables of its objects at the value "rhs" (0 by default). The
+//! coefficients are given by parameters with dotted keys like "Object1.x" or
+//! "Object2.psi", where "Object1" and "Object2" are names of the constraint.
+
+use std::collections::HashMap;
+
+use optimization::problem::Objective;
+use pyo3::prelude::*;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyDict;
+use pyo3::wrap_pyfunction;
+
+use crate::build_constraints;
+use crate::minimizer::SolverOptions;
+use crate::solver_result::SolverResult;
+use crate::solver_system::SolverSystem;
+use crate::system::{Plan, System};
+
+
+/// Placement values of the objects by object name and variable name, borrowed
+/// from the Python arguments
+type Placements<'a> = HashMap<&'a str, HashMap<&'a str, f64>>;
+
+
+create_exception!(solver, SolverError, PyValueError);
+
+
+impl From<crate::SolverError> for PyErr {
+    fn from(error: crate::SolverError) -> PyErr {
+        SolverError::new_err(error.to_string())
+    }
+}
+
+
+#[pymodule]
+fn solver(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_wrapped(wrap_pyfunction!(solve_constraint_system))?;
+    m.add_wrapped(wrap_pyfunction!(solve))?;
+    m.add_wrapped(wrap_pyfunction!(count_dof))?;
+    m.add_wrapped(wrap_pyfunction!(get_residuals))?;
+    #[cfg(feature = "serde")]
+    m.add_wrapped(wrap_pyfunction!(save_system))?;
+    #[cfg(feature = "serde")]
+    m.add_wrapped(wrap_pyfunction!(load_system))?;
+    m.add_class::<SolverSystem>()?;
+    m.add_class::<Plan>()?;
+    m.add_class::<SolverResult>()?;
+    m.add("SolverError", py.get_type::<SolverError>())?;
+
+    Ok(())
+}
+
+/// Set-up the constraints functions
+///
+/// objects: map of all objects in the system with their current placement values.
+///     This map is returned with the resulting values after solving the system.
+///     An object can also have named attachment points, given by the keys
+///     "<name>.x", "<name>.y" and "<name>.z" with the offsets of the point in
+///     the local coordinate system of the object (the missing ones are 0).
+/// constraint_names: map of all constraints with the name of constrained objects
+/// constraint_parameters: map of all constraints parameters. For example the
+///     values of the axis to lock for a Lock constraint. Axis not enabled in a
+///     constraint will be omitted in this map (if a lock constraint does not
+///     lock the x-axis, then it will not be included in constraint_parameters)
+///     The rotation of a Lock constraint can also be given as a unit
+///     quaternion with "q0", "q1", "q2" and "q3" instead of the Euler angles.
+///     A Lock constraint with a "weight" parameter is a soft lock: the
+///     variables are not locked but pulled to their values by the penalty
+///     weight * sum_i (x_i - value_i)^2.
+///     Any other constraint can have an optional positive "weight" (1 by
+///     default) that multiplies its constraint function, so constraints with
+///     a larger weight are preferred when not all of them can be satisfied.
+///     The parameters of each type of constraint are described in the
+///     documentation of this module.
+/// options: optional map of solver options. For example {"method": "lm"} solves
+///     the system with the Levenberg-Marquardt method. The other methods are
+///     "auto" (the default), "trust-ncg", "newton", "newton-cg",
+///     "gauss-newton", "lbfgs" and "lbfgs+newton". With {"restarts": n} a
+///     failed solve is tried again up to n times from random perturbations of
+///     the initial placements (seeded by the "seed" option).
+/// initial_guess: optional map with the same layout as objects. The solver
+///     starts from these placements instead of the placements in objects, for
+///     example from the solution of a previous solve. Missing objects and
+///     variables keep the values in objects, and locked variables keep their
+///     locked values.
+///
+/// The GIL is released while the system is being solved, so different systems
+/// can be solved at the same time from different Python threads.
+#[pyfunction]
+fn solve_constraint_system<'a>(
+    py: Python,
+    mut objects: Placements<'a>,
+    constraint_names: HashMap<&'a str, HashMap<&'a str, &str>>,
+    constraint_parameters: HashMap<&'a str, HashMap<&'a str, f64>>,
+    options: Option<&PyDict>,
+    initial_guess: Option<HashMap<&str, HashMap<&str, f64>>>,
+) -> PyResult<(Placements<'a>, bool)> {
+    let options = SolverOptions::from_dict(options)?;
+
+    // Here we store the system information.
+    let mut system = System::new();
+    build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)?;
+
+    let x0 = initial_guess.map(|placements| system.position_from_placements(&placements));
+    let stats = py.allow_threads(|| match &x0 {
+        Some(x0) => system.solve_from(&options, x0),
+        None => system.solve(&options),
+    });
+    stats.check_memory()?;
+    let success = stats.success;
+
+    system.write_placements(&mut objects);
+    Ok((objects, success))
+}
+
+
+/// Solves a constraint system like solve_constraint_system, but returns a
+/// SolverResult with the convergence details instead of only whether the
+/// solver converged. The arguments are the same.
+#[pyfunction]
+fn solve<'a>(
+    py: Python,
+    mut objects: Placements<'a>,
+    constraint_names: HashMap<&'a str, HashMap<&'a str, &str>>,
+    constraint_parameters: HashMap<&'a str, HashMap<&'a str, f64>>,
+    options: Option<&PyDict>,
+    initial_guess: Option<HashMap<&str, HashMap<&str, f64>>>,
+) -> PyResult<(Placements<'a>, SolverResult)> {
+    let options = SolverOptions::from_dict(options)?;
+
+    let mut system = System::new();
+    build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)?;
+
+    let x0 = initial_guess.map(|placements| system.position_from_placements(&placements));
+    let stats = py.allow_threads(|| match &x0 {
+        Some(x0) => system.solve_from(&options, x0),
+        None => system.solve(&options),
+    });
+    stats.check_memory()?;
+
+    system.write_placements(&mut objects);
+    Ok((objects, SolverResult::new(&stats, &system)))
+}
+
+
+/// Returns the degrees of freedom left in a constraint system without solving
+/// it (see System::count_dof). The arguments are the same maps passed to
+/// solve_constraint_system. A negative value means that the system is
+/// over-constrained.
+#[pyfunction]
+fn count_dof(
+    objects: HashMap<&str, HashMap<&str, f64>>,
+    constraint_names: HashMap<&str, HashMap<&str, &str>>,
+    constraint_parameters: HashMap<&str, HashMap<&str, f64>>,
+) -> PyResult<i64> {
+    let mut system = System::new();
+    build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)?;
+    Ok(system.count_dof())
+}
+
+
+/// Returns a dictionary with the value of the constraint function of each
+/// constraint at the given placements, without solving the system. The
+/// arguments are the same maps passed to solve_constraint_system, usually with
+/// the placements returned by the solver, so that the constraints that are not
+/// satisfied can be found.
+#[pyfunction]
+fn get_residuals(
+    objects: HashMap<&str, HashMap<&str, f64>>,
+    constraint_names: HashMap<&str, HashMap<&str, &str>>,
+    constraint_parameters: HashMap<&str, HashMap<&str, f64>>,
+) -> PyResult<HashMap<String, f64>> {
+    let mut system = System::new();
+    build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)?;
+    // the objects must be updated from the placements before the evaluation,
+    // and the equal variables take the values of their roots like in solve
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    Ok(system.constraint_residuals().into_iter().collect())
+}
+
+
+/// Returns the placements and the states of the variables of the objects of a
+/// SolverSystem as a JSON string, for example to save a partially solved
+/// system. The constraints are not saved (see load_system).
+#[cfg(feature = "serde")]
+#[pyfunction]
+fn save_system(system: PyRef<SolverSystem>) -> PyResult<String> {
+    system
+        .to_json()
+        .map_err(|error| PyValueError::new_err(format!("the system could not be saved: {}", error)))
+}
+
+
+/// Builds a SolverSystem from a JSON string returned by save_system. The
+/// constraints are built from the same constraint_names and
+/// constraint_parameters maps used to build the saved system, and the objects
+/// start from their saved placements.
+#[cfg(feature = "serde")]
+#[pyfunction]
+fn load_system(
+    json: &str,
+    constraint_names: HashMap<&str, HashMap<&str, &str>>,
+    constraint_parameters: HashMap<&str, HashMap<&str, f64>>,
+) -> PyResult<SolverSystem> {
+    SolverSystem::from_json(json, &constraint_names, &constraint_parameters)
+}
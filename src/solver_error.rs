@@ -0,0 +1,50 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::error::Error;
+use std::fmt;
+
+
+/// Error returned when the input of a constraint system is not valid, for
+/// example when an object used by a constraint is missing. With the python
+/// feature it is raised as the SolverError exception of the Python module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolverError {
+    /// Description of the problem found in the input
+    message: String,
+}
+
+
+impl SolverError {
+    pub fn new(message: String) -> SolverError {
+        SolverError { message }
+    }
+
+    /// Returns the description of the problem found in the input
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+
+impl Error for SolverError {}
@@ -0,0 +1,134 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use crate::minimizer::SolveStats;
+use crate::system::System;
+
+
+/// Outcome of solving a constraint system, so the caller can tell whether the
+/// assembly actually converged or stalled
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone)]
+pub struct SolverResult {
+    /// States whether every constraint was satisfied
+    pub success: bool,
+    /// Reason why the solver stopped (see Termination::as_str)
+    pub termination: &'static str,
+    /// Number of iterations of the minimization methods
+    pub iterations: usize,
+    /// Number of evaluations of the objective function
+    pub f_evals: usize,
+    /// Number of evaluations of the gradient
+    pub grad_evals: usize,
+    /// Value of the objective function (the sum of the squared errors of the
+    /// constraints) at the solution. It does not include the penalty of
+    /// SolverOptions::stay_near_start_weight.
+    pub objective: f64,
+    /// Largest error of a constraint at the solution. The error of a
+    /// constraint is the square root of its squared error.
+    pub max_residual: f64,
+}
+
+
+impl SolverResult {
+    /// Builds the result of a solve from its statistics and the solved
+    /// system. The constraints of the system must be already evaluated at the
+    /// solution.
+    pub fn new(stats: &SolveStats, system: &System) -> SolverResult {
+        let values = system.constraints.iter().map(|constraint| constraint.get_value());
+        SolverResult {
+            success: stats.success,
+            termination: stats.termination.as_str(),
+            iterations: stats.iterations,
+            f_evals: stats.f_evals,
+            grad_evals: stats.f_grad_evals,
+            objective: values.clone().sum(),
+            max_residual: values.fold(0.0, |max, value| max.max(value.sqrt())),
+        }
+    }
+}
+
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SolverResult {
+    /// States whether every constraint was satisfied
+    #[getter]
+    fn success(&self) -> bool {
+        self.success
+    }
+
+    /// Reason why the solver stopped (see Termination::as_str)
+    #[getter]
+    fn termination(&self) -> &'static str {
+        self.termination
+    }
+
+    /// Number of iterations of the minimization methods
+    #[getter]
+    fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Number of evaluations of the objective function
+    #[getter]
+    fn f_evals(&self) -> usize {
+        self.f_evals
+    }
+
+    /// Number of evaluations of the gradient
+    #[getter]
+    fn grad_evals(&self) -> usize {
+        self.grad_evals
+    }
+
+    /// Value of the objective function (the sum of the squared errors of the
+    /// constraints) at the solution. It does not include the penalty of the
+    /// stay_near_start_weight option.
+    #[getter]
+    fn objective(&self) -> f64 {
+        self.objective
+    }
+
+    /// Largest error of a constraint at the solution. The error of a
+    /// constraint is the square root of its squared error.
+    #[getter]
+    fn max_residual(&self) -> f64 {
+        self.max_residual
+    }
+
+    /// Value of the objective function at the solution (the same as
+    /// objective)
+    #[getter]
+    fn final_error(&self) -> f64 {
+        self.objective
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SolverResult(success={}, termination='{}', iterations={}, objective={:e}, max_residual={:e})",
+            if self.success { "True" } else { "False" },
+            self.termination,
+            self.iterations,
+            self.objective,
+            self.max_residual,
+        )
+    }
+}
@@ -0,0 +1,251 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::types::PyDict;
+
+use crate::build_constraints;
+use crate::minimizer::SolverOptions;
+use crate::system::{Plan, System};
+#[cfg(feature = "serde")]
+use crate::system::SystemSnapshot;
+
+
+/// Placement values of the objects by object name and variable name
+type Placements = HashMap<String, HashMap<String, f64>>;
+
+
+/// A constraint system that is built once and then solved from Python.
+///
+/// A SolverSystem owns all of its data (there is no shared state between
+/// different instances), and the GIL is released while the system is being
+/// solved. This means that different SolverSystem instances can be solved at the
+/// same time from different Python threads.
+///
+/// One SolverSystem must not be solved from two threads at the same time. If
+/// solve() is called while the same instance is still being solved by another
+/// thread, a RuntimeError is raised instead.
+#[pyclass]
+pub struct SolverSystem {
+    /// The constraint system with all of its objects and constraints
+    system: System,
+    /// Placement values of all the objects passed to the constructor. After
+    /// solving the system, these values are updated with the solved placements.
+    objects: Placements,
+}
+
+
+#[pymethods]
+impl SolverSystem {
+    /// Builds a new system. The arguments are the same maps passed to
+    /// solve_constraint_system. A SolverError is raised when some object,
+    /// placement or parameter used by a constraint is missing.
+    #[new]
+    fn new(
+        objects: HashMap<&str, HashMap<&str, f64>>,
+        constraint_names: HashMap<&str, HashMap<&str, &str>>,
+        constraint_parameters: HashMap<&str, HashMap<&str, f64>>,
+    ) -> PyResult<Self> {
+        let mut system = System::new();
+        build_constraints(&mut system, &objects, &constraint_names, &constraint_parameters)?;
+
+        let objects = objects
+            .iter()
+            .map(|(obj, vars)| {
+                let vars = vars
+                    .iter()
+                    .map(|(var, value)| (var.to_string(), *value))
+                    .collect();
+                (obj.to_string(), vars)
+            })
+            .collect();
+
+        Ok(SolverSystem {
+            system,
+            objects,
+        })
+    }
+
+    /// Analyzes the system without solving it. Returns a Plan with the number
+    /// of variables, the grounded objects, the independent components, the
+    /// estimated memory and the warnings about the input. options is the same
+    /// optional map of solver options passed to solve().
+    fn analyze(&mut self, options: Option<&PyDict>) -> PyResult<Plan> {
+        let options = SolverOptions::from_dict(options)?;
+        Ok(self.system.analyze(&options))
+    }
+
+    /// Solves the system. Returns the solved placements of the objects and
+    /// whether the solver converged. options is the same optional map of solver
+    /// options passed to solve_constraint_system.
+    ///
+    /// plan is an optional Plan returned by analyze(). A ValueError is raised if
+    /// objects or constraints were removed after the plan was made.
+    fn solve(
+        slf: &PyCell<Self>,
+        py: Python,
+        options: Option<&PyDict>,
+        plan: Option<Plan>,
+    ) -> PyResult<(Placements, bool)> {
+        let options = SolverOptions::from_dict(options)?;
+
+        // The borrow of this object is kept while the GIL is released, so any
+        // other thread trying to solve this same object will fail here.
+        let mut solver_system = slf.try_borrow_mut().map_err(|_| {
+            PyRuntimeError::new_err(
+                "SolverSystem is already being solved by another thread"
+            )
+        })?;
+        let solver_system = &mut *solver_system;
+
+        let system = &mut solver_system.system;
+        let invalid = system.invalid_constraints();
+        if !invalid.is_empty() {
+            return Err(PyValueError::new_err(format!(
+                "the constraints {} use removed objects; remove them with remove_constraint() before solving",
+                invalid.join(", "),
+            )));
+        }
+        if let Some(plan) = &plan {
+            if !system.is_current(plan) {
+                return Err(PyValueError::new_err(
+                    "the plan is stale: it was made for another system or the system changed after analyze()"
+                ));
+            }
+        }
+        let stats = py.allow_threads(|| match &plan {
+            Some(plan) => system.solve_planned(&options, plan),
+            None => system.solve(&options),
+        });
+        stats.check_memory()?;
+        let success = stats.success;
+
+        system.write_placements(&mut solver_system.objects);
+        Ok((solver_system.objects.clone(), success))
+    }
+
+    /// Changes the placement of an object before solving the system again, for
+    /// example after the user moved a part. placement maps variable names ("x",
+    /// "y", "z", "phi", "theta" or "psi") to their new values; the missing
+    /// variables keep their values. Variables locked by a Lock constraint are
+    /// not changed.
+    ///
+    /// The constraints of the system are not rebuilt, so solving the system
+    /// again is as cheap as possible. The other objects start from their last
+    /// solved placements.
+    fn update_placement(&mut self, name: &str, placement: HashMap<&str, f64>) -> PyResult<()> {
+        for var_name in placement.keys() {
+            if !["x", "y", "z", "phi", "theta", "psi"].contains(var_name) {
+                return Err(PyValueError::new_err(format!("unknown placement variable '{}'", var_name)));
+            }
+        }
+        if !self.system.update_placement(name, &placement) {
+            return Err(PyKeyError::new_err(format!("unknown object '{}'", name)));
+        }
+        self.system.write_placements(&mut self.objects);
+        Ok(())
+    }
+
+    /// Returns the groups of variables that are equal to each other through
+    /// Equality constraints. Each group is a list of (object name, variable
+    /// name) pairs.
+    fn get_equalities(&self) -> Vec<Vec<(String, &'static str)>> {
+        self.system
+            .get_equalities()
+            .into_iter()
+            .map(|class| {
+                class
+                    .into_iter()
+                    .map(|(obj_name, var_name)| (obj_name, var_name.as_str()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Removes an object from the system. Returns the names of the constraints
+    /// that used the object.
+    ///
+    /// With cascade=True these constraints are removed too. Otherwise (the
+    /// default) they are kept as invalid constraints and solve() raises a
+    /// ValueError until they are removed with remove_constraint(). Adding again
+    /// an object with the same name does not bring back the old constraints.
+    fn remove_object(&mut self, name: &str, cascade: Option<bool>) -> PyResult<Vec<String>> {
+        let dependents = self.system
+            .remove_object(name, cascade.unwrap_or(false))
+            .ok_or_else(|| PyKeyError::new_err(format!("unknown object '{}'", name)))?;
+        self.objects.remove(name);
+        Ok(dependents)
+    }
+
+    /// Removes a constraint from the system, including the invalid constraints
+    /// left by remove_object().
+    fn remove_constraint(&mut self, name: &str) -> PyResult<()> {
+        if !self.system.remove_constraint(name) {
+            return Err(PyKeyError::new_err(format!("unknown constraint '{}'", name)));
+        }
+        Ok(())
+    }
+
+    /// Returns a dictionary with information about the last solve. Its
+    /// "attempts" entry has the outcome of the first attempt and of each
+    /// restart.
+    fn report(&self, py: Python) -> PyResult<PyObject> {
+        Ok(self.system.report.to_dict(py)?.to_object(py))
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl SolverSystem {
+    /// Returns the objects of the system as a JSON string (see SystemSnapshot)
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.system.snapshot())
+    }
+
+    /// Builds a system from a JSON string returned by to_json. The constraints
+    /// are built again from the maps passed to solve_constraint_system, and
+    /// they start from the saved placements of the objects.
+    pub fn from_json(
+        json: &str,
+        constraint_names: &HashMap<&str, HashMap<&str, &str>>,
+        constraint_parameters: &HashMap<&str, HashMap<&str, f64>>,
+    ) -> PyResult<SolverSystem> {
+        let snapshot: SystemSnapshot = serde_json::from_str(json)
+            .map_err(|error| PyValueError::new_err(format!("invalid saved system: {}", error)))?;
+        let mut system = snapshot.restore();
+
+        let objects = snapshot.placements();
+        let placements: HashMap<&str, HashMap<&str, f64>> = objects
+            .iter()
+            .map(|(obj, vars)| {
+                let vars = vars
+                    .iter()
+                    .map(|(var, value)| (var.as_str(), *value))
+                    .collect();
+                (obj.as_str(), vars)
+            })
+            .collect();
+        build_constraints(&mut system, &placements, constraint_names, constraint_parameters)?;
+
+        Ok(SolverSystem {
+            system,
+            objects,
+        })
+    }
+}
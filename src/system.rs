@@ -14,16 +14,64 @@
 
 
 use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::constraints::*;
-use crate::system_object::{SystemObject, VariableName as VN};
+use crate::system_object::{attachment_key, SystemObject, VariableName as VN};
 use ndarray::{Array1, Array2};
 
 use optimization::problem::{Objective, Gradient, Hessian};
 
+use log::{info, warn};
+
+use crate::minimizer::{
+    self, ConstraintAttribution, ConstraintError, GradientContribution, LockShadow, Monitor, NonFiniteRecord, SolveReport, SolveStats, SolverOptions, Termination,
+};
+
+mod components;
+
+mod dof;
+
+mod plan;
+pub use plan::Plan;
+
+#[cfg(feature = "parallel")]
+mod parallel;
+
+mod removal;
+
+mod snapshot;
+pub use snapshot::SystemSnapshot;
+
+mod validate;
+
+#[cfg(test)]
+mod tests;
+
+
+/// Hessian diagonal entries smaller than this value (in absolute value) are
+/// considered to be zero
+const SINGULAR_TOLERANCE: f64 = 1e-14;
+
+/// Variables that changed less than this value did not move during a solve
+const MOVE_TOLERANCE: f64 = 1e-9;
+
+/// A constraint decreased materially when its final value is smaller than
+/// its initial value times this factor
+const MATERIAL_DECREASE: f64 = 0.5;
+
+/// Identifier of the next system created
+static NEXT_SYSTEM_ID: AtomicU64 = AtomicU64::new(0);
+
 /// A Variable represents one of the six values used to determine an object in
 /// 3D space. It is used internally to keep track of the placement of constrained
 /// objects through the solving procedure.
-#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Debug, Clone)]
 pub struct Variable {
     /// index of this variable in the solver array
     pub index: Option<usize>,
@@ -36,9 +84,16 @@ pub struct Variable {
     /// determines whether the variable is enabled or not. If not enabled, then
     /// this variable will be ignored
     pub enabled: bool,
-    /// contains the index of the variable that is equal to this variable or none
-    /// if an equality constraint is not applied
-    pub equal: Option<(usize, VN)>,
+    /// contains the index of the variable that is equal to this variable and
+    /// the factor between them (this variable is factor times that variable),
+    /// or none if an equality constraint is not applied. A factor of -1 links
+    /// the variables with opposite sign.
+    pub equal: Option<(usize, VN, f64)>,
+    /// derivative of the value of this variable with respect to its solver
+    /// variable: the product of the factors of its chain of equalities (1 for
+    /// variables that are not equal to other variables). The derivatives of
+    /// the constraints are multiplied by it when they are added to the system.
+    pub scale: f64,
 }
 
 impl Variable {
@@ -49,77 +104,296 @@ impl Variable {
             locked: false,
             enabled: false,
             equal: None,
+            scale: 1.0,
         }
     }
 }
 
 
+impl Default for Variable {
+    fn default() -> Variable {
+        Variable::new()
+    }
+}
+
+
 /// Represents the entire system. This struct contains all the variables, objects,
 /// and constraints in the system.
+///
+/// The system owns all of its data (including the object names) so it can be
+/// moved to another thread while it is being solved.
 #[derive(Debug)]
-pub struct System<'a> {
+pub struct System {
     /// Contains all the constraints in the system. When evaluating the objective
     /// function we are evaluating all the constraints of this vector.
     pub constraints: Vec<ConstraintType>,
     /// Contains all the objects in the system
     pub sys_objects: Vec<SystemObject>,
     /// Contains the indices of the system objects in sys_objects
-    pub sys_objects_idx: HashMap<&'a str, usize>,
+    pub sys_objects_idx: HashMap<String, usize>,
+    /// Follows the progress of the minimization of the system
+    pub monitor: Monitor,
+    /// Information about the last time the system was solved
+    pub report: SolveReport,
+    /// Direction of the last step taken with move_step
+    direction: Array1<f64>,
+    /// Number of evaluations of the objective function (including the
+    /// evaluations of the residuals) since the system started to be solved
+    pub f_evals: usize,
+    /// Number of evaluations of the gradient since the system started to be
+    /// solved
+    pub grad_evals: usize,
+    /// Number of evaluations of the hessian since the system started to be
+    /// solved
+    pub hess_evals: usize,
+    /// States whether the indices of the variables are up to date. It must be
+    /// set to false when variables are enabled, locked or unlocked.
+    pub indexed: bool,
+    /// Lock constraints of the system as (lock name, object index, locked
+    /// variables). Locks are not constraint functions (they lock the values of
+    /// the variables), so they are only kept to be reported. Soft locks are
+    /// LockConstraints and they are in constraints instead.
+    pub locks: Vec<(String, usize, Vec<VN>)>,
+    /// Identifier of the system (different for each system created)
+    pub id: u64,
+    /// Number of structural changes made to the system through its methods
+    /// (objects or constraints added or removed). It is used to find out
+    /// whether a plan of the system is stale, so changing the placement of an
+    /// object does not change it.
+    pub revision: u64,
+    /// Constraints that used an object removed from the system (see
+    /// remove_object). They are never evaluated.
+    pub invalid_constraints: Vec<ConstraintType>,
+    /// Weight of the penalty pulling the solver variables towards their values
+    /// in start_values (see SolverOptions::stay_near_start_weight)
+    pub start_weight: f64,
+    /// Values of the variables of each object when the solve started
+    pub start_values: Vec<[f64; 6]>,
+    /// Factories of the custom constraint types, keyed by their type names
+    /// (see register_constraint)
+    pub factories: HashMap<String, ConstraintFactory>,
 }
 
 
-impl<'a> System<'a> {
-    pub fn new() -> System<'a> {
+impl System {
+    pub fn new() -> System {
         System {
             constraints: Vec::new(),
             sys_objects: Vec::new(),
             sys_objects_idx: HashMap::new(),
+            monitor: Monitor::new(&SolverOptions::new()),
+            report: SolveReport::new(),
+            direction: Array1::zeros(0),
+            f_evals: 0,
+            grad_evals: 0,
+            hess_evals: 0,
+            indexed: false,
+            locks: Vec::new(),
+            id: NEXT_SYSTEM_ID.fetch_add(1, Ordering::Relaxed),
+            revision: 0,
+            invalid_constraints: Vec::new(),
+            start_weight: 0.0,
+            start_values: Vec::new(),
+            factories: HashMap::new(),
         }
     }
 
     /// Adds a new to the system. If new_object already exists, then nothing will
     /// be done. It also adds 6 new variables to the system since these variables
     /// represent the placement of the new_object.
+    ///
+    /// object_params must have the 6 placement variables of the object, it
+    /// panics otherwise. The keys "<name>.x", "<name>.y" and "<name>.z" add the
+    /// attachment point <name> with that offset in the local coordinate system
+    /// of the object (the missing coordinates are 0).
     pub fn add_object(
             &mut self,
-            new_object_name: &'a str,
+            new_object_name: &str,
             object_params: &HashMap<&str, f64>,
     ) {
-        match self.sys_objects_idx.get(new_object_name) {
-            None => {
-                let mut new_object = SystemObject::new();
+        if self.sys_objects_idx.contains_key(new_object_name) {
+            return;
+        }
+        let mut new_object = SystemObject::new();
 
-                // initial value of each variable
-                let mut x: f64;
-                let var_names_str = ["x", "y", "z", "phi", "theta", "psi"];
+        // initial value of each variable
+        let mut x: f64;
+        let var_names_str = ["x", "y", "z", "phi", "theta", "psi"];
 
-                for (var_name_str, var_name) in var_names_str.iter().zip(VN::get_variable_iter()) {
-                    let mut new_var = new_object.get_mut_variable(var_name);
-                    x = *object_params.get(var_name_str).unwrap();
-                    new_var.value = x;
+        for (var_name_str, var_name) in var_names_str.iter().zip(VN::get_variable_iter()) {
+            let new_var = new_object.get_mut_variable(var_name);
+            // the placements given from Python are validated by
+            // build_constraints, so every key is present here
+            x = object_params[var_name_str];
+            new_var.value = x;
+        }
+        for (key, value) in object_params {
+            if let Some((name, coordinate)) = attachment_key(key) {
+                let mut offset = new_object.get_attachment(name).unwrap_or([0.0; 3]);
+                offset[coordinate] = *value;
+                new_object.add_attachment(name, offset);
+            }
+        }
+        for var_name in VN::get_rotation_iter() {
+            let angle = new_object.get_variable(var_name).value;
+            if let Some(warning) = angle_warning(new_object_name, var_name, angle) {
+                warn!("{}", warning);
+            }
+        }
+        self.sys_objects.push(new_object);
+        self.revision += 1;
+        // object index in the system object HashMap
+        let n = self.sys_objects_idx.len();
+        self.sys_objects_idx.insert(new_object_name.to_string(), n);
+    }
+
+
+    /// Finds which objects need their position vectors and rotation quaternions
+    /// updated from the footprints of the constraints, and lets the constraints
+    /// drive the free variables they determine (see Constraint::drive). It must
+    /// be called after adding constraints and locks to the system.
+    pub fn finalize(&mut self) {
+        for object in &mut self.sys_objects {
+            object.q_enable = false;
+            object.v_enable = false;
+        }
+        for constraint in &self.constraints {
+            for (obj_idx, block) in constraint.footprint() {
+                let object = &mut self.sys_objects[obj_idx];
+                match block {
+                    Footprint::Position => object.v_enable = true,
+                    Footprint::Rotation => object.q_enable = true,
+                    Footprint::Variables => (),
                 }
-                self.sys_objects.push(new_object);
-                // object index in the system object HashMap
-                let n = self.sys_objects_idx.len();
-                self.sys_objects_idx.insert(new_object_name, n);
-            },
-            Some(_) => ()
+            }
+            constraint.drive(&mut self.sys_objects);
+        }
+    }
+
+    /// Adds a constraint to the system. Its objects must be already added to
+    /// the system and its variables enabled. The plans of the system become
+    /// stale, and finalize must be called after adding the constraints.
+    pub fn add_constraint(&mut self, constraint: ConstraintType) {
+        self.constraints.push(constraint);
+        self.indexed = false;
+        self.revision += 1;
+    }
+
+    /// Adds a constraint defined outside of this crate. Its objects must be
+    /// already added to the system and its variables enabled.
+    pub fn add_custom_constraint(&mut self, constraint: Box<dyn CustomConstraint>) {
+        self.add_constraint(ConstraintType::Custom(constraint));
+        self.finalize();
+    }
+
+    /// Registers the factory of a custom constraint type, so build_constraints
+    /// creates the constraints of this type with it instead of any built-in
+    /// constraint. The type of a constraint is the part of its name before the
+    /// first '_' (the type of "Spring_1" is "Spring"). Registering the same type
+    /// name again replaces its factory.
+    ///
+    /// An error is returned if type_name is empty, contains '_' or is the name
+    /// of a built-in type (see BUILT_IN_TYPES).
+    pub fn register_constraint(&mut self, type_name: &str, factory: ConstraintFactory) -> Result<(), String> {
+        if type_name.is_empty() || type_name.contains('_') {
+            return Err(format!("'{}' is not a constraint type name", type_name));
+        }
+        if BUILT_IN_TYPES.contains(&type_name) {
+            return Err(format!("'{}' is a built-in constraint type", type_name));
         }
+        self.factories.insert(type_name.to_string(), factory);
+        Ok(())
     }
 
+    /// Changes the initial values of the variables of an object. Locked
+    /// variables keep their values. The constraints and the indices of the
+    /// variables are not changed, so the system can be solved again right away.
+    ///
+    /// Returns false, without changing anything, if there is no object with the
+    /// given name or some key of the placement is not a variable name.
+    pub fn update_placement(&mut self, object_name: &str, placement: &HashMap<&str, f64>) -> bool {
+        let obj_idx = match self.sys_objects_idx.get(object_name) {
+            Some(obj_idx) => *obj_idx,
+            None => return false,
+        };
+        let mut values = Vec::with_capacity(placement.len());
+        for (var_name_str, value) in placement {
+            match VN::parse(var_name_str) {
+                Some(var_name) => values.push((var_name, *value)),
+                None => return false,
+            }
+        }
+        let object = &mut self.sys_objects[obj_idx];
+        for (var_name, value) in values {
+            let variable = object.get_mut_variable(var_name);
+            if !variable.locked {
+                variable.value = value;
+            }
+        }
+        true
+    }
 
-    /// Adds indices to the enabled variables in the system
+    /// Adds indices to the enabled variables if they are not up to date
+    pub fn update_indices(&mut self) {
+        if !self.indexed {
+            self.add_indices();
+        }
+    }
+
+    /// Adds indices to the enabled variables in the system. The indices added
+    /// by a previous call are removed first, so this can be called again after
+    /// locking or unlocking variables.
+    ///
+    /// Only the variables used by some constraint (directly or through a
+    /// variable equal to them) get an index, so every row of the gradient and
+    /// hessian belongs to a live variable.
+    ///
+    /// Every variable of an equality chain gets the index of the root of the
+    /// chain, and the value of the root times its scale (the product of the
+    /// factors of the chain). It panics if the equalities form a cycle (see
+    /// equality_order).
     pub fn add_indices(&mut self) {
+        let order = self.equality_order().expect("equalities of the system form a cycle");
+        // the root of the chain of each equal variable and its scale, found in
+        // the order of the chains so the root of the next variable in a chain
+        // is known
+        let mut roots: HashMap<(usize, VN), ((usize, VN), f64)> = HashMap::new();
+        for (obj_idx, var_name) in &order {
+            let (j, j_var_name, factor) = self.sys_objects[*obj_idx].get_variable(*var_name).equal.unwrap();
+            let next = (j, j_var_name);
+            let (root, scale) = roots.get(&next).copied().unwrap_or((next, 1.0));
+            roots.insert((*obj_idx, *var_name), (root, factor * scale));
+        }
+
+        // find the variables used by the constraints. A variable equal to
+        // another variable uses the index of the root of its chain.
+        let mut used = vec![[false; 6]; self.sys_objects.len()];
+        for constraint in &self.constraints {
+            for (obj_idx, var_name) in constraint.get_variables() {
+                let variable = self.sys_objects[obj_idx].get_variable(var_name);
+                if !variable.enabled {
+                    continue;
+                }
+                match roots.get(&(obj_idx, var_name)) {
+                    Some(((j, j_var_name), _)) => used[*j][*j_var_name as usize] = true,
+                    None => used[obj_idx][var_name as usize] = true,
+                }
+            }
+        }
+
         let mut i = 0;
-        for obj in self.sys_objects.iter_mut() {
-            for variable in &mut obj.get_variables_mut_iter() {
+        for (obj_idx, obj) in self.sys_objects.iter_mut().enumerate() {
+            for (var_name, variable) in VN::get_variable_iter().zip(obj.get_variables_mut_iter()) {
+                variable.index = None;
+                variable.scale = 1.0;
                 if variable.enabled {
                     match variable.equal {
                         // we add indices of equal variables later
                         Some(_) => (),
                         None => {
-                            // Only add indices to unlocked variable
-                            if !variable.locked {
+                            // Only add indices to unlocked variables that are
+                            // used by some constraint
+                            if !variable.locked && used[obj_idx][var_name as usize] {
                                 variable.index = Some(i);
                                 i += 1;
                             }
@@ -128,64 +402,692 @@ impl<'a> System<'a> {
                 }
             }
         }
-        let mut new_index: Option<usize>;
-        for i in 0..self.sys_objects.len() {
+        // equal variables are the same solver variable, so they also take the
+        // value of the root (scaled by the factors of the chain)
+        for (obj_idx, var_name) in order {
+            let ((j, j_var_name), scale) = roots[&(obj_idx, var_name)];
+            let root = self.sys_objects[j].get_variable(j_var_name);
+            let (new_index, root_value) = (root.index, root.value);
+            let variable = self.sys_objects[obj_idx].get_mut_variable(var_name);
+            variable.index = new_index;
+            variable.value = scale * root_value;
+            variable.scale = scale;
+        }
+        debug_assert_eq!(i, self.get_enabled_size());
+        self.indexed = true;
+    }
+
+    /// Returns the variables that are equal to other variables, ordered so that
+    /// the variable a variable is equal to comes before it (the variables of
+    /// each equality chain are ordered from its root).
+    ///
+    /// An error is returned if the equalities form a cycle, since a cycle has
+    /// no root to take the index from.
+    pub fn equality_order(&self) -> Result<Vec<(usize, VN)>, String> {
+        let mut order = Vec::new();
+        let mut visited = vec![[false; 6]; self.sys_objects.len()];
+        for obj_idx in 0..self.sys_objects.len() {
             for var_name in VN::get_variable_iter() {
-                if let Some((j, j_var_name)) = self.sys_objects[i].get_variable(var_name).equal {
-                    new_index = self.sys_objects[j].get_variable(j_var_name).index;
-                    self.sys_objects[i].get_mut_variable(var_name).index = new_index;
+                // follow the chain until a visited variable or the root of the
+                // chain is found
+                let mut chain: Vec<(usize, VN)> = Vec::new();
+                let mut current = (obj_idx, var_name);
+                while !visited[current.0][current.1 as usize] {
+                    let next = match self.sys_objects[current.0].get_variable(current.1).equal {
+                        Some((j, j_var_name, _)) => (j, j_var_name),
+                        None => break,
+                    };
+                    if chain.contains(&current) {
+                        return Err(format!(
+                            "the equalities of variable '{}' of object '{}' form a cycle",
+                            current.1.as_str(),
+                            self.get_object_name(current.0),
+                        ));
+                    }
+                    chain.push(current);
+                    current = next;
+                }
+                for (i, var_name) in chain.iter().rev() {
+                    visited[*i][*var_name as usize] = true;
+                    order.push((*i, *var_name));
                 }
             }
-
         }
-
+        Ok(order)
     }
 
-    /// Returns the number of enabled variables
+    /// Returns the number of solver variables. These are the variables with an
+    /// index that are not equal to other variables, so add_indices must be
+    /// called first.
     pub fn get_enabled_size(&self) -> usize {
         let mut i = 0;
         for obj in self.sys_objects.iter() {
             for variable in obj.get_variables_iter() {
-                if variable.enabled && !variable.locked {
-                    if let None = variable.equal {
-                        // Only consider variables that are enabled, unlocked and
-                        // that are not equal to other variables
-                        i += 1;
-                    }
+                if variable.index.is_some() && variable.equal.is_none() {
+                    i += 1;
                 }
             }
         }
         i
     }
 
-    /// Returns the starting point for the solver
-    pub fn start_position(&self) -> Array1<f64> {
+    /// Returns the current values of the solver variables. Before solving, this
+    /// is the starting point for the solver.
+    pub fn get_position(&self) -> Array1<f64> {
         let n = self.get_enabled_size();
         let mut output = Array1::zeros(n);
         for obj in self.sys_objects.iter() {
             for variable in obj.get_variables_iter() {
                 if let Some(k) = variable.index {
-                    output[k] = variable.value;
+                    output[k] = variable.value / variable.scale;
                 }
             }
         }
         output
     }
+
+    /// Solves the system and returns the statistics of the solve. The solved
+    /// values are stored in the variables of the system objects.
+    ///
+    /// The system is not solved while it has invalid constraints (see
+    /// remove_object).
+    pub fn solve(&mut self, options: &SolverOptions) -> SolveStats {
+        let stats = if !self.invalid_constraints.is_empty() {
+            SolveStats::unsolved(Termination::InvalidConstraints)
+        } else if options.split_components {
+            self.solve_components(options, None)
+        } else {
+            minimizer::solve(self, options)
+        };
+        log_stats(&stats);
+        stats
+    }
+
+    /// Solves the system reusing the analysis of a plan. The plan must be
+    /// current (see is_current).
+    pub fn solve_planned(&mut self, options: &SolverOptions, plan: &Plan) -> SolveStats {
+        debug_assert!(self.is_current(plan));
+        let stats = if !self.invalid_constraints.is_empty() {
+            SolveStats::unsolved(Termination::InvalidConstraints)
+        } else if options.split_components {
+            self.solve_components(options, Some(&plan.component_indices))
+        } else {
+            minimizer::solve(self, options)
+        };
+        log_stats(&stats);
+        stats
+    }
+
+    /// Solves the system starting from the solver position x0 instead of the
+    /// current values of the variables, for example from the solution of a
+    /// previous solve after a small change of a constraint parameter. x0 must
+    /// have the layout of get_position (see position_from_placements);
+    /// otherwise the system is not solved and the returned statistics have the
+    /// InvalidStart termination.
+    ///
+    /// The convergence details (including the error at the solution) are
+    /// given by SolverResult::new with the returned statistics.
+    pub fn solve_from(&mut self, options: &SolverOptions, x0: &Array1<f64>) -> SolveStats {
+        self.update_indices();
+        if x0.len() != self.get_enabled_size() {
+            let stats = SolveStats::unsolved(Termination::InvalidStart);
+            log_stats(&stats);
+            return stats;
+        }
+        self.update_x(x0);
+        self.solve(options)
+    }
+
+    /// Returns the solver position with the values of the variables given in
+    /// placements (maps of variable names to values keyed by object name).
+    /// Variables missing from placements, and variables of objects that are
+    /// not in placements, keep their current values.
+    pub fn position_from_placements<K, V>(&mut self, placements: &HashMap<K, HashMap<V, f64>>) -> Array1<f64>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.update_indices();
+        let mut x = self.get_position();
+        for (obj, vars) in placements {
+            let sys_object = match self.sys_objects_idx.get(obj.as_ref()) {
+                Some(obj_idx) => &self.sys_objects[*obj_idx],
+                None => continue,
+            };
+            for (var_name_str, value) in vars {
+                // attachment points are not variables
+                let var_name = match VN::parse(var_name_str.as_ref()) {
+                    Some(var_name) => var_name,
+                    None => continue,
+                };
+                let variable = sys_object.get_variable(var_name);
+                if let Some(k) = variable.index {
+                    x[k] = *value / variable.scale;
+                }
+            }
+        }
+        x
+    }
+
+    /// Returns the residuals of all the constraints in the system. There is one
+    /// vector of residuals for each constraint (in the same order as the
+    /// constraints vector).
+    ///
+    /// When the stay-near-start penalty is enabled, its residuals are added as
+    /// one more vector at the end.
+    pub fn get_residuals(&mut self) -> Vec<Vec<Residual>> {
+        self.f_evals += 1;
+        self.monitor.check_budget(self.f_evals, self.grad_evals);
+        let mut residuals = Vec::with_capacity(self.constraints.len() + 1);
+        for constraint in &self.constraints {
+            let mut constraint_residuals = Vec::new();
+            constraint.get_residuals(&mut constraint_residuals, &self.sys_objects);
+            residuals.push(constraint_residuals);
+        }
+        if self.start_weight > 0.0 {
+            let sqrt_weight = self.start_weight.sqrt();
+            residuals.push(
+                self.start_offsets()
+                    .into_iter()
+                    .map(|(k, offset)| Residual {
+                        value: sqrt_weight * offset,
+                        jacobian: vec![(k, sqrt_weight)],
+                    })
+                    .collect()
+            );
+        }
+        residuals
+    }
+
+    /// Keeps the current values of the variables as the values the
+    /// stay-near-start penalty pulls towards. A weight of 0 disables the
+    /// penalty.
+    pub fn set_start(&mut self, weight: f64) {
+        self.start_weight = weight;
+        self.start_values = self.variable_values();
+    }
+
+    /// Returns the values of the variables of every object
+    fn variable_values(&self) -> Vec<[f64; 6]> {
+        self.sys_objects
+            .iter()
+            .map(|object| {
+                let mut values = [0.0; 6];
+                for (value, variable) in values.iter_mut().zip(object.get_variables_iter()) {
+                    *value = variable.value;
+                }
+                values
+            })
+            .collect()
+    }
+
+    /// Returns the value of the stay-near-start penalty
+    pub fn start_penalty(&self) -> f64 {
+        self.start_offsets()
+            .iter()
+            .map(|(_, offset)| self.start_weight * offset * offset)
+            .sum()
+    }
+
+    /// Returns the solver index and the offset from its start value of each
+    /// solver variable. It is empty when the stay-near-start penalty is
+    /// disabled.
+    fn start_offsets(&self) -> Vec<(usize, f64)> {
+        let mut offsets = Vec::new();
+        if self.start_weight == 0.0 {
+            return offsets;
+        }
+        for (object, start) in self.sys_objects.iter().zip(&self.start_values) {
+            for (variable, start_value) in object.get_variables_iter().zip(start) {
+                if let (Some(k), None) = (variable.index, variable.equal) {
+                    offsets.push((k, variable.value - start_value));
+                }
+            }
+        }
+        offsets
+    }
+
+    /// Returns true when every constraint is satisfied within the tolerances of
+    /// the monitor. The constraints must be already evaluated.
+    pub fn within_tolerance(&self) -> bool {
+        self.monitor.within_tolerance(
+            self.constraints.iter().map(|c| (c.get_kind(), c.get_value()))
+        )
+    }
+
+    /// Returns the indices of the constraints that are not satisfied within the
+    /// tolerances of the monitor. The constraints must be already evaluated.
+    pub fn unsatisfied_constraints(&self) -> Vec<usize> {
+        self.constraints
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                !self.monitor.within_tolerance(std::iter::once((c.get_kind(), c.get_value())))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns the indices of the constraints that use any of the changed
+    /// objects (indices in sys_objects), in the order they were added to the
+    /// system. Only these constraints have to be evaluated again after changing
+    /// the variables of those objects; the values of the other constraints
+    /// stay the same. Indices that are not in sys_objects are skipped.
+    pub fn dirty_constraints(&self, changed_objects: &[usize]) -> Vec<usize> {
+        let mut changed = vec![false; self.sys_objects.len()];
+        for obj_idx in changed_objects {
+            if let Some(changed) = changed.get_mut(*obj_idx) {
+                *changed = true;
+            }
+        }
+        self.constraints
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.affected_objects().iter().any(|obj_idx| changed[*obj_idx]))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns the name and the value of the constraint function of every
+    /// constraint, in the order they were added to the system. The constraints
+    /// must be already evaluated.
+    pub fn constraint_residuals(&self) -> Vec<(String, f64)> {
+        self.constraints
+            .iter()
+            .map(|c| (c.get_name().to_string(), c.get_value()))
+            .collect()
+    }
+
+    /// Returns the errors of the constraints that are not satisfied at the
+    /// current values of the system variables, from the largest to the
+    /// smallest value of the constraint function.
+    pub fn worst_constraints(&mut self) -> Vec<ConstraintError> {
+        self.eval();
+        let mut errors: Vec<ConstraintError> = self.unsatisfied_constraints()
+            .into_iter()
+            .map(|c_idx| {
+                let constraint = &self.constraints[c_idx];
+                let residuals = constraint.residual_breakdown(&self.sys_objects)
+                    .into_iter()
+                    .map(|(label, value, unit)| (label.to_string(), value, unit.to_string()))
+                    .collect();
+                ConstraintError {
+                    constraint: constraint.get_name().to_string(),
+                    value: constraint.get_value(),
+                    residuals,
+                }
+            })
+            .collect();
+        // non-finite values go first
+        errors.sort_by(|a, b| {
+            b.value.partial_cmp(&a.value).unwrap_or_else(|| b.value.is_nan().cmp(&a.value.is_nan()))
+        });
+        errors
+    }
+
+    /// Returns true when the constraints are evaluated in parallel: the crate
+    /// is built with the parallel feature and the system has enough
+    /// constraints (see parallel::PARALLEL_MIN_CONSTRAINTS)
+    pub fn parallel_constraints(&self) -> bool {
+        #[cfg(feature = "parallel")]
+        return self.constraints.len() >= parallel::PARALLEL_MIN_CONSTRAINTS;
+        #[cfg(not(feature = "parallel"))]
+        return false;
+    }
+
+    /// Finds the gradient of the objective function from the constraints
+    /// gradients. Unlike Gradient::grad, the iterate is not reported to the
+    /// monitor. The constraints must be already evaluated.
+    pub fn constraint_gradient(&mut self, output: &mut Array1<f64>) {
+        output.fill(0.0);
+        if self.parallel_constraints() {
+            #[cfg(feature = "parallel")]
+            self.parallel_gradient(output);
+        } else {
+            for constraint in &self.constraints {
+                constraint.get_gradient(output, &self.sys_objects);
+            }
+        }
+        for (k, offset) in self.start_offsets() {
+            output[k] += 2.0 * self.start_weight * offset;
+        }
+    }
+
+    /// Returns the name of the object at the given index of sys_objects
+    pub fn get_object_name(&self, obj_idx: usize) -> &str {
+        self.sys_objects_idx
+            .iter()
+            .find(|(_, idx)| **idx == obj_idx)
+            .map(|(name, _)| name.as_str())
+            .unwrap_or("")
+    }
+
+    /// Returns the record of a constraint that returned a non-finite value with
+    /// the current values of the enabled variables of its objects
+    fn non_finite_record(&self, c_idx: usize) -> NonFiniteRecord {
+        let constraint = &self.constraints[c_idx];
+        let mut variables = Vec::new();
+        for obj_idx in constraint.affected_objects() {
+            let object = &self.sys_objects[obj_idx];
+            for var_name in VN::get_variable_iter() {
+                let variable = object.get_variable(var_name);
+                if variable.enabled {
+                    variables.push((
+                        self.get_object_name(obj_idx).to_string(),
+                        var_name.as_str().to_string(),
+                        variable.value,
+                    ));
+                }
+            }
+        }
+        NonFiniteRecord {
+            constraint: constraint.get_name().to_string(),
+            value: constraint.get_value(),
+            iteration: self.monitor.iterations,
+            variables,
+        }
+    }
+
+    /// Returns the gradient contribution of each constraint at the current
+    /// values of the system variables (the contributions add up to the gradient
+    /// of the system). Each variable is labeled with its object and variable
+    /// names, and variables with a zero derivative are not included.
+    pub fn gradient_breakdown(&mut self) -> Vec<GradientContribution> {
+        self.eval();
+        let n = self.get_enabled_size();
+        let mut names = Vec::with_capacity(n);
+        for index in 0..n {
+            // Every index belongs to exactly one variable that is not equal to
+            // other variables
+            let (obj_name, var_name) = self.get_variable_names(index).remove(0);
+            names.push((obj_name, var_name.as_str().to_string()));
+        }
+
+        let mut breakdown = Vec::with_capacity(self.constraints.len());
+        let mut gradient = Array1::zeros(n);
+        for constraint in &self.constraints {
+            gradient.fill(0.0);
+            constraint.get_gradient(&mut gradient, &self.sys_objects);
+            let contribution = gradient
+                .iter()
+                .zip(&names)
+                .filter(|(value, _)| **value != 0.0)
+                .map(|(value, (obj_name, var_name))| (obj_name.clone(), var_name.clone(), *value))
+                .collect();
+            breakdown.push(GradientContribution {
+                constraint: constraint.get_name().to_string(),
+                gradient: contribution,
+            });
+        }
+        breakdown
+    }
+
+    /// Finds how much each lock fights the rest of the constraints at the
+    /// current values of the system variables. For each locked variable, the
+    /// gradient of the other constraints with respect to it tells how the
+    /// constraints would move the variable if it were not locked.
+    ///
+    /// Only the locked variables used by some constraint with a nonzero
+    /// derivative are reported.
+    pub fn lock_shadows(&mut self) -> Vec<LockShadow> {
+        // The locked variables are unlocked so they get solver indices
+        let mut unlocked = Vec::new();
+        for (obj_idx, object) in self.sys_objects.iter_mut().enumerate() {
+            for (var_name, variable) in VN::get_variable_iter().zip(object.get_variables_mut_iter()) {
+                if variable.enabled && variable.locked && variable.equal.is_none() {
+                    variable.locked = false;
+                    unlocked.push((obj_idx, var_name));
+                }
+            }
+        }
+        if unlocked.is_empty() {
+            return Vec::new();
+        }
+        // The objects must be updated because their vectors and quaternions
+        // only have derivatives with respect to unlocked variables
+        self.add_indices();
+        let x = self.get_position();
+        self.update_x(&x);
+        self.eval();
+
+        let n = self.get_enabled_size();
+        let mut hess = Array2::zeros((n, n));
+        let mut gradients = Vec::with_capacity(self.constraints.len());
+        for constraint in &self.constraints {
+            let mut gradient = Array1::zeros(n);
+            constraint.get_gradient(&mut gradient, &self.sys_objects);
+            constraint.get_hessian(&mut hess, &self.sys_objects);
+            gradients.push(gradient);
+        }
+
+        let mut shadows = Vec::new();
+        for (lock_name, obj_idx, var_names) in &self.locks {
+            for var_name in var_names {
+                let variable = self.sys_objects[*obj_idx].get_variable(*var_name);
+                let k = match variable.index {
+                    Some(k) if variable.equal.is_none() => k,
+                    _ => continue,
+                };
+                let opponents: Vec<(String, f64)> = self.constraints
+                    .iter()
+                    .zip(&gradients)
+                    .filter(|(_, gradient)| gradient[k] != 0.0)
+                    .map(|(constraint, gradient)| (constraint.get_name().to_string(), gradient[k]))
+                    .collect();
+                if opponents.is_empty() {
+                    continue;
+                }
+                let gradient: f64 = opponents.iter().map(|(_, g)| g).sum();
+                // Newton step of the variable alone
+                let shift = if hess[[k, k]] > SINGULAR_TOLERANCE {
+                    Some(-gradient / hess[[k, k]])
+                } else {
+                    None
+                };
+                shadows.push(LockShadow {
+                    lock: lock_name.clone(),
+                    object: self.get_object_name(*obj_idx).to_string(),
+                    variable: var_name.as_str().to_string(),
+                    value: variable.value,
+                    gradient,
+                    shift,
+                    opponents,
+                });
+            }
+        }
+
+        for (obj_idx, var_name) in unlocked {
+            self.sys_objects[obj_idx].get_mut_variable(var_name).locked = true;
+        }
+        self.add_indices();
+        let x = self.get_position();
+        self.update_x(&x);
+        self.eval();
+        shadows
+    }
+
+    /// Returns the values of the variables of every object and the value of
+    /// every constraint at the current solver position.
+    pub fn attribution_snapshot(&mut self) -> (Vec<[f64; 6]>, Vec<f64>) {
+        let x = self.get_position();
+        self.update_x(&x);
+        self.eval();
+        let variables = self.variable_values();
+        let constraints = self.constraints.iter().map(|c| c.get_value()).collect();
+        (variables, constraints)
+    }
+
+    /// Finds which variables each constraint moved since the attribution
+    /// snapshot initial was taken. A constraint is active when some of its
+    /// variables moved and its value decreased materially.
+    pub fn attribution(&mut self, initial: &(Vec<[f64; 6]>, Vec<f64>)) -> Vec<ConstraintAttribution> {
+        let (initial_variables, initial_constraints) = initial;
+        let (final_variables, final_constraints) = self.attribution_snapshot();
+        let mut attribution = Vec::with_capacity(self.constraints.len());
+        for (c_idx, constraint) in self.constraints.iter().enumerate() {
+            let mut moved = Vec::new();
+            for (obj_idx, var_name) in constraint.get_variables() {
+                if !self.sys_objects[obj_idx].get_variable(var_name).enabled {
+                    continue;
+                }
+                let k = var_name as usize;
+                let change = final_variables[obj_idx][k] - initial_variables[obj_idx][k];
+                let name = (self.get_object_name(obj_idx).to_string(), var_name.as_str().to_string());
+                if change.abs() > MOVE_TOLERANCE && !moved.contains(&name) {
+                    moved.push(name);
+                }
+            }
+            let initial_value = initial_constraints[c_idx];
+            let final_value = final_constraints[c_idx];
+            attribution.push(ConstraintAttribution {
+                constraint: constraint.get_name().to_string(),
+                active: !moved.is_empty() && final_value < initial_value * MATERIAL_DECREASE,
+                initial_value,
+                final_value,
+                moved,
+            });
+        }
+        attribution
+    }
+
+    /// Returns the names of the objects and variables that have the given solver
+    /// index. Variables equal to other variables are not included.
+    pub fn get_variable_names(&self, index: usize) -> Vec<(String, VN)> {
+        let mut names = Vec::new();
+        for (obj_name, obj_idx) in &self.sys_objects_idx {
+            let object = &self.sys_objects[*obj_idx];
+            for var_name in VN::get_variable_iter() {
+                let variable = object.get_variable(var_name);
+                if variable.index == Some(index) && variable.equal.is_none() {
+                    names.push((obj_name.clone(), var_name));
+                }
+            }
+        }
+        names
+    }
+
+    /// Returns the placements (x, y, z, phi, theta and psi) of every object in
+    /// the system with the current values of the variables. After solving, these
+    /// are the solved placements.
+    pub fn placements(&self) -> HashMap<String, HashMap<String, f64>> {
+        self.sys_objects_idx
+            .iter()
+            .map(|(name, obj_idx)| {
+                let object = &self.sys_objects[*obj_idx];
+                let placement = VN::get_variable_iter()
+                    .map(|var_name| (var_name.as_str().to_string(), object.get_variable(var_name).value))
+                    .collect();
+                (name.clone(), placement)
+            })
+            .collect()
+    }
+
+    /// Writes the current values of the system variables into the placement
+    /// maps of the objects. Objects that are not in the system (no constraint
+    /// uses them) keep their values.
+    pub fn write_placements<K, V>(&self, objects: &mut HashMap<K, HashMap<V, f64>>)
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut sys_object: &SystemObject;
+        let mut var_name: VN;
+        for (obj, vars) in objects.iter_mut() {
+            sys_object = match self.sys_objects_idx.get(obj.as_ref()) {
+                Some(obj_idx) => &self.sys_objects[*obj_idx],
+                None => continue,
+            };
+            for (var_name_str, var_value) in vars.iter_mut() {
+                // the attachment points are returned unchanged
+                var_name = match VN::parse(var_name_str.as_ref()) {
+                    Some(var_name) => var_name,
+                    None => continue,
+                };
+                *var_value = sys_object.get_variable(var_name).value;
+            }
+        }
+    }
+
+    /// Returns the hessian of the objective function as a sparse matrix. The
+    /// system must be already evaluated, like for Hessian::hess.
+    ///
+    /// The sparse hessian of a system with many objects needs much less memory
+    /// than the dense one; it is used by the newton-cg method.
+    pub fn sparse_hessian(&mut self) -> SparseHessian {
+        let n = self.get_enabled_size();
+        let mut output = SparseHessian::new(n);
+        self.accumulate_hessian(&mut output, n);
+        output
+    }
+
+    /// Adds the hessian of the objective function to output, an n x n matrix of
+    /// zeros
+    fn accumulate_hessian(&mut self, output: &mut dyn HessianAccumulator, n: usize) {
+        self.hess_evals += 1;
+        if self.parallel_constraints() {
+            #[cfg(feature = "parallel")]
+            self.parallel_hessian(output);
+        } else {
+            for constraint in &mut self.constraints {
+                constraint.get_hessian(output, &self.sys_objects)
+            }
+        }
+        for (k, _) in self.start_offsets() {
+            output.add(k, k, 2.0 * self.start_weight);
+        }
+
+        // Variables that no constraint curves have a zero row and column in the
+        // hessian. A small value is added to their diagonal entries so the
+        // minimizer doesn't take huge steps along these directions.
+        let regularization = self.monitor.regularization;
+        if regularization > 0.0 {
+            for i in 0..n {
+                if output.get(i, i).abs() <= SINGULAR_TOLERANCE {
+                    output.add(i, i, regularization);
+                    self.monitor.regularized.insert(i);
+                }
+            }
+        }
+    }
 }
 
 
-impl<'a> Objective for System<'a> {
+impl Default for System {
+    fn default() -> System {
+        System::new()
+    }
+}
+
+
+impl Objective for System {
     fn eval(&mut self) {
-        for constraint in &mut self.constraints {
-            constraint.evaluate(&self.sys_objects);
+        self.f_evals += 1;
+        self.monitor.check_budget(self.f_evals, self.grad_evals);
+        if self.parallel_constraints() {
+            #[cfg(feature = "parallel")]
+            self.parallel_eval();
+        } else {
+            for constraint in &mut self.constraints {
+                constraint.evaluate(&self.sys_objects);
+            }
         }
     }
 
     fn eval_real(&mut self) -> f64 {
         self.eval();
         let mut value = 0.0;
-        for constraint in &self.constraints {
-            value += constraint.get_value();
+        let mut non_finite = None;
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            let c_value = constraint.get_value();
+            if !c_value.is_finite() && non_finite.is_none() {
+                non_finite = Some(i);
+            }
+            value += c_value;
+        }
+        value += self.start_penalty();
+        if let Some(i) = non_finite {
+            let record = self.non_finite_record(i);
+            self.monitor.non_finite_value(record);
         }
         value
     }
@@ -194,7 +1096,7 @@ impl<'a> Objective for System<'a> {
         for obj in &mut self.sys_objects {
             for variable in &mut obj.get_variables_mut_iter() {
                 if let Some(k) = variable.index {
-                    variable.value = x[k];
+                    variable.value = variable.scale * x[k];
                 }
             }
             if obj.q_enable {
@@ -206,39 +1108,82 @@ impl<'a> Objective for System<'a> {
         }
     }
 
-    fn move_step(&mut self, _x: &Array1<f64>, _p: &Array1<f64>, _alpha: f64) {
-        // we dont need this function, (at least for TrustNCG)
-        // If we implement an optimization method that requires to calculate the step
-        // then we need a method of updating the step. and to take partial derivatives
-        // with respect to alpha. Note that we could simply add a new method to the constraint
-        // functions that updates the internal variables by adding the corresponding step
-        // to the variables since the system itself doesn't know about the number system used
+    fn move_step(&mut self, x: &Array1<f64>, p: &Array1<f64>, alpha: f64) {
+        // The direction is kept so the directional derivative can be found
+        // after evaluating the system at the new point.
+        self.direction = p.clone();
+        let x_new = x + &(alpha * p);
+        self.update_x(&x_new);
     }
 }
 
-impl<'a> Gradient for System<'a> {
+impl Gradient for System {
     fn grad(&mut self, output: &mut Array1<f64>) {
         // HACK: This should be done in the library
         output.fill(0.0);
-        for constraint in &mut self.constraints {
-            constraint.get_gradient(output, &self.sys_objects);
+
+        // The gradient is evaluated once at each new iterate so here we report
+        // the iterate to the monitor. When the monitor stops the minimization
+        // the gradient is left at zero (and it is not counted as an
+        // evaluation), which makes the minimizer stop at the current point.
+        let values = self.constraints.iter().map(|c| (c.get_kind(), c.get_value()));
+        let x = self.get_position();
+        let penalty = self.start_penalty();
+        if self.monitor.iteration(values, penalty, &x) {
+            return;
         }
+        self.grad_evals += 1;
+        self.monitor.check_budget(self.f_evals, self.grad_evals);
+        self.constraint_gradient(output);
     }
 
+    /// Returns the directional derivative of the objective function along the
+    /// direction of the last call to move_step. The system must be already
+    /// evaluated.
     fn diff(&mut self) -> f64 {
-        0.0
+        let mut diff = 0.0;
+        for constraint in &self.constraints {
+            diff += constraint.get_diff(&self.direction, &self.sys_objects);
+        }
+        for (k, offset) in self.start_offsets() {
+            diff += 2.0 * self.start_weight * offset * self.direction[k];
+        }
+        diff
     }
 }
 
 
-impl<'a> Hessian for System<'a> {
+impl Hessian for System {
     fn hess(&mut self, output: &mut Array2<f64>) {
         // HACK: This should be done in the library
         output.fill(0.0);
-        for constraint in &mut self.constraints {
-            constraint.get_hessian(output, &self.sys_objects)
-        }
-
+        let n = output.nrows();
+        self.accumulate_hessian(output, n);
     }
 
 }
+
+
+/// Logs the statistics of a solve
+fn log_stats(stats: &SolveStats) {
+    info!(
+        "solve {}: {} iterations, {} function evaluations, {} gradient evaluations, \
+        {} hessian evaluations in {:?}",
+        stats.termination.as_str(), stats.iterations, stats.f_evals, stats.f_grad_evals,
+        stats.hessian_evals, stats.wall_time,
+    );
+}
+
+
+/// Returns a warning if the value of an angle is so large that it was most
+/// likely given in degrees instead of radians
+fn angle_warning(object_name: &str, var_name: VN, angle: f64) -> Option<String> {
+    if angle.abs() > 2.0 * PI {
+        Some(format!(
+            "angle {} of object {} is {} (larger than 2*pi), angles must be given in radians",
+            var_name.as_str(), object_name, angle,
+        ))
+    } else {
+        None
+    }
+}
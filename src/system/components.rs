@@ -0,0 +1,313 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::minimizer::{self, ComponentReport, SolveReport, SolveStats, SolverOptions, Termination};
+use crate::system::System;
+use crate::system_object::VariableName as VN;
+
+
+impl System {
+    /// Splits the objects of the system into independent components. Two objects
+    /// are in the same component when a constraint uses both of them or when
+    /// one of their variables is equal to a variable of the other object.
+    ///
+    /// Each component contains the sorted indices of its objects. Objects that
+    /// are not used by any constraint are not included.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let n = self.sys_objects.len();
+        let mut parents: Vec<usize> = (0..n).collect();
+        let mut constrained = vec![false; n];
+
+        for constraint in &self.constraints {
+            let objects = constraint.affected_objects();
+            for obj_idx in &objects {
+                constrained[*obj_idx] = true;
+            }
+            for pair in objects.windows(2) {
+                union(&mut parents, pair[0], pair[1]);
+            }
+        }
+        for (obj_idx, object) in self.sys_objects.iter().enumerate() {
+            for variable in object.get_variables_iter() {
+                if let Some((other_idx, _, _)) = variable.equal {
+                    union(&mut parents, obj_idx, other_idx);
+                }
+            }
+        }
+
+        // Components are ordered by their first object
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        let mut component_idx: HashMap<usize, usize> = HashMap::new();
+        for obj_idx in 0..n {
+            let root = find(&mut parents, obj_idx);
+            let i = *component_idx.entry(root).or_insert_with(|| {
+                components.push(Vec::new());
+                components.len() - 1
+            });
+            components[i].push(obj_idx);
+        }
+        components
+            .into_iter()
+            .filter(|objects| objects.iter().any(|obj_idx| constrained[*obj_idx]))
+            .collect()
+    }
+
+    /// Returns the reference object of each floating component of the system: a
+    /// component without locked variables and without constraints tying a
+    /// single object to the global coordinate system (like FixedPosition or a
+    /// soft Lock). Such a component can move as a whole without changing any
+    /// constraint, which makes the hessian singular.
+    ///
+    /// The reference of a component is its object used by the most
+    /// constraints, and the object with the first name among those.
+    pub fn floating_references(&self) -> Vec<usize> {
+        let mut uses = vec![0; self.sys_objects.len()];
+        let mut anchored = vec![false; self.sys_objects.len()];
+        for constraint in &self.constraints {
+            let objects = constraint.affected_objects();
+            for obj_idx in &objects {
+                uses[*obj_idx] += 1;
+            }
+            if objects.len() == 1 {
+                anchored[objects[0]] = true;
+            }
+        }
+        for (obj_idx, object) in self.sys_objects.iter().enumerate() {
+            if object.get_variables_iter().any(|variable| variable.locked) {
+                anchored[obj_idx] = true;
+            }
+        }
+
+        self.components()
+            .into_iter()
+            .filter(|objects| !objects.iter().any(|obj_idx| anchored[*obj_idx]))
+            .filter_map(|objects| {
+                objects
+                    .into_iter()
+                    .min_by(|a, b| {
+                        uses[*b]
+                            .cmp(&uses[*a])
+                            .then_with(|| self.get_object_name(*a).cmp(self.get_object_name(*b)))
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the groups of variables that are equal to each other, following
+    /// the equality chains to the end. Each group has the (object name,
+    /// variable name) pairs of its variables ordered by object name, and the
+    /// groups are ordered by their first variable. Variables that are not equal
+    /// to any other variable are not included.
+    pub fn get_equalities(&self) -> Vec<Vec<(String, VN)>> {
+        let n = self.sys_objects.len();
+        let mut parents: Vec<usize> = (0..6 * n).collect();
+        for (obj_idx, object) in self.sys_objects.iter().enumerate() {
+            for (var_name, variable) in VN::get_variable_iter().zip(object.get_variables_iter()) {
+                if let Some((other_idx, other_var_name, _)) = variable.equal {
+                    union(
+                        &mut parents,
+                        6 * other_idx + other_var_name as usize,
+                        6 * obj_idx + var_name as usize,
+                    );
+                }
+            }
+        }
+
+        let mut classes: HashMap<usize, Vec<(String, VN)>> = HashMap::new();
+        for obj_idx in 0..n {
+            for var_name in VN::get_variable_iter() {
+                let i = 6 * obj_idx + var_name as usize;
+                let root = find(&mut parents, i);
+                classes
+                    .entry(root)
+                    .or_default()
+                    .push((self.get_object_name(obj_idx).to_string(), var_name));
+            }
+        }
+        let mut classes: Vec<Vec<(String, VN)>> = classes
+            .into_values()
+            .filter(|class| class.len() > 1)
+            .collect();
+        for class in &mut classes {
+            class.sort_by(|a, b| (&a.0, a.1 as usize).cmp(&(&b.0, b.1 as usize)));
+        }
+        classes.sort_by(|a, b| (&a[0].0, a[0].1 as usize).cmp(&(&b[0].0, b[0].1 as usize)));
+        classes
+    }
+
+    /// Returns a new system with copies of the given objects and of the
+    /// constraints using them. objects must be a component of this system.
+    pub fn component_system(&self, objects: &[usize]) -> System {
+        let mut system = System::new();
+        let object_map: HashMap<usize, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(new_idx, old_idx)| (*old_idx, new_idx))
+            .collect();
+
+        let mut names: HashMap<usize, &String> = HashMap::new();
+        for (name, obj_idx) in &self.sys_objects_idx {
+            names.insert(*obj_idx, name);
+        }
+        for (new_idx, old_idx) in objects.iter().enumerate() {
+            let mut object = self.sys_objects[*old_idx].clone();
+            for variable in object.get_variables_mut_iter() {
+                if let Some((other_idx, var_name, factor)) = variable.equal {
+                    variable.equal = Some((object_map[&other_idx], var_name, factor));
+                }
+            }
+            system.sys_objects.push(object);
+            system.sys_objects_idx.insert(names[old_idx].clone(), new_idx);
+        }
+
+        for constraint in &self.constraints {
+            let used = constraint.affected_objects();
+            if used.iter().all(|obj_idx| object_map.contains_key(obj_idx)) {
+                let mut constraint = constraint.clone();
+                constraint.remap_objects(&object_map);
+                system.constraints.push(constraint);
+            }
+        }
+        for (lock_name, obj_idx, var_names) in &self.locks {
+            if let Some(new_idx) = object_map.get(obj_idx) {
+                system.locks.push((lock_name.clone(), *new_idx, var_names.clone()));
+            }
+        }
+        system
+    }
+
+    /// Copies the values of the variables of a component system into the
+    /// objects of this system
+    pub fn merge_component(&mut self, component: &System) {
+        for (name, comp_idx) in &component.sys_objects_idx {
+            let obj_idx = self.sys_objects_idx[name];
+            for var_name in VN::get_variable_iter() {
+                let value = component.sys_objects[*comp_idx].get_variable(var_name).value;
+                self.sys_objects[obj_idx].get_mut_variable(var_name).value = value;
+            }
+        }
+    }
+
+    /// Solves each independent component of the system on its own and merges
+    /// the solved placements. With the parallel feature the components are
+    /// solved at the same time on different threads.
+    ///
+    /// The returned statistics add up the statistics of the components, and
+    /// the termination reason is the one of the first component that failed.
+    /// The report of the system contains the report of each component.
+    ///
+    /// components are the components found by System::components; they are
+    /// found again when they are not given.
+    pub fn solve_components(
+            &mut self,
+            options: &SolverOptions,
+            components: Option<&[Vec<usize>]>,
+    ) -> SolveStats {
+        let start = Instant::now();
+        let found;
+        let components = match components {
+            Some(components) => components,
+            None => {
+                found = self.components();
+                &found[..]
+            },
+        };
+        let mut systems: Vec<System> = components
+            .iter()
+            .map(|objects| self.component_system(objects))
+            .collect();
+
+        #[cfg(feature = "parallel")]
+        let results: Vec<SolveStats> = systems
+            .par_iter_mut()
+            .map(|system| minimizer::solve(system, options))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<SolveStats> = systems
+            .iter_mut()
+            .map(|system| minimizer::solve(system, options))
+            .collect();
+
+        self.report = SolveReport::new();
+        let mut stats = SolveStats {
+            success: true,
+            iterations: 0,
+            f_evals: 0,
+            f_grad_evals: 0,
+            hessian_evals: 0,
+            wall_time: Duration::default(),
+            memory_estimate: 0,
+            termination: Termination::Converged,
+        };
+        for (system, component_stats) in systems.iter().zip(&results) {
+            self.merge_component(system);
+            let mut objects: Vec<String> = system.sys_objects_idx.keys().cloned().collect();
+            objects.sort();
+            self.report.components.push(ComponentReport {
+                objects,
+                success: component_stats.success,
+                report: system.report.clone(),
+            });
+            stats.iterations += component_stats.iterations;
+            stats.f_evals += component_stats.f_evals;
+            stats.f_grad_evals += component_stats.f_grad_evals;
+            stats.hessian_evals += component_stats.hessian_evals;
+            // The components may be solved at the same time
+            stats.memory_estimate += component_stats.memory_estimate;
+            if stats.success && !component_stats.success {
+                stats.success = false;
+                stats.termination = component_stats.termination;
+            }
+        }
+        stats.wall_time = start.elapsed();
+        self.report.stats = Some(stats.clone());
+        self.report.preset = options.preset.map(|preset| preset.to_string());
+        stats
+    }
+}
+
+
+/// Finds the root of an element of a union-find forest
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // path compression
+    let mut j = i;
+    while parents[j] != root {
+        let next = parents[j];
+        parents[j] = root;
+        j = next;
+    }
+    root
+}
+
+
+/// Joins the trees of two elements of a union-find forest
+fn union(parents: &mut [usize], i: usize, j: usize) {
+    let root_i = find(parents, i);
+    let root_j = find(parents, j);
+    if root_i != root_j {
+        parents[root_j] = root_i;
+    }
+}
@@ -0,0 +1,62 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::Array2;
+
+use optimization::problem::Objective;
+
+use crate::minimizer::rank;
+use crate::system::System;
+
+
+impl System {
+    /// Returns the degrees of freedom left in the system: the number of solver
+    /// variables (enabled variables that are not locked or equal to another
+    /// variable) minus the number of independent equations of the constraints.
+    ///
+    /// The equations of a constraint are its residuals, and the number of its
+    /// independent equations is the rank of their jacobian at the current
+    /// placements. For example, a Parallel constraint has three residuals but
+    /// only two independent equations. Residuals of locked variables do not
+    /// count, and neither do the travel limits of a Slider while the travel is
+    /// inside its range.
+    ///
+    /// A negative value means that the system is over-constrained (some of the
+    /// equations of different constraints are redundant), and a positive value
+    /// that it is under-constrained. The count may be wrong at singular
+    /// placements, where the rank of some jacobian is smaller than usual.
+    pub fn count_dof(&mut self) -> i64 {
+        self.update_indices();
+        let x = self.get_position();
+        self.update_x(&x);
+        self.eval();
+
+        let n = self.get_enabled_size();
+        let mut equations = 0;
+        for constraint in &self.constraints {
+            let mut residuals = Vec::new();
+            constraint.get_residuals(&mut residuals, &self.sys_objects);
+            let mut jacobian = Array2::zeros((residuals.len(), n));
+            for (i, residual) in residuals.iter().enumerate() {
+                for (k, derivative) in &residual.jacobian {
+                    jacobian[[i, *k]] += derivative;
+                }
+            }
+            equations += rank(&jacobian);
+        }
+        n as i64 - equations as i64
+    }
+}
@@ -0,0 +1,119 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use ndarray::Array1;
+use rayon::prelude::*;
+
+use crate::constraints::HessianAccumulator;
+use crate::system::System;
+
+
+/// Systems with fewer constraints than this value are evaluated sequentially;
+/// the constraints of a small system are cheaper to evaluate than to share
+/// between threads.
+pub const PARALLEL_MIN_CONSTRAINTS: usize = 64;
+
+
+/// The hessian entries added by one constraint as (row, column, value), in the
+/// order they were added
+struct HessianEntries(Vec<(usize, usize, f64)>);
+
+
+impl HessianAccumulator for HessianEntries {
+    fn add(&mut self, row: usize, col: usize, value: f64) {
+        self.0.push((row, col, value));
+    }
+
+    fn get(&self, row: usize, col: usize) -> f64 {
+        self.0
+            .iter()
+            .filter(|(i, j, _)| *i == row && *j == col)
+            .map(|(_, _, value)| value)
+            .sum()
+    }
+}
+
+
+impl System {
+    /// Evaluates the constraints in parallel (see Objective::eval)
+    pub(super) fn parallel_eval(&mut self) {
+        let sys_objects = &self.sys_objects;
+        self.constraints
+            .par_iter_mut()
+            .for_each(|constraint| constraint.evaluate(sys_objects));
+    }
+
+    /// Adds the gradients of the constraints to output. The gradient of each
+    /// constraint is found in parallel, and they are added to output in the
+    /// order of the constraints, so the sums do not depend on the number of
+    /// threads.
+    pub(super) fn parallel_gradient(&mut self, output: &mut Array1<f64>) {
+        let n = output.len();
+        let sys_objects = &self.sys_objects;
+        let gradients: Vec<Vec<(usize, f64)>> = self.constraints
+            .par_iter_mut()
+            .map_init(
+                || Array1::zeros(n),
+                |grad, constraint| {
+                    // Only the entries of the variables of the constraint are
+                    // kept, and they are cleared for the next constraint of
+                    // this thread
+                    constraint.get_gradient(grad, sys_objects);
+                    let mut entries = Vec::new();
+                    for (obj_idx, var_name) in constraint.get_variables() {
+                        if let Some(k) = sys_objects[obj_idx].get_variable(var_name).index {
+                            if grad[k] != 0.0 {
+                                entries.push((k, grad[k]));
+                                grad[k] = 0.0;
+                            }
+                        }
+                    }
+                    debug_assert!(
+                        grad.iter().all(|value| *value == 0.0),
+                        "constraint '{}' has a gradient outside its variables", constraint.get_name(),
+                    );
+                    entries
+                },
+            )
+            .collect();
+        for entries in &gradients {
+            for (k, value) in entries {
+                output[*k] += value;
+            }
+        }
+    }
+
+    /// Adds the hessians of the constraints to output, an n x n matrix. The
+    /// entries of each constraint are found in parallel, and they are added to
+    /// output in the order of the constraints and in the order each constraint
+    /// added them, like a sequential evaluation does.
+    pub(super) fn parallel_hessian(&mut self, output: &mut dyn HessianAccumulator) {
+        let sys_objects = &self.sys_objects;
+        let hessians: Vec<HessianEntries> = self.constraints
+            .par_iter_mut()
+            .map(|constraint| {
+                let mut entries = HessianEntries(Vec::new());
+                constraint.get_hessian(&mut entries, sys_objects);
+                entries
+            })
+            .collect();
+        for entries in &hessians {
+            for (row, col, value) in &entries.0 {
+                output.add(*row, *col, *value);
+            }
+        }
+    }
+}
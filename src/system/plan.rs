@@ -0,0 +1,224 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+
+use crate::minimizer::{SolverOptions, memory_estimate};
+use crate::system::{System, angle_warning};
+use crate::system_object::VariableName as VN;
+
+
+/// What the solver is going to do with a system, found without solving it.
+///
+/// A plan is only valid for the system (and the revision of the system) it was
+/// made for; adding or removing objects or constraints after the plan was made
+/// makes it stale. Moving objects with update_placement does not.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Debug, Clone)]
+pub struct Plan {
+    /// Identifier of the system the plan was made for
+    pub system_id: u64,
+    /// Revision of the system when the plan was made
+    pub revision: u64,
+    /// Number of solver variables
+    pub variables: usize,
+    /// Number of objects in the system
+    pub objects: usize,
+    /// Number of constraints in the system
+    pub constraints: usize,
+    /// Names of the objects without solver variables (every variable used by
+    /// the constraints is locked)
+    pub grounded: Vec<String>,
+    /// Names of the objects of each independent component of the system
+    pub components: Vec<Vec<String>>,
+    /// Indices of the objects of each independent component of the system
+    pub component_indices: Vec<Vec<usize>>,
+    /// Number of groups of variables equal to each other (see
+    /// System::get_equalities)
+    pub equality_classes: usize,
+    /// Number of variables of the largest group of equal variables
+    pub largest_equality_class: usize,
+    /// Estimated peak memory (in bytes) needed to solve the system with the
+    /// options given to analyze
+    pub memory_estimate: usize,
+    /// Problems found in the input that do not stop the system from being
+    /// solved
+    pub warnings: Vec<String>,
+}
+
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Plan {
+    /// Number of solver variables
+    #[getter]
+    fn variables(&self) -> usize {
+        self.variables
+    }
+
+    /// Number of objects in the system
+    #[getter]
+    fn objects(&self) -> usize {
+        self.objects
+    }
+
+    /// Number of constraints in the system
+    #[getter]
+    fn constraints(&self) -> usize {
+        self.constraints
+    }
+
+    /// Names of the objects without solver variables (every variable used by
+    /// the constraints is locked)
+    #[getter]
+    fn grounded(&self) -> Vec<String> {
+        self.grounded.clone()
+    }
+
+    /// Names of the objects of each independent component of the system
+    #[getter]
+    fn components(&self) -> Vec<Vec<String>> {
+        self.components.clone()
+    }
+
+    /// Number of groups of variables equal to each other (see
+    /// System::get_equalities)
+    #[getter]
+    fn equality_classes(&self) -> usize {
+        self.equality_classes
+    }
+
+    /// Number of variables of the largest group of equal variables
+    #[getter]
+    fn largest_equality_class(&self) -> usize {
+        self.largest_equality_class
+    }
+
+    /// Estimated peak memory (in bytes) needed to solve the system with the
+    /// options given to analyze
+    #[getter]
+    fn memory_estimate(&self) -> usize {
+        self.memory_estimate
+    }
+
+    /// Problems found in the input that do not stop the system from being
+    /// solved
+    #[getter]
+    fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+
+    /// Returns the plan as a dictionary
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let plan = PyDict::new(py);
+        plan.set_item("variables", self.variables)?;
+        plan.set_item("objects", self.objects)?;
+        plan.set_item("constraints", self.constraints)?;
+        plan.set_item("grounded", self.grounded.clone())?;
+        plan.set_item("components", self.components.clone())?;
+        plan.set_item("equality_classes", self.equality_classes)?;
+        plan.set_item("largest_equality_class", self.largest_equality_class)?;
+        plan.set_item("memory_estimate", self.memory_estimate)?;
+        plan.set_item("warnings", self.warnings.clone())?;
+        Ok(plan)
+    }
+}
+
+
+impl System {
+    /// Analyzes the system without solving it. The returned plan can be
+    /// passed to solve_planned to avoid finding the components again.
+    pub fn analyze(&mut self, options: &SolverOptions) -> Plan {
+        self.update_indices();
+
+        let component_indices = self.components();
+        let components = component_indices
+            .iter()
+            .map(|objects| {
+                let mut names: Vec<String> = objects
+                    .iter()
+                    .map(|obj_idx| self.get_object_name(*obj_idx).to_string())
+                    .collect();
+                names.sort();
+                names
+            })
+            .collect();
+
+        let mut grounded: Vec<String> = self.sys_objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.get_variables_iter().all(|variable| variable.index.is_none()))
+            .map(|(obj_idx, _)| self.get_object_name(obj_idx).to_string())
+            .collect();
+        grounded.sort();
+
+        let equalities = self.get_equalities();
+
+        Plan {
+            system_id: self.id,
+            revision: self.revision,
+            variables: self.get_enabled_size(),
+            objects: self.sys_objects.len(),
+            constraints: self.constraints.len(),
+            grounded,
+            components,
+            component_indices,
+            equality_classes: equalities.len(),
+            largest_equality_class: equalities.iter().map(|class| class.len()).max().unwrap_or(0),
+            memory_estimate: memory_estimate(self, options),
+            warnings: self.warnings(),
+        }
+    }
+
+    /// Returns true if the plan was made for this system and no objects or
+    /// constraints were added or removed since then
+    pub fn is_current(&self, plan: &Plan) -> bool {
+        plan.system_id == self.id && plan.revision == self.revision
+    }
+
+    /// Returns the problems found in the input of the system
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (obj_idx, object) in self.sys_objects.iter().enumerate() {
+            for var_name in VN::get_rotation_iter() {
+                let angle = object.get_variable(var_name).value;
+                if let Some(warning) = angle_warning(self.get_object_name(obj_idx), var_name, angle) {
+                    warnings.push(warning);
+                }
+            }
+        }
+        for constraint in &self.constraints {
+            let free = constraint.get_variables().iter().any(|(obj_idx, var_name)| {
+                self.sys_objects[*obj_idx].get_variable(*var_name).index.is_some()
+            });
+            if !free {
+                warnings.push(format!(
+                    "constraint {} does not have any free variable", constraint.get_name()
+                ));
+            }
+        }
+        for constraint_name in self.invalid_constraints() {
+            warnings.push(format!(
+                "constraint {} uses a removed object and must be removed before solving",
+                constraint_name,
+            ));
+        }
+        warnings
+    }
+}
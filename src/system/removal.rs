@@ -0,0 +1,151 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::system::System;
+
+
+impl System {
+    /// Removes an object from the system. Returns None if there is no object
+    /// with the given name, otherwise the names of the constraints that
+    /// depended on the object (found from the footprints of the constraints).
+    ///
+    /// With cascade the dependent constraints are removed together with the
+    /// object. Without it they are marked as invalid and the system is not
+    /// solved until they are removed with remove_constraint. In both cases the
+    /// locks of the object are removed and the variables of other objects equal
+    /// to the variables of the object become independent variables.
+    ///
+    /// Adding again an object with the same name creates a new object; the
+    /// dependent constraints of the removed object are never used again.
+    pub fn remove_object(&mut self, object_name: &str, cascade: bool) -> Option<Vec<String>> {
+        let removed_idx = self.sys_objects_idx.remove(object_name)?;
+
+        let mut dependents = Vec::new();
+        let mut constraints = Vec::with_capacity(self.constraints.len());
+        for constraint in self.constraints.drain(..) {
+            let depends = constraint
+                .footprint()
+                .iter()
+                .any(|(obj_idx, _)| *obj_idx == removed_idx);
+            if !depends {
+                constraints.push(constraint);
+                continue;
+            }
+            dependents.push(constraint.get_name().to_string());
+            if !cascade {
+                self.invalid_constraints.push(constraint);
+            }
+        }
+        self.constraints = constraints;
+        self.locks.retain(|(_, obj_idx, _)| *obj_idx != removed_idx);
+
+        // Variables equal to a variable of the removed object keep its value
+        let removed = self.sys_objects.remove(removed_idx);
+        for object in &mut self.sys_objects {
+            for variable in object.get_variables_mut_iter() {
+                if let Some((obj_idx, var_name, factor)) = variable.equal {
+                    if obj_idx == removed_idx {
+                        variable.equal = None;
+                        variable.value = factor * removed.get_variable(var_name).value;
+                    }
+                }
+            }
+        }
+
+        // The objects after the removed object move one place back
+        let object_map: HashMap<usize, usize> = (0..=self.sys_objects.len())
+            .filter(|obj_idx| *obj_idx != removed_idx)
+            .map(|obj_idx| (obj_idx, if obj_idx > removed_idx { obj_idx - 1 } else { obj_idx }))
+            .collect();
+        for obj_idx in self.sys_objects_idx.values_mut() {
+            if let Some(new_idx) = object_map.get(obj_idx) {
+                *obj_idx = *new_idx;
+            }
+        }
+        for constraint in &mut self.constraints {
+            constraint.remap_objects(&object_map);
+        }
+        for (_, obj_idx, _) in &mut self.locks {
+            if let Some(new_idx) = object_map.get(obj_idx) {
+                *obj_idx = *new_idx;
+            }
+        }
+        for object in &mut self.sys_objects {
+            for variable in object.get_variables_mut_iter() {
+                if let Some((obj_idx, var_name, factor)) = variable.equal {
+                    if let Some(new_idx) = object_map.get(&obj_idx) {
+                        variable.equal = Some((*new_idx, var_name, factor));
+                    }
+                }
+            }
+        }
+
+        if !dependents.is_empty() {
+            let action = if cascade { "removed" } else { "marked as invalid" };
+            info!(
+                "removing object {} {} the constraints {}",
+                object_name, action, dependents.join(", "),
+            );
+        }
+        self.finalize();
+        self.indexed = false;
+        self.revision += 1;
+        Some(dependents)
+    }
+
+    /// Removes a constraint (valid or invalid) from the system. Returns false if
+    /// there is no constraint with the given name.
+    pub fn remove_constraint(&mut self, constraint_name: &str) -> bool {
+        let found = if let Some(c_idx) = self
+            .constraints
+            .iter()
+            .position(|constraint| constraint.get_name() == constraint_name)
+        {
+            self.constraints.remove(c_idx);
+            true
+        } else if let Some(c_idx) = self
+            .invalid_constraints
+            .iter()
+            .position(|constraint| constraint.get_name() == constraint_name)
+        {
+            self.invalid_constraints.remove(c_idx);
+            true
+        } else {
+            false
+        };
+        if found {
+            self.finalize();
+            self.indexed = false;
+            self.revision += 1;
+        }
+        found
+    }
+
+    /// Returns the names of the constraints whose objects were removed from
+    /// the system. The system is not solved while there are invalid
+    /// constraints.
+    pub fn invalid_constraints(&self) -> Vec<&str> {
+        self.invalid_constraints
+            .iter()
+            .map(|constraint| constraint.get_name())
+            .collect()
+    }
+}
@@ -0,0 +1,88 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+
+use crate::system::System;
+use crate::system_object::{SystemObject, VariableName as VN};
+
+
+/// The objects of a system with the values and states of their variables, for
+/// example to save a partially solved system and to solve it later.
+///
+/// The constraints are not part of the snapshot (a custom constraint can be any
+/// Rust type). They are added again to the restored system, for example by
+/// build_constraints with the same maps used to build the saved system; the
+/// objects already in the system are reused, so the constraints start from the
+/// saved placements.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Debug, Clone)]
+pub struct SystemSnapshot {
+    /// objects of the system in the order of their indices
+    pub objects: Vec<SystemObject>,
+    /// indices of the objects by name
+    pub names: HashMap<String, usize>,
+}
+
+
+impl SystemSnapshot {
+    /// Returns a new system with the objects of the snapshot and without
+    /// constraints
+    pub fn restore(&self) -> System {
+        let mut system = System::new();
+        system.sys_objects = self.objects.clone();
+        system.sys_objects_idx = self.names.clone();
+        for object in system.sys_objects.iter_mut() {
+            if object.q_enable {
+                object.update_q();
+            }
+            if object.v_enable {
+                object.update_v();
+            }
+        }
+        system
+    }
+
+    /// Returns the placements of the objects by name, like the objects map
+    /// passed to build_constraints
+    pub fn placements(&self) -> HashMap<String, HashMap<String, f64>> {
+        self.names
+            .iter()
+            .map(|(name, obj_idx)| {
+                let object = &self.objects[*obj_idx];
+                let placement = VN::get_variable_iter()
+                    .map(|var_name| (var_name.as_str().to_string(), object.get_variable(var_name).value))
+                    .collect();
+                (name.clone(), placement)
+            })
+            .collect()
+    }
+}
+
+
+impl System {
+    /// Returns a snapshot of the objects of the system (see SystemSnapshot)
+    pub fn snapshot(&self) -> SystemSnapshot {
+        SystemSnapshot {
+            objects: self.sys_objects.clone(),
+            names: self.sys_objects_idx.clone(),
+        }
+    }
+}
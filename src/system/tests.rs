@@ -0,0 +1,2400 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use std::collections::HashMap;
+#[cfg(feature = "parallel")]
+use std::collections::HashSet;
+use std::sync::atomic::Ordering;
+#[cfg(feature = "parallel")]
+use std::sync::{Arc, Mutex};
+use std::thread;
+#[cfg(feature = "parallel")]
+use std::thread::ThreadId;
+#[cfg(feature = "parallel")]
+use std::time::Duration;
+
+use ndarray::{Array1, Array2};
+use optimization::geometry::HDQuaternion;
+use optimization::problem::{Hessian, Objective};
+
+use crate::build_constraints;
+use crate::constraints::ConstraintType;
+#[cfg(feature = "parallel")]
+use crate::constraints::{Constraint, Footprint, HessianAccumulator, Residual, ResidualKind};
+use crate::geometry::euler::wrap_angle;
+use crate::minimizer::{self, GradientContribution, LockShadow, Method, SolverOptions, Termination};
+use crate::system::System;
+#[cfg(feature = "serde")]
+use crate::system::SystemSnapshot;
+#[cfg(feature = "parallel")]
+use crate::system_object::SystemObject;
+use crate::system_object::VariableName as VN;
+use crate::test_fixtures::*;
+use crate::testing::verify_constraint_derivatives;
+
+
+#[test]
+fn systems_are_solved_from_several_threads() {
+    let handles: Vec<_> = (0..4)
+        .map(|k| {
+            thread::spawn(move || {
+                let mut system = two_part(1.0 + k as f64).system();
+                let stats = system.solve(&SolverOptions::new());
+                (stats.success, placement_of(&system, "B"))
+            })
+        })
+        .collect();
+
+    for (k, handle) in handles.into_iter().enumerate() {
+        let (success, placement) = handle.join().unwrap();
+        let mut expected = two_part(1.0 + k as f64).system();
+        assert!(expected.solve(&SolverOptions::new()).success);
+        assert!(success);
+        assert_eq!(placement, placement_of(&expected, "B"));
+    }
+}
+
+
+#[test]
+fn components_match_the_serial_solves() {
+    let fixture = four_assemblies(5.0);
+    let options = SolverOptions {
+        split_components: true,
+        ..SolverOptions::new()
+    };
+    let mut system = fixture.system();
+    let components = system.components();
+    assert_eq!(components.len(), 4);
+    let stats = system.solve(&options);
+    assert!(stats.success);
+    assert_eq!(system.report.components.len(), 4);
+    assert!(system.report.components.iter().all(|component| component.success));
+
+    // Each component solved on its own, one after the other
+    let reference = fixture.system();
+    for objects in &components {
+        let mut component = reference.component_system(objects);
+        assert!(minimizer::solve(&mut component, &options).success);
+        for name in component.sys_objects_idx.keys() {
+            assert_eq!(placement_of(&system, name), placement_of(&component, name), "{}", name);
+        }
+    }
+}
+
+
+/// A locked "Base" with "A" fixed relative to it and "B" fixed relative to "A"
+/// at the same y and z.
+/// With aliases, the y and z variables of "A" and "B" are equal and the x of
+/// "B" is locked at its solved value.
+fn aliased(aliases: bool) -> Fixture {
+    let fixture = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("A", [1.5, 1.0, 0.0, 0.2, 0.1, 0.0])
+        .object("B", [2.5, 2.5, 1.0, 0.0, -0.1, 0.3])
+        .constraint("Lock_0", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("x", 1.0), ("y", 2.0), ("z", 0.5),
+        ])
+        .constraint("FixRotation_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 2.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("FixRotation_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ]);
+    if !aliases {
+        return fixture;
+    }
+    fixture
+        .constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], &[("y", 1.0), ("z", 1.0)])
+        .constraint("Lock_1", &[("Object", "B")], &[("x", 3.0)])
+}
+
+
+#[test]
+fn arrays_only_have_live_rows() {
+    let mut system = aliased(true).system();
+    system.update_indices();
+
+    // 6 variables of "A" and 6 of "B", minus the locked x and the aliased y
+    // and z of "B"
+    let n = system.get_enabled_size();
+    assert_eq!(n, 9);
+    let mut rows = vec![0; n];
+    for object in &system.sys_objects {
+        for variable in object.get_variables_iter() {
+            if let Some(k) = variable.index {
+                assert!(!variable.locked);
+                rows[k] += 1;
+            }
+        }
+    }
+    let b = &system.sys_objects[system.sys_objects_idx["B"]];
+    assert!(b.get_variable(VN::x).index.is_none());
+    assert_eq!(rows.iter().sum::<usize>(), 11);
+
+    // Every row of the hessian has a non-zero entry without regularization
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    let mut hess = Array2::zeros((n, n));
+    system.hess(&mut hess);
+    assert!(system.monitor.regularized.is_empty());
+    for k in 0..n {
+        assert!(hess.row(k).iter().any(|value| *value != 0.0), "{}", k);
+    }
+
+    // The aliases don't change the solution
+    let options = SolverOptions::new();
+    assert!(system.solve(&options).success);
+    let mut expected = aliased(false).system();
+    assert!(expected.solve(&options).success);
+    assert!(system_distance(&system, &expected) < 1e-6);
+}
+
+
+/// Adds up the gradient contributions of the constraints of a system at the
+/// solver index of each variable
+fn breakdown_sum(system: &System, breakdown: &[GradientContribution]) -> Array1<f64> {
+    let mut sum = Array1::zeros(system.get_enabled_size());
+    for contribution in breakdown {
+        for (obj_name, var_name, value) in &contribution.gradient {
+            let object = &system.sys_objects[system.sys_objects_idx[obj_name]];
+            let k = object.get_variable(VN::get_from_str(var_name)).index.unwrap();
+            sum[k] += value;
+        }
+    }
+    sum
+}
+
+
+#[test]
+fn gradient_contributions_add_up_to_the_gradient() {
+    for fixture in [chain(4, 0.3), aliased(true)].iter() {
+        let mut system = fixture.system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let breakdown = system.gradient_breakdown();
+        assert_eq!(breakdown.len(), system.constraints.len());
+        let mut gradient = Array1::zeros(x.len());
+        system.constraint_gradient(&mut gradient);
+        assert!(gradient.iter().any(|value| *value != 0.0));
+        let sum = breakdown_sum(&system, &breakdown);
+        for (value, expected) in sum.iter().zip(&gradient) {
+            assert!((value - expected).abs() <= 1e-12 * expected.abs().max(1.0));
+        }
+    }
+
+    // The report has the contributions at the end of a failed solve
+    let mut system = chain(6, 0.4).system();
+    let stats = system.solve(&SolverOptions {
+        max_iterations: 1,
+        gradient_breakdown: true,
+        method: Method::Lbfgs,
+        ..SolverOptions::new()
+    });
+    assert!(!stats.success);
+    let mut gradient = Array1::zeros(system.get_enabled_size());
+    system.constraint_gradient(&mut gradient);
+    let sum = breakdown_sum(&system, &system.report.gradient_breakdown);
+    for (value, expected) in sum.iter().zip(&gradient) {
+        assert!((value - expected).abs() <= 1e-12 * expected.abs().max(1.0));
+    }
+}
+
+
+#[test]
+fn unsatisfiable_fix_reports_the_gap() {
+    // "B" can't be 5 away from "Base1" and 3 away from "Base2" along x when
+    // both bases are at the origin, so it stops halfway, 1 away from each
+    let locked = [("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0)];
+    let mut system = Fixture::new()
+        .object("Base1", [0.0; 6])
+        .object("Base2", [0.0; 6])
+        .object("B", [1.0, 2.0, 3.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_1", &[("Object", "Base1")], &locked)
+        .constraint("Lock_2", &[("Object", "Base2")], &locked)
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "Base1")], &[
+            ("x", 5.0), ("y", 1.0), ("z", 0.0),
+        ])
+        .constraint("FixBase_2", &[("Object", "B"), ("Reference", "Base2")], &[
+            ("x", 3.0), ("y", 1.0), ("z", 0.0),
+        ])
+        .system();
+    assert!(!system.solve(&SolverOptions::new()).success);
+    assert!((placement_of(&system, "B")[0] - 4.0).abs() < 1e-6);
+
+    let errors = &system.report.worst_constraints;
+    assert_eq!(errors.len(), 2);
+    for error in errors {
+        assert!(error.constraint == "FixBase_1" || error.constraint == "FixBase_2");
+        assert!((error.value - 1.0).abs() < 1e-6, "{}", error.value);
+        let labels: Vec<&str> = error.residuals.iter().map(|(label, _, _)| label.as_str()).collect();
+        assert_eq!(labels, ["x", "y", "z"]);
+        for (label, value, unit) in &error.residuals {
+            let gap = if label == "x" { 1.0 } else { 0.0 };
+            assert!((value.abs() - gap).abs() < 1e-6, "{} {}: {}", error.constraint, label, value);
+            assert_eq!(unit, "length");
+        }
+    }
+}
+
+
+#[test]
+fn update_placement_rejects_unknown_names() {
+    let mut system = two_part(5.0).system();
+    let before = placement_of(&system, "B");
+    let placement: HashMap<&str, f64> = [("x", 3.0), ("rx", 1.0)].iter().cloned().collect();
+    assert!(!system.update_placement("B", &placement));
+    assert_eq!(placement_of(&system, "B"), before);
+    assert!(!system.update_placement("C", &to_placement([3.0; 6])));
+}
+
+
+#[test]
+fn update_placement_keeps_the_constraints() {
+    let fixture = two_part(5.0);
+    let mut system = fixture.system();
+    let counter = Counter::new(system.sys_objects_idx["B"]);
+    system.add_custom_constraint(Box::new(counter.clone()));
+    let options = SolverOptions::new();
+    assert!(system.solve(&options).success);
+    let clones = counter.clones.load(Ordering::SeqCst);
+    let constraints = (system.constraints.as_ptr(), system.constraints.len());
+
+    // "A" is locked so only "B" moves
+    let moved = [4.0, -1.0, 2.0, 0.3, 0.2, -0.1];
+    assert!(system.update_placement("B", &to_placement(moved)));
+    assert!(system.update_placement("A", &to_placement([1.0; 6])));
+    assert_eq!(placement_of(&system, "B"), moved);
+    assert!(system.indexed);
+    assert!(system.solve(&options).success);
+    assert_eq!(counter.clones.load(Ordering::SeqCst), clones);
+    assert_eq!((system.constraints.as_ptr(), system.constraints.len()), constraints);
+
+    // The same as building the system with the new placement
+    let mut expected = fixture.object("B", moved).system();
+    assert!(expected.solve(&options).success);
+    assert!(system_distance(&system, &expected) < 1e-12);
+}
+
+
+#[test]
+fn lock_shadows_show_the_conflict() {
+    let mut system = lock_fix_conflict().system();
+    let stats = system.solve(&SolverOptions {
+        lock_shadows: true,
+        ..SolverOptions::new()
+    });
+    assert!(!stats.success);
+    assert!((placement_of(&system, "A")[0] + 0.5).abs() < 1e-6);
+
+    // The lock of "B" only locks its x, which only FixBase_2 pulls
+    let shadows: Vec<&LockShadow> = system.report.lock_shadows
+        .iter()
+        .filter(|shadow| shadow.lock == "Lock_2")
+        .collect();
+    assert_eq!(shadows.len(), 1);
+    let shadow = shadows[0];
+    assert_eq!((shadow.object.as_str(), shadow.variable.as_str()), ("B", "x"));
+    assert_eq!(shadow.value, 3.0);
+    assert_eq!(shadow.opponents.len(), 1);
+    assert_eq!(shadow.opponents[0].0, "FixBase_2");
+    // "B" alone would move 1.5 along x to satisfy FixBase_2
+    assert!((shadow.gradient + 3.0).abs() < 1e-6, "{}", shadow.gradient);
+    assert!((shadow.shift.unwrap() - 1.5).abs() < 1e-6);
+
+    // The variables are locked again after the analysis
+    let b = &system.sys_objects[system.sys_objects_idx["B"]];
+    assert!(b.get_variable(VN::x).locked);
+    assert!(b.get_variable(VN::x).index.is_none());
+}
+
+
+#[test]
+fn angles_in_degrees_are_reported() {
+    // 720 is an angle in degrees passed as radians by mistake
+    let start = [1.0, 2.0, 3.0, 720.0, 0.2, -720.0];
+    let mut system = two_part(5.0).object("B", start).system();
+    let warnings = system.analyze(&SolverOptions::new()).warnings;
+    assert_eq!(warnings.iter().filter(|warning| warning.contains("radians")).count(), 2);
+    assert!(warnings.iter().any(|warning| warning.contains("angle phi of object B is 720")));
+
+    // The constraint errors are those of the same rotation with small angles
+    let value = |placement: [f64; 6]| {
+        let mut system = two_part(5.0).object("B", placement).system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        system.eval_real()
+    };
+    let wrapped = [1.0, 2.0, 3.0, wrap_angle(720.0), 0.2, wrap_angle(-720.0)];
+    let expected = value(wrapped);
+    assert!((value(start) - expected).abs() < 1e-9 * expected);
+}
+
+
+/// Returns the placements of all the objects of a system, sorted by object
+/// name
+fn solution(system: &System) -> Vec<[f64; 6]> {
+    let mut names: Vec<&String> = system.sys_objects_idx.keys().collect();
+    names.sort();
+    names.into_iter().map(|name| placement_of(system, name)).collect()
+}
+
+
+#[test]
+fn repeated_solves_are_identical() {
+    // The fixture is built again each time, so the input maps iterate in a
+    // different order on each solve
+    let solve = || {
+        let mut system = chain(6, 0.4).system();
+        assert!(system.solve(&SolverOptions::new()).success);
+        solution(&system)
+    };
+    let expected = solve();
+    for _ in 0..50 {
+        assert_eq!(solve(), expected);
+    }
+}
+
+
+#[cfg(feature = "parallel")]
+#[test]
+fn repeated_solves_are_identical_with_any_number_of_threads() {
+    let options = SolverOptions {
+        split_components: true,
+        ..SolverOptions::new()
+    };
+    let mut expected = four_assemblies(5.0).system();
+    assert!(expected.solve(&options).success);
+    let expected = solution(&expected);
+    for threads in [1, 2, 3, 4] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        for _ in 0..50 {
+            let mut system = four_assemblies(5.0).system();
+            assert!(pool.install(|| system.solve(&options)).success);
+            assert_eq!(solution(&system), expected, "{} threads", threads);
+        }
+    }
+}
+
+
+/// A spring keeping the x variable of an object at distance 1 from the x
+/// variable of another object, which records the threads evaluating it
+#[cfg(feature = "parallel")]
+#[derive(Debug, Clone)]
+struct ThreadSpring {
+    obj1_index: usize,
+    obj2_index: usize,
+    residual: f64,
+    threads: Arc<Mutex<HashSet<ThreadId>>>,
+}
+
+
+#[cfg(feature = "parallel")]
+impl ThreadSpring {
+    fn jacobian(&self, sys_objects: &[SystemObject]) -> Vec<(usize, f64)> {
+        [(self.obj1_index, -1.0), (self.obj2_index, 1.0)]
+            .iter()
+            .filter_map(|(obj_idx, jac)| {
+                let variable = sys_objects[*obj_idx].get_variable(VN::x);
+                variable.index.map(|k| (k, variable.scale * jac))
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(feature = "parallel")]
+impl Constraint for ThreadSpring {
+    fn evaluate(&mut self, sys_objects: &[SystemObject]) {
+        // The first evaluation on each thread is slow so the other components
+        // are picked up by other threads
+        if self.threads.lock().unwrap().insert(thread::current().id()) {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let x1 = sys_objects[self.obj1_index].get_variable(VN::x).value;
+        let x2 = sys_objects[self.obj2_index].get_variable(VN::x).value;
+        self.residual = x2 - x1 - 1.0;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_grad[k] += 2.0 * self.residual * jac;
+        }
+    }
+
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        self.jacobian(sys_objects)
+            .iter()
+            .map(|(k, jac)| 2.0 * self.residual * jac * direction[*k])
+            .sum()
+    }
+
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        let jacobian = self.jacobian(sys_objects);
+        for (i, jac_i) in &jacobian {
+            for (j, jac_j) in &jacobian {
+                sys_hess.add(*i, *j, 2.0 * jac_i * jac_j);
+            }
+        }
+    }
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]) {
+        residuals.push(Residual {
+            value: self.residual,
+            jacobian: self.jacobian(sys_objects),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj1_index, self.obj2_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj1_index, Footprint::Variables), (self.obj2_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.obj1_index, VN::x), (self.obj2_index, VN::x)]
+    }
+
+    fn get_name(&self) -> &str {
+        "ThreadSpring"
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj1_index = object_map[&self.obj1_index];
+        self.obj2_index = object_map[&self.obj2_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+#[cfg(feature = "parallel")]
+#[test]
+fn components_are_solved_in_parallel() {
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let mut system = System::new();
+    for i in 0..4 {
+        let ground = format!("Ground{}", i);
+        let cart = format!("Cart{}", i);
+        system.add_object(&ground, &to_placement([0.0; 6]));
+        system.add_object(&cart, &to_placement([3.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+        let obj1_index = system.sys_objects_idx[&ground];
+        let obj2_index = system.sys_objects_idx[&cart];
+        system.sys_objects[obj1_index].enable_variables(&["x"]);
+        system.sys_objects[obj1_index].lock_variables(&["x"]);
+        system.sys_objects[obj2_index].enable_variables(&["x"]);
+        system.add_custom_constraint(Box::new(ThreadSpring {
+            obj1_index,
+            obj2_index,
+            residual: 0.0,
+            threads: threads.clone(),
+        }));
+    }
+    assert_eq!(system.components().len(), 4);
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    let stats = pool.install(|| system.solve(&SolverOptions {
+        split_components: true,
+        ..SolverOptions::new()
+    }));
+    assert!(stats.success);
+    assert!(threads.lock().unwrap().len() > 1);
+}
+
+
+#[test]
+fn custom_constraints_are_built_from_the_input_maps() {
+    // A is fixed at x = 1 from the locked Base, B hangs from A by a spring of
+    // length 2 and C by a spring of length 1. "Springs_1" is not of type
+    // "Spring", so it is not built even if its name starts with "Spring".
+    let fixture = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("A", [0.3, 0.2, -0.1, 0.1, 0.0, 0.0])
+        .object("B", [5.0, 1.0, 0.0, 0.0, 0.0, 0.0])
+        .object("C", [-4.0, 0.0, 2.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_1", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("x", 1.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("Spring_1", &[("Object1", "A"), ("Object2", "B")], &[("length", 2.0)])
+        .constraint("Spring_2", &[("Object1", "C"), ("Object2", "B")], &[("length", 1.0)])
+        .constraint("Springs_1", &[("Object1", "A"), ("Object2", "C")], &[("length", 7.0)]);
+
+    // without the factory the springs are not constraints of the system
+    let system = fixture.system();
+    assert!(system.constraints.iter().all(|c| !c.get_name().starts_with("Spring")));
+
+    let mut system = System::new();
+    system.register_constraint("Spring", spring_factory).unwrap();
+    build_constraints(&mut system, &fixture.objects, &fixture.names, &fixture.parameters).unwrap();
+    let mut names: Vec<&str> = system.constraints.iter().map(|c| c.get_name()).collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["FixBase_1", "Spring_1", "Spring_2"]);
+
+    let stats = system.solve(&SolverOptions::new());
+    assert!(stats.success);
+    let a = placement_of(&system, "A");
+    let b = placement_of(&system, "B");
+    let c = placement_of(&system, "C");
+    assert!((a[0] - 1.0).abs() < 1e-6);
+    assert!((b[0] - 3.0).abs() < 1e-6);
+    assert!((c[0] - 2.0).abs() < 1e-6);
+    // the springs only move the x variables
+    assert_eq!(b[1], 1.0);
+    assert_eq!(c[2], 2.0);
+}
+
+
+/// Returns the components of a hyper-dual quaternion
+fn quaternion_components(q: &HDQuaternion) -> Vec<f64> {
+    [q.q0, q.q1, q.q2, q.q3]
+        .iter()
+        .flat_map(|c| vec![c.re, c.e1, c.e2, c.e1e2])
+        .collect()
+}
+
+
+#[test]
+fn cached_inverse_quaternions_match_the_inverses() {
+    let fixture = chain(6, 0.4);
+    let mut system = fixture.system();
+    system.update_indices();
+    let blocks = [None, Some(VN::x), Some(VN::phi), Some(VN::theta), Some(VN::psi)];
+
+    // the cache must follow the rotations when the variables change
+    let start = system.get_position();
+    for shift in &[0.0, 0.3, -1.1] {
+        let x = &start + *shift;
+        system.update_x(&x);
+        for object in system.sys_objects.iter().filter(|object| object.q_enable) {
+            for var1 in &blocks {
+                for var2 in &blocks {
+                    let cached = quaternion_components(&object.get_inverse_quaternion(*var1, *var2));
+                    let inverse = quaternion_components(&object.get_quaternion(*var1, *var2).inv());
+                    for (a, b) in cached.iter().zip(&inverse) {
+                        assert!((a - b).abs() < 1e-12, "{:?} {:?}: {} != {}", var1, var2, a, b);
+                    }
+                }
+            }
+        }
+
+        // the fixes read the cache, and they match inverting the quaternion
+        for constraint in &system.constraints {
+            if !constraint.get_name().starts_with("FixBase") {
+                continue;
+            }
+            let roles = &fixture.names[constraint.get_name()];
+            let object = &system.sys_objects[system.sys_objects_idx[roles["Object"]]];
+            let reference = &system.sys_objects[system.sys_objects_idx[roles["Reference"]]];
+            let offset = object.get_vector(None, None) - reference.get_vector(None, None);
+            let local = reference.get_quaternion(None, None).inv().mul_vec(&offset);
+            let expected = [local.x.re, local.y.re, local.z.re];
+            for (a, b) in constraint.current_parameters(&system.sys_objects).iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-12, "{}: {} != {}", constraint.get_name(), a, b);
+            }
+        }
+    }
+}
+
+
+#[test]
+fn plans_count_the_variables_and_components() {
+    let options = SolverOptions::new();
+    let plan = chain(4, 0.3).system().analyze(&options);
+    assert_eq!((plan.variables, plan.objects, plan.constraints), (24, 5, 8));
+    assert_eq!(plan.grounded, vec!["Base"]);
+    assert_eq!(plan.components, vec![vec!["Base", "P0", "P1", "P2", "P3"]]);
+
+    let plan = four_assemblies(1.0).system().analyze(&options);
+    assert_eq!((plan.variables, plan.objects, plan.constraints), (24, 8, 8));
+    assert_eq!(plan.grounded, vec!["A1", "A2", "A3", "A4"]);
+    assert_eq!(plan.components, vec![
+        vec!["A1", "B1"], vec!["A2", "B2"], vec!["A3", "B3"], vec!["A4", "B4"],
+    ]);
+    assert!(plan.warnings.is_empty());
+}
+
+
+#[test]
+fn structural_edits_make_plans_stale() {
+    let options = SolverOptions::new();
+    let mut system = chain(4, 0.3).system();
+    let plan = system.analyze(&options);
+    assert!(system.is_current(&plan));
+
+    // moving an object keeps the plan
+    assert!(system.update_placement("P2", &to_placement([1.0, 2.0, 3.0, 0.4, 0.5, 0.6])));
+    assert!(system.is_current(&plan));
+    assert!(system.solve_planned(&options, &plan).success);
+    assert!(system.is_current(&plan));
+
+    // a plan of another system is never current
+    let other = chain(4, 0.3).system().analyze(&options);
+    assert!(!system.is_current(&other));
+
+    // adding or removing objects or constraints makes it stale
+    system.add_object("Q", &to_placement([0.0; 6]));
+    assert!(!system.is_current(&plan));
+    let plan = system.analyze(&options);
+    assert!(system.remove_constraint("FixRotation_3"));
+    assert!(!system.is_current(&plan));
+    let plan = system.analyze(&options);
+    let obj_index = system.sys_objects_idx["P3"];
+    system.add_custom_constraint(Box::new(Counter::new(obj_index)));
+    assert!(!system.is_current(&plan));
+    let plan = system.analyze(&options);
+    assert!(system.remove_object("P3", true).is_some());
+    assert!(!system.is_current(&plan));
+}
+
+
+#[test]
+fn footprints_enable_the_blocks() {
+    // A is fixed to Base (without its rotation) and C and D are only used by
+    // an equality of their x variables
+    let mut system = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("A", [1.5, 1.0, 0.0, 0.2, 0.1, 0.0])
+        .object("C", [0.5, 0.0, 0.0, 0.3, 0.0, 0.0])
+        .object("D", [2.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_0", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("x", 1.0), ("y", 2.0), ("z", 0.5),
+        ])
+        .constraint("Equality_1", &[("Object1", "C"), ("Object2", "D")], &[("x", 1.0)])
+        .system();
+    let flags = |system: &System, name: &str| {
+        let object = &system.sys_objects[system.sys_objects_idx[name]];
+        (object.v_enable, object.q_enable)
+    };
+    assert_eq!(flags(&system, "Base"), (true, true));
+    assert_eq!(flags(&system, "A"), (true, false));
+    assert_eq!(flags(&system, "C"), (false, false));
+    assert_eq!(flags(&system, "D"), (false, false));
+
+    // the flags nothing needs anymore are cleared
+    assert!(system.remove_constraint("FixBase_1"));
+    assert_eq!(flags(&system, "Base"), (false, false));
+    assert_eq!(flags(&system, "A"), (false, false));
+}
+
+
+#[test]
+fn redundant_constraints_are_inactive() {
+    // FixBase_1 moves B along x. Equality_1 repeats the y part of the fix, and
+    // C is already in place relative to B, so the fix of C moves C (with B)
+    // but its value does not decrease.
+    let mut system = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("B", [1.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .object("C", [2.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_0", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "Base")], &[
+            ("x", 5.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("FixBase_2", &[("Object", "C"), ("Reference", "B")], &[
+            ("x", 1.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("Equality_1", &[("Object1", "Base"), ("Object2", "B")], &[("y", 1.0)])
+        .system();
+    let stats = system.solve(&SolverOptions {
+        attribution: true,
+        ..SolverOptions::new()
+    });
+    assert!(stats.success);
+    assert!((placement_of(&system, "C")[0] - 6.0).abs() < 1e-6);
+
+    let attribution: HashMap<&str, &minimizer::ConstraintAttribution> = system.report.attribution
+        .iter()
+        .map(|constraint| (constraint.constraint.as_str(), constraint))
+        .collect();
+    let fix = attribution["FixBase_1"];
+    assert!(fix.active);
+    assert!(fix.initial_value > 1.0 && fix.final_value < 1e-10);
+    assert!(fix.moved.contains(&("B".to_string(), "x".to_string())));
+
+    let duplicate = attribution["Equality_1"];
+    assert!(!duplicate.active);
+    assert!(duplicate.moved.is_empty());
+
+    let follower = attribution["FixBase_2"];
+    assert!(!follower.active);
+    assert!(follower.moved.contains(&("C".to_string(), "x".to_string())));
+}
+
+
+/// Names of the constraints of a system, sorted
+fn constraint_names(system: &System) -> Vec<&str> {
+    let mut names: Vec<&str> = system.constraints.iter().map(|c| c.get_name()).collect();
+    names.sort_unstable();
+    names
+}
+
+
+#[test]
+fn removed_objects_cascade_to_their_constraints() {
+    let mut system = chain(4, 0.3).system();
+    let mut dependents = system.remove_object("P1", true).unwrap();
+    dependents.sort();
+    assert_eq!(dependents, vec!["FixBase_1", "FixBase_2", "FixRotation_1", "FixRotation_2"]);
+    assert_eq!(constraint_names(&system), vec!["FixBase_0", "FixBase_3", "FixRotation_0", "FixRotation_3"]);
+    assert!(system.invalid_constraints().is_empty());
+    assert!(system.remove_object("P1", true).is_none());
+
+    // the object added again with the same name is not used by the old
+    // constraints
+    let placement = [7.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    system.add_object("P1", &to_placement(placement));
+    assert_eq!(constraint_names(&system).len(), 4);
+    let stats = system.solve(&SolverOptions::new());
+    assert!(stats.success);
+    assert_eq!(placement_of(&system, "P1"), placement);
+    for constraint in &system.constraints {
+        assert!(constraint.get_value() < 1e-10, "{}", constraint.get_name());
+    }
+}
+
+
+#[test]
+fn removed_objects_invalidate_their_constraints() {
+    let mut system = chain(4, 0.3).system();
+    let dependents = system.remove_object("P1", false).unwrap();
+    assert_eq!(dependents.len(), 4);
+    let mut invalid = system.invalid_constraints();
+    invalid.sort_unstable();
+    assert_eq!(invalid, vec!["FixBase_1", "FixBase_2", "FixRotation_1", "FixRotation_2"]);
+    assert_eq!(constraint_names(&system), vec!["FixBase_0", "FixBase_3", "FixRotation_0", "FixRotation_3"]);
+
+    // adding the object again does not revive the constraints
+    system.add_object("P1", &to_placement([0.0; 6]));
+    let before = placement_of(&system, "P0");
+    let stats = system.solve(&SolverOptions::new());
+    assert_eq!(stats.termination, Termination::InvalidConstraints);
+    assert_eq!(placement_of(&system, "P0"), before);
+    assert_eq!(system.invalid_constraints().len(), 4);
+
+    for name in &dependents {
+        assert!(system.remove_constraint(name));
+    }
+    assert!(system.invalid_constraints().is_empty());
+    assert!(system.solve(&SolverOptions::new()).success);
+}
+
+
+#[test]
+fn solve_from_rejects_a_start_of_the_wrong_length() {
+    let mut system = chain(3, 0.3).system();
+    let before = placement_of(&system, "P0");
+    let stats = system.solve_from(&SolverOptions::new(), &Array1::zeros(2));
+    assert!(!stats.success);
+    assert_eq!(stats.termination, Termination::InvalidStart);
+    assert_eq!(placement_of(&system, "P0"), before);
+
+    let x0 = system.get_position();
+    assert!(system.solve_from(&SolverOptions::new(), &x0).success);
+}
+
+
+#[test]
+fn scrambled_equality_chain_is_one_class() {
+    // the chain A = B = C = D of the z variables is declared out of order and
+    // with mixed object roles
+    let mut system = Fixture::new()
+        .object("A", [0.0, 0.0, 1.0, 0.0, 0.0, 0.0])
+        .object("B", [1.0, 0.0, 2.0, 0.0, 0.0, 0.0])
+        .object("C", [2.0, 0.0, 3.0, 0.0, 0.0, 0.0])
+        .object("D", [3.0, 0.0, 4.0, 0.0, 0.0, 0.0])
+        .constraint("Equality_1", &[("Object1", "C"), ("Object2", "D")], &[("z", 1.0)])
+        .constraint("Equality_2", &[("Object1", "B"), ("Object2", "A")], &[("z", 1.0)])
+        .constraint("Equality_3", &[("Object1", "C"), ("Object2", "B")], &[("z", 1.0)])
+        .system();
+    let plan = system.analyze(&SolverOptions::new());
+    assert_eq!((plan.equality_classes, plan.largest_equality_class), (1, 4));
+    let expected: Vec<(String, VN)> = ["A", "B", "C", "D"]
+        .iter()
+        .map(|name| (name.to_string(), VN::z))
+        .collect();
+    assert_eq!(system.get_equalities(), vec![expected]);
+}
+
+
+/// Returns the rotation matrix of the Euler angles (phi, theta, psi): a
+/// rotation phi about x, then theta about y and then psi about z
+fn rotation_matrix(phi: f64, theta: f64, psi: f64) -> Array2<f64> {
+    let (c1, s1) = (phi.cos(), phi.sin());
+    let (c2, s2) = (theta.cos(), theta.sin());
+    let (c3, s3) = (psi.cos(), psi.sin());
+    let rx = Array2::from_shape_vec((3, 3), vec![1.0, 0.0, 0.0, 0.0, c1, -s1, 0.0, s1, c1]).unwrap();
+    let ry = Array2::from_shape_vec((3, 3), vec![c2, 0.0, s2, 0.0, 1.0, 0.0, -s2, 0.0, c2]).unwrap();
+    let rz = Array2::from_shape_vec((3, 3), vec![c3, -s3, 0.0, s3, c3, 0.0, 0.0, 0.0, 1.0]).unwrap();
+    rz.dot(&ry).dot(&rx)
+}
+
+
+#[test]
+fn fix_reaches_the_translation_and_rotation_offsets() {
+    let reference = [1.0, -2.0, 0.5, 0.4, -0.3, 1.0];
+    let mut system = Fixture::new()
+        .object("A", reference)
+        .object("B", [2.0, 0.0, 1.0, 0.5, 0.0, 1.2])
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", reference[0]), ("y", reference[1]), ("z", reference[2]),
+            ("phi", reference[3]), ("theta", reference[4]), ("psi", reference[5]),
+        ])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 1.0), ("y", 2.0), ("z", 3.0),
+        ])
+        .constraint("FixRotation_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("phi", 0.3), ("theta", -0.2), ("psi", 0.5),
+        ])
+        .system();
+    assert!(system.solve(&SolverOptions::new()).success);
+
+    // the offset is given in the coordinate system of the reference
+    let rotation = rotation_matrix(reference[3], reference[4], reference[5]);
+    let position = Array1::from(vec![reference[0], reference[1], reference[2]])
+        + rotation.dot(&Array1::from(vec![1.0, 2.0, 3.0]));
+    let expected_rotation = rotation.dot(&rotation_matrix(0.3, -0.2, 0.5));
+
+    let b = placement_of(&system, "B");
+    for k in 0..3 {
+        assert!((b[k] - position[k]).abs() < 1e-6, "{:?} {}", b, position);
+    }
+    let b_rotation = rotation_matrix(b[3], b[4], b[5]);
+    for (a, e) in b_rotation.iter().zip(expected_rotation.iter()) {
+        assert!((a - e).abs() < 1e-6, "{} {}", b_rotation, expected_rotation);
+    }
+}
+
+
+#[test]
+fn solve_returns_the_placements_of_every_object() {
+    // "Free" is not used by any constraint and "A" has an attachment point
+    let mut fixture = two_part(5.0).object("Free", [3.0, -1.0, 2.0, 0.1, 0.2, 0.3]);
+    fixture.objects.get_mut("A").unwrap().insert("Hole.x", 0.25);
+    let solved = crate::solve(&fixture.objects, &fixture.names, &fixture.parameters, &SolverOptions::new())
+        .unwrap();
+    assert!(solved.result.success);
+
+    let mut names: Vec<&str> = solved.objects.keys().cloned().collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["A", "B", "Free"]);
+    assert_eq!(solved.objects["Free"], fixture.objects["Free"]);
+    // the lock sets the placement of A, and its attachment point is unchanged
+    assert_eq!(solved.objects["A"]["phi"], 0.5);
+    assert_eq!(solved.objects["A"]["Hole.x"], 0.25);
+    let mut expected = two_part(5.0).system();
+    assert!(expected.solve(&SolverOptions::new()).success);
+    let expected = placement_of(&expected, "B");
+    for (k, var_name) in ["x", "y", "z", "phi", "theta", "psi"].iter().enumerate() {
+        assert!((solved.objects["B"][var_name] - expected[k]).abs() < 1e-9, "{}", var_name);
+    }
+}
+
+
+#[test]
+fn placements_have_the_solved_values() {
+    let fixture = chain(4, 0.3);
+    let mut system = fixture.system();
+    assert!(system.solve(&SolverOptions::new()).success);
+    let placements = system.placements();
+
+    // the same placements are written into the input maps by solve
+    let solved = crate::solve(&fixture.objects, &fixture.names, &fixture.parameters, &SolverOptions::new())
+        .unwrap();
+    assert_eq!(placements.len(), solved.objects.len());
+    for (name, placement) in &solved.objects {
+        assert_eq!(placements[*name].len(), 6);
+        for (var_name, value) in placement {
+            assert_eq!(placements[*name][*var_name], *value, "{} {}", name, var_name);
+        }
+    }
+    assert_ne!(placements["P3"]["x"], fixture.objects["P3"]["x"]);
+}
+
+
+#[test]
+fn result_objective_excludes_the_start_penalty() {
+    let fixture = two_part(5.0);
+    let options = SolverOptions {
+        stay_near_start_weight: 1e-2,
+        ..SolverOptions::accurate()
+    };
+    let solved = crate::solve(&fixture.objects, &fixture.names, &fixture.parameters, &options).unwrap();
+    assert!(solved.result.success);
+
+    // B moved far from its start, so the penalty would dominate the residuals
+    let penalty: f64 = ["x", "y", "z", "phi", "theta", "psi"]
+        .iter()
+        .map(|var_name| (solved.objects["B"][var_name] - fixture.objects["B"][var_name]).powi(2))
+        .sum::<f64>() * options.stay_near_start_weight;
+    assert!(penalty > 1e-2);
+    assert!(solved.result.objective < 1e-12, "{}", solved.result.objective);
+}
+
+
+/// Returns the message of the error of building the constraints of a fixture
+fn build_error(fixture: &Fixture) -> String {
+    let mut system = System::new();
+    build_constraints(&mut system, &fixture.objects, &fixture.names, &fixture.parameters)
+        .unwrap_err()
+        .message()
+        .to_string()
+}
+
+
+#[test]
+fn malformed_maps_are_reported() {
+    let fixture = two_part(5.0)
+        .constraint("FixBase_3", &[("Object", "Bolt_2"), ("Reference", "A")], &[("x", 1.0)]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'FixBase_3' references object 'Bolt_2' which is not in the objects map",
+    );
+
+    let fixture = two_part(5.0).constraint("Lock_2", &[("Reference", "B")], &[("x", 1.0)]);
+    assert_eq!(build_error(&fixture), "constraint 'Lock_2' is missing its 'Object' entry");
+
+    let mut fixture = two_part(5.0);
+    fixture.objects.get_mut("B").unwrap().remove("theta");
+    assert_eq!(
+        build_error(&fixture),
+        "object 'B' (used by constraint 'FixBase_1') is missing the placement key 'theta'",
+    );
+
+    let mut fixture = two_part(5.0);
+    fixture.parameters.remove("FixRotation_1");
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'FixRotation_1' is not in the constraint parameters map",
+    );
+}
+
+
+#[test]
+fn axis_coincident_parts_rotate_and_slide_along_the_axis() {
+    let mut system = Fixture::new()
+        .object("Hole", [0.0; 6])
+        .object("Pin", [0.3, -0.2, 1.0, 0.1, -0.1, 0.7])
+        .constraint("Lock_1", &[("Object", "Hole")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("AxisCoincident_1", &[("Object1", "Pin"), ("Object2", "Hole")], &[])
+        .system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let pin = placement_of(&system, "Pin");
+    let axis = rotation_matrix(pin[3], pin[4], pin[5]).column(2).to_owned();
+    assert!(pin[0].abs() < 1e-9 && pin[1].abs() < 1e-9, "{:?}", pin);
+    assert!((axis[2] - 1.0).abs() < 1e-9, "{:?}", axis);
+
+    // sliding and turning the pin keeps the constraint satisfied
+    assert_eq!(system.count_dof(), 2);
+    let moved: HashMap<&str, f64> = [("z", 4.0), ("psi", -1.5)].iter().cloned().collect();
+    assert!(system.update_placement("Pin", &moved));
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let pin = placement_of(&system, "Pin");
+    assert!((pin[2] - 4.0).abs() < 1e-9 && (pin[5] + 1.5).abs() < 1e-9, "{:?}", pin);
+}
+
+
+#[test]
+fn locks_take_rotation_quaternions() {
+    // a turn of 0.8 about the z-axis and a turn of -0.6 about the x-axis
+    let turns = [
+        ([0.4f64.cos(), 0.0, 0.0, 0.4f64.sin()], [0.0, 0.0, 0.8]),
+        ([0.3f64.cos(), -(0.3f64.sin()), 0.0, 0.0], [-0.6, 0.0, 0.0]),
+    ];
+    for (q, angles) in turns.iter() {
+        let fixture = Fixture::new()
+            .object("A", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+            .constraint("Lock_1", &[("Object", "A")], &[
+                ("x", 0.0), ("q0", q[0]), ("q1", q[1]), ("q2", q[2]), ("q3", q[3]),
+            ]);
+        let placement = placement_of(&fixture.system(), "A");
+        for k in 0..3 {
+            assert!((placement[3 + k] - angles[k]).abs() < 1e-12, "{:?}", placement);
+        }
+    }
+
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .constraint("Lock_1", &[("Object", "A")], &[("q0", 2.0), ("q1", 0.0), ("q2", 0.0), ("q3", 0.0)]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'Lock_1': the rotation quaternion is not normalized (its norm is 2)",
+    );
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .constraint("Lock_1", &[("Object", "A")], &[("q0", f64::NAN), ("q1", 0.0), ("q2", 0.0), ("q3", 0.0)]);
+    assert!(build_error(&fixture).contains("is not normalized"));
+}
+
+
+#[test]
+fn hinges_are_free_about_their_axis() {
+    let mut system = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("Arm", [0.4, -0.3, 0.2, 0.2, -0.1, 0.6])
+        .constraint("Lock_1", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("Hinge_1", &[("Object1", "Base"), ("Object2", "Arm")], &[])
+        .system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    assert_eq!(system.count_dof(), 1);
+
+    // turning the arm about the shared z-axis keeps the gradient at zero
+    let turned: HashMap<&str, f64> = [("psi", 2.0)].iter().cloned().collect();
+    assert!(system.update_placement("Arm", &turned));
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    let mut gradient = Array1::zeros(x.len());
+    system.constraint_gradient(&mut gradient);
+    let arm = &system.sys_objects[system.sys_objects_idx["Arm"]];
+    let psi = arm.get_variable(VN::psi).index.unwrap();
+    assert!(gradient[psi].abs() < 1e-12, "{}", gradient[psi]);
+    assert!(gradient.iter().all(|value| value.abs() < 1e-9), "{:?}", gradient);
+}
+
+
+#[test]
+fn ball_pins_the_origin_and_leaves_the_rotation_free() {
+    let reference = [1.0, -2.0, 0.5, 0.4, -0.3, 1.0];
+    let start = [3.0, 1.0, -1.0, 0.7, 0.2, -0.4];
+    for locked in [true, false].iter() {
+        let mut fixture = Fixture::new()
+            .object("Socket", reference)
+            .object("Ball", start)
+            .constraint("Ball_1", &[("Object", "Ball"), ("Reference", "Socket")], &[
+                ("x", 0.5), ("y", -1.0), ("z", 2.0),
+            ]);
+        if *locked {
+            fixture = fixture.constraint("Lock_1", &[("Object", "Socket")], &[
+                ("x", reference[0]), ("y", reference[1]), ("z", reference[2]),
+                ("phi", reference[3]), ("theta", reference[4]), ("psi", reference[5]),
+            ]);
+        }
+        let mut system = fixture.system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", locked);
+        let socket = placement_of(&system, "Socket");
+        let ball = placement_of(&system, "Ball");
+        if *locked {
+            assert_eq!(socket, reference);
+        }
+
+        let rotation = rotation_matrix(socket[3], socket[4], socket[5]);
+        let point = rotation.dot(&Array1::from(vec![0.5, -1.0, 2.0]));
+        for k in 0..3 {
+            assert!((ball[k] - socket[k] - point[k]).abs() < 1e-9, "{}: {:?}", locked, ball);
+        }
+        // the rotation of the ball is not a variable of the constraint
+        assert_eq!(ball[3..], start[3..]);
+    }
+}
+
+
+#[test]
+fn dof_counts_missing_and_redundant_equations() {
+    // two_part fixes B to the locked A
+    assert_eq!(two_part(5.0).system().count_dof(), 0);
+    // a distance leaves the origin of B on a sphere
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+        .constraint("Lock_1", &[("Object", "A")], &[("x", 0.0), ("y", 0.0), ("z", 0.0)])
+        .constraint("Distance_1", &[("Object", "B"), ("Reference", "A")], &[("d", 2.0)]);
+    assert_eq!(fixture.system().count_dof(), 2);
+    // the distance repeats an equation of the fix
+    let fixture = two_part(5.0)
+        .constraint("Distance_1", &[("Object", "B"), ("Reference", "A")], &[("d", 26f64.sqrt())]);
+    assert_eq!(fixture.system().count_dof(), -1);
+}
+
+
+#[test]
+fn screws_couple_the_turn_and_the_advance() {
+    for pitch in [2.0, -2.0].iter() {
+        let fixture = Fixture::new()
+            .object("Nut", [0.0; 6])
+            .object("Bolt", [0.2, -0.1, 0.5, 0.1, 0.1, 1.0])
+            .constraint("Coaxial_1", &[("Object", "Bolt"), ("Reference", "Nut")], &[])
+            .constraint("Screw_1", &[("Object1", "Bolt"), ("Object2", "Nut")], &[("pitch", *pitch)]);
+
+        // the hessian has entries between z and psi of both objects
+        let mut system = fixture.system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        for constraint in system.constraints.iter_mut() {
+            if let ConstraintType::ScrewConstraint(screw) = constraint {
+                let report = verify_constraint_derivatives(screw, &system.sys_objects, 1e-6);
+                assert!(report.passed(), "{:?}", report.mismatches);
+            }
+        }
+
+        let fixture = fixture.constraint("Lock_1", &[("Object", "Nut")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ]);
+        let mut system = fixture.system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", pitch);
+        let bolt = placement_of(&system, "Bolt");
+        let advance = pitch / (2.0 * std::f64::consts::PI) * bolt[5];
+        assert!((bolt[2] - advance).abs() < 1e-9, "{}: {:?}", pitch, bolt);
+    }
+}
+
+
+#[test]
+fn residuals_are_reported_by_constraint_name() {
+    let mut system = two_part(5.0).system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    let residuals = system.constraint_residuals();
+    let names: Vec<&str> = residuals.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, constraint_names(&system));
+    let residuals: HashMap<String, f64> = residuals.into_iter().collect();
+    // B starts away from its fixed placement
+    assert!(residuals["FixBase_1"] > 1.0, "{:?}", residuals);
+    assert!(residuals["FixRotation_1"] > 0.0, "{:?}", residuals);
+
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    for (name, value) in system.constraint_residuals() {
+        assert!(value < 1e-10, "{}: {}", name, value);
+    }
+}
+
+
+#[test]
+fn driven_angles_can_be_retargeted() {
+    let fixture = Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("Arm", [0.4, -0.3, 0.2, 0.2, -0.1, 0.6])
+        .constraint("Lock_1", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("Hinge_1", &[("Object1", "Base"), ("Object2", "Arm")], &[])
+        .constraint("Driven_1", &[("Object", "Arm")], &[("psi", 0.5)]);
+    let mut system = fixture.system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    assert!((placement_of(&system, "Arm")[5] - 0.5).abs() < 1e-9);
+
+    // sweeping the target does not rebuild the system
+    let driven = system.constraints.iter().position(|c| c.get_name() == "Driven_1").unwrap();
+    for target in [1.2, -0.7].iter() {
+        system.constraints[driven].set_parameter(0, *target);
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", target);
+        let arm = placement_of(&system, "Arm");
+        assert!((arm[5] - target).abs() < 1e-9, "{}: {:?}", target, arm);
+    }
+
+    let fixture = fixture.constraint("Driven_1", &[("Object", "Arm")], &[("phi", 0.1), ("psi", 0.5)]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'Driven_1' must have exactly one of the parameters 'phi', 'theta' or 'psi'",
+    );
+}
+
+
+#[test]
+fn soft_locks_settle_at_the_weighted_mean() {
+    let fixture = Fixture::new()
+        .object("A", [1.0, 1.0, 1.0, 0.1, 0.2, 0.3])
+        .constraint("Lock_1", &[("Object", "A")], &[("x", 0.0), ("psi", 0.0), ("weight", 1.0)])
+        .constraint("Lock_2", &[("Object", "A")], &[("x", 3.0), ("psi", 0.6), ("weight", 2.0)]);
+    let mut system = fixture.system();
+    assert_eq!(system.constraints.len(), 2);
+
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    for constraint in system.constraints.iter_mut() {
+        if let ConstraintType::LockConstraint(lock) = constraint {
+            let report = verify_constraint_derivatives(lock, &system.sys_objects, 1e-6);
+            assert!(report.passed(), "{:?}", report.mismatches);
+        }
+    }
+
+    // the locks conflict, so the error at the solution is not zero
+    system.solve(&SolverOptions::accurate());
+    let a = placement_of(&system, "A");
+    assert!((a[0] - 2.0).abs() < 1e-6 && (a[5] - 0.4).abs() < 1e-6, "{:?}", a);
+
+    // a lock without a weight locks its variables
+    let system = Fixture::new()
+        .object("A", [1.0, 1.0, 1.0, 0.1, 0.2, 0.3])
+        .constraint("Lock_1", &[("Object", "A")], &[("x", 0.0), ("psi", 0.0)])
+        .system();
+    assert!(system.constraints.is_empty());
+    let a = placement_of(&system, "A");
+    assert_eq!((a[0], a[5]), (0.0, 0.0));
+}
+
+
+/// An object at the fraction t (0.5 when None) between the origins of two
+/// references
+fn midpoint(first: [f64; 6], second: [f64; 6], t: Option<f64>) -> Fixture {
+    Fixture::new()
+        .object("R1", first)
+        .object("R2", second)
+        .object("M", [0.3, -0.7, 2.0, 0.1, 0.2, 0.3])
+        .constraint(
+            "Midpoint_1",
+            &[("Object", "M"), ("Reference1", "R1"), ("Reference2", "R2")],
+            &t.map(|t| vec![("t", t)]).unwrap_or_default(),
+        )
+}
+
+
+#[test]
+fn moved_references_drag_the_midpoint() {
+    let origin = [0.0; 6];
+    let corner = [4.0, 2.0, -2.0, 0.5, 0.0, 0.0];
+    let moved = [-2.0, 6.0, 1.0, 0.0, 0.0, 0.0];
+    let cases = [
+        (origin, corner, None, 0.5),
+        (moved, corner, None, 0.5),
+        (origin, moved, None, 0.5),
+        (origin, corner, Some(0.25), 0.25),
+    ];
+    let lock = |placement: &[f64; 6]| -> Vec<(&'static str, f64)> {
+        VN::get_variable_iter().map(|var_name| var_name.as_str()).zip(placement.iter().cloned()).collect()
+    };
+    for (first, second, t, fraction) in cases.iter() {
+        let mut system = midpoint(*first, *second, *t)
+            .constraint("Lock_1", &[("Object", "R1")], &lock(first))
+            .constraint("Lock_2", &[("Object", "R2")], &lock(second))
+            .system();
+        assert!(system.solve(&SolverOptions::accurate()).success);
+        let m = placement_of(&system, "M");
+        for k in 0..3 {
+            let expected = (1.0 - fraction) * first[k] + fraction * second[k];
+            assert!((m[k] - expected).abs() < 1e-9, "{:?} {:?} {}: {:?}", first, second, fraction, m);
+        }
+    }
+
+    // without the locks all nine position variables are checked
+    let mut system = midpoint(origin, corner, Some(0.25)).system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    let constraint = match &mut system.constraints[0] {
+        ConstraintType::MidpointConstraint(constraint) => constraint,
+        _ => unreachable!(),
+    };
+    let report = verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6);
+    assert!(report.passed(), "{:?}", report.mismatches);
+    assert_eq!(report.checked_variables, 9);
+}
+
+
+#[test]
+fn penalty_equalities_match_the_shared_indices() {
+    let penalty = |fixture: Fixture| {
+        fixture.constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], &[
+            ("y", 1.0), ("z", 1.0), ("penalty", 1.0),
+        ])
+    };
+    let mut shared = aliased(true).system();
+    let mut separate = penalty(aliased(true)).system();
+    assert!(shared.solve(&SolverOptions::accurate()).success);
+    assert!(separate.solve(&SolverOptions::accurate()).success);
+    assert!(system_distance(&shared, &separate) < 1e-8);
+
+    // the y and z variables of a penalty equality are independent, so the
+    // distance between them is reported
+    let mut system = penalty(Fixture::new()
+        .object("A", [1.5, 1.0, 0.0, 0.2, 0.1, 0.0])
+        .object("B", [2.5, 2.5, 1.0, 0.0, -0.1, 0.3]))
+        .system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    system.eval();
+    assert_eq!(system.constraint_residuals(), vec![("Equality_1".to_string(), 3.25)]);
+    let constraint = match &mut system.constraints[0] {
+        ConstraintType::EqualityConstraint(constraint) => constraint,
+        _ => unreachable!(),
+    };
+    let report = verify_constraint_derivatives(constraint.as_mut(), &system.sys_objects, 1e-6);
+    assert!(report.passed(), "{:?}", report.mismatches);
+    assert_eq!(report.checked_variables, 4);
+}
+
+
+#[test]
+fn fixes_without_a_reference_use_the_global_frame() {
+    let start = [1.0, 2.0, 3.0, 0.1, 0.2, 0.3];
+    let fixes = [
+        Fixture::new().object("A", start)
+            .constraint("FixBase_1", &[("Object", "A")], &[("x", 4.0), ("y", -1.0)])
+            .constraint("FixRotation_1", &[("Object", "A")], &[("psi", 0.5)]),
+        Fixture::new().object("A", start)
+            .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Origin")], &[("x", 4.0), ("y", -1.0)])
+            .constraint("FixRotation_1", &[("Object", "A"), ("Reference", "Origin")], &[("psi", 0.5)]),
+    ];
+    for fixture in fixes.iter() {
+        let mut system = fixture.system();
+        assert_eq!(system.sys_objects.len(), 1);
+        assert!(system.solve(&SolverOptions::accurate()).success);
+        let a = placement_of(&system, "A");
+        // the missing values are 0
+        assert!(placement_distance(a, [4.0, -1.0, 0.0, 0.0, 0.0, 0.5]) < 1e-9, "{:?}", a);
+    }
+
+    // FixGlobal only fixes the variables it is given
+    let mut system = Fixture::new().object("A", start)
+        .constraint("FixGlobal_1", &[("Object", "A")], &[("z", -2.0), ("theta", 0.4)])
+        .system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let a = placement_of(&system, "A");
+    assert!(placement_distance(a, [1.0, 2.0, -2.0, 0.1, 0.4, 0.3]) < 1e-9, "{:?}", a);
+
+    // an object named "Origin" is a real reference
+    let mut system = Fixture::new().object("A", start)
+        .object("Origin", [1.0, 1.0, 1.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_1", &[("Object", "Origin")], &[
+            ("x", 1.0), ("y", 1.0), ("z", 1.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Origin")], &[
+            ("x", 4.0), ("y", -1.0), ("z", 0.0),
+        ])
+        .system();
+    assert_eq!(system.sys_objects.len(), 2);
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let a = placement_of(&system, "A");
+    assert!(placement_distance(a, [5.0, 0.0, 1.0, 0.1, 0.2, 0.3]) < 1e-9, "{:?}", a);
+}
+
+
+#[test]
+fn plane_parallel_normals_can_be_flipped() {
+    let start = [0.5, -1.0, 2.0, 0.3, -0.4, 0.2];
+    for (flip, normal) in [(0.0, 1.0), (1.0, -1.0)].iter() {
+        let fixture = Fixture::new()
+            .object("A", [0.0; 6])
+            .object("B", start)
+            .constraint("PlaneParallel_1", &[("Object1", "A"), ("Object2", "B")], &[("flip", *flip)]);
+
+        let mut system = fixture.system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::PlaneParallelConstraint(constraint) => {
+                verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{:?}", report.mismatches);
+        // only the rotations are variables of the constraint
+        assert_eq!(report.checked_variables, 6);
+
+        let mut system = fixture
+            .constraint("Lock_1", &[("Object", "A")], &[
+                ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+            ])
+            .system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", flip);
+        let b = placement_of(&system, "B");
+        let axis = rotation_matrix(b[3], b[4], b[5]).column(2).to_owned();
+        assert!((axis[2] - normal).abs() < 1e-9, "{}: {:?}", flip, axis);
+        // the distance between the planes is free
+        assert_eq!(b[..3], start[..3]);
+    }
+}
+
+
+#[test]
+fn nearly_parallel_axes_are_turned_perpendicular() {
+    let methods = [
+        Method::Auto, Method::TrustNCG, Method::LevenbergMarquardt, Method::GaussNewton,
+        Method::Newton, Method::NewtonCg, Method::Lbfgs, Method::LbfgsNewton,
+    ];
+    for name in ["Perpendicular_1", "AxisPerpendicular_1"].iter() {
+        for theta in [1e-2, 1e-4, 1e-6, 0.0].iter() {
+            let fixture = Fixture::new()
+                .object("A", [0.0; 6])
+                .object("B", [1.0, 2.0, 3.0, 0.0, *theta, 0.0])
+                .constraint("Lock_1", &[("Object", "A")], &[
+                    ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+                ])
+                .constraint(name, &[("Object1", "A"), ("Object2", "B")], &[]);
+            for method in methods.iter().cloned() {
+                let mut system = fixture.system();
+                assert!(matches!(system.constraints[0], ConstraintType::PerpendicularConstraint(_)));
+                let options = SolverOptions {
+                    method,
+                    ..SolverOptions::accurate()
+                };
+                assert!(system.solve(&options).success, "{} {} {:?}", name, theta, method);
+                let b = placement_of(&system, "B");
+                let axis = rotation_matrix(b[3], b[4], b[5]).column(2).to_owned();
+                assert!(axis[2].abs() < 1e-6, "{} {} {:?}: {:?}", name, theta, method, axis);
+            }
+        }
+    }
+}
+
+
+#[test]
+fn validate_reports_every_structural_problem() {
+    assert!(two_part(5.0).system().validate().is_ok());
+
+    let fixture = two_part(5.0).constraint("Lock_2", &[("Object", "B")], &[("x", 5.0)]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'FixBase_1' fixes the locked variables [\"x\"] of object 'B' to the locked object 'A'",
+    );
+
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [1.0; 6])
+        .constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], &[("x", 1.0)])
+        .constraint("Equality_2", &[("Object1", "B"), ("Object2", "A")], &[("x", 1.0)]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'Equality_2': making 'x' of 'A' equal to 'x' of 'B' makes a cycle of equal variables",
+    );
+
+    let messages = |system: &System| -> Vec<String> {
+        system.validate().unwrap_err().iter().map(|error| error.to_string()).collect()
+    };
+    let mut system = two_part(5.0).system();
+    let a = system.sys_objects_idx["A"];
+    for var_name in VN::get_rotation_iter() {
+        system.sys_objects[a].get_mut_variable(var_name).enabled = false;
+    }
+    assert_eq!(messages(&system), vec!["the reference 'A' of constraint 'FixBase_1' has no rotation variable enabled"]);
+
+    // every problem is reported, not only the first one
+    system.sys_objects.truncate(1);
+    assert_eq!(messages(&system), vec![
+        "constraint 'FixBase_1' uses the object with index 1, which is not in the system",
+        "constraint 'FixRotation_1' uses the object with index 1, which is not in the system",
+    ]);
+}
+
+
+/// Two fixes of "B" to the locked "A" that pull it to x = 0 and x = 10 with
+/// different weights
+fn weighted_fixes(near: f64, far: f64) -> Fixture {
+    Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [3.0, 1.0, -1.0, 0.2, 0.1, 0.3])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("weight", near),
+        ])
+        .constraint("FixBase_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 10.0), ("y", 0.0), ("z", 0.0), ("weight", far),
+        ])
+        .constraint("FixRotation_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+}
+
+
+#[test]
+fn weighted_constraints_settle_at_the_weighted_mean() {
+    let mut system = weighted_fixes(9.0, 1.0).system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    let mut checked = 0;
+    for constraint in system.constraints.iter_mut() {
+        if let ConstraintType::WeightedConstraint(weighted) = constraint {
+            let report = verify_constraint_derivatives(weighted, &system.sys_objects, 1e-6);
+            assert!(report.passed(), "{:?}", report.mismatches);
+            checked += 1;
+        }
+    }
+    assert_eq!(checked, 2);
+
+    let mut system = weighted_fixes(9.0, 1.0)
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .system();
+    // the fixes conflict, so the error at the solution is not zero
+    system.solve(&SolverOptions::accurate());
+    let b = placement_of(&system, "B");
+    assert!(placement_distance(b, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0]) < 1e-6, "{:?}", b);
+
+    assert_eq!(
+        build_error(&weighted_fixes(9.0, 0.0)),
+        "the weight of constraint 'FixBase_2' must be a positive number",
+    );
+}
+
+
+/// Three objects with a Colinear constraint on their origins
+fn colinear(p1: [f64; 6], p2: [f64; 6], p3: [f64; 6]) -> Fixture {
+    Fixture::new()
+        .object("P1", p1)
+        .object("P2", p2)
+        .object("P3", p3)
+        .constraint("Colinear_1", &[("Object1", "P1"), ("Object2", "P2"), ("Object3", "P3")], &[])
+}
+
+
+#[test]
+fn colinear_points_handle_coincident_origins() {
+    let free = [3.0, 0.0, 2.0, 0.1, 0.2, 0.3];
+    let cases = [
+        ([0.0; 6], [1.0, 1.0, 0.0, 0.0, 0.0, 0.0]),
+        // two coincident points make the cross product identically zero
+        ([1.0; 6], [1.0; 6]),
+    ];
+    for (p1, p2) in cases.iter() {
+        let mut system = colinear(*p1, *p2, free).system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::ColinearConstraint(constraint) => {
+                verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{:?}", report.mismatches);
+        assert_eq!(report.checked_variables, 9);
+
+        let mut system = colinear(*p1, *p2, free)
+            .constraint("Lock_1", &[("Object", "P1")], &[("x", p1[0]), ("y", p1[1]), ("z", p1[2])])
+            .constraint("Lock_2", &[("Object", "P2")], &[("x", p2[0]), ("y", p2[1]), ("z", p2[2])])
+            .system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{:?} {:?}", p1, p2);
+        let p3 = placement_of(&system, "P3");
+        assert!(p3.iter().all(|value| value.is_finite()), "{:?}", p3);
+        let a = [p2[0] - p1[0], p2[1] - p1[1], p2[2] - p1[2]];
+        let b = [p3[0] - p1[0], p3[1] - p1[1], p3[2] - p1[2]];
+        let cross = [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]];
+        assert!(cross.iter().all(|value| value.abs() < 1e-6), "{:?}", p3);
+    }
+}
+
+
+#[test]
+fn tangent_circles_touch_the_tilted_plane() {
+    let plane = [0.0, 0.0, 1.0, 0.3, 0.2, 0.0];
+    let fixture = Fixture::new()
+        .object("Plane", plane)
+        .object("Wheel", [2.0, 1.0, 5.0, 0.1, 0.2, 0.3])
+        .constraint("Tangent_1", &[("Object", "Wheel"), ("Reference", "Plane")], &[("radius", 0.5)]);
+
+    let mut system = fixture.system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    let report = match &mut system.constraints[0] {
+        ConstraintType::TangentConstraint(constraint) => {
+            verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+        }
+        _ => unreachable!(),
+    };
+    assert!(report.passed(), "{:?}", report.mismatches);
+
+    let mut system = fixture
+        .constraint("Lock_1", &[("Object", "Plane")], &[
+            ("x", plane[0]), ("y", plane[1]), ("z", plane[2]),
+            ("phi", plane[3]), ("theta", plane[4]), ("psi", plane[5]),
+        ])
+        .system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let wheel = placement_of(&system, "Wheel");
+    let normal = rotation_matrix(plane[3], plane[4], plane[5]).column(2).to_owned();
+    let height: f64 = (0..3).map(|k| (wheel[k] - plane[k]) * normal[k]).sum();
+    assert!((height - 0.5).abs() < 1e-9, "{:?}", wheel);
+
+    let fixture = Fixture::new()
+        .object("Plane", plane)
+        .object("Wheel", [2.0, 1.0, 5.0, 0.1, 0.2, 0.3])
+        .constraint("Tangent_1", &[("Object", "Wheel"), ("Reference", "Plane")], &[("r", 0.5)]);
+    assert_eq!(build_error(&fixture), "constraint 'Tangent_1' is missing the parameter 'radius'");
+}
+
+
+#[test]
+fn centric_features_join_their_offset_centers() {
+    let socket = [1.0, 2.0, 3.0, 0.4, -0.3, 0.2];
+    let fixture = Fixture::new()
+        .object("Ball", [0.0, 0.5, -1.0, 0.7, 0.1, -0.2])
+        .object("Socket", socket)
+        .constraint("Centric_1", &[("Object1", "Ball"), ("Object2", "Socket")], &[
+            ("offset1", 0.5), ("offset2", -1.0),
+        ]);
+
+    let mut system = fixture.system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    let report = match &mut system.constraints[0] {
+        ConstraintType::CentricConstraint(constraint) => {
+            verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+        }
+        _ => unreachable!(),
+    };
+    assert!(report.passed(), "{:?}", report.mismatches);
+
+    let mut system = fixture
+        .constraint("Lock_1", &[("Object", "Socket")], &[
+            ("x", socket[0]), ("y", socket[1]), ("z", socket[2]),
+            ("phi", socket[3]), ("theta", socket[4]), ("psi", socket[5]),
+        ])
+        .system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let ball = placement_of(&system, "Ball");
+    let ball_axis = rotation_matrix(ball[3], ball[4], ball[5]).column(2).to_owned();
+    let socket_axis = rotation_matrix(socket[3], socket[4], socket[5]).column(2).to_owned();
+    for k in 0..3 {
+        let gap = ball[k] + 0.5 * ball_axis[k] - (socket[k] - socket_axis[k]);
+        assert!(gap.abs() < 1e-9, "{:?}", ball);
+    }
+}
+
+
+/// "B" starts at the distance `start` along x from the locked "A" and must stay
+/// between the optional ends of a DistanceRange
+fn distance_range(start: f64, ends: &[(&'static str, f64)]) -> Fixture {
+    Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [start, 0.0, 0.0, 0.1, 0.2, 0.3])
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("DistanceRange_1", &[("Object", "B"), ("Reference", "A")], ends)
+}
+
+
+#[test]
+fn distance_ranges_stop_at_both_ends() {
+    let band = [("min", 2.0), ("max", 3.0)];
+    // starts below, inside and above the band, and bands with a single end
+    let cases = [
+        (1.0, &band[..], 2.0),
+        (2.5, &band[..], 2.5),
+        (4.0, &band[..], 3.0),
+        (1.0, &band[..1], 2.0),
+        (4.0, &band[1..], 3.0),
+        (4.0, &band[..1], 4.0),
+    ];
+    for (start, ends, distance) in cases.iter() {
+        let mut system = distance_range(*start, ends).system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{} {:?}", start, ends);
+        let b = placement_of(&system, "B");
+        let reached = (b[0].powi(2) + b[1].powi(2) + b[2].powi(2)).sqrt();
+        // the cubic penalty is flat near the ends, so they are only reached
+        // approximately
+        assert!((reached - distance).abs() < 1e-4, "{} {:?}: {}", start, ends, reached);
+        if (start - distance).abs() < 1e-12 {
+            assert_eq!(b[0], *start);
+        }
+    }
+
+    // inside the band the derivatives are exactly zero, and just outside both
+    // ends they match finite differences
+    for start in [2.5, 2.0 - 1e-3, 3.0 + 1e-3, 1.0, 4.0].iter() {
+        let mut system = distance_range(*start, &band).system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        system.eval();
+        let mut gradient = Array1::zeros(x.len());
+        system.constraint_gradient(&mut gradient);
+        assert_eq!(gradient.iter().all(|value| *value == 0.0), *start == 2.5, "{}", start);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::DistanceRangeConstraint(constraint) => {
+                verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{}: {:?}", start, report.mismatches);
+    }
+
+    assert_eq!(
+        build_error(&distance_range(1.0, &[("min", 3.0), ("max", 2.0)])),
+        "constraint 'DistanceRange_1' has a minimum distance greater than its maximum",
+    );
+}
+
+
+#[test]
+fn fix_base_derivatives_match_finite_differences() {
+    let references = [[0.0; 6], [1.0, -2.0, 0.5, 0.4, -0.3, 1.0], [0.0, 0.0, 0.0, 0.0, 1.5, 0.0]];
+    for reference in references.iter() {
+        let mut system = Fixture::new()
+            .object("A", *reference)
+            .object("B", [3.0, 1.0, -1.0, 0.7, 0.2, -0.4])
+            .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+                ("x", 0.5), ("y", -1.0), ("z", 2.0),
+            ])
+            .system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::FixBaseConstraint(constraint) => {
+                verify_constraint_derivatives(constraint.as_mut(), &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{:?}: {:?}", reference, report.mismatches);
+        // the position of B and the placement of A
+        assert_eq!(report.checked_variables, 9);
+    }
+}
+
+
+#[test]
+fn placement_equal_copies_the_whole_placement() {
+    let a = [1.0, -2.0, 0.5, 0.4, -0.3, 1.0];
+    let locked = Fixture::new()
+        .object("A", a)
+        .object("B", [3.0, 1.0, -1.0, 0.7, 0.2, -0.4])
+        .object("C", [0.0; 6])
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", a[0]), ("y", a[1]), ("z", a[2]), ("phi", a[3]), ("theta", a[4]), ("psi", a[5]),
+        ]);
+    let fixture = locked.clone()
+        .constraint("PlacementEqual_1", &[("Object1", "A"), ("Object2", "B")], &[])
+        .constraint("PlacementEqual_2", &[("Object1", "B"), ("Object2", "C")], &[]);
+    let mut system = fixture.system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    assert_eq!(placement_of(&system, "B"), a);
+    assert_eq!(placement_of(&system, "C"), a);
+
+    // two sources for B, and a cycle between A and B
+    let sources = locked.clone()
+        .constraint("PlacementEqual_1", &[("Object1", "A"), ("Object2", "B")], &[])
+        .constraint("PlacementEqual_2", &[("Object1", "C"), ("Object2", "B")], &[]);
+    let cycle = locked
+        .constraint("PlacementEqual_1", &[("Object1", "A"), ("Object2", "B")], &[])
+        .constraint("PlacementEqual_2", &[("Object1", "B"), ("Object2", "A")], &[]);
+    for (fixture, object1, object2) in [(sources, "C", "B"), (cycle, "B", "A")].iter() {
+        assert_eq!(
+            build_error(fixture),
+            format!(
+                "constraint 'PlacementEqual_2': variable 'x' of '{}' and '{}' is already used by another equality",
+                object1, object2,
+            ),
+        );
+    }
+}
+
+
+#[cfg(feature = "serde")]
+#[test]
+fn saved_systems_resume_from_their_placements() {
+    let fixture = chain(5, 0.3);
+    let mut system = fixture.system();
+    system.solve(&SolverOptions {
+        max_iterations: 2,
+        ..SolverOptions::new()
+    });
+
+    let json = serde_json::to_string(&system.snapshot()).unwrap();
+    let snapshot: SystemSnapshot = serde_json::from_str(&json).unwrap();
+    let mut restored = snapshot.restore();
+    assert_eq!(restored.sys_objects_idx, system.sys_objects_idx);
+    assert_eq!(system_distance(&restored, &system), 0.0);
+
+    // the constraints are built again from the input maps and start from the
+    // saved placements
+    build_constraints(&mut restored, &fixture.objects, &fixture.names, &fixture.parameters).unwrap();
+    assert_eq!(system_distance(&restored, &system), 0.0);
+    assert!(restored.solve(&SolverOptions::new()).success);
+    assert!(system.solve(&SolverOptions::new()).success);
+    assert!(system_distance(&restored, &system) < 1e-9);
+}
+
+
+#[test]
+fn equalities_link_variables_of_one_object() {
+    let part = |pairs: &[(&'static str, f64)]| {
+        Fixture::new()
+            .object("P", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+            .constraint("Lock_1", &[("Object", "P")], &[("x", 1.5)])
+            .constraint("Equality_1", &[("Object1", "P"), ("Object2", "P")], pairs)
+    };
+    for penalty in [0.0, 1.0].iter() {
+        let mut system = part(&[("x=y", 1.0), ("y=z", 1.0), ("penalty", *penalty)]).system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", penalty);
+        let p = placement_of(&system, "P");
+        assert!((p[1] - 1.5).abs() < 1e-9 && (p[2] - 1.5).abs() < 1e-9, "{}: {:?}", penalty, p);
+        if *penalty == 0.0 {
+            let class = system.get_equalities().pop().unwrap();
+            let mut names: Vec<&str> = class.iter().map(|(_, var_name)| var_name.as_str()).collect();
+            names.sort_unstable();
+            assert_eq!(names, vec!["x", "y", "z"]);
+        }
+    }
+
+    assert_eq!(
+        build_error(&part(&[("x", 1.0)])),
+        "constraint 'Equality_1': variable 'x' of 'P' can't be equal to itself",
+    );
+    assert!(build_error(&part(&[("x=y", 1.0), ("y=x", 1.0)])).contains("makes a cycle of equal variables"));
+    assert_eq!(
+        build_error(&part(&[("x=w", 1.0)])),
+        "constraint 'Equality_1': 'x=w' is not a pair of variable names",
+    );
+    let fixture = part(&[]).constraint("PlacementEqual_1", &[("Object1", "P"), ("Object2", "P")], &[]);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'PlacementEqual_1': the placement of 'P' can't be equal to itself",
+    );
+}
+
+
+/// A locked "Base" and n objects fixed relative to it, with 2n + 1
+/// constraints
+#[cfg(feature = "parallel")]
+fn star(n: usize) -> System {
+    let names: Vec<String> = (0..n).map(|i| format!("P{}", i)).collect();
+    let fixes: Vec<String> = (0..n).map(|i| format!("FixBase_{}", i)).collect();
+    let rotations: Vec<String> = (0..n).map(|i| format!("FixRotation_{}", i)).collect();
+    let mut objects = HashMap::new();
+    let mut object_names = HashMap::new();
+    let mut parameters = HashMap::new();
+    objects.insert("Base", to_placement([0.0; 6]));
+    object_names.insert("Lock_0", [("Object", "Base")].iter().cloned().collect());
+    parameters.insert("Lock_0", to_placement([0.0; 6]));
+    for i in 0..n {
+        let k = i as f64;
+        objects.insert(names[i].as_str(), to_placement([k, 0.5 * k, -k, 0.3, 0.1, -0.3]));
+        let fix = [("x", 1.0), ("y", 2.0), ("z", 0.5)];
+        let rotation = [("phi", 0.2), ("theta", 0.0), ("psi", 0.0)];
+        for (name, offsets) in [(&fixes[i], fix), (&rotations[i], rotation)] {
+            let objects = [("Object", names[i].as_str()), ("Reference", "Base")];
+            object_names.insert(name.as_str(), objects.iter().cloned().collect());
+            parameters.insert(name.as_str(), offsets.iter().cloned().collect());
+        }
+    }
+    let mut system = System::new();
+    build_constraints(&mut system, &objects, &object_names, &parameters).unwrap();
+    system
+}
+
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_constraints_give_the_same_solution_with_any_number_of_threads() {
+    let solve = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let mut system = star(33);
+        assert!(system.parallel_constraints());
+        assert!(pool.install(|| system.solve(&SolverOptions::new())).success, "{} threads", threads);
+        solution(&system)
+    };
+    let expected = solve(1);
+    for threads in [2, 4] {
+        for _ in 0..3 {
+            assert_eq!(solve(threads), expected, "{} threads", threads);
+        }
+    }
+}
+
+
+#[test]
+fn opposite_equalities_mirror_the_variables() {
+    let pair = |equality: &[(&'static str, f64)]| {
+        Fixture::new()
+            .object("A", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+            .object("B", [0.5, -1.0, 2.0, 0.0, 0.1, -0.2])
+            .constraint("Equality_1", &[("Object1", "A"), ("Object2", "B")], equality)
+    };
+    for penalty in [0.0, 1.0] {
+        let equality = [("x", -1.0), ("phi=psi", -1.0), ("penalty", penalty)];
+        let cases = [
+            // the variables of A are driven by a fix or locked (a lock on the
+            // variables of B is split by the caller, see build_constraints)
+            pair(&equality).constraint("FixBase_1", &[("Object", "A")], &[("x", 2.0), ("y", 0.0), ("z", 0.0)]),
+            pair(&equality).constraint("Lock_1", &[("Object", "A")], &[("x", 2.0), ("phi", 0.4)]),
+        ];
+        for (i, fixture) in cases.iter().enumerate() {
+            let mut system = fixture.system();
+            assert!(system.solve(&SolverOptions::accurate()).success, "{} {}", penalty, i);
+            let a = placement_of(&system, "A");
+            let b = placement_of(&system, "B");
+            assert!((a[0] - 2.0).abs() < 1e-8 && (b[0] + 2.0).abs() < 1e-8, "{} {}: {:?} {:?}", penalty, i, a, b);
+            assert!((a[3] + b[5]).abs() < 1e-8, "{} {}: {:?} {:?}", penalty, i, a, b);
+            if i == 1 {
+                assert!((a[3] - 0.4).abs() < 1e-8, "{} {}: {:?}", penalty, i, a);
+            }
+        }
+    }
+
+    // the derivatives of a constraint on the mirrored variables take the sign
+    // of the link
+    let mut system = pair(&[("x", -1.0), ("y", -1.0)])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[("x", 2.0), ("y", 1.0), ("z", 0.5)])
+        .system();
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    assert_eq!(system.sys_objects[1].get_variable(VN::x).scale, -1.0);
+    let constraint = system.constraints
+        .iter_mut()
+        .find_map(|constraint| match constraint {
+            ConstraintType::FixBaseConstraint(constraint) => Some(constraint),
+            _ => None,
+        })
+        .unwrap();
+    let report = verify_constraint_derivatives(constraint.as_mut(), &system.sys_objects, 1e-6);
+    assert!(report.passed(), "{:?}", report.mismatches);
+    // the placement of A (shared with x and y of B) and z of B
+    assert_eq!(report.checked_variables, 7);
+}
+
+
+/// The distance of a point from the plane through three points
+fn plane_distance(p: [f64; 6], p1: [f64; 6], p2: [f64; 6], p3: [f64; 6]) -> f64 {
+    let u = [p2[0] - p1[0], p2[1] - p1[1], p2[2] - p1[2]];
+    let v = [p3[0] - p1[0], p3[1] - p1[1], p3[2] - p1[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let norm = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    (0..3).map(|k| n[k] * (p[k] - p1[k])).sum::<f64>() / norm
+}
+
+
+#[test]
+fn coplanar_objects_share_a_plane() {
+    let points = [
+        [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+        [1.0, 1.0, 0.5], [2.0, -1.0, -0.3], [0.5, 3.0, 1.0],
+    ];
+    let names = ["P1", "P2", "P3", "P4", "P5", "P6"];
+    let locks = ["Lock_1", "Lock_2", "Lock_3"];
+    let roles = ["Object1", "Object2", "Object3", "Object4", "Object5", "Object6"];
+    let coplanar = |n: usize, locked: bool| {
+        let mut fixture = Fixture::new();
+        for (name, point) in names.iter().zip(points.iter()).take(n) {
+            fixture = fixture.object(name, [point[0], point[1], point[2], 0.1, 0.2, 0.3]);
+        }
+        if locked {
+            for ((lock, name), point) in locks.iter().zip(names.iter()).zip(points.iter()) {
+                fixture = fixture.constraint(lock, &[("Object", name)], &[
+                    ("x", point[0]), ("y", point[1]), ("z", point[2]),
+                ]);
+            }
+        }
+        let objects: Vec<(&str, &str)> = roles.iter().cloned().zip(names.iter().cloned()).take(n).collect();
+        fixture.constraint("Coplanar_1", &objects, &[])
+    };
+
+    for n in [4, 6] {
+        for locked in [false, true] {
+            let mut system = coplanar(n, locked).system();
+            assert!(system.solve(&SolverOptions::accurate()).success, "{} {}", n, locked);
+            let p: Vec<[f64; 6]> = names[..n].iter().map(|name| placement_of(&system, name)).collect();
+            for (name, point) in names.iter().zip(p.iter()).skip(3) {
+                let distance = plane_distance(*point, p[0], p[1], p[2]);
+                assert!(distance.abs() < 1e-6, "{} {} {}: {}", n, locked, name, distance);
+                if locked {
+                    assert!(point[2].abs() < 1e-6, "{} {}: {:?}", n, name, point);
+                }
+            }
+        }
+
+        let mut system = coplanar(n, false).system();
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::CoplanarConstraint(constraint) => {
+                verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{}: {:?}", n, report.mismatches);
+        assert_eq!(report.checked_variables, 3 * n);
+    }
+
+    assert_eq!(
+        build_error(&coplanar(3, false)),
+        "constraint 'Coplanar_1' needs at least four objects but has 3",
+    );
+    let mut fixture = coplanar(4, false);
+    let names = fixture.names.get_mut("Coplanar_1").unwrap();
+    names.remove("Object4");
+    names.insert("Object5", "P4");
+    assert_eq!(build_error(&fixture), "constraint 'Coplanar_1' is missing its 'Object4' entry");
+}
+
+
+/// A point "P" at the given distances from locked anchors
+fn trilateration(anchors: &[[f64; 3]], distances: &[f64]) -> Fixture {
+    let names = ["A1", "A2", "A3", "A4", "A5", "A6"];
+    let locks = ["Lock_1", "Lock_2", "Lock_3", "Lock_4", "Lock_5", "Lock_6"];
+    let constraints = ["Distance_1", "Distance_2", "Distance_3", "Distance_4", "Distance_5", "Distance_6"];
+    let mut fixture = Fixture::new().object("P", [0.5, 0.5, 0.5, 0.0, 0.0, 0.0]);
+    for (k, (anchor, d)) in anchors.iter().zip(distances.iter()).enumerate() {
+        fixture = fixture
+            .object(names[k], [anchor[0], anchor[1], anchor[2], 0.0, 0.0, 0.0])
+            .constraint(locks[k], &[("Object", names[k])], &[("x", anchor[0]), ("y", anchor[1]), ("z", anchor[2])])
+            .constraint(constraints[k], &[("Object", "P"), ("Reference", names[k])], &[("d", *d)]);
+    }
+    fixture
+}
+
+
+#[test]
+fn gauss_newton_solves_overdetermined_systems() {
+    let target = [1.0, 2.0, 3.0];
+    let anchors = [
+        [0.0, 0.0, 0.0], [4.0, 0.0, 0.0], [0.0, 5.0, 0.0],
+        [0.0, 0.0, 6.0], [3.0, 3.0, 3.0], [-2.0, 1.0, 4.0],
+    ];
+    let distance = |anchor: &[f64; 3]| (0..3).map(|k| (target[k] - anchor[k]).powi(2)).sum::<f64>().sqrt();
+    let exact: Vec<f64> = anchors.iter().map(distance).collect();
+    let noisy: Vec<f64> = exact.iter().zip([0.01, -0.02, 0.015, -0.01, 0.02, -0.005]).map(|(d, e)| d + e).collect();
+    let solve = |fixture: &Fixture, method| {
+        let mut system = fixture.system();
+        let options = SolverOptions {
+            method,
+            ..SolverOptions::accurate()
+        };
+        let success = system.solve(&options).success;
+        (success, placement_of(&system, "P"))
+    };
+
+    // six equations for the three variables of P
+    let (success, p) = solve(&trilateration(&anchors, &exact), Method::GaussNewton);
+    assert!(success);
+    assert!((0..3).all(|k| (p[k] - target[k]).abs() < 1e-8), "{:?}", p);
+
+    // without an exact solution, every method reaches the same least-squares
+    // minimum (the solves don't report success, since the distances are not
+    // reached)
+    let fixture = trilateration(&anchors, &noisy);
+    let (_, expected) = solve(&fixture, Method::GaussNewton);
+    for method in [Method::TrustNCG, Method::LevenbergMarquardt] {
+        let (_, p) = solve(&fixture, method);
+        assert!(placement_distance(p, expected) < 1e-6, "{:?}: {:?} {:?}", method, p, expected);
+    }
+
+    // a single distance leaves P on a sphere, and the rank deficient jacobian
+    // is handed over to Levenberg-Marquardt
+    let (success, p) = solve(&trilateration(&anchors[..1], &exact[..1]), Method::GaussNewton);
+    assert!(success);
+    let reached = (p[0].powi(2) + p[1].powi(2) + p[2].powi(2)).sqrt();
+    assert!((reached - exact[0]).abs() < 1e-8, "{:?}", p);
+}
+
+
+/// The global position of a point given in the local coordinate system of an
+/// object with the placement p
+fn global_point(p: [f64; 6], local: [f64; 3]) -> [f64; 3] {
+    let rotation = rotation_matrix(p[3], p[4], p[5]);
+    let mut point = [p[0], p[1], p[2]];
+    for (i, value) in point.iter_mut().enumerate() {
+        *value += (0..3).map(|j| rotation[[i, j]] * local[j]).sum::<f64>();
+    }
+    point
+}
+
+
+#[test]
+fn attachment_points_are_joined() {
+    let a = [1.0, 2.0, 3.0, 0.5, 0.2, 0.1];
+    let hole = [0.5, -0.25, 1.0];
+    let pin = [0.2, 0.3, -0.4];
+    let attached = |constraint, objects: &[(&'static str, &'static str)], parameters: &[(&'static str, f64)]| {
+        let mut fixture = Fixture::new()
+            .object("A", a)
+            .object("B", [3.0, -1.0, 0.5, 0.0, 0.3, -0.2])
+            .constraint("Lock_1", &[("Object", "A")], &[
+                ("x", a[0]), ("y", a[1]), ("z", a[2]), ("phi", a[3]), ("theta", a[4]), ("psi", a[5]),
+            ])
+            .constraint(constraint, objects, parameters);
+        let attachments = [("A", ["Hole.x", "Hole.y", "Hole.z"], hole), ("B", ["Pin.x", "Pin.y", "Pin.z"], pin)];
+        for (object, keys, point) in attachments {
+            fixture.objects.get_mut(object).unwrap().extend(keys.iter().cloned().zip(point));
+        }
+        fixture
+    };
+
+    // the points are offsets from the attachment points
+    for (point1, point2) in [([0.0; 3], [0.0; 3]), ([0.1, 0.0, -0.2], [0.0, 0.3, 0.0])].iter() {
+        let parameters = [
+            ("x1", point1[0]), ("y1", point1[1]), ("z1", point1[2]),
+            ("x2", point2[0]), ("y2", point2[1]), ("z2", point2[2]),
+        ];
+        let fixture = attached("PointCoincident_1", &[
+            ("Object1", "A"), ("Attachment1", "Hole"), ("Object2", "B"), ("Attachment2", "Pin"),
+        ], &parameters);
+        let mut system = fixture.system();
+        assert!(system.solve(&SolverOptions::accurate()).success);
+        let offset = |attachment: [f64; 3], point: &[f64; 3]| [0, 1, 2].map(|k| attachment[k] + point[k]);
+        let global1 = global_point(placement_of(&system, "A"), offset(hole, point1));
+        let global2 = global_point(placement_of(&system, "B"), offset(pin, point2));
+        assert!((0..3).all(|k| (global1[k] - global2[k]).abs() < 1e-6), "{:?} {:?}", global1, global2);
+        if *point1 == [0.0; 3] {
+            let hole = system.sys_objects[system.sys_objects_idx["A"]].world_attachment_point("Hole");
+            assert!((hole.x.re - global1[0]).abs() < 1e-9 && (hole.z.re - global1[2]).abs() < 1e-9);
+        }
+    }
+
+    // the axes of coaxial objects go through the attachment points
+    let axes = [
+        ("Coaxial_1", [
+            ("Object", "B"), ("ObjectAttachment", "Pin"), ("Reference", "A"), ("ReferenceAttachment", "Hole"),
+        ]),
+        ("AxisCoincident_1", [("Object1", "B"), ("Attachment1", "Pin"), ("Object2", "A"), ("Attachment2", "Hole")]),
+    ];
+    for (name, objects) in axes.iter() {
+        let mut system = attached(name, objects, &[]).system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{}", name);
+        let a = placement_of(&system, "A");
+        let b = placement_of(&system, "B");
+        let axis = rotation_matrix(a[3], a[4], a[5]).column(2).to_owned();
+        let b_axis = rotation_matrix(b[3], b[4], b[5]).column(2).to_owned();
+        let hole = global_point(a, hole);
+        let pin = global_point(b, pin);
+        let d = [pin[0] - hole[0], pin[1] - hole[1], pin[2] - hole[2]];
+        // the pin is on the axis of the hole, and both axes are parallel
+        let along = (0..3).map(|k| d[k] * axis[k]).sum::<f64>();
+        assert!((0..3).all(|k| (d[k] - along * axis[k]).abs() < 1e-6), "{}: {:?}", name, d);
+        assert!((axis.dot(&b_axis).abs() - 1.0).abs() < 1e-9, "{}: {} {}", name, axis, b_axis);
+    }
+
+    assert_eq!(
+        build_error(&attached("PointCoincident_1", &[("Object1", "A"), ("Attachment1", "Pin"), ("Object2", "B")], &[])),
+        "constraint 'PointCoincident_1' references attachment point 'Pin' which object 'A' does not have",
+    );
+}
+
+
+#[test]
+fn dirty_constraints_use_the_changed_objects() {
+    let system = chain(4, 0.3).system();
+    let index = |name: &str| system.sys_objects_idx[name];
+    let names = |dirty: Vec<usize>| -> Vec<&str> {
+        dirty.into_iter().map(|i| system.constraints[i].get_name()).collect()
+    };
+    // P2 is the object of the constraints "_2" and the reference of the
+    // constraints "_3"
+    let expected: Vec<&str> = constraint_names(&system)
+        .into_iter()
+        .filter(|name| name.ends_with("_2") || name.ends_with("_3"))
+        .collect();
+    let mut dirty = names(system.dirty_constraints(&[index("P2")]));
+    dirty.sort_unstable();
+    assert_eq!(dirty, expected);
+    let all: Vec<usize> = (0..system.sys_objects.len()).collect();
+    assert_eq!(system.dirty_constraints(&all), (0..system.constraints.len()).collect::<Vec<_>>());
+    assert!(system.dirty_constraints(&[]).is_empty());
+
+    // out of range indices are skipped
+    assert_eq!(
+        system.dirty_constraints(&[index("P2"), system.sys_objects.len(), usize::MAX]),
+        system.dirty_constraints(&[index("P2")]),
+    );
+}
+
+
+/// A rack moving 2 along x for each radian the pinion turns around z
+fn rack_and_pinion(locks: &[(&'static str, f64)]) -> Fixture {
+    Fixture::new()
+        .object("Rack", [0.3, 1.0, 0.0, 0.0, 0.0, 0.0])
+        .object("Pinion", [0.0, 0.0, 0.0, 0.0, 0.0, 0.2])
+        .constraint("Lock_1", &[("Object", "Pinion")], locks)
+        .constraint("Linear_1", &[("Object1", "Rack"), ("Object2", "Pinion")], &[
+            ("Object1.x", 1.0), ("Object2.psi", -2.0), ("rhs", 0.5),
+        ])
+}
+
+
+#[test]
+fn linear_combinations_are_kept() {
+    // the locked pinion drives the rack, and a free pinion turns to meet it
+    for locks in [&[("psi", 0.4)][..], &[("x", 0.0)]] {
+        let mut system = rack_and_pinion(locks).system();
+        assert!(system.solve(&SolverOptions::accurate()).success, "{:?}", locks);
+        let rack = placement_of(&system, "Rack");
+        let pinion = placement_of(&system, "Pinion");
+        assert!((rack[0] - 2.0 * pinion[5] - 0.5).abs() < 1e-8, "{:?}: {:?} {:?}", locks, rack, pinion);
+        if locks[0].0 == "psi" {
+            assert!((rack[0] - 1.3).abs() < 1e-8, "{:?}", rack);
+        }
+        // the other variables are not used
+        assert_eq!(rack[1], 1.0);
+    }
+
+    // the hessian is constant, and the term of a variable made equal with
+    // opposite sign takes the sign of the link
+    let fixture = rack_and_pinion(&[("x", 0.0)])
+        .object("Gear", [0.0, 0.0, 0.0, 0.0, 0.0, 0.7])
+        .constraint("Equality_1", &[("Object1", "Gear"), ("Object2", "Pinion")], &[("psi", -1.0)]);
+    let mut system = fixture.system();
+    assert!(system.solve(&SolverOptions::accurate()).success);
+    let gear = placement_of(&system, "Gear");
+    assert!((placement_of(&system, "Rack")[0] + 2.0 * gear[5] - 0.5).abs() < 1e-8);
+    system.update_indices();
+    let x = system.get_position();
+    system.update_x(&x);
+    assert_eq!(system.sys_objects[system.sys_objects_idx["Pinion"]].get_variable(VN::psi).scale, -1.0);
+    let constraint = system.constraints
+        .iter_mut()
+        .find_map(|constraint| match constraint {
+            ConstraintType::LinearConstraint(constraint) => Some(constraint),
+            _ => None,
+        })
+        .unwrap();
+    let report = verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6);
+    assert!(report.passed(), "{:?}", report.mismatches);
+    // x of the rack and psi of the gear (shared with the pinion)
+    assert_eq!(report.checked_variables, 2);
+
+    let mut fixture = rack_and_pinion(&[]);
+    fixture.parameters.get_mut("Linear_1").unwrap().insert("Object3.x", 1.0);
+    assert_eq!(build_error(&fixture), "constraint 'Linear_1' is missing its 'Object3' entry");
+    let mut fixture = rack_and_pinion(&[]);
+    fixture.parameters.get_mut("Linear_1").unwrap().insert("Object1.w", 1.0);
+    assert_eq!(
+        build_error(&fixture),
+        "constraint 'Linear_1' has the parameter 'Object1.w' of the unknown variable 'w'",
+    );
+}
+
+
+#[test]
+fn fixed_positions_use_the_global_frame() {
+    let start = [1.0, 2.0, 3.0, 0.1, 0.2, 0.3];
+    let target = [("x", 4.0), ("y", -1.0)];
+    let fixed = |objects: &[(&'static str, &'static str)]| {
+        Fixture::new()
+            .object("A", start)
+            .object("B", [0.0; 6])
+            .constraint("FixedPosition_1", objects, &target)
+    };
+    for objects in [&[("Object", "A")][..], &[("Object", "A"), ("Reference", "Origin")]] {
+        let mut system = fixed(objects).system();
+        assert!(matches!(system.constraints[0], ConstraintType::FixedPositionConstraint(_)));
+        assert!(system.solve(&SolverOptions::accurate()).success, "{:?}", objects);
+        // the missing z is 0 and the rotation is free, so it is unchanged
+        let a = placement_of(&system, "A");
+        assert!(placement_distance(a, [4.0, -1.0, 0.0, 0.1, 0.2, 0.3]) < 1e-8, "{:?}: {:?}", objects, a);
+
+        system.update_indices();
+        let x = system.get_position();
+        system.update_x(&x);
+        let report = match &mut system.constraints[0] {
+            ConstraintType::FixedPositionConstraint(constraint) => {
+                verify_constraint_derivatives(constraint, &system.sys_objects, 1e-6)
+            }
+            _ => unreachable!(),
+        };
+        assert!(report.passed(), "{:?}", report.mismatches);
+        assert_eq!(report.checked_variables, 3);
+    }
+
+    assert_eq!(
+        build_error(&fixed(&[("Object", "A"), ("Reference", "B")])),
+        "constraint 'FixedPosition_1' fixes a global position but has the reference 'B'",
+    );
+}
+
+
+#[test]
+fn custom_types_replace_the_built_in_constraints() {
+    // "GearRatio_1" contains the built-in type "Ratio"
+    let fixture = Fixture::new()
+        .object("A", [0.0; 6])
+        .object("B", [0.5, 1.0, 2.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_1", &[("Object", "A")], &[("x", 0.0)])
+        .constraint("GearRatio_1", &[("Object1", "A"), ("Object2", "B")], &[("length", 2.0)]);
+    let mut system = System::new();
+    system.register_constraint("GearRatio", spring_factory).unwrap();
+    build_constraints(&mut system, &fixture.objects, &fixture.names, &fixture.parameters).unwrap();
+    assert_eq!(system.constraints.len(), 1);
+    assert!(matches!(system.constraints[0], ConstraintType::Custom(_)));
+    assert!(system.solve(&SolverOptions::new()).success);
+    assert!((placement_of(&system, "B")[0] - 2.0).abs() < 1e-6);
+
+    let mut system = System::new();
+    assert_eq!(
+        system.register_constraint("Gear_Ratio", spring_factory),
+        Err("'Gear_Ratio' is not a constraint type name".to_string()),
+    );
+    assert!(system.register_constraint("", spring_factory).is_err());
+    assert_eq!(
+        system.register_constraint("Ratio", spring_factory),
+        Err("'Ratio' is a built-in constraint type".to_string()),
+    );
+    assert!(system.factories.is_empty());
+}
+
+
+#[test]
+fn built_constraints_make_plans_stale() {
+    let options = SolverOptions::new();
+    let fixture = chain(4, 0.3);
+    let mut system = fixture.system();
+    let plan = system.analyze(&options);
+
+    // a constraint between objects of the system
+    let objects = [("Object", "P3"), ("Reference", "P1")];
+    let added = Fixture { objects: fixture.objects.clone(), ..Fixture::new() }
+        .constraint("Distance_9", &objects, &[("d", 3.0)]);
+    build_constraints(&mut system, &added.objects, &added.names, &added.parameters).unwrap();
+    assert_eq!(system.sys_objects.len(), 5);
+    assert!(!system.is_current(&plan));
+    let plan = system.analyze(&options);
+    assert!(system.is_current(&plan));
+
+    // a lock of an object of the system
+    let locked = Fixture { objects: fixture.objects.clone(), ..Fixture::new() }
+        .constraint("Lock_9", &[("Object", "P2")], &[("z", 0.0)]);
+    build_constraints(&mut system, &locked.objects, &locked.names, &locked.parameters).unwrap();
+    assert!(!system.is_current(&plan));
+}
+
@@ -0,0 +1,109 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+use crate::constraints::ConstraintType;
+use crate::system::System;
+use crate::system_object::VariableName as VN;
+use crate::SolverError;
+
+
+impl System {
+    /// Checks the structure of the system before it is solved and returns every
+    /// problem found:
+    /// - constraints that use objects that are not in the system
+    /// - equalities that form a cycle (see equality_order)
+    /// - FixBase constraints that fix locked position variables of the object
+    ///   to a reference with all its variables locked, so the locks alone
+    ///   decide those axes
+    /// - FixBase constraints whose reference has no rotation variable enabled
+    ///
+    /// These systems would otherwise fail later with a panic (for example when
+    /// the indices are added) or with a singular hessian.
+    pub fn validate(&self) -> Result<(), Vec<SolverError>> {
+        let mut errors = Vec::new();
+        let n = self.sys_objects.len();
+
+        for constraint in &self.constraints {
+            let name = constraint.get_name();
+            let objects = constraint.affected_objects();
+            let missing: Vec<usize> = objects
+                .iter()
+                .copied()
+                .chain(constraint.get_variables().into_iter().map(|(obj_idx, _)| obj_idx))
+                .filter(|obj_idx| *obj_idx >= n)
+                .collect();
+            if let Some(obj_idx) = missing.first() {
+                errors.push(SolverError::new(format!(
+                    "constraint '{}' uses the object with index {}, which is not in the system", name, obj_idx,
+                )));
+                continue;
+            }
+
+            let unweighted = match constraint {
+                ConstraintType::WeightedConstraint(weighted) => weighted.inner(),
+                _ => constraint,
+            };
+            if let ConstraintType::FixBaseConstraint(_) = unweighted {
+                let (object, reference) = (&self.sys_objects[objects[0]], &self.sys_objects[objects[1]]);
+                let reference_locked = reference.get_variables_iter().all(|variable| variable.locked);
+                let locked: Vec<&str> = VN::get_position_iter()
+                    .filter(|var_name| object.get_variable(*var_name).locked)
+                    .map(|var_name| var_name.as_str())
+                    .collect();
+                if reference_locked && !locked.is_empty() {
+                    errors.push(SolverError::new(format!(
+                        "constraint '{}' fixes the locked variables {:?} of object '{}' to the locked object '{}'",
+                        name, locked, self.get_object_name(objects[0]), self.get_object_name(objects[1]),
+                    )));
+                }
+                if VN::get_rotation_iter().all(|var_name| !reference.get_variable(var_name).enabled) {
+                    errors.push(SolverError::new(format!(
+                        "the reference '{}' of constraint '{}' has no rotation variable enabled",
+                        self.get_object_name(objects[1]), name,
+                    )));
+                }
+            }
+        }
+
+        // equal variables must point to objects of the system before their
+        // chains can be followed
+        let mut equalities_valid = true;
+        for (obj_idx, object) in self.sys_objects.iter().enumerate() {
+            for (var_name, variable) in VN::get_variable_iter().zip(object.get_variables_iter()) {
+                if let Some((equal_idx, _, _)) = variable.equal {
+                    if equal_idx >= n {
+                        equalities_valid = false;
+                        errors.push(SolverError::new(format!(
+                            "variable '{}' of object '{}' is equal to a variable of the object with index {}, which is not in the system",
+                            var_name.as_str(), self.get_object_name(obj_idx), equal_idx,
+                        )));
+                    }
+                }
+            }
+        }
+        if equalities_valid {
+            if let Err(message) = self.equality_order() {
+                errors.push(SolverError::new(message));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -16,9 +16,11 @@
 use std::ops::{Index, IndexMut};
 use std::collections::HashMap;
 
+use log::warn;
+
 use optimization::geometry::{HDVector, HDQuaternion};
 use crate::system::Variable;
-use crate::geometry::{Quaternion, Vector};
+use crate::geometry::{constant_vector, Quaternion, Vector};
 
 
 /// Represents an object in the constraint system.
@@ -26,29 +28,52 @@ use crate::geometry::{Quaternion, Vector};
 /// A SystemObject contains all data regarding the placement of an object in 3D space
 /// including the variables used to represent the object. An object will be used by
 /// constraint functions.
-#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Debug, Clone)]
 pub struct SystemObject{
     // Stores the actual variables that represent the position and rotation of this
     // object in 3D space.
     vars: ObjectVariables,
     /// This field stores the quaternion rotation information about this object.
     /// The quaternion information is used by constraints functions in other to
-    /// calculate the error.
+    /// calculate the error. The quaternions and the position vector are not
+    /// saved by a SystemSnapshot; they are computed again from the variables.
+    #[cfg_attr(feature = "serde", serde(skip, default = "Quaternion::new"))]
     q_vals: Quaternion,
-    /// When enabled, it means that q_vals will be updated at each iteration. If
-    /// disabled, then q_vals will not be updated
+    /// Inverses of the quaternions in q_vals. They are computed once each time
+    /// q_vals is updated and shared by all the constraints using this object
+    /// as a reference.
+    #[cfg_attr(feature = "serde", serde(skip, default = "Quaternion::new"))]
+    q_inv: Quaternion,
+    /// When enabled, it means that q_vals (and q_inv) will be updated at each
+    /// iteration. If disabled, then q_vals will not be updated
     pub q_enable: bool,
     /// This field stores the position vector information about this object.
     /// The vector contains the partial derivatives with respect to the variables
     /// x, y, and z of this object.
+    #[cfg_attr(feature = "serde", serde(skip, default = "Vector::new"))]
     v_vals: Vector,
     /// When enabled, it means that v_vals will be updated at each iteration.
     pub v_enable: bool,
+    /// Named attachment points of the object, given by their offsets in the
+    /// local coordinate system of the object. Constraints can reference them by
+    /// name instead of repeating the coordinates in their parameters.
+    #[cfg_attr(feature = "serde", serde(default))]
+    attachments: HashMap<String, [f64; 3]>,
 }
 
 
 /// Stores the 6 variables of an object
-#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Debug, Clone)]
 pub struct ObjectVariables {
     /// This variable represents the global x-axis position of this object
     pub x: Variable,
@@ -65,10 +90,29 @@ pub struct ObjectVariables {
 }
 
 
+/// Splits a key of the placement map of an object that gives a coordinate of
+/// an attachment point ("<name>.x", "<name>.y" or "<name>.z") into the name
+/// of the attachment and the index of the coordinate. Returns None for any
+/// other key.
+pub fn attachment_key(key: &str) -> Option<(&str, usize)> {
+    let (name, coordinate) = key.rsplit_once('.')?;
+    let index = ["x", "y", "z"].iter().position(|c| *c == coordinate)?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, index))
+}
+
+
 /// Object variable indices. This enum represents the indices of a variable
 /// inside an Object.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum VariableName {
     x,
     y,
@@ -84,9 +128,11 @@ impl SystemObject {
         SystemObject {
             vars: ObjectVariables::new(),
             q_vals: Quaternion::new(),
+            q_inv: Quaternion::new(),
             q_enable: false,
             v_vals: Vector::new(),
             v_enable: false,
+            attachments: HashMap::new(),
         }
     }
 
@@ -105,9 +151,13 @@ impl SystemObject {
     /// from a constraint parameters hashmap where the keys are the enabled variables
     /// so this function helps to enable the variables from a hashmap in order to avoid
     /// repeating code.
+    ///
+    /// Other parameters of the constraint (like a "weight") are skipped.
     pub fn enable_variables_from_params(&mut self, c_params: &HashMap<&str, f64>) {
-        for variable in c_params.keys() {
-            self.vars.get_mut_variable(variable).enabled = true;
+        for var_name in VariableName::get_variable_iter() {
+            if c_params.contains_key(var_name.as_str()) {
+                self.vars[var_name].enabled = true;
+            }
         }
     }
 
@@ -126,8 +176,9 @@ impl SystemObject {
     ///
     /// equal_indices: contains all the necessary information to add the equality
     /// indices to the variables in the object. It contains the variable names and the
-    /// equality indices (equality indices are of the form of (usize, VariableName))
-    pub fn add_equal_indices(&mut self, equal_indices: &[(&str, (usize, VariableName))]) {
+    /// equality indices (equality indices are of the form of (usize, VariableName,
+    /// factor), where the variable is factor times the variable it is equal to)
+    pub fn add_equal_indices(&mut self, equal_indices: &[(&str, (usize, VariableName, f64))]) {
         for (variable, indices) in equal_indices {
             self.vars.get_mut_variable(variable).equal = Some(*indices);
         }
@@ -138,6 +189,13 @@ impl SystemObject {
     /// NOTE: call this function after updating the object variables!
     pub fn update_q(&mut self) {
         self.q_vals.evaluate_quaternion(&self.vars.phi, &self.vars.theta, &self.vars.psi);
+        if let Some(norm) = self.q_vals.normalize() {
+            warn!(
+                "rotation quaternion with norm {} was normalized (phi: {}, theta: {}, psi: {})",
+                norm, self.vars.phi.value, self.vars.theta.value, self.vars.psi.value,
+            );
+        }
+        self.q_inv = self.q_vals.inverse();
     }
 
     /// updates the position vector of the object
@@ -186,56 +244,55 @@ impl SystemObject {
     /// x, y, z, phi, theta, psi. And the returning quaternion will contain the
     /// partial derivatives with respect of these two variables
     pub fn get_quaternion(&self, var1: Option<VariableName>, var2: Option<VariableName>) -> HDQuaternion {
-        match var1 {
-            Some(VariableName::phi) => match var2 {
-                Some(VariableName::phi) => self.q_vals.get_phi_phi(),
-                Some(VariableName::theta) => self.q_vals.get_phi_theta(),
-                Some(VariableName::psi) => self.q_vals.get_phi_psi(),
-                None | Some(_) => self.q_vals.get_phi_const(),
-            },
-            Some(VariableName::theta) => match var2 {
-                Some(VariableName::phi) => self.q_vals.get_theta_phi(),
-                Some(VariableName::theta) => self.q_vals.get_theta_theta(),
-                Some(VariableName::psi) => self.q_vals.get_theta_psi(),
-                None | Some(_) => self.q_vals.get_theta_const(),
-            },
-            Some(VariableName::psi) => match var2 {
-                Some(VariableName::phi) => self.q_vals.get_psi_phi(),
-                Some(VariableName::theta) => self.q_vals.get_psi_theta(),
-                Some(VariableName::psi) => self.q_vals.get_psi_psi(),
-                None | Some(_) => self.q_vals.get_psi_const(),
-            }
-            None | Some(_) => match var2 {
-                Some(VariableName::phi) => self.q_vals.get_const_phi(),
-                Some(VariableName::theta) => self.q_vals.get_const_theta(),
-                Some(VariableName::psi) => self.q_vals.get_const_psi(),
-                None | Some(_) => self.q_vals.get_const_const(),
-            }
-        }
+        quaternion_block(&self.q_vals, var1, var2)
+    }
+
+    /// Returns the inverse of the rotation quaternion with the given enabled
+    /// variables (see get_quaternion). The inverses are cached, so this is
+    /// cheaper than inverting the quaternion returned by get_quaternion.
+    pub fn get_inverse_quaternion(
+            &self,
+            var1: Option<VariableName>,
+            var2: Option<VariableName>,
+    ) -> HDQuaternion {
+        quaternion_block(&self.q_inv, var1, var2)
+    }
+
+    /// Adds (or replaces) a named attachment point with the given offset in
+    /// the local coordinate system of the object
+    pub fn add_attachment(&mut self, name: &str, offset: [f64; 3]) {
+        self.attachments.insert(name.to_string(), offset);
+    }
+
+    /// Returns the offset of a named attachment point in the local coordinate
+    /// system of the object, or None if the object has no attachment with
+    /// that name
+    pub fn get_attachment(&self, name: &str) -> Option<[f64; 3]> {
+        self.attachments.get(name).copied()
+    }
+
+    /// Returns the position of a named attachment point in the global
+    /// coordinate system: its offset rotated by the quaternion of the object
+    /// plus the position vector of the object. The vector has no partial
+    /// derivatives; the residuals of a constraint get them from the offset
+    /// (see get_attachment) and the placement of the object.
+    ///
+    /// The position vector and the quaternion must be already updated, and it
+    /// panics if the object has no attachment with that name.
+    pub fn world_attachment_point(&self, name: &str) -> HDVector {
+        let offset = self.attachments[name];
+        let local = constant_vector(offset[0], offset[1], offset[2]);
+        self.get_vector(None, None) + self.get_quaternion(None, None).mul_vec(&local)
     }
 
     /// Returns a reference to a variable of this object by name
     pub fn get_variable(&self, var_name: VariableName) -> &Variable {
-        match var_name {
-            VariableName::x => &self.vars.x,
-            VariableName::y => &self.vars.y,
-            VariableName::z => &self.vars.z,
-            VariableName::phi => &self.vars.phi,
-            VariableName::theta => &self.vars.theta,
-            VariableName::psi => &self.vars.psi,
-        }
+        &self.vars[var_name]
     }
 
     /// Returns a mutable reference to a variable of this object by name
-    pub fn get_mut_variable(&mut self, var_name: VariableName) -> &mut  Variable {
-        match var_name {
-            VariableName::x => &mut self.vars.x,
-            VariableName::y => &mut self.vars.y,
-            VariableName::z => &mut self.vars.z,
-            VariableName::phi => &mut self.vars.phi,
-            VariableName::theta => &mut self.vars.theta,
-            VariableName::psi => &mut self.vars.psi,
-        }
+    pub fn get_mut_variable(&mut self, var_name: VariableName) -> &mut Variable {
+        &mut self.vars[var_name]
     }
 
     /// Gets an iterator containing the variables of this object
@@ -250,6 +307,45 @@ impl SystemObject {
 }
 
 
+impl Default for SystemObject {
+    fn default() -> SystemObject {
+        SystemObject::new()
+    }
+}
+
+
+/// Returns the quaternion of q with the partial derivatives with respect to the
+/// given variables
+fn quaternion_block(q: &Quaternion, var1: Option<VariableName>, var2: Option<VariableName>) -> HDQuaternion {
+    match var1 {
+        Some(VariableName::phi) => match var2 {
+            Some(VariableName::phi) => q.get_phi_phi(),
+            Some(VariableName::theta) => q.get_phi_theta(),
+            Some(VariableName::psi) => q.get_phi_psi(),
+            None | Some(_) => q.get_phi_const(),
+        },
+        Some(VariableName::theta) => match var2 {
+            Some(VariableName::phi) => q.get_theta_phi(),
+            Some(VariableName::theta) => q.get_theta_theta(),
+            Some(VariableName::psi) => q.get_theta_psi(),
+            None | Some(_) => q.get_theta_const(),
+        },
+        Some(VariableName::psi) => match var2 {
+            Some(VariableName::phi) => q.get_psi_phi(),
+            Some(VariableName::theta) => q.get_psi_theta(),
+            Some(VariableName::psi) => q.get_psi_psi(),
+            None | Some(_) => q.get_psi_const(),
+        }
+        None | Some(_) => match var2 {
+            Some(VariableName::phi) => q.get_const_phi(),
+            Some(VariableName::theta) => q.get_const_theta(),
+            Some(VariableName::psi) => q.get_const_psi(),
+            None | Some(_) => q.get_const_const(),
+        }
+    }
+}
+
+
 impl ObjectVariables {
     pub fn new() -> ObjectVariables {
         ObjectVariables {
@@ -276,20 +372,6 @@ impl ObjectVariables {
         }
     }
 
-    /// returns a reference to a variable by name
-    pub fn get_variable(&self, var_name: &str) -> &Variable {
-        match var_name {
-            "x" => &self.x,
-            "y" => &self.y,
-            "z" => &self.z,
-            "phi" => &self.phi,
-            "theta" => &self.theta,
-            "psi" => &self.psi,
-            // we should never call something else than the previous variable names!
-            _ => unreachable!(),
-        }
-    }
-
     /// returns a mutable reference to a variable by name
     pub fn get_mut_variable(&mut self, var_name: &str) -> &mut Variable {
         match var_name {
@@ -402,6 +484,31 @@ impl VariableName {
         }
     }
 
+    /// Returns the VariableName of a placement key, or None if the key is not
+    /// the name of a variable (for example the coordinate of an attachment
+    /// point, see attachment_key)
+    pub fn parse(variable: &str) -> Option<VariableName> {
+        VariableName::get_variable_iter().find(|var_name| var_name.as_str() == variable)
+    }
+
+    /// Returns the name of the variable as a str. This is the inverse of
+    /// get_from_str
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VariableName::x => "x",
+            VariableName::y => "y",
+            VariableName::z => "z",
+            VariableName::phi => "phi",
+            VariableName::theta => "theta",
+            VariableName::psi => "psi",
+        }
+    }
+
+    /// Returns true for the rotation variables (phi, theta, psi)
+    pub fn is_rotation(&self) -> bool {
+        matches!(self, VariableName::phi | VariableName::theta | VariableName::psi)
+    }
+
     /// Returns an iterator over all the different options of VariableName
     pub fn get_variable_iter() -> VariableNameIter {
         VariableNameIter {
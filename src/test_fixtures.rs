@@ -0,0 +1,476 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! Constraint systems shared by the tests. They are built from the same maps
+//! passed to solve_constraint_system from Python.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ndarray::Array1;
+
+use crate::build_constraints;
+use crate::constraints::{
+    Constraint, CustomConstraint, Footprint, HessianAccumulator, Residual, ResidualKind,
+};
+use crate::system::System;
+use crate::system_object::{SystemObject, VariableName as VN};
+use crate::SolverError;
+
+
+/// The input maps of a constraint system
+#[derive(Debug, Clone, Default)]
+pub struct Fixture {
+    pub objects: HashMap<&'static str, HashMap<&'static str, f64>>,
+    pub names: HashMap<&'static str, HashMap<&'static str, &'static str>>,
+    pub parameters: HashMap<&'static str, HashMap<&'static str, f64>>,
+}
+
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        Fixture::default()
+    }
+
+    /// Adds an object with its placement as [x, y, z, phi, theta, psi]
+    pub fn object(mut self, name: &'static str, placement: [f64; 6]) -> Fixture {
+        self.objects.insert(name, to_placement(placement));
+        self
+    }
+
+    /// Adds a constraint with its objects as (role, object name) and its
+    /// parameters as (name, value)
+    pub fn constraint(
+        mut self,
+        name: &'static str,
+        objects: &[(&'static str, &'static str)],
+        parameters: &[(&'static str, f64)],
+    ) -> Fixture {
+        self.names.insert(name, objects.iter().cloned().collect());
+        self.parameters.insert(name, parameters.iter().cloned().collect());
+        self
+    }
+
+    /// Builds the constraint system
+    pub fn try_system(&self) -> Result<System, SolverError> {
+        let mut system = System::new();
+        build_constraints(&mut system, &self.objects, &self.names, &self.parameters)?;
+        Ok(system)
+    }
+
+    /// Builds the constraint system. Panics if the input is not valid.
+    pub fn system(&self) -> System {
+        self.try_system().unwrap()
+    }
+}
+
+
+/// Returns a placement map from [x, y, z, phi, theta, psi]
+pub fn to_placement(placement: [f64; 6]) -> HashMap<&'static str, f64> {
+    VN::get_variable_iter()
+        .map(|var_name| var_name.as_str())
+        .zip(placement.iter().cloned())
+        .collect()
+}
+
+
+/// Returns the placement of an object of the system as [x, y, z, phi, theta,
+/// psi]
+pub fn placement_of(system: &System, name: &str) -> [f64; 6] {
+    let object = &system.sys_objects[system.sys_objects_idx[name]];
+    let mut placement = [0.0; 6];
+    for (value, var_name) in placement.iter_mut().zip(VN::get_variable_iter()) {
+        *value = object.get_variable(var_name).value;
+    }
+    placement
+}
+
+
+/// Returns the largest difference between the variables of two placements
+pub fn placement_distance(a: [f64; 6], b: [f64; 6]) -> f64 {
+    a.iter().zip(&b).fold(0.0, |max: f64, (a, b)| max.max((a - b).abs()))
+}
+
+
+/// Returns the largest difference between the placements of the objects of
+/// two systems with the same objects
+pub fn system_distance(a: &System, b: &System) -> f64 {
+    a.sys_objects_idx
+        .keys()
+        .map(|name| placement_distance(placement_of(a, name), placement_of(b, name)))
+        .fold(0.0, f64::max)
+}
+
+
+/// Two objects: "A" is locked and "B" is fixed at an offset of dx along x
+/// relative to "A" and with the same rotation
+pub fn two_part(dx: f64) -> Fixture {
+    Fixture::new()
+        .object("A", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .object("B", [1.0, 2.0, 3.0, 0.1, 0.2, 0.3])
+        .constraint("FixBase_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", dx), ("y", 1.0), ("z", 0.0),
+        ])
+        .constraint("FixRotation_1", &[("Object", "B"), ("Reference", "A")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("Lock_1", &[("Object", "A")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.5), ("theta", 0.0), ("psi", 0.0),
+        ])
+}
+
+
+/// A locked "Base" and a chain of n objects "P0", "P1"... (up to 8) where each
+/// object is fixed relative to the previous one, turned by 0.2 around its z
+/// axis. rotation sets how far the initial rotations are from the solution.
+pub fn chain(n: usize, rotation: f64) -> Fixture {
+    const OBJECTS: [&str; 8] = ["P0", "P1", "P2", "P3", "P4", "P5", "P6", "P7"];
+    const FIXES: [&str; 8] = [
+        "FixBase_0", "FixBase_1", "FixBase_2", "FixBase_3",
+        "FixBase_4", "FixBase_5", "FixBase_6", "FixBase_7",
+    ];
+    const ROTATIONS: [&str; 8] = [
+        "FixRotation_0", "FixRotation_1", "FixRotation_2", "FixRotation_3",
+        "FixRotation_4", "FixRotation_5", "FixRotation_6", "FixRotation_7",
+    ];
+    let mut fixture = Fixture::new()
+        .object("Base", [0.0; 6])
+        .constraint("Lock_0", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ]);
+    let mut previous = "Base";
+    for i in 0..n {
+        let k = i as f64;
+        fixture = fixture
+            .object(OBJECTS[i], [k, 0.5 * k, -k, rotation * k, 0.1 * k, -rotation * k])
+            .constraint(FIXES[i], &[("Object", OBJECTS[i]), ("Reference", previous)], &[
+                ("x", 1.0), ("y", 2.0), ("z", 0.5),
+            ])
+            .constraint(ROTATIONS[i], &[("Object", OBJECTS[i]), ("Reference", previous)], &[
+                ("phi", 0.2), ("theta", 0.0), ("psi", 0.0),
+            ]);
+        previous = OBJECTS[i];
+    }
+    fixture
+}
+
+
+/// A locked "Base", "A" fixed 1 away from it along x and "B" fixed 5 away
+/// from "A" along x, while a lock keeps the x of "B" at 3. The best compromise
+/// has "A" at x = -0.5 and both fixes off by 1.5.
+pub fn lock_fix_conflict() -> Fixture {
+    Fixture::new()
+        .object("Base", [0.0; 6])
+        .object("A", [1.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .object("B", [3.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+        .constraint("Lock_1", &[("Object", "Base")], &[
+            ("x", 0.0), ("y", 0.0), ("z", 0.0), ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("x", 1.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("FixRotation_1", &[("Object", "A"), ("Reference", "Base")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("FixBase_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("x", 5.0), ("y", 0.0), ("z", 0.0),
+        ])
+        .constraint("FixRotation_2", &[("Object", "B"), ("Reference", "A")], &[
+            ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+        ])
+        .constraint("Lock_2", &[("Object", "B")], &[("x", 3.0)])
+}
+
+
+/// The systems solved by the tests comparing solver methods and options
+pub fn corpus() -> Vec<(&'static str, Fixture)> {
+    vec![
+        ("two_part", two_part(5.0)),
+        ("chain", chain(4, 0.3)),
+        ("long_chain", chain(6, 0.4)),
+    ]
+}
+
+
+/// Four independent copies of two_part(dx) with the objects "A1", "B1"...
+/// "A4", "B4"
+pub fn four_assemblies(dx: f64) -> Fixture {
+    const A: [&str; 4] = ["A1", "A2", "A3", "A4"];
+    const B: [&str; 4] = ["B1", "B2", "B3", "B4"];
+    const FIXES: [&str; 4] = ["FixBase_1", "FixBase_2", "FixBase_3", "FixBase_4"];
+    const ROTATIONS: [&str; 4] = ["FixRotation_1", "FixRotation_2", "FixRotation_3", "FixRotation_4"];
+    const LOCKS: [&str; 4] = ["Lock_1", "Lock_2", "Lock_3", "Lock_4"];
+    let mut fixture = Fixture::new();
+    for i in 0..4 {
+        let k = i as f64;
+        fixture = fixture
+            .object(A[i], [10.0 * k, 0.0, 0.0, 0.0, 0.0, 0.0])
+            .object(B[i], [1.0, 2.0 + k, 3.0, 0.1, 0.2, 0.3 * k])
+            .constraint(FIXES[i], &[("Object", B[i]), ("Reference", A[i])], &[
+                ("x", dx), ("y", 1.0), ("z", 0.0),
+            ])
+            .constraint(ROTATIONS[i], &[("Object", B[i]), ("Reference", A[i])], &[
+                ("phi", 0.0), ("theta", 0.0), ("psi", 0.0),
+            ])
+            .constraint(LOCKS[i], &[("Object", A[i])], &[
+                ("x", 10.0 * k), ("y", 0.0), ("z", 0.0), ("phi", 0.5), ("theta", 0.0), ("psi", 0.0),
+            ]);
+    }
+    fixture
+}
+
+
+/// A constraint of an object that is always satisfied and counts how many
+/// times the system evaluates it. The counters are shared by its clones, so
+/// they can be read after adding a clone to a system.
+#[derive(Debug)]
+pub struct Counter {
+    pub obj_index: usize,
+    /// calls to evaluate or get_residuals
+    pub evaluations: Arc<AtomicUsize>,
+    /// calls to get_gradient
+    pub gradients: Arc<AtomicUsize>,
+    /// number of times the constraint was cloned
+    pub clones: Arc<AtomicUsize>,
+}
+
+
+impl Counter {
+    pub fn new(obj_index: usize) -> Counter {
+        Counter {
+            obj_index,
+            evaluations: Arc::new(AtomicUsize::new(0)),
+            gradients: Arc::new(AtomicUsize::new(0)),
+            clones: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+
+impl Clone for Counter {
+    fn clone(&self) -> Counter {
+        self.clones.fetch_add(1, Ordering::SeqCst);
+        Counter {
+            obj_index: self.obj_index,
+            evaluations: self.evaluations.clone(),
+            gradients: self.gradients.clone(),
+            clones: self.clones.clone(),
+        }
+    }
+}
+
+
+impl Constraint for Counter {
+    fn evaluate(&mut self, _sys_objects: &[SystemObject]) {
+        self.evaluations.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn get_value(&self) -> f64 {
+        0.0
+    }
+
+    fn get_gradient(&self, _sys_grad: &mut Array1<f64>, _sys_objects: &[SystemObject]) {
+        self.gradients.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn get_diff(&self, _direction: &Array1<f64>, _sys_objects: &[SystemObject]) -> f64 {
+        0.0
+    }
+
+    fn get_hessian(&self, _sys_hess: &mut dyn HessianAccumulator, _sys_objects: &[SystemObject]) {}
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, _sys_objects: &[SystemObject]) {
+        self.evaluations.fetch_add(1, Ordering::SeqCst);
+        residuals.push(Residual {
+            value: 0.0,
+            jacobian: Vec::new(),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        Vec::new()
+    }
+
+    fn get_name(&self) -> &str {
+        "Counter"
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj_index = object_map[&self.obj_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        Vec::new()
+    }
+
+    fn set_parameters(&mut self, _parameters: &[f64]) {}
+
+    fn current_parameters(&self, _sys_objects: &[SystemObject]) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+
+/// A spring keeping the x variable of an object at a distance length from the
+/// x variable of another object. The constraint function is
+/// (x2 - x1 - length)^2.
+#[derive(Debug, Clone)]
+pub struct Spring {
+    name: String,
+    length: f64,
+    /// value of x2 - x1 - length
+    residual: f64,
+    obj1_index: usize,
+    obj2_index: usize,
+}
+
+
+impl Spring {
+    pub fn new(
+        name: &str,
+        sys_objects: &mut [SystemObject],
+        length: f64,
+        obj1_index: usize,
+        obj2_index: usize,
+    ) -> Spring {
+        sys_objects[obj1_index].enable_variables(&["x"]);
+        sys_objects[obj2_index].enable_variables(&["x"]);
+        Spring {
+            name: name.to_string(),
+            length,
+            residual: 0.0,
+            obj1_index,
+            obj2_index,
+        }
+    }
+
+    /// Partial derivatives of the residual as (solver index, derivative)
+    fn jacobian(&self, sys_objects: &[SystemObject]) -> Vec<(usize, f64)> {
+        [(self.obj1_index, -1.0), (self.obj2_index, 1.0)]
+            .iter()
+            .filter_map(|(obj_idx, jac)| {
+                let variable = sys_objects[*obj_idx].get_variable(VN::x);
+                variable.index.map(|k| (k, variable.scale * jac))
+            })
+            .collect()
+    }
+}
+
+
+/// Creates a spring between the objects with the roles "Object1" and "Object2"
+/// with the length given by the parameter "length"
+pub fn spring_factory(
+    name: &str,
+    objects: &HashMap<&str, usize>,
+    sys_objects: &mut [SystemObject],
+    params: &HashMap<&str, f64>,
+) -> Box<dyn CustomConstraint> {
+    Box::new(Spring::new(name, sys_objects, params["length"], objects["Object1"], objects["Object2"]))
+}
+
+
+impl Constraint for Spring {
+    fn evaluate(&mut self, sys_objects: &[SystemObject]) {
+        let x1 = sys_objects[self.obj1_index].get_variable(VN::x).value;
+        let x2 = sys_objects[self.obj2_index].get_variable(VN::x).value;
+        self.residual = x2 - x1 - self.length;
+    }
+
+    fn get_value(&self) -> f64 {
+        self.residual.powi(2)
+    }
+
+    fn get_gradient(&self, sys_grad: &mut Array1<f64>, sys_objects: &[SystemObject]) {
+        for (k, jac) in self.jacobian(sys_objects) {
+            sys_grad[k] += 2.0 * self.residual * jac;
+        }
+    }
+
+    fn get_diff(&self, direction: &Array1<f64>, sys_objects: &[SystemObject]) -> f64 {
+        self.jacobian(sys_objects)
+            .iter()
+            .map(|(k, jac)| 2.0 * self.residual * jac * direction[*k])
+            .sum()
+    }
+
+    fn get_hessian(&self, sys_hess: &mut dyn HessianAccumulator, sys_objects: &[SystemObject]) {
+        let jacobian = self.jacobian(sys_objects);
+        for (i, jac_i) in &jacobian {
+            for (j, jac_j) in &jacobian {
+                sys_hess.add(*i, *j, 2.0 * jac_i * jac_j);
+            }
+        }
+    }
+
+    fn get_residuals(&self, residuals: &mut Vec<Residual>, sys_objects: &[SystemObject]) {
+        residuals.push(Residual {
+            value: self.residual,
+            jacobian: self.jacobian(sys_objects),
+        });
+    }
+
+    fn get_kind(&self) -> ResidualKind {
+        ResidualKind::Length
+    }
+
+    fn affected_objects(&self) -> Vec<usize> {
+        vec![self.obj1_index, self.obj2_index]
+    }
+
+    fn footprint(&self) -> Vec<(usize, Footprint)> {
+        vec![(self.obj1_index, Footprint::Variables), (self.obj2_index, Footprint::Variables)]
+    }
+
+    fn get_variables(&self) -> Vec<(usize, VN)> {
+        vec![(self.obj1_index, VN::x), (self.obj2_index, VN::x)]
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn remap_objects(&mut self, object_map: &HashMap<usize, usize>) {
+        self.obj1_index = object_map[&self.obj1_index];
+        self.obj2_index = object_map[&self.obj2_index];
+    }
+
+    fn get_parameters(&self) -> Vec<f64> {
+        vec![self.length]
+    }
+
+    fn set_parameters(&mut self, parameters: &[f64]) {
+        self.length = parameters[0];
+    }
+
+    fn current_parameters(&self, sys_objects: &[SystemObject]) -> Vec<f64> {
+        let x1 = sys_objects[self.obj1_index].get_variable(VN::x).value;
+        let x2 = sys_objects[self.obj2_index].get_variable(VN::x).value;
+        vec![x2 - x1]
+    }
+}
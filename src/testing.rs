@@ -0,0 +1,202 @@
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 2.1 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+
+
+//! Checks of the derivatives of constraints against finite differences.
+//!
+//! Every constraint computes the gradient and hessian of its function itself,
+//! so a wrong partial derivative only shows up as a solver that converges
+//! slowly or not at all. verify_constraint_derivatives compares them with
+//! central differences of the value and of the gradient.
+
+use ndarray::{Array1, Array2};
+
+use crate::constraints::Constraint;
+use crate::system_object::{SystemObject, VariableName as VN};
+
+
+/// Largest error allowed between a derivative and its finite difference. It is
+/// relative for derivatives larger than 1 and absolute otherwise.
+const TOLERANCE: f64 = 1e-4;
+
+
+/// A derivative that does not match its finite difference
+#[derive(Debug, Clone)]
+pub struct DerivativeMismatch {
+    /// variable of the derivative as (object index, variable name)
+    pub variable: (usize, VN),
+    /// second variable of a hessian entry, None for a gradient entry
+    pub other: Option<(usize, VN)>,
+    /// derivative computed by the constraint
+    pub analytic: f64,
+    /// derivative found with finite differences
+    pub numeric: f64,
+}
+
+
+/// The result of verify_constraint_derivatives
+#[derive(Debug, Clone)]
+pub struct DerivativeReport {
+    /// number of variables (with a solver index) that were perturbed
+    pub checked_variables: usize,
+    /// gradient and hessian entries that do not match their finite differences
+    pub mismatches: Vec<DerivativeMismatch>,
+}
+
+
+impl DerivativeReport {
+    /// Returns true when every derivative matches its finite difference
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+
+/// Compares the gradient and the hessian of a constraint with central finite
+/// differences at the placements of sys_objects.
+///
+/// Every variable with a solver index is perturbed by eps in both directions
+/// (the variables sharing the index of an equal variable move together). The
+/// gradient is compared with the differences of the constraint value and each
+/// column of the hessian with the differences of the gradient, so a constraint
+/// with a correct gradient but a wrong hessian is reported too. The constraint
+/// is left evaluated at the unperturbed placements.
+pub fn verify_constraint_derivatives(
+    constraint: &mut dyn Constraint,
+    sys_objects: &[SystemObject],
+    eps: f64,
+) -> DerivativeReport {
+    let mut objects = sys_objects.to_vec();
+    let variables = solver_variables(&objects);
+    let n = variables.len();
+
+    let (_, grad, hess) = derivatives(constraint, &objects, n);
+    let mut mismatches = Vec::new();
+    for (k, variable) in variables.iter().enumerate() {
+        let variable = match variable {
+            Some(variable) => *variable,
+            None => continue,
+        };
+        let value = variable_value(&objects, k);
+        set_variable(&mut objects, k, value + eps);
+        let (value_plus, grad_plus, _) = derivatives(constraint, &objects, n);
+        set_variable(&mut objects, k, value - eps);
+        let (value_minus, grad_minus, _) = derivatives(constraint, &objects, n);
+        set_variable(&mut objects, k, value);
+
+        let numeric = (value_plus - value_minus) / (2.0 * eps);
+        if !matches(grad[k], numeric) {
+            mismatches.push(DerivativeMismatch {
+                variable,
+                other: None,
+                analytic: grad[k],
+                numeric,
+            });
+        }
+        for (i, row_variable) in variables.iter().enumerate() {
+            let row_variable = match row_variable {
+                Some(row_variable) => *row_variable,
+                None => continue,
+            };
+            let numeric = (grad_plus[i] - grad_minus[i]) / (2.0 * eps);
+            if !matches(hess[[i, k]], numeric) {
+                mismatches.push(DerivativeMismatch {
+                    variable: row_variable,
+                    other: Some(variable),
+                    analytic: hess[[i, k]],
+                    numeric,
+                });
+            }
+        }
+    }
+    constraint.evaluate(&objects);
+
+    DerivativeReport {
+        checked_variables: variables.iter().flatten().count(),
+        mismatches,
+    }
+}
+
+
+/// Returns the variable of each solver index as (object index, variable name),
+/// or None for an index used by no variable. When several variables share an
+/// index the first one is returned.
+fn solver_variables(objects: &[SystemObject]) -> Vec<Option<(usize, VN)>> {
+    let mut variables: Vec<Option<(usize, VN)>> = Vec::new();
+    for (obj_index, object) in objects.iter().enumerate() {
+        for var_name in VN::get_variable_iter() {
+            let variable = object.get_variable(var_name);
+            if let (true, Some(k)) = (variable.enabled, variable.index) {
+                if variables.len() <= k {
+                    variables.resize(k + 1, None);
+                }
+                variables[k].get_or_insert((obj_index, var_name));
+            }
+        }
+    }
+    variables
+}
+
+
+/// Returns the value of the solver variable k (the value of a variable with
+/// that index divided by its scale)
+fn variable_value(objects: &[SystemObject], k: usize) -> f64 {
+    objects
+        .iter()
+        .flat_map(|object| object.get_variables_iter())
+        .find(|variable| variable.index == Some(k))
+        .map_or(0.0, |variable| variable.value / variable.scale)
+}
+
+
+/// Sets the value of the solver variable k (every variable with that index
+/// takes the value times its scale) and updates the position vectors and
+/// quaternions of the objects
+fn set_variable(objects: &mut [SystemObject], k: usize, value: f64) {
+    for object in objects.iter_mut() {
+        for variable in object.get_variables_mut_iter() {
+            if variable.index == Some(k) {
+                variable.value = variable.scale * value;
+            }
+        }
+        if object.q_enable {
+            object.update_q();
+        }
+        if object.v_enable {
+            object.update_v();
+        }
+    }
+}
+
+
+/// Evaluates the constraint and returns its value, gradient and hessian
+fn derivatives(
+    constraint: &mut dyn Constraint,
+    objects: &[SystemObject],
+    n: usize,
+) -> (f64, Array1<f64>, Array2<f64>) {
+    constraint.evaluate(objects);
+    let mut grad = Array1::zeros(n);
+    constraint.get_gradient(&mut grad, objects);
+    let mut hess = Array2::zeros((n, n));
+    constraint.get_hessian(&mut hess, objects);
+    (constraint.get_value(), grad, hess)
+}
+
+
+/// Returns true when a derivative matches its finite difference
+fn matches(analytic: f64, numeric: f64) -> bool {
+    (analytic - numeric).abs() <= TOLERANCE * numeric.abs().max(1.0)
+}
@@ -0,0 +1,67 @@
+# This library is free software; you can redistribute it and/or
+# modify it under the terms of the GNU Lesser General Public
+# License as published by the Free Software Foundation; either
+# version 2.1 of the License, or (at your option) any later version.
+#
+# This library is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+# Lesser General Public License for more details.
+#
+# You should have received a copy of the GNU Lesser General Public
+# License along with this library; if not, write to the Free Software
+# Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+# Solver option presets passed from Python. Run with
+# python -m unittest discover tests (after building the module).
+
+import unittest
+
+from asm4_solver.solver import SolverSystem
+
+
+def two_part():
+    """A locked object "A" and an object "B" fixed 5 away from it along x"""
+    objects = {
+        "A": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "B": dict(x=1.0, y=2.0, z=3.0, phi=0.1, theta=0.2, psi=0.3),
+    }
+    names = {
+        "Lock_1": {"Object": "A"},
+        "FixBase_1": {"Object": "B", "Reference": "A"},
+        "FixRotation_1": {"Object": "B", "Reference": "A"},
+    }
+    params = {
+        "Lock_1": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "FixBase_1": dict(x=5.0, y=0.0, z=0.0),
+        "FixRotation_1": dict(phi=0.0, theta=0.0, psi=0.0),
+    }
+    return objects, names, params
+
+
+class PresetsTest(unittest.TestCase):
+    def test_preset_is_reported(self):
+        for preset in ("interactive", "accurate", "draft"):
+            system = SolverSystem(*two_part())
+            placements, success = system.solve({"preset": preset})
+            self.assertTrue(success)
+            self.assertAlmostEqual(placements["B"]["x"], 5.0, places=3)
+            self.assertEqual(system.report()["preset"], preset)
+
+    def test_keys_override_the_preset(self):
+        # the keys are applied after the preset whatever their order
+        system = SolverSystem(*two_part())
+        system.solve({"max_iterations": 1, "preset": "accurate", "restarts": 0, "method": "lbfgs"})
+        report = system.report()
+        self.assertEqual(report["preset"], "accurate")
+        self.assertEqual(len(report["attempts"]), 1)
+        self.assertLessEqual(report["attempts"][0]["iterations"], 1)
+
+    def test_unknown_preset(self):
+        system = SolverSystem(*two_part())
+        with self.assertRaises(ValueError):
+            system.solve({"preset": "fast"})
+
+
+if __name__ == "__main__":
+    unittest.main()
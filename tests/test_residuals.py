@@ -0,0 +1,63 @@
+# This library is free software; you can redistribute it and/or
+# modify it under the terms of the GNU Lesser General Public
+# License as published by the Free Software Foundation; either
+# version 2.1 of the License, or (at your option) any later version.
+#
+# This library is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+# Lesser General Public License for more details.
+#
+# You should have received a copy of the GNU Lesser General Public
+# License along with this library; if not, write to the Free Software
+# Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+# Residuals of the constraints at given placements. Run with
+# python -m unittest discover tests (after building the module).
+
+import unittest
+
+from asm4_solver.solver import get_residuals, solve_constraint_system
+
+
+def equal_parts(b_x):
+    """A locked "Base", "A" fixed 2 away from it along x and "B" with the x of
+    "A", also fixed 2 away from "Base" along x"""
+    objects = {
+        "Base": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "A": dict(x=2.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "B": dict(x=b_x, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+    }
+    names = {
+        "Lock_1": {"Object": "Base"},
+        "FixBase_1": {"Object": "A", "Reference": "Base"},
+        "FixBase_2": {"Object": "B", "Reference": "Base"},
+        "Equality_1": {"Object1": "A", "Object2": "B"},
+    }
+    params = {
+        "Lock_1": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "FixBase_1": dict(x=2.0, y=0.0, z=0.0),
+        "FixBase_2": dict(x=2.0, y=0.0, z=0.0),
+        "Equality_1": dict(x=1.0),
+    }
+    return objects, names, params
+
+
+class ResidualsTest(unittest.TestCase):
+    def test_residuals_of_the_solution(self):
+        objects, names, params = equal_parts(7.0)
+        placements, success = solve_constraint_system(objects, names, params)
+        self.assertTrue(success)
+        for name, residual in get_residuals(placements, names, params).items():
+            self.assertAlmostEqual(residual, 0.0, places=9, msg=name)
+
+    def test_equal_variables_take_the_value_of_their_root(self):
+        # the x of "B" is the x of "A" for the solver, so the placement given
+        # for "B" does not change the residuals
+        residuals = get_residuals(*equal_parts(7.0))
+        self.assertEqual(residuals, get_residuals(*equal_parts(2.0)))
+        self.assertAlmostEqual(residuals["FixBase_2"], 0.0, places=12)
+
+
+if __name__ == "__main__":
+    unittest.main()
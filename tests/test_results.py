@@ -0,0 +1,61 @@
+# This library is free software; you can redistribute it and/or
+# modify it under the terms of the GNU Lesser General Public
+# License as published by the Free Software Foundation; either
+# version 2.1 of the License, or (at your option) any later version.
+#
+# This library is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+# Lesser General Public License for more details.
+#
+# You should have received a copy of the GNU Lesser General Public
+# License along with this library; if not, write to the Free Software
+# Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+# The SolverResult returned by solve. Run with
+# python -m unittest discover tests (after building the module).
+
+import unittest
+
+from asm4_solver.solver import solve
+
+
+def two_part(dx):
+    """A locked object "A" and an object "B" fixed dx away from it along x,
+    while a second fix keeps "B" 1 away from "A" along x"""
+    objects = {
+        "A": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "B": dict(x=1.0, y=2.0, z=3.0, phi=0.1, theta=0.2, psi=0.3),
+    }
+    names = {
+        "Lock_1": {"Object": "A"},
+        "FixBase_1": {"Object": "B", "Reference": "A"},
+        "FixBase_2": {"Object": "B", "Reference": "A"},
+    }
+    params = {
+        "Lock_1": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0),
+        "FixBase_1": dict(x=dx, y=0.0, z=0.0),
+        "FixBase_2": dict(x=1.0, y=0.0, z=0.0),
+    }
+    return objects, names, params
+
+
+class ResultsTest(unittest.TestCase):
+    def test_final_error_of_a_solved_system(self):
+        placements, result = solve(*two_part(1.0))
+        self.assertTrue(result.success)
+        self.assertAlmostEqual(placements["B"]["x"], 1.0, places=6)
+        self.assertLess(result.final_error, 1e-12)
+        self.assertEqual(result.final_error, result.objective)
+
+    def test_final_error_of_conflicting_fixes(self):
+        # the fixes are 2 apart, so the best compromise leaves an error of 1
+        # in each of them
+        placements, result = solve(*two_part(3.0))
+        self.assertAlmostEqual(placements["B"]["x"], 2.0, places=4)
+        self.assertAlmostEqual(result.final_error, 2.0, places=4)
+        self.assertEqual(result.final_error, result.objective)
+
+
+if __name__ == "__main__":
+    unittest.main()
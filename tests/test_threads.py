@@ -0,0 +1,102 @@
+# This library is free software; you can redistribute it and/or
+# modify it under the terms of the GNU Lesser General Public
+# License as published by the Free Software Foundation; either
+# version 2.1 of the License, or (at your option) any later version.
+#
+# This library is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+# Lesser General Public License for more details.
+#
+# You should have received a copy of the GNU Lesser General Public
+# License along with this library; if not, write to the Free Software
+# Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301 USA
+
+# Solving SolverSystem instances from several Python threads. Run with
+# python -m unittest discover tests (after building the module).
+
+import threading
+import unittest
+
+from asm4_solver.solver import SolverSystem
+
+
+def chain(n, offset):
+    """A locked base and a chain of n objects, each one fixed at an offset
+    along x relative to the previous one"""
+    objects = {"Base": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0)}
+    names = {"Lock_0": {"Object": "Base"}}
+    params = {"Lock_0": dict(x=0.0, y=0.0, z=0.0, phi=0.0, theta=0.0, psi=0.0)}
+    previous = "Base"
+    for i in range(n):
+        name = "P{}".format(i)
+        objects[name] = dict(x=float(i), y=0.5 * i, z=-1.0 * i, phi=0.3 * i, theta=0.1 * i, psi=-0.3 * i)
+        names["FixBase_{}".format(i)] = {"Object": name, "Reference": previous}
+        params["FixBase_{}".format(i)] = dict(x=offset, y=0.0, z=0.0)
+        names["FixRotation_{}".format(i)] = {"Object": name, "Reference": previous}
+        params["FixRotation_{}".format(i)] = dict(phi=0.0, theta=0.0, psi=0.0)
+        previous = name
+    return objects, names, params
+
+
+class ThreadsTest(unittest.TestCase):
+    def test_independent_systems(self):
+        # Each thread builds and solves its own system with a different offset
+        results = [None] * 4
+
+        def run(k):
+            system = SolverSystem(*chain(6, 1.0 + k))
+            results[k] = system.solve()
+
+        threads = [threading.Thread(target=run, args=(k,)) for k in range(4)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+
+        for k, (placements, success) in enumerate(results):
+            self.assertTrue(success)
+            for i in range(6):
+                part = placements["P{}".format(i)]
+                self.assertAlmostEqual(part["x"], (i + 1) * (1.0 + k), places=5)
+                self.assertAlmostEqual(part["y"], 0.0, places=5)
+                self.assertAlmostEqual(part["z"], 0.0, places=5)
+
+    def test_concurrent_use_of_one_system(self):
+        # Two threads move the first object away and solve the same instance
+        # over and over. The instance is borrowed while the GIL is released, so
+        # a solve started while the other thread is solving raises a
+        # RuntimeError.
+        system = SolverSystem(*chain(10, 1.0))
+        errors = []
+        solved = []
+        barrier = threading.Barrier(2)
+
+        def run():
+            barrier.wait()
+            for k in range(5):
+                try:
+                    system.update_placement("P0", dict(x=-5.0 * k, phi=1.0))
+                except RuntimeError:
+                    # the other thread is solving the system
+                    pass
+                try:
+                    solved.append(system.solve()[1])
+                except RuntimeError as error:
+                    errors.append(str(error))
+
+        threads = [threading.Thread(target=run) for _ in range(2)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+
+        self.assertTrue(errors)
+        for error in errors:
+            self.assertIn("already being solved by another thread", error)
+        self.assertTrue(solved)
+        self.assertTrue(all(solved))
+
+
+if __name__ == "__main__":
+    unittest.main()